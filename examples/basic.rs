@@ -13,25 +13,31 @@ fn main() {
     let guard = ShutdownGuard::new();
 
     // Register a cleanup callback
-    guard.register(Box::new(|| {
-        println!("🧹 Cleanup callback 1: Saving application state...");
-        thread::sleep(Duration::from_secs(1));
-        println!("✅ Application state saved!");
-    }));
+    guard
+        .register(Box::new(|_| {
+            println!("🧹 Cleanup callback 1: Saving application state...");
+            thread::sleep(Duration::from_secs(1));
+            println!("✅ Application state saved!");
+        }))
+        .expect("Failed to register the cleanup");
 
     // Register another callback
-    guard.register(Box::new(|| {
-        println!("🧹 Cleanup callback 2: Closing database connections...");
-        thread::sleep(Duration::from_secs(1));
-        println!("✅ Database connections closed!");
-    }));
+    guard
+        .register(Box::new(|_| {
+            println!("🧹 Cleanup callback 2: Closing database connections...");
+            thread::sleep(Duration::from_secs(1));
+            println!("✅ Database connections closed!");
+        }))
+        .expect("Failed to register the cleanup");
 
     // Register a third callback
-    guard.register(Box::new(|| {
-        println!("🧹 Cleanup callback 3: Flushing logs...");
-        thread::sleep(Duration::from_secs(1));
-        println!("✅ Logs flushed!");
-    }));
+    guard
+        .register(Box::new(|_| {
+            println!("🧹 Cleanup callback 3: Flushing logs...");
+            thread::sleep(Duration::from_secs(1));
+            println!("✅ Logs flushed!");
+        }))
+        .expect("Failed to register the cleanup");
 
     println!("Registered {} cleanup callbacks", guard.callback_count());
 
@@ -45,14 +51,9 @@ fn main() {
     }
 
     println!("\n📢 Application is running. Try shutting down your system to see the cleanup callbacks in action.");
-    println!("📢 Press Ctrl+C to exit (note: Ctrl+C won't trigger shutdown callbacks, only system shutdown will).\n");
-
-    // Keep the application running
-    loop {
-        thread::sleep(Duration::from_secs(10));
-        println!(
-            "⏰ Application still running... ({})",
-            chrono::Local::now().format("%H:%M:%S")
-        );
-    }
+    println!("📢 Press Ctrl+C to exit (note: Ctrl+C won't trigger shutdown callbacks without treat_interrupt_as_shutdown, only system shutdown will).\n");
+
+    // Keep the application running until the callbacks ran
+    let context = guard.wait_for_shutdown();
+    println!("👋 Shut down ({})", context.reason);
 }
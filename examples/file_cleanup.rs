@@ -25,23 +25,29 @@ fn main() {
     let counter_cleanup = Arc::clone(&counter);
 
     // Register cleanup callback
-    guard.register(Box::new(move || {
-        println!("🧹 Shutdown detected! Performing file cleanup...");
+    guard
+        .register(Box::new(move |context| {
+            println!(
+                "🧹 Shutdown detected ({})! Performing file cleanup...",
+                context.reason
+            );
 
-        // Write final statistics to log
-        if let Ok(mut file) = OpenOptions::new().append(true).open(&log_path_cleanup) {
-            let count = counter_cleanup.load(Ordering::SeqCst);
-            writeln!(file, "\n=== SHUTDOWN ===").ok();
-            writeln!(file, "Total operations: {}", count).ok();
-            writeln!(file, "Timestamp: {}", chrono::Local::now()).ok();
-            println!("✅ Final statistics written to log");
-        }
+            // Write final statistics to log
+            if let Ok(mut file) = OpenOptions::new().append(true).open(&log_path_cleanup) {
+                let count = counter_cleanup.load(Ordering::SeqCst);
+                writeln!(file, "\n=== SHUTDOWN ===").ok();
+                writeln!(file, "Total operations: {}", count).ok();
+                writeln!(file, "Reason: {}", context.reason).ok();
+                writeln!(file, "Timestamp: {}", chrono::Local::now()).ok();
+                println!("✅ Final statistics written to log");
+            }
 
-        // Give time for I/O to complete
-        thread::sleep(Duration::from_millis(500));
+            // Give time for I/O to complete
+            thread::sleep(Duration::from_millis(500));
 
-        println!("✅ File cleanup completed!");
-    }));
+            println!("✅ File cleanup completed!");
+        }))
+        .expect("Failed to register the cleanup");
 
     // Start monitoring
     guard.start().expect("Failed to start monitoring");
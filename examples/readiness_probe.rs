@@ -0,0 +1,74 @@
+//! Example of a readiness endpoint that starts failing once a shutdown is
+//! detected, so an orchestrator such as Kubernetes drains the instance
+//!
+//! Run with: cargo run --example readiness_probe
+//! Then: curl -i http://127.0.0.1:8080/readyz
+
+use shutdown_guard_rs::{Registry, ShutdownGuard};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/// Answers one request with the readiness state; a framework handler does
+/// the same, e.g. in axum
+/// `StatusCode::from_u16(guard.readiness_state().http_status())`
+fn answer(guard: &Registry, stream: TcpStream) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let readiness = guard.readiness_state();
+    let status = match readiness.http_status() {
+        200 => "200 OK",
+        _ => "503 Service Unavailable",
+    };
+    let body = format!("{}\n", readiness);
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn main() {
+    let guard = ShutdownGuard::builder()
+        .treat_interrupt_as_shutdown(true)
+        .build();
+
+    // Keep serving while the callbacks run so the probe can report draining
+    guard
+        .register(Box::new(|_| {
+            println!("🚦 Draining: the readiness probe now answers 503");
+            thread::sleep(Duration::from_secs(5));
+            println!("✅ In-flight work finished");
+        }))
+        .expect("Failed to register the cleanup");
+
+    if let Err(e) = guard.start() {
+        eprintln!("❌ Failed to start shutdown monitoring: {}", e);
+        return;
+    }
+
+    let listener = match TcpListener::bind("127.0.0.1:8080") {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("❌ Failed to bind 127.0.0.1:8080: {}", e);
+            return;
+        }
+    };
+    println!("📢 Serving GET /readyz on 127.0.0.1:8080; press Ctrl+C to start draining");
+
+    let registry = Registry::clone(&guard);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = answer(&registry, stream) {
+                eprintln!("Failed to answer a probe: {}", e);
+            }
+        }
+    });
+
+    let context = guard.wait_for_shutdown();
+    println!("👋 Shut down ({})", context.reason);
+}
@@ -1,9 +1,8 @@
 use shutdown_guard_rs::ShutdownGuard;
+use std::env;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::PathBuf;
-use std::time::Duration;
-use std::{env, thread};
 
 fn main() {
     println!("Starting Shutdown Guard...");
@@ -15,42 +14,46 @@ fn main() {
     let guard = ShutdownGuard::new();
     let output_file_clone = output_file.clone();
 
-    guard.register(Box::new(move || {
-        let timestamp = chrono::Local::now();
-        let message = format!(
-            "Shutdown time: {}\nTimestamp: {}\n",
-            timestamp.format("%Y-%m-%d %H:%M:%S"),
-            timestamp.timestamp()
-        );
+    guard
+        .register(Box::new(move |context| {
+            let timestamp = chrono::Local::now();
+            let message = format!(
+                "Shutdown time: {}\nTimestamp: {}\nReason: {}\nSource: {:?}\n",
+                timestamp.format("%Y-%m-%d %H:%M:%S"),
+                timestamp.timestamp(),
+                context.reason,
+                context.source
+            );
 
-        println!("\n{:=<50}", "");
-        println!("Shutdown signal detected!");
-        println!("{:=<50}", "");
-        println!("{}", message);
+            println!("\n{:=<50}", "");
+            println!("Shutdown signal detected!");
+            println!("{:=<50}", "");
+            println!("{}", message);
 
-        match OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&output_file_clone)
-        {
-            Ok(mut file) => {
-                if let Err(e) = write!(file, "{}", message) {
-                    eprintln!("Write failed: {}", e);
-                } else {
-                    if let Err(e) = file.sync_all() {
-                        eprintln!("Sync failed: {}", e);
+            match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&output_file_clone)
+            {
+                Ok(mut file) => {
+                    if let Err(e) = write!(file, "{}", message) {
+                        eprintln!("Write failed: {}", e);
                     } else {
-                        println!("Written and synced to: {:?}", output_file_clone);
+                        if let Err(e) = file.sync_all() {
+                            eprintln!("Sync failed: {}", e);
+                        } else {
+                            println!("Written and synced to: {:?}", output_file_clone);
+                        }
                     }
                 }
+                Err(e) => {
+                    eprintln!("Open failed: {}", e);
+                }
             }
-            Err(e) => {
-                eprintln!("Open failed: {}", e);
-            }
-        }
 
-        println!("{:=<50}\n", "");
-    }));
+            println!("{:=<50}\n", "");
+        }))
+        .expect("Failed to register the cleanup");
 
     match guard.start() {
         Ok(_) => println!("Shutdown monitoring started"),
@@ -66,11 +69,5 @@ fn main() {
     println!("Ctrl+C will not trigger callback");
     println!("{:=<50}\n", "");
 
-    let mut counter = 0;
-    loop {
-        thread::sleep(Duration::from_secs(5));
-        counter += 1;
-        let now = chrono::Local::now();
-        println!("[{}] Running... count: {}", now.format("%H:%M:%S"), counter);
-    }
+    guard.wait_for_shutdown();
 }
@@ -0,0 +1,39 @@
+/*
+ * C API of shutdown-guard-rs, available with the `capi` feature.
+ *
+ * Build the library with
+ *     cargo rustc --release --features capi --crate-type staticlib
+ *
+ * Callbacks run on the library's monitoring thread, not on the thread that
+ * registered them, so they and their context must be thread-safe. The
+ * context pointer is passed back unchanged and must outlive the guard.
+ */
+#ifndef SHUTDOWN_GUARD_H
+#define SHUTDOWN_GUARD_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SgGuard SgGuard;
+
+typedef void (*sg_callback)(void *context);
+
+/* Creates a guard; free it with sg_guard_free. */
+SgGuard *sg_guard_new(void);
+
+/* Registers a callback run before shutdown. Returns 0, or -1 if guard is NULL
+   or refused the callback (logged to stderr). */
+int sg_register(SgGuard *guard, sg_callback callback, void *context);
+
+/* Starts monitoring. Returns 0, or -1 on failure (logged to stderr). */
+int sg_start(SgGuard *guard);
+
+/* Stops monitoring and frees the guard. NULL is ignored. */
+void sg_guard_free(SgGuard *guard);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SHUTDOWN_GUARD_H */
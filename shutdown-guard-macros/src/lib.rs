@@ -0,0 +1,74 @@
+//! The `#[shutdown_hook]` attribute, re-exported by `shutdown-guard-rs` with
+//! the `macros` feature
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Expr, ItemFn, LitStr};
+
+/// Registers a `fn(ShutdownContext)` with the global guard at load time
+///
+/// The function is collected before `main` runs and registered when
+/// `shutdown_guard_rs::start()` is called. Optional arguments:
+///
+/// - `priority = <i32>`: like `register_with_priority`, higher runs first
+/// - `name = "<name>"`: the callback's name, the function's name by default
+///
+/// Hooks with the same priority run in the order they were collected, which
+/// follows link order and is not otherwise specified.
+#[proc_macro_attribute]
+pub fn shutdown_hook(args: TokenStream, item: TokenStream) -> TokenStream {
+    let function = parse_macro_input!(item as ItemFn);
+
+    let mut priority: Option<Expr> = None;
+    let mut name: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("priority") {
+            priority = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `priority` or `name`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let ident = &function.sig.ident;
+    let priority = priority.map_or_else(|| quote!(0), |priority| quote!(#priority));
+    let name = name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let collector = format_ident!("__SHUTDOWN_HOOK_{}", ident);
+
+    // A constructor in the platform's initializer section, as the `ctor`
+    // crate does, so the hook is collected before `main`
+    quote! {
+        #function
+
+        #[used]
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals)]
+        #[cfg_attr(
+            any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "netbsd",
+                target_os = "openbsd"
+            ),
+            link_section = ".init_array"
+        )]
+        #[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+        #[cfg_attr(windows, link_section = ".CRT$XCU")]
+        static #collector: extern "C" fn() = {
+            extern "C" fn collect() {
+                ::shutdown_guard_rs::__private::collect(::shutdown_guard_rs::__private::CollectedHook {
+                    name: #name,
+                    priority: #priority,
+                    callback: #ident,
+                });
+            }
+            collect
+        };
+    }
+    .into()
+}
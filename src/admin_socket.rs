@@ -0,0 +1,101 @@
+//! Shutdown commands over a Unix domain socket
+
+use crate::{Registry, ShutdownContext, ShutdownReason, TriggerSource};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// The line that triggers a shutdown; any other line is answered with an
+/// error and ignored
+const SHUTDOWN_COMMAND: &str = "shutdown";
+
+/// A socket opened by `Registry::listen_on_socket`, closed when the guard
+/// stops
+pub(crate) struct AdminSocket {
+    path: PathBuf,
+    closed: Arc<AtomicBool>,
+}
+
+impl AdminSocket {
+    /// Stops accepting connections and removes the socket file
+    pub(crate) fn close(self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // Wakes the listener blocked in accept so it sees the flag
+        let _ = UnixStream::connect(&self.path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Binds `path` and serves the connections on a thread of its own
+///
+/// A socket file nobody accepts on any more, e.g. left behind by a crashed
+/// process, is replaced; one that is still in use fails with
+/// `AddrInUse`.
+pub(crate) fn listen(registry: Registry, path: &Path) -> io::Result<(AdminSocket, JoinHandle<()>)> {
+    let listener = match UnixListener::bind(path) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse && is_stale(path) => {
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)?
+        }
+        result => result?,
+    };
+
+    let closed = Arc::new(AtomicBool::new(false));
+    let thread = {
+        let closed = Arc::clone(&closed);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if closed.load(Ordering::SeqCst) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => serve(&registry, stream),
+                    Err(e) => eprintln!("Admin socket failed to accept a connection: {}", e),
+                }
+            }
+        })
+    };
+
+    let socket = AdminSocket {
+        path: path.to_path_buf(),
+        closed,
+    };
+    Ok((socket, thread))
+}
+
+/// Returns true if `path` is a socket file no process accepts on
+fn is_stale(path: &Path) -> bool {
+    matches!(
+        UnixStream::connect(path),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused
+    )
+}
+
+/// Answers each line of one connection until the client hangs up
+///
+/// The reply to `shutdown` is only sent once the callbacks ran, so the
+/// client can wait for the cleanup to finish.
+fn serve(registry: &Registry, stream: UnixStream) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { return };
+        let reply = if line.trim() == SHUTDOWN_COMMAND {
+            registry.execute(ShutdownContext::new(
+                ShutdownReason::Admin,
+                TriggerSource::AdminSocket,
+            ));
+            "ok"
+        } else {
+            "unknown command"
+        };
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Async shutdown callbacks for tokio applications
+
+use crate::{CallbackTimeout, ShutdownContext, ShutdownReason, TriggerSource};
+use parking_lot::RwLock;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// The future returned by an [`AsyncShutdownCallback`]
+pub type ShutdownFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// An async callback that will be awaited before shutdown
+pub type AsyncShutdownCallback = Arc<dyn Fn(ShutdownContext) -> ShutdownFuture + Send + Sync>;
+
+/// Awaits shutdown signals and runs async cleanup callbacks concurrently
+///
+/// Unlike [`ShutdownGuard`](crate::ShutdownGuard), this installs no raw
+/// signal handlers: it waits on `tokio::signal` streams, so callbacks run as
+/// ordinary tasks on the caller's runtime and may await freely.
+///
+/// ```no_run
+/// use shutdown_guard_rs::AsyncShutdownGuard;
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let guard = AsyncShutdownGuard::new();
+/// guard.register_async(|context| {
+///     Box::pin(async move {
+///         println!("Flushing connections ({})...", context.reason);
+///     })
+/// });
+///
+/// // Resolves once a shutdown signal arrived and the callbacks finished
+/// guard.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct AsyncShutdownGuard {
+    callbacks: RwLock<Vec<AsyncShutdownCallback>>,
+    timeout: Option<Duration>,
+}
+
+impl AsyncShutdownGuard {
+    /// Creates a new guard without a callback timeout
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a guard that abandons each callback after `timeout`
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            callbacks: RwLock::new(Vec::new()),
+            timeout: Some(timeout),
+        }
+    }
+
+    /// Registers an async callback to be awaited before shutdown
+    pub fn register_async<F>(&self, callback: F)
+    where
+        F: Fn(ShutdownContext) -> ShutdownFuture + Send + Sync + 'static,
+    {
+        self.callbacks.write().push(Arc::new(callback));
+    }
+
+    /// Returns the number of registered callbacks
+    pub fn callback_count(&self) -> usize {
+        self.callbacks.read().len()
+    }
+
+    /// Waits for a shutdown signal, then runs all callbacks
+    ///
+    /// Waits for the platform's default signals (see
+    /// [`ShutdownGuard::start`](crate::ShutdownGuard::start)) on Unix, and
+    /// for close, logoff and shutdown console events on Windows; Ctrl+C is
+    /// left alone on both.
+    /// Returns the context the callbacks ran with.
+    pub async fn run(&self) -> std::io::Result<ShutdownContext> {
+        let context = wait_for_shutdown().await?;
+        self.execute_callbacks(context).await;
+        Ok(context)
+    }
+
+    /// Runs all callbacks concurrently and waits for them to finish
+    ///
+    /// A callback that panics or exceeds the timeout is logged and does not
+    /// affect the others. Must be called within a tokio runtime.
+    pub async fn execute_callbacks(&self, context: ShutdownContext) {
+        let mut tasks = JoinSet::new();
+        for callback in self.callbacks.read().iter() {
+            let future = callback(context);
+            let timeout = self.timeout;
+            tasks.spawn(async move {
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, future)
+                        .await
+                        .map_err(|_| CallbackTimeout { timeout }),
+                    None => {
+                        future.await;
+                        Ok(())
+                    }
+                }
+            });
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("Async shutdown callback failed: {}", e),
+                Err(e) => eprintln!("Async shutdown callback failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Resolves with the context of the first shutdown signal
+#[cfg(unix)]
+async fn wait_for_shutdown() -> std::io::Result<ShutdownContext> {
+    use crate::platform;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    for &sig in platform::DEFAULT_SIGNALS {
+        let mut stream = signal(SignalKind::from_raw(sig))?;
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if stream.recv().await.is_some() {
+                let _ = tx.send(sig);
+            }
+        });
+    }
+    drop(tx);
+
+    let sig = rx
+        .recv()
+        .await
+        .ok_or_else(|| std::io::Error::other("signal streams closed"))?;
+
+    #[cfg(target_os = "linux")]
+    let reason = if sig == libc::SIGPWR {
+        ShutdownReason::PowerFailure
+    } else {
+        ShutdownReason::Signal(sig)
+    };
+    #[cfg(not(target_os = "linux"))]
+    let reason = ShutdownReason::Signal(sig);
+
+    Ok(ShutdownContext::new(reason, TriggerSource::UnixSignal(sig)))
+}
+
+/// Resolves with the context of the first console control event
+#[cfg(windows)]
+async fn wait_for_shutdown() -> std::io::Result<ShutdownContext> {
+    use tokio::signal::windows::{ctrl_close, ctrl_logoff, ctrl_shutdown};
+    use windows::Win32::System::Console::{
+        CTRL_CLOSE_EVENT, CTRL_LOGOFF_EVENT, CTRL_SHUTDOWN_EVENT,
+    };
+
+    let mut close = ctrl_close()?;
+    let mut logoff = ctrl_logoff()?;
+    let mut shutdown = ctrl_shutdown()?;
+
+    let (reason, ctrl_type) = tokio::select! {
+        _ = close.recv() => (ShutdownReason::Unknown, CTRL_CLOSE_EVENT),
+        _ = logoff.recv() => (ShutdownReason::Logout, CTRL_LOGOFF_EVENT),
+        _ = shutdown.recv() => (ShutdownReason::PowerOff, CTRL_SHUTDOWN_EVENT),
+    };
+    Ok(ShutdownContext::new(
+        reason,
+        TriggerSource::WindowsConsoleEvent(ctrl_type),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_callbacks_run_concurrently() {
+        let guard = AsyncShutdownGuard::new();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..3 {
+            let count = Arc::clone(&count);
+            guard.register_async(move |_| {
+                let count = Arc::clone(&count);
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    count.fetch_add(1, Ordering::SeqCst);
+                })
+            });
+        }
+
+        let started = Instant::now();
+        guard
+            .execute_callbacks(ShutdownContext::new(
+                ShutdownReason::Unknown,
+                TriggerSource::Manual,
+            ))
+            .await;
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_abandons_hung_callback() {
+        let guard = AsyncShutdownGuard::with_timeout(Duration::from_millis(50));
+        let finished = Arc::new(AtomicUsize::new(0));
+        let finished_clone = Arc::clone(&finished);
+
+        guard.register_async(|_| Box::pin(std::future::pending()));
+        guard.register_async(move |_| {
+            let finished = Arc::clone(&finished_clone);
+            Box::pin(async move {
+                finished.fetch_add(1, Ordering::SeqCst);
+            })
+        });
+
+        let started = Instant::now();
+        guard
+            .execute_callbacks(ShutdownContext::new(
+                ShutdownReason::Unknown,
+                TriggerSource::Manual,
+            ))
+            .await;
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
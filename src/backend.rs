@@ -0,0 +1,96 @@
+//! Which platform mechanism a started guard watches, for diagnostics
+
+use std::fmt;
+
+/// The mechanism a platform backend uses to detect shutdowns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum Backend {
+    /// Unix signal handlers, on Linux without a reachable logind and on macOS
+    Signals,
+    /// The D-Bus signals of systemd-logind or a compatible login manager,
+    /// with the `dbus-support` feature
+    Logind,
+    /// Signal handlers plus `NSWorkspace` and `NSApplication` notifications,
+    /// on macOS with the `cocoa` feature
+    SignalsAndWorkspace,
+    /// A console control handler and a hidden window on Windows
+    MessageWindow,
+    /// A message window plus the control handler of a Windows service, with
+    /// the `windows-service` feature
+    WindowsService,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Signals => write!(f, "signal handlers"),
+            Backend::Logind => write!(f, "logind over D-Bus"),
+            Backend::SignalsAndWorkspace => {
+                write!(f, "signal handlers and NSWorkspace notifications")
+            }
+            Backend::MessageWindow => write!(f, "console control handler and hidden window"),
+            Backend::WindowsService => write!(f, "service control handler and hidden window"),
+        }
+    }
+}
+
+/// The service found at `org.freedesktop.login1` by the logind backend
+///
+/// Distributions without systemd often run elogind, which provides the same
+/// D-Bus interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum LoginManager {
+    /// systemd's `systemd-logind`
+    SystemdLogind,
+    /// The standalone `elogind`
+    Elogind,
+    /// A service whose process could not be identified, e.g. on a bus other
+    /// than the system bus or a mock in tests
+    Other,
+}
+
+impl fmt::Display for LoginManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginManager::SystemdLogind => write!(f, "systemd-logind"),
+            LoginManager::Elogind => write!(f, "elogind"),
+            LoginManager::Other => write!(f, "unidentified login manager"),
+        }
+    }
+}
+
+/// The backend monitoring for shutdowns and what it watches, returned by
+/// [`ShutdownGuard::active_backend`](crate::ShutdownGuard::active_backend)
+///
+/// Meant for bug reports and startup logs, e.g. to tell that a container
+/// without a system bus fell back to signal handlers:
+///
+/// ```text
+/// signal handlers watching SIGTERM, SIGHUP, SIGPWR
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ActiveBackend {
+    /// The mechanism in use
+    pub backend: Backend,
+    /// The signals, D-Bus signals, messages, console events or service
+    /// controls it handles, by name
+    pub watched: Vec<&'static str>,
+    /// The login manager found, for [`Backend::Logind`]
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub login_manager: Option<LoginManager>,
+}
+
+impl fmt::Display for ActiveBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.backend)?;
+        if let Some(login_manager) = self.login_manager {
+            write!(f, " ({})", login_manager)?;
+        }
+        write!(f, " watching {}", self.watched.join(", "))
+    }
+}
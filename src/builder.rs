@@ -0,0 +1,788 @@
+//! Builder for configuring a [`ShutdownGuard`] before it is started
+
+use crate::clock::SharedClock;
+use crate::executor::SharedExecutor;
+use crate::system_log;
+#[cfg(target_os = "windows")]
+use crate::EndSessionPhase;
+#[cfg(unix)]
+use crate::ExitBehavior;
+use crate::{
+    platform, CreatedAt, DeadlineAction, DispatchPriority, ExecutionOrder, GroupId, GuardState,
+    PanicPolicy, Registry, ShutdownGuard, ShutdownReason, DEADLINE_CAP_EXIT_CODE,
+};
+#[cfg(all(target_os = "linux", feature = "dbus-support"))]
+use crate::{DBusBus, LinuxBackend, NoBackendPolicy};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configures a [`ShutdownGuard`]
+///
+/// [`ShutdownGuard::new`] is equivalent to `ShutdownGuardBuilder::new().build()`.
+///
+/// ```rust
+/// use shutdown_guard_rs::ShutdownGuardBuilder;
+/// use std::time::Duration;
+///
+/// let guard = ShutdownGuardBuilder::new()
+///     .global_timeout(Duration::from_secs(10))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShutdownGuardBuilder {
+    signals: Vec<i32>,
+    deadline: Option<(Duration, DeadlineAction)>,
+    panic_policy: PanicPolicy,
+    execution_order: ExecutionOrder,
+    graceful_timeout: Option<Duration>,
+    dispatch_priority: DispatchPriority,
+    emit_system_log: bool,
+    system_log_message: Option<String>,
+    debounce: Option<Duration>,
+    critical_priority_floor: Option<i32>,
+    budget_threshold: Option<Duration>,
+    group_policy: HashMap<ShutdownReason, Vec<GroupId>>,
+    verbose: bool,
+    max_callbacks: Option<usize>,
+    seal_on_start: bool,
+    summary_path: Option<PathBuf>,
+    executor: SharedExecutor,
+    clock: SharedClock,
+    run_on_drop: bool,
+    interrupt_is_shutdown: bool,
+    #[cfg(unix)]
+    exit_behavior: ExitBehavior,
+    #[cfg(unix)]
+    call_global_sync: bool,
+    #[cfg(unix)]
+    post_cleanup_delay: Option<Duration>,
+    #[cfg(unix)]
+    signal_reasons: HashMap<i32, ShutdownReason>,
+    #[cfg(unix)]
+    abort_on_dispatch_panic: bool,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    reconnect_max_backoff: Option<Duration>,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    linux_backend: LinuxBackend,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    no_backend_policy: NoBackendPolicy,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    dbus_bus: DBusBus,
+    #[cfg(target_os = "windows")]
+    shutdown_block_reason: Option<String>,
+    #[cfg(target_os = "windows")]
+    run_callbacks_on: EndSessionPhase,
+    #[cfg(all(target_os = "windows", feature = "windows-service"))]
+    service_name: Option<String>,
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    decision_timeout: Option<Duration>,
+}
+
+impl ShutdownGuardBuilder {
+    /// Creates a builder with the default configuration
+    pub fn new() -> Self {
+        Self {
+            signals: platform::DEFAULT_SIGNALS.to_vec(),
+            deadline: None,
+            panic_policy: PanicPolicy::default(),
+            execution_order: ExecutionOrder::default(),
+            graceful_timeout: None,
+            dispatch_priority: DispatchPriority::Normal,
+            emit_system_log: false,
+            system_log_message: None,
+            debounce: None,
+            critical_priority_floor: None,
+            budget_threshold: None,
+            group_policy: HashMap::new(),
+            verbose: false,
+            max_callbacks: None,
+            seal_on_start: false,
+            summary_path: None,
+            executor: SharedExecutor::default(),
+            clock: SharedClock::default(),
+            run_on_drop: false,
+            interrupt_is_shutdown: false,
+            #[cfg(unix)]
+            exit_behavior: ExitBehavior::default(),
+            #[cfg(unix)]
+            call_global_sync: true,
+            #[cfg(unix)]
+            post_cleanup_delay: None,
+            #[cfg(unix)]
+            signal_reasons: HashMap::new(),
+            #[cfg(unix)]
+            abort_on_dispatch_panic: false,
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            reconnect_max_backoff: None,
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            linux_backend: LinuxBackend::default(),
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            no_backend_policy: NoBackendPolicy::default(),
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            dbus_bus: DBusBus::default(),
+            #[cfg(target_os = "windows")]
+            shutdown_block_reason: None,
+            #[cfg(target_os = "windows")]
+            run_callbacks_on: EndSessionPhase::default(),
+            #[cfg(any(
+                target_os = "windows",
+                all(target_os = "linux", feature = "dbus-support")
+            ))]
+            decision_timeout: None,
+            #[cfg(all(target_os = "windows", feature = "windows-service"))]
+            service_name: None,
+        }
+    }
+
+    /// Sets the Unix signals handled by [`ShutdownGuard::start`]
+    ///
+    /// See [`ShutdownGuard::start_with_signals`] for the supported signals.
+    pub fn signals(mut self, signals: &[i32]) -> Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// Sets a deadline for the shutdown callbacks, like
+    /// [`Registry::set_deadline`]
+    pub fn deadline(mut self, deadline: Duration, action: DeadlineAction) -> Self {
+        self.deadline = Some((deadline, action));
+        self
+    }
+
+    /// Terminates the process with [`DEADLINE_CAP_EXIT_CODE`] if the shutdown
+    /// callbacks together take longer than `timeout`
+    ///
+    /// The timer starts when the shutdown is detected, and the callbacks that
+    /// are still pending are logged before exiting. Choose a timeout below the
+    /// time the OS grants, so the process exits on its own terms rather than
+    /// being killed mid-write.
+    pub fn global_timeout(self, timeout: Duration) -> Self {
+        self.deadline(timeout, DeadlineAction::ForceExit(DEADLINE_CAP_EXIT_CODE))
+    }
+
+    /// Sets what happens when a callback panics; defaults to
+    /// [`PanicPolicy::ContinueOnPanic`]
+    pub fn panic_policy(mut self, panic_policy: PanicPolicy) -> Self {
+        self.panic_policy = panic_policy;
+        self
+    }
+
+    /// Sets the order of callbacks that share a priority; defaults to
+    /// [`ExecutionOrder::Fifo`]
+    pub fn execution_order(mut self, execution_order: ExecutionOrder) -> Self {
+        self.execution_order = execution_order;
+        self
+    }
+
+    /// Limits how long the [`Phase::Graceful`](crate::Phase::Graceful)
+    /// callbacks may take together before the forceful phase starts
+    ///
+    /// Graceful callbacks still running at the timeout are abandoned on their
+    /// threads, and those that did not start yet are skipped; both are
+    /// reported as [`CallbackTimeout`](crate::CallbackTimeout) failures. See
+    /// [`Registry::register_phase`].
+    pub fn graceful_timeout(mut self, timeout: Duration) -> Self {
+        self.graceful_timeout = Some(timeout);
+        self
+    }
+
+    /// Raises the scheduling priority of the thread running the callbacks of
+    /// a detected shutdown; defaults to [`DispatchPriority::Normal`]
+    ///
+    /// The previous priority is restored after the callbacks. On Linux the
+    /// thread's nice value is lowered, which needs `CAP_SYS_NICE` or a
+    /// matching `RLIMIT_NICE`; on Windows `SetThreadPriority` is used. A
+    /// refused change is logged and the callbacks run at the usual priority.
+    /// macOS has no per-thread nice value, so nothing changes there.
+    pub fn dispatch_priority(mut self, priority: DispatchPriority) -> Self {
+        self.dispatch_priority = priority;
+        self
+    }
+
+    /// Sets how long after the first trigger of a shutdown further triggers
+    /// count as part of it; defaults to 500 milliseconds
+    ///
+    /// A shutdown often arrives through several mechanisms at once, e.g.
+    /// SIGTERM, then SIGHUP, then a session-end message within milliseconds.
+    /// The first trigger runs the callbacks; the ones within this window are
+    /// coalesced into that run and their reasons recorded in
+    /// [`ShutdownSummary::reasons`](crate::ShutdownSummary::reasons). Later
+    /// ones are ignored like before, as the callbacks run once either way.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = Some(debounce);
+        self
+    }
+
+    /// Writes a line to the system log when a shutdown is detected, before
+    /// the callbacks run; off by default
+    ///
+    /// Leaves a trail that the machine ran its cleanup, in a log that
+    /// survives the reboot unlike the application's own output: syslog on
+    /// Linux, where the journal collects it, and on macOS, where it ends up
+    /// in the unified log; the Application event log on Windows, with the
+    /// source `ShutdownGuard`. Shutdowns run by hand, e.g. with
+    /// `execute_callbacks`, are not logged.
+    pub fn emit_system_log(mut self, emit: bool) -> Self {
+        self.emit_system_log = emit;
+        self
+    }
+
+    /// Sets the line [`emit_system_log`](Self::emit_system_log) writes
+    ///
+    /// `{reason}`, `{source}` and `{callbacks}` are replaced with the reason
+    /// and trigger of the shutdown and the number of callbacks about to run.
+    /// Defaults to `"Shutdown detected ({reason}, {source}), running
+    /// {callbacks} cleanup callbacks"`.
+    pub fn system_log_message(mut self, template: impl Into<String>) -> Self {
+        self.system_log_message = Some(template.into());
+        self
+    }
+
+    /// Sets the priority from which callbacks count as critical; together
+    /// with [`budget_threshold`](Self::budget_threshold), off by default
+    ///
+    /// Once less than the threshold is left before the deadline set with
+    /// [`deadline`](Self::deadline), callbacks registered with a lower
+    /// priority are skipped and reported as
+    /// [`CallbackOutcome::Skipped`](crate::CallbackOutcome::Skipped), so the
+    /// critical ones (releasing a lock, flushing a log) keep the remaining
+    /// time instead of optional cleanup. Without a deadline nothing is
+    /// skipped. Applies to sequential runs; see
+    /// [`Registry::register_with_priority`].
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{DeadlineAction, ShutdownGuard};
+    /// use std::time::Duration;
+    ///
+    /// let guard = ShutdownGuard::builder()
+    ///     .deadline(Duration::from_secs(5), DeadlineAction::ForceExit(1))
+    ///     .critical_priority_floor(100)
+    ///     .budget_threshold(Duration::from_secs(2))
+    ///     .build();
+    /// guard.register_with_priority(100, Box::new(|_| println!("Flushing WAL")))?;
+    /// guard.register(Box::new(|_| println!("Deleting temp files")))?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn critical_priority_floor(mut self, floor: i32) -> Self {
+        self.critical_priority_floor = Some(floor);
+        self
+    }
+
+    /// Sets how little time before the deadline has to be left for only
+    /// critical callbacks to run; see
+    /// [`critical_priority_floor`](Self::critical_priority_floor)
+    pub fn budget_threshold(mut self, threshold: Duration) -> Self {
+        self.budget_threshold = Some(threshold);
+        self
+    }
+
+    /// Runs only the callbacks in `groups` when a shutdown has the given
+    /// reason; by default every callback runs for every reason
+    ///
+    /// Callbacks without a group and those in other groups are left out for
+    /// that reason, as if they were not registered; other reasons still run
+    /// everything. A later call for the same reason replaces the groups.
+    /// [`ShutdownReason::ANY_SIGNAL`] covers the signals without a reason of
+    /// their own. See [`Registry::register_in_group`].
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{GroupId, ShutdownGuard, ShutdownReason};
+    ///
+    /// let critical = GroupId::new();
+    /// let guard = ShutdownGuard::builder()
+    ///     .run_groups_for(ShutdownReason::PowerFailure, &[critical])
+    ///     .build();
+    /// guard.register_in_group(critical, Box::new(|_| println!("Flushing WAL")))?;
+    /// guard.register(Box::new(|_| println!("Deleting temp files")))?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn run_groups_for(mut self, reason: ShutdownReason, groups: &[GroupId]) -> Self {
+        self.group_policy.insert(reason, groups.to_vec());
+        self
+    }
+
+    /// Prints status messages, e.g. which backend started monitoring, to
+    /// stderr; defaults to silent
+    ///
+    /// Failures are always reported. With the `tracing` feature the status
+    /// messages are emitted as info events either way.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Limits how many callbacks can be registered at once; unlimited by
+    /// default
+    ///
+    /// A process that registers a callback per request and never unregisters
+    /// it would otherwise grow the registry and slow down its shutdown. Once
+    /// the limit is reached, [`Registry::register`] and the other register
+    /// methods return
+    /// [`RegisterError::CapacityExceeded`](crate::RegisterError::CapacityExceeded),
+    /// so such a leak fails loudly. Unregistered callbacks free their slot
+    /// again.
+    pub fn max_callbacks(mut self, max: usize) -> Self {
+        self.max_callbacks = Some(max);
+        self
+    }
+
+    /// Fixes the callbacks once [`ShutdownGuard::start`] succeeded; defaults
+    /// to off
+    ///
+    /// A sealed guard runs a snapshot of the callbacks taken at start, so a
+    /// shutdown never waits for the registration lock. Afterwards
+    /// [`Registry::register`] and the other register methods return
+    /// [`RegisterError::Sealed`](crate::RegisterError::Sealed), as does
+    /// [`Registry::clear`], and unregistering does nothing.
+    /// The guard stays sealed after [`ShutdownGuard::stop`].
+    pub fn seal_on_start(mut self, seal_on_start: bool) -> Self {
+        self.seal_on_start = seal_on_start;
+        self
+    }
+
+    /// Appends a [`ShutdownSummary`](crate::ShutdownSummary) of every
+    /// shutdown to the file at `path`
+    ///
+    /// The file is opened by [`ShutdownGuard::start`], which fails if it
+    /// cannot be, and written and synced right after the callbacks ran,
+    /// before the process exits. This keeps the timing and outcome of each
+    /// callback for a post-mortem on platforms that exit right after the
+    /// callbacks, such as Linux and macOS on a signal. Name callbacks with
+    /// [`Registry::register_named`] to make the lines readable.
+    pub fn summary_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.summary_path = Some(path.into());
+        self
+    }
+
+    /// Runs the ordered callbacks with `executor` instead of the
+    /// [`SequentialExecutor`](crate::SequentialExecutor)
+    ///
+    /// Lets tests observe the order the callbacks run in without depending
+    /// on thread scheduling. Only available in this crate's tests and with
+    /// the `testing` feature.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn executor(mut self, executor: Box<dyn crate::executor::Executor>) -> Self {
+        self.executor = SharedExecutor(Arc::from(executor));
+        self
+    }
+
+    /// Measures per-callback timeouts, the graceful phase and the deadline
+    /// watchdog with `clock` instead of the system clock
+    ///
+    /// With a [`MockClock`](crate::MockClock), a test advances time
+    /// past a timeout instead of sleeping through it. The
+    /// [`ShutdownDeadline`](crate::ShutdownDeadline) callbacks see and the
+    /// detection time stay on the system clock. Only available in this
+    /// crate's tests and with the `testing` feature.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn clock(mut self, clock: impl crate::clock::Clock + 'static) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Runs the callbacks when the guard is dropped, unless they already ran;
+    /// defaults to off
+    ///
+    /// This covers a normal exit, e.g. `main` returning, where no shutdown is
+    /// reported. Rust does not drop statics, so this only works for a guard
+    /// that goes out of scope, not for one stored in a `static` or leaked, nor
+    /// on [`std::process::exit`]. The callbacks see
+    /// [`TriggerSource::Drop`](crate::TriggerSource::Drop).
+    pub fn run_on_drop(mut self, run_on_drop: bool) -> Self {
+        self.run_on_drop = run_on_drop;
+        self
+    }
+
+    /// Sets whether Ctrl+C runs the callbacks like a shutdown; defaults to
+    /// off on every platform
+    ///
+    /// When on, SIGINT is handled on Unix and `CTRL_C_EVENT` on Windows, both
+    /// reported as [`ShutdownReason::Interrupt`]. The process then ends as
+    /// after any other trigger: on Unix as set with `exit_behavior`, on
+    /// Windows through the default console handler. When off, Ctrl+C keeps
+    /// its default effect of ending the process without running the
+    /// callbacks, unless SIGINT is listed in [`signals`](Self::signals).
+    pub fn treat_interrupt_as_shutdown(mut self, enabled: bool) -> Self {
+        self.interrupt_is_shutdown = enabled;
+        self
+    }
+
+    /// Sets how the process ends after the callbacks for a Unix signal ran;
+    /// defaults to `ExitBehavior::Exit(0)`
+    ///
+    /// Daemons usually want [`ExitBehavior::SignalDefault`], so their
+    /// supervisor sees that a signal terminated them.
+    #[cfg(unix)]
+    pub fn exit_behavior(mut self, exit_behavior: ExitBehavior) -> Self {
+        self.exit_behavior = exit_behavior;
+        self
+    }
+
+    /// Sets whether to call sync(2) after the callbacks for a Unix signal
+    /// ran; defaults to on
+    ///
+    /// sync(2) flushes every file system on the machine. Applications that
+    /// `fsync` their own files can turn it off to exit sooner.
+    #[cfg(unix)]
+    pub fn call_global_sync(mut self, call_global_sync: bool) -> Self {
+        self.call_global_sync = call_global_sync;
+        self
+    }
+
+    /// Sets how long to wait between the callbacks for a Unix signal and
+    /// exiting; defaults to 100ms on macOS, where sync(2) returns before the
+    /// writes finished, and to none on Linux
+    ///
+    /// Use zero for a fast restart, or a longer delay for slow disks. Nothing
+    /// waits with [`ExitBehavior::DoNotExit`].
+    #[cfg(unix)]
+    pub fn post_cleanup_delay(mut self, delay: Duration) -> Self {
+        self.post_cleanup_delay = Some(delay);
+        self
+    }
+
+    /// Overrides the reason passed to the callbacks for the given signals
+    ///
+    /// Signals missing from `reasons` keep the built-in mapping:
+    /// `ShutdownReason::Signal(signo)`, `ShutdownReason::Interrupt` for
+    /// SIGINT, or `ShutdownReason::PowerFailure` for SIGPWR on Linux. Supervisors disagree on what a signal means, e.g. one
+    /// that stops services with SIGINT can map it to a clean
+    /// `ShutdownReason::Reboot` instead of an interactive abort. Calling this
+    /// again replaces the previous mapping.
+    #[cfg(unix)]
+    pub fn signal_reasons(mut self, reasons: HashMap<i32, ShutdownReason>) -> Self {
+        self.signal_reasons = reasons;
+        self
+    }
+
+    /// Aborts the process if a panic escapes the handling of a caught
+    /// signal; defaults to off
+    ///
+    /// Signal handlers never run the callbacks themselves: they only hand
+    /// the signal to a dispatch thread, and the callbacks' panics are caught
+    /// according to the [`PanicPolicy`]. A panic outside of them, e.g. in the
+    /// [`on_raw_signal`](crate::ShutdownGuard::on_raw_signal) callback, ends
+    /// the dispatch thread instead, after which caught signals are silently
+    /// dropped and the process cannot be shut down gracefully. With this set
+    /// such a panic calls `abort(3)` after writing a diagnostic to stderr with
+    /// `write(2)`. With several started guards, the one that started the
+    /// signal handlers decides.
+    #[cfg(unix)]
+    pub fn abort_on_dispatch_panic(mut self, enabled: bool) -> Self {
+        self.abort_on_dispatch_panic = enabled;
+        self
+    }
+
+    /// Chooses between systemd-logind and signal handlers; defaults to
+    /// [`LinuxBackend::Auto`]
+    ///
+    /// `Auto` connects to the system bus in [`ShutdownGuard::start`] and
+    /// falls back to signal handlers if that fails, so one binary works both
+    /// on a desktop and in a container without a system bus.
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub fn linux_backend(mut self, backend: LinuxBackend) -> Self {
+        self.linux_backend = backend;
+        self
+    }
+
+    /// Chooses what [`LinuxBackend::Auto`] does when no login manager
+    /// answers at start; defaults to [`NoBackendPolicy::FallbackToSignals`]
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::{NoBackendPolicy, ShutdownError, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::builder()
+    ///     .on_no_backend(NoBackendPolicy::Error)
+    ///     .build();
+    /// if let Err(ShutdownError::NoBackendAvailable(e)) = guard.start() {
+    ///     eprintln!("Not monitoring: {}", e);
+    /// }
+    /// ```
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub fn on_no_backend(mut self, policy: NoBackendPolicy) -> Self {
+        self.no_backend_policy = policy;
+        self
+    }
+
+    /// Chooses the bus the logind backend connects to; defaults to
+    /// [`DBusBus::System`]
+    ///
+    /// A session bus or an explicit address is mostly useful for testing
+    /// against a mock logind, e.g. one started under `dbus-launch`.
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub fn dbus_bus(mut self, bus: DBusBus) -> Self {
+        self.dbus_bus = bus;
+        self
+    }
+
+    /// Caps the delay between attempts to reconnect to the system bus;
+    /// defaults to 30 seconds
+    ///
+    /// If the D-Bus connection fails or drops, e.g. because the bus
+    /// restarted, the monitor reconnects with exponential backoff starting at
+    /// half a second, so shutdown detection survives transient failures.
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub fn reconnect_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.reconnect_max_backoff = Some(max_backoff);
+        self
+    }
+
+    /// Holds an announced shutdown open for up to `timeout` so the
+    /// application can answer it with
+    /// [`ShutdownGuard::respond_to_pending`](crate::ShutdownGuard::respond_to_pending);
+    /// by default the callbacks run right away
+    ///
+    /// On Windows the hidden window keeps pumping messages while it waits in
+    /// `WM_QUERYENDSESSION`, but Windows reports an application that takes
+    /// longer than about five seconds to answer; with logind the wait counts
+    /// against `InhibitDelayMaxSec`, five seconds by default.
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    pub fn shutdown_decision_timeout(mut self, timeout: Duration) -> Self {
+        self.decision_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the reason Windows shows in its "this app is preventing shutdown"
+    /// screen while the callbacks run, e.g. `"Flushing pending writes…"`
+    #[cfg(target_os = "windows")]
+    pub fn shutdown_block_reason(mut self, reason: impl Into<String>) -> Self {
+        self.shutdown_block_reason = Some(reason.into());
+        self
+    }
+
+    /// Sets which session-end message runs the callbacks; defaults to
+    /// [`EndSessionPhase::EndSession`]
+    ///
+    /// Windows may end the process right after `WM_ENDSESSION`, so
+    /// durability-sensitive applications can run them in
+    /// `WM_QUERYENDSESSION` instead, at the risk that another application
+    /// cancels the shutdown after they ran. They run once either way.
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::{EndSessionPhase, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::builder()
+    ///     .run_callbacks_on(EndSessionPhase::QueryEndSession)
+    ///     .build();
+    /// ```
+    #[cfg(target_os = "windows")]
+    pub fn run_callbacks_on(mut self, phase: EndSessionPhase) -> Self {
+        self.run_callbacks_on = phase;
+        self
+    }
+
+    /// Registers the control handler of the Windows service `name` in
+    /// [`ShutdownGuard::start`], so the callbacks run on
+    /// `SERVICE_CONTROL_SHUTDOWN`, `SERVICE_CONTROL_PRESHUTDOWN` and
+    /// `SERVICE_CONTROL_STOP`
+    ///
+    /// Services run in session 0, isolated from the interactive desktop, where
+    /// the hidden window may never receive `WM_QUERYENDSESSION`. Call `start`
+    /// from the service's `ServiceMain`: the guard then owns the service's
+    /// status, reporting it as running once started, as stop-pending while
+    /// the callbacks run and as stopped afterwards. Don't use this if the
+    /// service registers a control handler of its own.
+    #[cfg(all(target_os = "windows", feature = "windows-service"))]
+    pub fn windows_service(mut self, name: impl Into<String>) -> Self {
+        self.service_name = Some(name.into());
+        self
+    }
+
+    /// Creates the configured guard
+    pub fn build(mut self) -> ShutdownGuard {
+        let mut signals = std::mem::take(&mut self.signals);
+        #[cfg(unix)]
+        if self.interrupt_is_shutdown && !signals.contains(&libc::SIGINT) {
+            signals.push(libc::SIGINT);
+        }
+        let run_on_drop = self.run_on_drop;
+        ShutdownGuard::with_state(self.into_state(), signals, run_on_drop)
+    }
+
+    /// Creates a [`Registry`] with the configured panic policy, ordering and
+    /// timeouts, for applications that trigger the callbacks themselves
+    ///
+    /// The signals, platform settings and `run_on_drop` have no effect on a
+    /// registry.
+    pub fn build_registry(self) -> Registry {
+        Registry::from_state(Arc::new(self.into_state()))
+    }
+
+    fn into_state(self) -> GuardState {
+        GuardState {
+            created: CreatedAt(self.clock.now()),
+            deadline: RwLock::new(self.deadline),
+            panic_policy: self.panic_policy,
+            execution_order: self.execution_order,
+            graceful_timeout: self.graceful_timeout,
+            dispatch_priority: self.dispatch_priority,
+            debounce: self.debounce,
+            system_log: self.emit_system_log.then(|| {
+                self.system_log_message
+                    .unwrap_or_else(|| system_log::DEFAULT_SYSTEM_LOG_MESSAGE.to_string())
+            }),
+            critical_priority_floor: self.critical_priority_floor,
+            budget_threshold: self.budget_threshold,
+            group_policy: self.group_policy,
+            verbose: self.verbose,
+            max_callbacks: self.max_callbacks,
+            seal_on_start: self.seal_on_start,
+            summary_path: self.summary_path,
+            executor: self.executor,
+            clock: self.clock,
+            #[cfg(unix)]
+            exit_behavior: self.exit_behavior,
+            #[cfg(unix)]
+            skip_global_sync: !self.call_global_sync,
+            #[cfg(unix)]
+            post_cleanup_delay: self.post_cleanup_delay,
+            #[cfg(unix)]
+            signal_reasons: self.signal_reasons,
+            #[cfg(unix)]
+            abort_on_dispatch_panic: self.abort_on_dispatch_panic,
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            reconnect_max_backoff: self.reconnect_max_backoff,
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            linux_backend: self.linux_backend,
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            no_backend_policy: self.no_backend_policy,
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            dbus_bus: self.dbus_bus,
+            #[cfg(target_os = "windows")]
+            shutdown_block_reason: self.shutdown_block_reason,
+            #[cfg(target_os = "windows")]
+            run_callbacks_on: self.run_callbacks_on,
+            #[cfg(any(
+                target_os = "windows",
+                all(target_os = "linux", feature = "dbus-support")
+            ))]
+            decision_timeout: self.decision_timeout,
+            #[cfg(target_os = "windows")]
+            interrupt_is_shutdown: self.interrupt_is_shutdown,
+            #[cfg(all(target_os = "windows", feature = "windows-service"))]
+            service_name: self.service_name,
+            ..GuardState::default()
+        }
+    }
+}
+
+impl Default for ShutdownGuardBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_timeout_sets_deadline() {
+        let guard = ShutdownGuardBuilder::new()
+            .global_timeout(Duration::from_secs(3))
+            .build();
+        assert_eq!(
+            *guard.state.deadline.read(),
+            Some((
+                Duration::from_secs(3),
+                DeadlineAction::ForceExit(DEADLINE_CAP_EXIT_CODE)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_run_on_drop_runs_callbacks_once() {
+        use crate::TriggerSource;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let sources = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        for executed_before in [false, true] {
+            let guard = ShutdownGuard::builder().run_on_drop(true).build();
+            let (runs, sources) = (Arc::clone(&runs), Arc::clone(&sources));
+            guard
+                .register(Box::new(move |context| {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    sources.lock().push(context.source);
+                }))
+                .unwrap();
+            if executed_before {
+                guard.execute_callbacks();
+            }
+        }
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *sources.lock(),
+            vec![TriggerSource::Drop, TriggerSource::Manual]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_global_sync_is_on_unless_disabled() {
+        let guard = ShutdownGuardBuilder::new().build();
+        assert!(!guard.state.skip_global_sync);
+        assert_eq!(guard.state.post_cleanup_delay, None);
+
+        let guard = ShutdownGuardBuilder::new()
+            .call_global_sync(false)
+            .post_cleanup_delay(Duration::ZERO)
+            .build();
+        assert!(guard.state.skip_global_sync);
+        assert_eq!(guard.state.post_cleanup_delay, Some(Duration::ZERO));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_start_uses_configured_signals() {
+        let guard = ShutdownGuard::builder().signals(&[libc::SIGKILL]).build();
+        assert!(guard.start().is_err());
+        assert!(!guard.is_running());
+    }
+
+    #[test]
+    fn test_run_groups_for_filters_by_reason() {
+        let (critical, optional) = (GroupId::new(), GroupId::new());
+        let guard = ShutdownGuardBuilder::new()
+            .run_groups_for(ShutdownReason::PowerFailure, &[critical])
+            .run_groups_for(ShutdownReason::ANY_SIGNAL, &[critical, optional])
+            .build();
+        let ran = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        for (group, label) in [
+            (Some(critical), "critical"),
+            (Some(optional), "optional"),
+            (None, "ungrouped"),
+        ] {
+            let ran = Arc::clone(&ran);
+            let callback = Box::new(move |_: crate::ShutdownContext| ran.lock().push(label));
+            match group {
+                Some(group) => guard.register_in_group(group, callback).unwrap(),
+                None => guard.register(callback).unwrap(),
+            };
+        }
+
+        for (reason, expected) in [
+            (ShutdownReason::PowerFailure, vec!["critical"]),
+            (ShutdownReason::Signal(15), vec!["critical", "optional"]),
+            (
+                ShutdownReason::Reboot,
+                vec!["critical", "optional", "ungrouped"],
+            ),
+        ] {
+            ran.lock().clear();
+            guard.reset_executed();
+            guard.simulate_shutdown(reason);
+            assert_eq!(*ran.lock(), expected, "{}", reason);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! The time source behind timeouts, deadlines and summary timings
+
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a wait re-reads a clock other than the system clock, so an
+/// advanced [`MockClock`] is noticed promptly
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A source of the current instant
+///
+/// The default [`SystemClock`] reads [`Instant::now`]. Tests can build a
+/// guard with a [`MockClock`] through `ShutdownGuardBuilder::clock` to
+/// trigger per-callback timeouts, the graceful phase budget and the deadline
+/// watchdog by advancing time instead of sleeping. The deadline callbacks
+/// see in [`ShutdownContext::deadline`](crate::ShutdownContext::deadline)
+/// and the detection time always follow the real clock.
+pub trait Clock: Send + Sync {
+    /// Returns the current instant
+    fn now(&self) -> Instant;
+}
+
+/// Reads the system's monotonic clock
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(not(feature = "testing"), allow(dead_code))]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// Clones share their time, so a test keeps one to
+/// [`advance`](Self::advance) while the guard holds the other.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(any(test, feature = "testing")), allow(dead_code))]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+#[cfg_attr(not(any(test, feature = "testing")), allow(dead_code))]
+impl MockClock {
+    /// Creates a clock stopped at the current instant
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock()
+    }
+}
+
+/// A clock shared between a builder and the guards it builds
+///
+/// Holds no clock for the system clock, whose waits block on the deadline
+/// itself instead of polling.
+#[derive(Clone, Default)]
+pub(crate) struct SharedClock(Option<Arc<dyn Clock>>);
+
+impl SharedClock {
+    #[cfg_attr(not(any(test, feature = "testing")), allow(dead_code))]
+    pub(crate) fn new(clock: impl Clock + 'static) -> Self {
+        Self(Some(Arc::new(clock)))
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        match &self.0 {
+            Some(clock) => clock.now(),
+            None => Instant::now(),
+        }
+    }
+
+    /// Returns the time passed since `earlier` on this clock
+    pub(crate) fn elapsed(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+
+    /// Waits for a message on `rx` until `until` passes on this clock
+    pub(crate) fn recv_until<T>(
+        &self,
+        rx: &Receiver<T>,
+        until: Instant,
+    ) -> Result<T, RecvTimeoutError> {
+        if self.0.is_none() {
+            return rx.recv_timeout(until.saturating_duration_since(Instant::now()));
+        }
+        loop {
+            let remaining = until.saturating_duration_since(self.now());
+            if remaining.is_zero() {
+                return rx.try_recv().map_err(|e| match e {
+                    TryRecvError::Empty => RecvTimeoutError::Timeout,
+                    TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                });
+            }
+            match rx.recv_timeout(remaining.min(POLL_INTERVAL)) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                received => return received,
+            }
+        }
+    }
+
+    /// Waits until `done` is set or `until` passes on this clock; returns
+    /// whether it was set
+    pub(crate) fn wait_until(
+        &self,
+        done: &(Mutex<bool>, parking_lot::Condvar),
+        until: Instant,
+    ) -> bool {
+        let (lock, condvar) = done;
+        let mut finished = lock.lock();
+        if self.0.is_none() {
+            while !*finished && !condvar.wait_until(&mut finished, until).timed_out() {}
+            return *finished;
+        }
+        while !*finished {
+            let remaining = until.saturating_duration_since(self.now());
+            if remaining.is_zero() {
+                break;
+            }
+            condvar.wait_for(&mut finished, remaining.min(POLL_INTERVAL));
+        }
+        *finished
+    }
+}
+
+impl fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Clock")
+    }
+}
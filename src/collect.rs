@@ -0,0 +1,37 @@
+//! Callbacks collected at load time by the `#[shutdown_hook]` attribute
+
+use crate::{Callback, Phase, ShutdownContext, ShutdownGuard};
+use parking_lot::Mutex;
+
+/// Hooks collected before `main`, waiting for the global guard to start
+static COLLECTED: Mutex<Vec<CollectedHook>> = Mutex::new(Vec::new());
+
+/// A function annotated with `#[shutdown_hook]`
+#[doc(hidden)]
+pub struct CollectedHook {
+    pub name: &'static str,
+    pub priority: i32,
+    pub callback: fn(ShutdownContext),
+}
+
+/// Called by the code `#[shutdown_hook]` generates, before `main`
+#[doc(hidden)]
+pub fn collect(hook: CollectedHook) {
+    COLLECTED.lock().push(hook);
+}
+
+/// Registers the hooks collected so far with `guard`, each of them once
+pub(crate) fn register_collected(guard: &ShutdownGuard) {
+    for hook in COLLECTED.lock().drain(..) {
+        let registered = guard.push(
+            Phase::Graceful,
+            hook.priority,
+            Some(hook.name.to_string()),
+            None,
+            Callback::Infallible(Box::new(hook.callback)),
+        );
+        if let Err(e) = registered {
+            eprintln!("Failed to register shutdown hook {}: {}", hook.name, e);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Context passed to shutdown callbacks
+
+use crate::{ShutdownDeadline, ShutdownReason};
+#[cfg(feature = "serde")]
+use std::fs::OpenOptions;
+#[cfg(feature = "serde")]
+use std::io::{self, Write};
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// What triggered the shutdown callbacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum TriggerSource {
+    /// A Unix signal with the given number was delivered
+    UnixSignal(i32),
+    /// A Windows window message such as `WM_ENDSESSION`
+    WindowsMessage(u32),
+    /// A Windows console control event such as `CTRL_SHUTDOWN_EVENT`
+    WindowsConsoleEvent(u32),
+    /// A Windows service control such as `SERVICE_CONTROL_SHUTDOWN`
+    WindowsServiceControl(u32),
+    /// systemd-logind announced `PrepareForShutdown` over D-Bus
+    DBusSignal,
+    /// macOS announced power-off, sleep or wake through an `NSWorkspace`
+    /// notification
+    WorkspaceNotification,
+    /// A macOS app is quitting, e.g. on Cmd-Q or at logout, as announced by
+    /// `NSApplicationWillTerminateNotification`
+    ApplicationNotification,
+    /// The application called `execute_callbacks` itself
+    Manual,
+    /// A test called `ShutdownGuard::simulate_shutdown`
+    Simulated,
+    /// A guard built with `run_on_drop` went out of scope
+    Drop,
+    /// A trigger passed to `Registry::register_trigger` fired
+    Custom,
+    /// A `shutdown` command arrived on the socket opened by
+    /// `Registry::listen_on_socket`
+    AdminSocket,
+}
+
+/// The process that sent a shutdown signal, as the kernel recorded it in the
+/// signal's `siginfo_t`
+///
+/// On macOS a system shutdown reaches the application as SIGTERM from
+/// launchd, so [`is_init`](Self::is_init) tells it apart from a `kill <pid>`
+/// run by a user. This is a heuristic with limits:
+///
+/// - launchd also sends SIGTERM when a job is stopped with `launchctl`, e.g.
+///   `launchctl kill TERM` or `launchctl bootout`, outside of any shutdown
+/// - a signal sent while the same signal is still pending is merged with it,
+///   so only the first sender is known
+/// - signals raised by the kernel, e.g. SIGINT from Ctrl+C in a terminal,
+///   have no sender
+/// - a process running as root can `kill` like any other, so `uid == 0` alone
+///   proves nothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignalSender {
+    /// The sender's process ID
+    pub pid: i32,
+    /// The sender's real user ID
+    pub uid: u32,
+}
+
+impl SignalSender {
+    /// Returns true if the signal came from process 1, launchd on macOS or
+    /// init on Linux
+    pub fn is_init(&self) -> bool {
+        self.pid == 1
+    }
+}
+
+/// Everything known about a shutdown when the callbacks run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShutdownContext {
+    /// Why the shutdown happens
+    pub reason: ShutdownReason,
+    /// When the shutdown was detected
+    pub detected_at: SystemTime,
+    /// What reported the shutdown
+    pub source: TriggerSource,
+    /// How much time the callbacks have left under the configured deadline
+    ///
+    /// Not serialized, since an instant is meaningless outside the process.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub deadline: ShutdownDeadline,
+    /// The process that sent the signal, for shutdowns triggered by a signal
+    /// another process sent with `kill(2)` or `sigqueue(3)`
+    pub sender: Option<SignalSender>,
+}
+
+impl ShutdownContext {
+    /// Creates a context for a shutdown detected now
+    pub fn new(reason: ShutdownReason, source: TriggerSource) -> Self {
+        Self {
+            reason,
+            detected_at: SystemTime::now(),
+            source,
+            deadline: ShutdownDeadline::default(),
+            sender: None,
+        }
+    }
+
+    /// Returns the context with the sender of the triggering signal
+    #[cfg(unix)]
+    pub(crate) fn with_sender(self, sender: Option<SignalSender>) -> Self {
+        Self { sender, ..self }
+    }
+
+    /// Returns the context with the deadline starting now
+    pub(crate) fn with_deadline(self, deadline: Option<Duration>) -> Self {
+        Self {
+            deadline: ShutdownDeadline::after(deadline),
+            ..self
+        }
+    }
+
+    /// Returns true if the application triggered the shutdown itself rather
+    /// than the system or a user
+    pub fn is_manual(&self) -> bool {
+        self.source == TriggerSource::Manual
+    }
+
+    /// Appends the context to `path` as a JSON line and syncs it to disk
+    ///
+    /// The file is created if needed, so every shutdown adds one parseable
+    /// record to an audit trail.
+    #[cfg(feature = "serde")]
+    pub fn log_to(&self, path: &Path) -> io::Result<()> {
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_records_reason_and_source() {
+        let before = SystemTime::now();
+        let context = ShutdownContext::new(ShutdownReason::Reboot, TriggerSource::UnixSignal(15));
+        assert!(context.reason.is_reboot());
+        assert!(!context.reason.is_power_off());
+        assert_eq!(context.source, TriggerSource::UnixSignal(15));
+        assert!(!context.is_manual());
+        assert!(context.detected_at >= before);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_log_to_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("shutdown-log-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let first = ShutdownContext::new(ShutdownReason::PowerOff, TriggerSource::DBusSignal);
+        let second =
+            ShutdownContext::new(ShutdownReason::Signal(15), TriggerSource::UnixSignal(15));
+        first.log_to(&path).unwrap();
+        second.log_to(&path).unwrap();
+
+        let logged: Vec<ShutdownContext> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged, vec![first, second]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+//! Coordinator callbacks, which drive the callbacks after them themselves
+
+use crate::{CallbackEntry, CallbackId, CallbackInfo, ShutdownContext};
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::time::Instant;
+
+/// A coordinator registered with
+/// [`Registry::register_coordinator`](crate::Registry::register_coordinator)
+pub(crate) type CoordinatorCallback =
+    Box<dyn Fn(ShutdownContext, &CallbackController<'_>) + Send + Sync + 'static>;
+
+/// Lets a coordinator run or skip the callbacks that would run after it
+///
+/// Each callback is controlled at most once: after a coordinator ran or
+/// skipped it, further calls for it return false, and the dispatcher passes
+/// over it. The callbacks a coordinator leaves alone run afterwards as usual.
+/// Callbacks run through the controller on the calling thread with the
+/// guard's panic policy, graceful timeout and deadline, and show up in the
+/// summary before the coordinator itself.
+pub struct CallbackController<'a> {
+    remaining: &'a [&'a CallbackEntry],
+    controlled: &'a Mutex<HashSet<CallbackId>>,
+    run: &'a dyn Fn(&'a CallbackEntry) -> bool,
+    /// When the guard was created, for `CallbackInfo::registered_at`
+    created: Instant,
+}
+
+impl<'a> CallbackController<'a> {
+    pub(crate) fn new(
+        remaining: &'a [&'a CallbackEntry],
+        controlled: &'a Mutex<HashSet<CallbackId>>,
+        run: &'a dyn Fn(&'a CallbackEntry) -> bool,
+        created: Instant,
+    ) -> Self {
+        Self {
+            remaining,
+            controlled,
+            run,
+            created,
+        }
+    }
+
+    /// Returns the callbacks that would still run after the coordinator, in
+    /// execution order
+    pub fn list(&self) -> Vec<CallbackInfo> {
+        let controlled = self.controlled.lock();
+        self.remaining
+            .iter()
+            .filter(|entry| !controlled.contains(&entry.id))
+            .map(|entry| entry.info(self.created))
+            .collect()
+    }
+
+    /// Runs the callback `id` now; returns false if it is not one of
+    /// [`list`](Self::list), e.g. because it already ran or was skipped, or
+    /// if an earlier callback's panic aborted the run under
+    /// `PanicPolicy::AbortOnPanic`
+    pub fn run(&self, id: CallbackId) -> bool {
+        self.claim(id).is_some_and(|entry| (self.run)(entry))
+    }
+
+    /// Keeps the callback `id` from running in this shutdown; returns false
+    /// if it is not one of [`list`](Self::list)
+    pub fn skip(&self, id: CallbackId) -> bool {
+        self.claim(id).is_some()
+    }
+
+    /// Marks `id` as controlled unless it already is
+    fn claim(&self, id: CallbackId) -> Option<&'a CallbackEntry> {
+        let entry = self.remaining.iter().find(|entry| entry.id == id)?;
+        self.controlled.lock().insert(id).then_some(*entry)
+    }
+}
@@ -0,0 +1,381 @@
+//! Hard deadline enforcement for shutdown callbacks
+
+use crate::clock::SharedClock;
+use crate::spawner::SharedSpawner;
+use crate::timeout::{run_with_timeout, SharedCallback};
+use crate::{CallbackId, ShutdownContext};
+use parking_lot::{Condvar, Mutex};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Exit code used when [`DeadlineAction::LogAndContinue`] reaches its cap
+pub const DEADLINE_CAP_EXIT_CODE: i32 = 1;
+
+/// How long each callback registered with
+/// [`Registry::register_final`](crate::Registry::register_final) may run
+pub const FINAL_CALLBACK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Reported for a callback below the
+/// [critical priority floor](crate::ShutdownGuardBuilder::critical_priority_floor)
+/// that was skipped because the deadline was too close
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackSkipped {
+    /// The time left before the deadline when the callback was due
+    pub remaining: Duration,
+}
+
+impl fmt::Display for CallbackSkipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "callback skipped with {:?} left before the deadline",
+            self.remaining
+        )
+    }
+}
+
+impl std::error::Error for CallbackSkipped {}
+
+/// What to do when shutdown callbacks are still running at the deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineAction {
+    /// Terminate the process immediately with the given exit code
+    ForceExit(i32),
+    /// Log that the deadline was missed and keep waiting until `absolute_cap`
+    /// (measured from shutdown detection, like the deadline itself), then
+    /// terminate with [`DEADLINE_CAP_EXIT_CODE`]
+    LogAndContinue {
+        /// The point after which the process is terminated regardless
+        absolute_cap: Duration,
+    },
+}
+
+/// How much time the shutdown callbacks have left, passed to them in
+/// [`ShutdownContext::deadline`](crate::ShutdownContext::deadline)
+///
+/// Long-running callbacks can check it between units of work and stop while
+/// their state is still consistent, rather than being cut off by the
+/// watchdog mid-write:
+///
+/// ```rust
+/// use shutdown_guard_rs::ShutdownGuard;
+/// use std::time::Duration;
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register(Box::new(|context| {
+///     for _batch in 0..100 {
+///         if context.deadline.remaining() < Some(Duration::from_millis(100)) {
+///             break;
+///         }
+///         // flush the batch
+///     }
+/// }))?;
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+///
+/// The deadline is measured from when the callbacks start. Without a
+/// configured deadline, [`remaining`](Self::remaining) is `None` and the
+/// deadline never expires.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShutdownDeadline {
+    expires_at: Option<Instant>,
+}
+
+impl ShutdownDeadline {
+    /// Creates a deadline that expires `deadline` from now, or never
+    pub(crate) fn after(deadline: Option<Duration>) -> Self {
+        Self {
+            expires_at: deadline.map(|deadline| Instant::now() + deadline),
+        }
+    }
+
+    /// Returns the time left until the deadline, zero once it passed, or
+    /// `None` if there is no deadline
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|expires_at| expires_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns true once the deadline passed
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+}
+
+/// Function used to terminate the process, replaceable in tests
+type ExitFn = Arc<dyn Fn(i32) + Send + Sync>;
+
+/// The callbacks registered with `register_final`, run once per shutdown:
+/// after the other callbacks, or by the watchdog right before it exits
+#[derive(Default)]
+pub(crate) struct FinalCallbacks {
+    callbacks: Mutex<Vec<SharedCallback>>,
+    ran: AtomicBool,
+}
+
+impl FinalCallbacks {
+    pub(crate) fn push(&self, callback: SharedCallback) {
+        self.callbacks.lock().push(callback);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.callbacks.lock().len()
+    }
+
+    /// Runs each callback on a scratch thread for up to
+    /// [`FINAL_CALLBACK_TIMEOUT`], unless they already ran for this shutdown
+    pub(crate) fn run(&self, context: ShutdownContext, clock: &SharedClock) {
+        if self.ran.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let callbacks = self.callbacks.lock().clone();
+        for callback in callbacks {
+            // On a thread of their own: the watchdog may run them because the
+            // application's pool is stuck
+            let spawner = SharedSpawner::default();
+            if let Err(e) =
+                run_with_timeout(callback, context, FINAL_CALLBACK_TIMEOUT, clock, &spawner)
+            {
+                eprintln!("Final shutdown callback failed: {}", e);
+            }
+        }
+    }
+
+    /// Lets the callbacks run again, along with the others
+    pub(crate) fn reset(&self) {
+        self.ran.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Ids and labels of the callbacks that have not finished yet, in execution
+/// order
+pub(crate) type PendingCallbacks = Arc<Mutex<Vec<(CallbackId, String)>>>;
+
+/// Watches a running set of callbacks and enforces the deadline action
+pub(crate) struct Watchdog {
+    done: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Watchdog {
+    /// Arms a watchdog that terminates the process on overrun, logging the
+    /// callbacks still in `pending` and giving `finals` their chance first
+    pub(crate) fn arm(
+        deadline: Duration,
+        action: DeadlineAction,
+        pending: PendingCallbacks,
+        finals: Arc<FinalCallbacks>,
+        context: ShutdownContext,
+        clock: SharedClock,
+    ) -> Self {
+        let finals_clock = clock.clone();
+        let exit = Arc::new(move |code| {
+            finals.run(context, &finals_clock);
+            force_exit(code);
+        });
+        Self::arm_with(deadline, action, pending, exit, clock)
+    }
+
+    fn arm_with(
+        deadline: Duration,
+        action: DeadlineAction,
+        pending: PendingCallbacks,
+        exit: ExitFn,
+        clock: SharedClock,
+    ) -> Self {
+        let done = Arc::new((Mutex::new(false), Condvar::new()));
+        let done_clone = Arc::clone(&done);
+        let started = clock.now();
+
+        std::thread::spawn(move || {
+            if clock.wait_until(&done_clone, started + deadline) {
+                return;
+            }
+
+            match action {
+                DeadlineAction::ForceExit(code) => {
+                    eprintln!(
+                        "Shutdown callbacks exceeded the {:?} deadline, exiting with code {} \
+                         (still pending: {})",
+                        deadline,
+                        code,
+                        describe(&pending)
+                    );
+                    exit(code);
+                }
+                DeadlineAction::LogAndContinue { absolute_cap } => {
+                    eprintln!(
+                        "Shutdown callbacks exceeded the {:?} deadline, waiting up to {:?} \
+                         (still pending: {})",
+                        deadline,
+                        absolute_cap,
+                        describe(&pending)
+                    );
+                    if clock.wait_until(&done_clone, started + absolute_cap) {
+                        return;
+                    }
+                    eprintln!(
+                        "Shutdown callbacks exceeded the {:?} cap, exiting with code {} \
+                         (still pending: {})",
+                        absolute_cap,
+                        DEADLINE_CAP_EXIT_CODE,
+                        describe(&pending)
+                    );
+                    exit(DEADLINE_CAP_EXIT_CODE);
+                }
+            }
+        });
+
+        Self { done }
+    }
+
+    /// Signals that the callbacks completed in time
+    pub(crate) fn disarm(self) {
+        let (lock, condvar) = &*self.done;
+        *lock.lock() = true;
+        condvar.notify_all();
+    }
+}
+
+/// Lists the labels of the pending callbacks for a log message
+fn describe(pending: &PendingCallbacks) -> String {
+    let pending = pending.lock();
+    let labels: Vec<&str> = pending.iter().map(|(_, label)| label.as_str()).collect();
+    labels.join(", ")
+}
+
+/// Terminates the process without running destructors
+///
+/// On Unix this skips atexit handlers too; on Windows `std::process::exit`
+/// ends in `ExitProcess`.
+fn force_exit(code: i32) {
+    #[cfg(unix)]
+    unsafe {
+        libc::_exit(code);
+    }
+
+    #[cfg(not(unix))]
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording_exit() -> (ExitFn, Arc<Mutex<Option<i32>>>) {
+        let recorded = Arc::new(Mutex::new(None));
+        let recorded_clone = Arc::clone(&recorded);
+        let exit: ExitFn = Arc::new(move |code| *recorded_clone.lock() = Some(code));
+        (exit, recorded)
+    }
+
+    #[test]
+    fn test_shutdown_deadline_counts_down() {
+        let unbounded = ShutdownDeadline::default();
+        assert_eq!(unbounded.remaining(), None);
+        assert!(!unbounded.is_expired());
+
+        let deadline = ShutdownDeadline::after(Some(Duration::from_millis(50)));
+        assert!(deadline.remaining().unwrap() > Duration::ZERO);
+        assert!(!deadline.is_expired());
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!(deadline.remaining(), Some(Duration::ZERO));
+        assert!(deadline.is_expired());
+    }
+
+    #[test]
+    fn test_final_callbacks_run_once_within_their_timeout() {
+        let finals = FinalCallbacks::default();
+        let count = Arc::new(Mutex::new(0));
+        let count_clone = Arc::clone(&count);
+        finals.push(Arc::new(move |_| *count_clone.lock() += 1));
+        finals.push(Arc::new(|_| std::thread::sleep(Duration::from_secs(5))));
+
+        let context =
+            ShutdownContext::new(crate::ShutdownReason::Unknown, crate::TriggerSource::Manual);
+        let started = Instant::now();
+        finals.run(context, &SharedClock::default());
+        finals.run(context, &SharedClock::default());
+        assert!(started.elapsed() < FINAL_CALLBACK_TIMEOUT * 3);
+        assert_eq!(*count.lock(), 1);
+
+        finals.reset();
+        finals.run(context, &SharedClock::default());
+        assert_eq!(*count.lock(), 2);
+    }
+
+    #[test]
+    fn test_force_exit_on_overrun() {
+        let (exit, recorded) = recording_exit();
+        let watchdog = Watchdog::arm_with(
+            Duration::from_millis(50),
+            DeadlineAction::ForceExit(3),
+            PendingCallbacks::default(),
+            exit,
+            SharedClock::default(),
+        );
+
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(*recorded.lock(), Some(3));
+        watchdog.disarm();
+    }
+
+    #[test]
+    fn test_watchdog_follows_the_clock() {
+        let (exit, recorded) = recording_exit();
+        let clock = crate::clock::MockClock::new();
+        let watchdog = Watchdog::arm_with(
+            Duration::from_secs(30),
+            DeadlineAction::ForceExit(3),
+            PendingCallbacks::default(),
+            exit,
+            SharedClock::new(clock.clone()),
+        );
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(*recorded.lock(), None);
+        clock.advance(Duration::from_secs(31));
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(*recorded.lock(), Some(3));
+        watchdog.disarm();
+    }
+
+    #[test]
+    fn test_log_and_continue_allows_completion_before_cap() {
+        let (exit, recorded) = recording_exit();
+        let watchdog = Watchdog::arm_with(
+            Duration::from_millis(50),
+            DeadlineAction::LogAndContinue {
+                absolute_cap: Duration::from_secs(5),
+            },
+            PendingCallbacks::default(),
+            exit,
+            SharedClock::default(),
+        );
+
+        // Overrun the soft deadline but finish well before the cap
+        std::thread::sleep(Duration::from_millis(200));
+        watchdog.disarm();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(*recorded.lock(), None);
+    }
+
+    #[test]
+    fn test_log_and_continue_exits_at_cap() {
+        let (exit, recorded) = recording_exit();
+        let watchdog = Watchdog::arm_with(
+            Duration::from_millis(50),
+            DeadlineAction::LogAndContinue {
+                absolute_cap: Duration::from_millis(150),
+            },
+            PendingCallbacks::default(),
+            exit,
+            SharedClock::default(),
+        );
+
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(*recorded.lock(), Some(DEADLINE_CAP_EXIT_CODE));
+        watchdog.disarm();
+    }
+}
@@ -0,0 +1,109 @@
+//! Letting the application decide on a shutdown the system announced
+
+use crate::ShutdownError;
+use parking_lot::{Condvar, Mutex};
+use std::time::Instant;
+
+/// The application's answer to a pending shutdown, given with
+/// [`ShutdownGuard::respond_to_pending`](crate::ShutdownGuard::respond_to_pending)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownDecision {
+    /// Let the shutdown go on and run the callbacks
+    Proceed,
+    /// Ask the system to call the shutdown off
+    Abort,
+}
+
+#[derive(Debug, Default)]
+enum Slot {
+    #[default]
+    Idle,
+    Waiting,
+    Decided(ShutdownDecision),
+}
+
+/// A shutdown the system announced and a backend holds open for the
+/// application's decision
+#[derive(Debug, Default)]
+pub(crate) struct PendingDecision {
+    slot: Mutex<Slot>,
+    decided: Condvar,
+}
+
+impl PendingDecision {
+    /// Starts accepting a decision
+    pub(crate) fn open(&self) {
+        *self.slot.lock() = Slot::Waiting;
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        !matches!(*self.slot.lock(), Slot::Idle)
+    }
+
+    /// Records `decision`, replacing an earlier one for the same shutdown
+    pub(crate) fn respond(&self, decision: ShutdownDecision) -> Result<(), ShutdownError> {
+        let mut slot = self.slot.lock();
+        if matches!(*slot, Slot::Idle) {
+            return Err(ShutdownError::NoPendingShutdown);
+        }
+        *slot = Slot::Decided(decision);
+        self.decided.notify_all();
+        Ok(())
+    }
+
+    /// Waits until a decision was made or `until` passes, without closing
+    pub(crate) fn wait_until(&self, until: Instant) -> Option<ShutdownDecision> {
+        let mut slot = self.slot.lock();
+        loop {
+            if let Slot::Decided(decision) = *slot {
+                return Some(decision);
+            }
+            if self.decided.wait_until(&mut slot, until).timed_out() {
+                return match *slot {
+                    Slot::Decided(decision) => Some(decision),
+                    _ => None,
+                };
+            }
+        }
+    }
+
+    /// Stops accepting decisions and returns the one made, if any
+    pub(crate) fn close(&self) -> Option<ShutdownDecision> {
+        match std::mem::take(&mut *self.slot.lock()) {
+            Slot::Decided(decision) => Some(decision),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_decision_reaches_the_waiting_backend() {
+        let pending = Arc::new(PendingDecision::default());
+        assert_eq!(
+            pending.respond(ShutdownDecision::Abort),
+            Err(ShutdownError::NoPendingShutdown)
+        );
+
+        pending.open();
+        assert!(pending.is_pending());
+        let responder = Arc::clone(&pending);
+        let thread = std::thread::spawn(move || responder.respond(ShutdownDecision::Abort));
+        let until = Instant::now() + Duration::from_secs(5);
+        assert_eq!(pending.wait_until(until), Some(ShutdownDecision::Abort));
+        thread.join().unwrap().unwrap();
+        assert_eq!(pending.close(), Some(ShutdownDecision::Abort));
+        assert!(!pending.is_pending());
+
+        // Nobody answered in time
+        pending.open();
+        let until = Instant::now() + Duration::from_millis(20);
+        assert_eq!(pending.wait_until(until), None);
+        assert_eq!(pending.close(), None);
+    }
+}
@@ -0,0 +1,30 @@
+//! Explicit ordering between callbacks
+
+use crate::CallbackEntry;
+
+/// Moves every entry behind its dependencies, keeping the given order
+/// otherwise
+///
+/// Dependencies must already be registered, so they cannot form a cycle, and
+/// those that were unregistered since no longer constrain anything.
+pub(crate) fn sort(ordered: Vec<&CallbackEntry>) -> Vec<&CallbackEntry> {
+    if ordered.iter().all(|entry| entry.after.is_empty()) {
+        return ordered;
+    }
+
+    let mut remaining = ordered;
+    let mut sorted: Vec<&CallbackEntry> = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .position(|entry| {
+                entry
+                    .after
+                    .iter()
+                    .all(|dep| !remaining.iter().any(|other| other.id == *dep))
+            })
+            .expect("callback dependencies form a cycle");
+        sorted.push(remaining.remove(ready));
+    }
+    sorted
+}
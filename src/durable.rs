@@ -0,0 +1,100 @@
+//! Durable file writes for shutdown callbacks
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Replaces the contents of `path` with `bytes` so that they survive the
+/// power going off right after it returns
+///
+/// The bytes are written to a temporary file next to `path`, which is synced
+/// and renamed over `path`, so a crash leaves either the old or the new
+/// contents and never a torn file. On Unix the directory is synced as well,
+/// since the rename is only durable once its directory entry is; Windows
+/// offers no way to do so through the standard library. Callbacks otherwise
+/// tend to `write` without syncing and lose the data when the system powers
+/// off ahead of the page cache.
+///
+/// # Example
+///
+/// ```no_run
+/// use shutdown_guard_rs::{durable_write, ShutdownGuard};
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register_fallible(Box::new(|context| {
+///     durable_write("state.txt", format!("stopped: {}", context.reason).as_bytes())?;
+///     Ok(())
+/// }))?;
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+pub fn durable_write(path: impl AsRef<Path>, bytes: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} does not name a file", path.display()),
+        )
+    })?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let written = write_synced(&temp, bytes).and_then(|()| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written?;
+    sync_parent(path)
+}
+
+/// Syncs the data of the existing file at `path`, and on Unix its directory
+/// entry
+pub(crate) fn sync_file(path: &Path) -> io::Result<()> {
+    // Windows only flushes handles opened for writing
+    fs::OpenOptions::new().write(true).open(path)?.sync_all()?;
+    sync_parent(path)
+}
+
+fn write_synced(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Syncs the directory holding `path`, making a rename into it durable
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durable_write_replaces_the_contents() {
+        let dir =
+            std::env::temp_dir().join(format!("shutdown-guard-durable-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.txt");
+
+        durable_write(&path, b"first").unwrap();
+        durable_write(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        // The temporary file was renamed away
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let err = durable_write(dir.join(".."), b"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
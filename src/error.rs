@@ -0,0 +1,105 @@
+//! Errors from starting and running shutdown monitoring
+
+use std::fmt;
+use std::io;
+
+/// Why shutdown monitoring could not be started or stopped working
+///
+/// Returned by [`ShutdownGuard::start`](crate::ShutdownGuard::start) and its
+/// variants, so callers can react to a specific cause, e.g. fall back to
+/// polling when the system bus is down. It converts into a
+/// `Box<dyn std::error::Error>` like any other error, so `?` keeps working in
+/// functions returning one. Failures of an already running monitoring thread
+/// reach [`ShutdownGuard::on_monitor_error`](crate::ShutdownGuard::on_monitor_error).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShutdownError {
+    /// The guard is already monitoring
+    AlreadyStarted,
+    /// The guard is not monitoring, returned by
+    /// [`ShutdownGuard::verify_active`](crate::ShutdownGuard::verify_active)
+    NotRunning,
+    /// The signal cannot be caught, like SIGKILL, or is not supported as a
+    /// shutdown trigger; on Windows, any Unix signal
+    UnsupportedSignal(i32),
+    /// `sigaction(2)` failed for the signal with the given `errno`
+    SignalRegistrationFailed {
+        /// The signal number
+        signo: i32,
+        /// The OS error code
+        errno: i32,
+    },
+    /// The D-Bus bus or systemd-logind could not be reached
+    DBusUnavailable(String),
+    /// No login manager answered and the guard was built with
+    /// `NoBackendPolicy::Error`, so no backend was started
+    NoBackendAvailable(String),
+    /// The hidden window that receives the Windows session messages could not
+    /// be created, even after a few retries
+    WindowCreationFailed(String),
+    /// Any other platform call failed, e.g. creating the signal pipe
+    Platform(String),
+    /// The running monitoring thread lost its event source, e.g. the system
+    /// bus connection dropped; reported to
+    /// [`ShutdownGuard::on_monitor_error`](crate::ShutdownGuard::on_monitor_error)
+    /// and by [`ShutdownGuard::verify_active`](crate::ShutdownGuard::verify_active)
+    MonitorFailed(String),
+    /// [`ShutdownGuard::run`](crate::ShutdownGuard::run) was called on a
+    /// guard that ends the process after the callbacks, so it could never
+    /// return; build it with `ExitBehavior::DoNotExit`
+    ExitsAfterShutdown,
+    /// `ShutdownGuard::respond_to_pending` was called while no announced
+    /// shutdown was waiting for a decision
+    NoPendingShutdown,
+    /// No callback with this name is registered, returned by
+    /// [`Registry::require`](crate::Registry::require)
+    MissingRequiredCallback(String),
+    /// The Windows message window did not exit in time when monitoring was
+    /// stopped; its thread may still be running
+    StopTimedOut,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownError::AlreadyStarted => write!(f, "shutdown monitoring already started"),
+            ShutdownError::NotRunning => write!(f, "shutdown monitoring is not running"),
+            ShutdownError::UnsupportedSignal(signo) => {
+                write!(f, "signal {} cannot be used as a shutdown trigger", signo)
+            }
+            ShutdownError::SignalRegistrationFailed { signo, errno } => write!(
+                f,
+                "Failed to register handler for signal {}: {}",
+                signo,
+                io::Error::from_raw_os_error(*errno)
+            ),
+            ShutdownError::DBusUnavailable(e) => {
+                write!(f, "Failed to connect to D-Bus: {}", e)
+            }
+            ShutdownError::NoBackendAvailable(e) => {
+                write!(f, "No shutdown monitoring backend available: {}", e)
+            }
+            ShutdownError::WindowCreationFailed(e) => {
+                write!(f, "Failed to create the shutdown window: {}", e)
+            }
+            ShutdownError::Platform(e) => write!(f, "{}", e),
+            ShutdownError::MonitorFailed(e) => write!(f, "Shutdown monitoring failed: {}", e),
+            ShutdownError::ExitsAfterShutdown => write!(
+                f,
+                "the guard exits the process after the callbacks; build it with \
+                 ExitBehavior::DoNotExit to return from run"
+            ),
+            ShutdownError::NoPendingShutdown => {
+                write!(f, "no shutdown is waiting for a decision")
+            }
+            ShutdownError::MissingRequiredCallback(name) => {
+                write!(f, "required shutdown callback {:?} is not registered", name)
+            }
+            ShutdownError::StopTimedOut => {
+                write!(f, "the shutdown window did not exit in time")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShutdownError {}
@@ -0,0 +1,97 @@
+//! Pluggable execution of the ordered shutdown callbacks
+
+use crate::CallbackId;
+use std::fmt;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+/// One callback of a shutdown, ready to run
+///
+/// Running a task invokes the callback with the guard's panic policy and
+/// graceful timeout, and records its outcome.
+pub struct Task<'a> {
+    id: CallbackId,
+    name: Option<&'a str>,
+    run: Box<dyn FnOnce() -> ControlFlow<()> + 'a>,
+}
+
+#[cfg_attr(not(any(test, feature = "testing")), allow(dead_code))]
+impl<'a> Task<'a> {
+    pub(crate) fn new(
+        id: CallbackId,
+        name: Option<&'a str>,
+        run: impl FnOnce() -> ControlFlow<()> + 'a,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            run: Box::new(run),
+        }
+    }
+
+    /// The id returned when the callback was registered
+    pub fn id(&self) -> CallbackId {
+        self.id
+    }
+
+    /// The name given with `register_named`, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name
+    }
+
+    /// Runs the callback; breaks if the remaining tasks must be skipped, e.g.
+    /// after a panic under `PanicPolicy::AbortOnPanic`
+    pub fn run(self) -> ControlFlow<()> {
+        (self.run)()
+    }
+}
+
+impl fmt::Debug for Task<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// Runs the tasks of a shutdown, which arrive in execution order
+///
+/// The default [`SequentialExecutor`] runs them one after another on the
+/// calling thread. Tests can wrap it to record the order the callbacks ran
+/// in without depending on thread scheduling. Executors are not used by
+/// `execute_callbacks_parallel`.
+pub trait Executor: Send + Sync {
+    /// Runs `tasks`, stopping once a task breaks
+    fn execute(&self, tasks: Vec<Task<'_>>);
+}
+
+/// Runs the tasks one after another on the calling thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SequentialExecutor;
+
+impl Executor for SequentialExecutor {
+    fn execute(&self, tasks: Vec<Task<'_>>) {
+        for task in tasks {
+            if task.run().is_break() {
+                break;
+            }
+        }
+    }
+}
+
+/// An executor shared between a builder and the guards it builds
+#[derive(Clone)]
+pub(crate) struct SharedExecutor(pub(crate) Arc<dyn Executor>);
+
+impl Default for SharedExecutor {
+    fn default() -> Self {
+        Self(Arc::new(SequentialExecutor))
+    }
+}
+
+impl fmt::Debug for SharedExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Executor")
+    }
+}
@@ -0,0 +1,24 @@
+//! What the signal-handler backends do once the callbacks ran
+
+/// How the process ends after the callbacks for a Unix signal ran
+///
+/// Only the signal-handler backends on Linux and macOS exit the process; the
+/// other triggers leave that to the system or the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitBehavior {
+    /// Exit with the given code; `Exit(0)` is the default
+    Exit(i32),
+    /// Restore the signal's default disposition and raise it again, so the
+    /// parent sees the process terminated by the signal (a shell reports
+    /// `128 + signo`)
+    SignalDefault,
+    /// Return control to the application, e.g. to exit after
+    /// [`Registry::wait_for_shutdown`](crate::Registry::wait_for_shutdown)
+    DoNotExit,
+}
+
+impl Default for ExitBehavior {
+    fn default() -> Self {
+        Self::Exit(0)
+    }
+}
@@ -0,0 +1,141 @@
+//! C API for registering shutdown callbacks from non-Rust code
+//!
+//! The matching declarations are in `include/shutdown_guard.h`. Build a
+//! linkable library with
+//! `cargo rustc --release --features capi --crate-type staticlib`
+//! (or `cdylib`).
+//!
+//! Callbacks run on the platform's monitoring thread, not on the thread that
+//! registered them, so they and their context must be safe to use from any
+//! thread. The context pointer is never dereferenced or freed by the guard; it
+//! must stay valid until the guard is freed.
+
+use crate::ShutdownGuard;
+use std::ffi::c_void;
+
+/// A C callback invoked with the context pointer given at registration
+pub type SgCallback = extern "C" fn(*mut c_void);
+
+/// An opaque guard handle for C callers
+pub struct SgGuard(ShutdownGuard);
+
+/// A context pointer handed back to C untouched
+struct Context(*mut c_void);
+
+// SAFETY: the C caller promises the context may be used from any thread,
+// see the module documentation
+unsafe impl Send for Context {}
+unsafe impl Sync for Context {}
+
+/// Creates a guard; free it with [`sg_guard_free`]
+#[no_mangle]
+pub extern "C" fn sg_guard_new() -> *mut SgGuard {
+    Box::into_raw(Box::new(SgGuard(ShutdownGuard::new())))
+}
+
+/// Registers `callback`, to be called with `context` before shutdown
+///
+/// Returns 0 on success and -1 if `guard` is null or refused the callback,
+/// e.g. because it is full; the error is logged to stderr.
+///
+/// # Safety
+///
+/// `guard` must be null or a pointer returned by [`sg_guard_new`] that was not
+/// freed yet. `context` must stay valid until the guard is freed and be usable
+/// from any thread.
+#[no_mangle]
+pub unsafe extern "C" fn sg_register(
+    guard: *mut SgGuard,
+    callback: SgCallback,
+    context: *mut c_void,
+) -> i32 {
+    let Some(guard) = guard.as_ref() else {
+        return -1;
+    };
+
+    let context = Context(context);
+    let registered = guard.0.register(Box::new(move |_| {
+        // Bind the whole wrapper, not just its raw pointer field
+        let context = &context;
+        callback(context.0)
+    }));
+    match registered {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Failed to register shutdown callback: {}", e);
+            -1
+        }
+    }
+}
+
+/// Starts shutdown monitoring
+///
+/// Returns 0 on success and -1 if `guard` is null, monitoring is already
+/// running or the platform backend failed to start; the error is logged to
+/// stderr.
+///
+/// # Safety
+///
+/// `guard` must be null or a pointer returned by [`sg_guard_new`] that was not
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sg_start(guard: *mut SgGuard) -> i32 {
+    let Some(guard) = guard.as_ref() else {
+        return -1;
+    };
+
+    match guard.0.start() {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Failed to start shutdown monitoring: {}", e);
+            -1
+        }
+    }
+}
+
+/// Stops monitoring if it is running and frees the guard; null is ignored
+///
+/// # Safety
+///
+/// `guard` must be null or a pointer returned by [`sg_guard_new`] that was not
+/// freed yet. It must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sg_guard_free(guard: *mut SgGuard) {
+    if guard.is_null() {
+        return;
+    }
+
+    let guard = Box::from_raw(guard);
+    if let Err(e) = guard.0.stop() {
+        eprintln!("Failed to stop shutdown monitoring: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    extern "C" fn count(context: *mut c_void) {
+        let counter = unsafe { &*(context as *const AtomicUsize) };
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_registered_c_callback_receives_context() {
+        let counter = AtomicUsize::new(0);
+
+        unsafe {
+            let guard = sg_guard_new();
+            let context = &counter as *const AtomicUsize as *mut c_void;
+            assert_eq!(sg_register(guard, count, context), 0);
+            (*guard).0.execute_callbacks();
+            sg_guard_free(guard);
+
+            assert_eq!(sg_register(std::ptr::null_mut(), count, context), -1);
+            assert_eq!(sg_start(std::ptr::null_mut()), -1);
+        }
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+}
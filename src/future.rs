@@ -0,0 +1,26 @@
+//! Async cleanup driven by a minimal executor, without a runtime
+
+use crate::timeout::SharedCallback;
+use parking_lot::Mutex;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Wraps `future` in a callback that blocks on it the first time it runs
+///
+/// A future can only be polled to completion once, so later runs, e.g. after
+/// `reset_executed`, do nothing.
+pub(crate) fn callback<F>(future: F) -> SharedCallback
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let future: Mutex<Option<BoxFuture>> = Mutex::new(Some(Box::pin(future)));
+    Arc::new(move |_| {
+        let pending = future.lock().take();
+        if let Some(pending) = pending {
+            futures_executor::block_on(pending);
+        }
+    })
+}
@@ -0,0 +1,63 @@
+//! A process-wide [`ShutdownGuard`] for programs that don't want to pass one around
+
+use crate::{
+    CallbackId, MonitorHandle, RegisterError, ShutdownCallback, ShutdownError, ShutdownGuard,
+};
+use std::sync::OnceLock;
+
+static GLOBAL_GUARD: OnceLock<ShutdownGuard> = OnceLock::new();
+
+/// Returns the process-wide guard, creating it on first use
+///
+/// Every thread gets the same guard, which is created with
+/// [`ShutdownGuard::new`].
+///
+/// ```rust
+/// let guard = shutdown_guard_rs::global();
+/// assert!(std::ptr::eq(guard, shutdown_guard_rs::global()));
+/// ```
+pub fn global() -> &'static ShutdownGuard {
+    GLOBAL_GUARD.get_or_init(ShutdownGuard::new)
+}
+
+/// Registers a callback on the [`global`] guard
+///
+/// ```no_run
+/// shutdown_guard_rs::register(Box::new(|context| {
+///     println!("Cleaning up before shutdown ({})...", context.reason);
+/// }))
+/// .expect("Failed to register the cleanup");
+/// shutdown_guard_rs::start().expect("Failed to start shutdown monitoring");
+/// ```
+pub fn register(callback: ShutdownCallback) -> Result<CallbackId, RegisterError> {
+    global().register(callback)
+}
+
+/// Starts shutdown monitoring on the [`global`] guard
+///
+/// Like [`ShutdownGuard::start`], this fails if the global guard is already
+/// running. With the `macros` feature, the functions annotated with
+/// `#[shutdown_hook]` are registered first.
+pub fn start() -> Result<MonitorHandle, ShutdownError> {
+    #[cfg(feature = "macros")]
+    crate::collect::register_collected(global());
+    global().start()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_is_shared_across_threads() {
+        let here = global() as *const ShutdownGuard as usize;
+        let there = std::thread::spawn(|| global() as *const ShutdownGuard as usize)
+            .join()
+            .unwrap();
+        assert_eq!(here, there);
+
+        let id = register(Box::new(|_| {})).unwrap();
+        assert!(global().inspect().iter().any(|info| info.id == id));
+        assert!(global().unregister(id));
+    }
+}
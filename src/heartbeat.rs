@@ -0,0 +1,62 @@
+//! Periodic callbacks showing that the guard is alive
+
+use crate::CallbackPanic;
+use parking_lot::{Condvar, Mutex};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A callback run periodically until the guard stops
+pub(crate) type HeartbeatCallback = Box<dyn Fn() + Send + Sync + 'static>;
+
+/// A timer thread started by `Registry::register_heartbeat`, stopped when
+/// the guard stops
+pub(crate) struct Heartbeat {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Heartbeat {
+    /// Runs `callback` every `interval` on a thread of its own
+    ///
+    /// The first beat comes one interval after the start. A panicking
+    /// callback is reported and the beat goes on.
+    pub(crate) fn start(interval: Duration, callback: HeartbeatCallback) -> (Self, JoinHandle<()>) {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let stopped = Arc::clone(&stopped);
+            thread::spawn(move || {
+                let (lock, changed) = &*stopped;
+                let mut next = Instant::now() + interval;
+                loop {
+                    let mut done = lock.lock();
+                    while !*done && Instant::now() < next {
+                        changed.wait_until(&mut done, next);
+                    }
+                    if *done {
+                        return;
+                    }
+                    drop(done);
+
+                    if let Err(payload) = std::panic::catch_unwind(AssertUnwindSafe(&callback)) {
+                        eprintln!(
+                            "Heartbeat callback panicked: {}",
+                            CallbackPanic::from_payload(&*payload)
+                        );
+                    }
+                    // Skips the beats a slow callback overran instead of
+                    // running them back to back
+                    next = (next + interval).max(Instant::now());
+                }
+            })
+        };
+        (Self { stopped }, thread)
+    }
+
+    /// Stops the beat; a callback already running is finished first
+    pub(crate) fn stop(self) {
+        let (lock, changed) = &*self.stopped;
+        *lock.lock() = true;
+        changed.notify_all();
+    }
+}
@@ -0,0 +1,32 @@
+//! Shutdown callbacks implemented by stateful objects
+
+use crate::ShutdownContext;
+
+/// An object that cleans up before shutdown
+///
+/// An alternative to closures for cleanup that lives on a stateful object:
+/// the same `Arc` can be registered with several guards, or registered again
+/// after [`Registry::clear`](crate::Registry::clear), without
+/// boxing a new closure each time.
+///
+/// ```
+/// use shutdown_guard_rs::{ShutdownContext, ShutdownGuard, ShutdownHook};
+/// use std::sync::Arc;
+///
+/// struct Database;
+///
+/// impl ShutdownHook for Database {
+///     fn on_shutdown(&self, context: &ShutdownContext) {
+///         println!("Closing database ({})...", context.reason);
+///     }
+/// }
+///
+/// let database = Arc::new(Database);
+/// let guard = ShutdownGuard::new();
+/// guard.register_hook(database.clone())?;
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+pub trait ShutdownHook: Send + Sync {
+    /// Cleans up before shutdown
+    fn on_shutdown(&self, context: &ShutdownContext);
+}
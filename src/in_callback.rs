@@ -0,0 +1,92 @@
+//! Whether the current thread is running a shutdown callback
+
+use std::cell::Cell;
+
+thread_local! {
+    static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns true if the calling thread is running a shutdown callback
+///
+/// Set on whichever thread runs the callback: the dispatching thread, a
+/// worker of the parallel dispatch, the thread of a timed callback, or one
+/// spawned through a [`ShutdownScope`](crate::ShutdownScope). Lets a library
+/// called from a callback behave accordingly, e.g. a logger that writes
+/// unbuffered while it is flushed during shutdown, or code that would
+/// otherwise register callbacks of its own.
+///
+/// ```
+/// use shutdown_guard_rs::{in_shutdown_callback, ShutdownGuard};
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register(Box::new(|_| assert!(in_shutdown_callback())))?;
+///
+/// assert!(!in_shutdown_callback());
+/// guard.execute_callbacks();
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+pub fn in_shutdown_callback() -> bool {
+    IN_CALLBACK.with(Cell::get)
+}
+
+/// Marks the calling thread as running a callback until dropped, also when
+/// the callback panics
+pub(crate) struct InCallback {
+    previous: bool,
+}
+
+impl InCallback {
+    pub(crate) fn enter() -> Self {
+        Self {
+            previous: IN_CALLBACK.with(|flag| flag.replace(true)),
+        }
+    }
+}
+
+impl Drop for InCallback {
+    fn drop(&mut self) {
+        // Lines logged by the callback are written once it finished
+        #[cfg(unix)]
+        crate::signal_log::flush_thread();
+        IN_CALLBACK.with(|flag| flag.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Registry;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_flag_is_set_on_the_thread_running_the_callback() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let registry = Registry::new();
+        for _ in 0..2 {
+            let seen = Arc::clone(&seen);
+            registry
+                .register(Box::new(move |_| seen.lock().push(in_shutdown_callback())))
+                .unwrap();
+        }
+        let timed = Arc::clone(&seen);
+        registry
+            .register_with_timeout(
+                Duration::from_secs(5),
+                Box::new(move |_| timed.lock().push(in_shutdown_callback())),
+            )
+            .unwrap();
+        registry.register(Box::new(|_| panic!("boom"))).unwrap();
+
+        registry.execute_callbacks();
+        assert_eq!(*seen.lock(), vec![true; 3]);
+        // Restored after the panic
+        assert!(!in_shutdown_callback());
+
+        seen.lock().clear();
+        registry.reset_executed();
+        registry.execute_callbacks_parallel();
+        assert_eq!(*seen.lock(), vec![true; 3]);
+    }
+}
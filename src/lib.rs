@@ -5,16 +5,16 @@
 //! ## Example
 //!
 //! ```rust
-//! use shutdown_guard_rs::{ShutdownGuard, ShutdownCallback};
+//! use shutdown_guard_rs::{ShutdownContext, ShutdownGuard};
 //!
 //! fn main() {
 //!     let guard = ShutdownGuard::new();
 //!     
 //!     // Register a cleanup callback
-//!     guard.register(Box::new(|| {
-//!         println!("Cleaning up before shutdown...");
+//!     guard.register(Box::new(|context: ShutdownContext| {
+//!         println!("Cleaning up before shutdown ({})...", context.reason);
 //!         // Perform cleanup operations
-//!     }));
+//!     })).unwrap();
 //!     
 //!     // Start monitoring for shutdown events
 //!     guard.start().expect("Failed to start shutdown monitoring");
@@ -22,12 +22,1109 @@
 //!     // Your application code here...
 //! }
 //! ```
+//!
+//! ## Signal safety
+//!
+//! Callbacks never run inside a signal handler. On Unix the handler only
+//! writes the signal number to a pipe created by `start`; a dispatch thread
+//! reads it and starts a thread that runs the callbacks as ordinary code, so
+//! they may allocate and take locks. The callback list is read under its lock
+//! on that thread, never in signal context, so it does not need to be frozen or
+//! snapshotted before a signal arrives. The lock is released before the first
+//! callback runs, so a callback may register, unregister or clear callbacks on
+//! its own guard without deadlocking; the changes apply to the next run. While
+//! a shutdown runs, `register` and its variants still succeed and `unregister`
+//! still removes callbacks, but `try_register` and `clear` return
+//! [`RegisterError::ShutdownInProgress`]. A sealed guard refuses registering
+//! and clearing with [`RegisterError::Sealed`], and `unregister` returns
+//! false. On Windows and with the D-Bus
+//! backend, notifications arrive on regular threads to begin with.
+//!
+//! ## Feature flags
+//!
+//! The blocking API is always available and never changes with the enabled
+//! features, so libraries can depend on it while their users independently
+//! opt into async integrations.
+//!
+//! - `dbus-support`: use systemd-logind D-Bus signals on Linux
+//! - `serde`: serialize callback metadata, e.g. `ShutdownGuard::inspect_json`,
+//!   and shutdown contexts, e.g. `ShutdownGuard::log_context_to`
+//! - `tokio`: notify tokio channels on shutdown and run async callbacks with
+//!   `AsyncShutdownGuard`
+//! - `crossbeam`: notify crossbeam channels on shutdown
+//! - `futures`: register futures with `Registry::register_future`, run by a
+//!   minimal executor instead of a tokio runtime
+//! - `cocoa`: also watch `NSWorkspaceWillPowerOffNotification` and, in GUI
+//!   apps, `NSApplicationWillTerminateNotification` on macOS
+//! - `tracing`: emit a span and per-callback events while the callbacks run
+//! - `testing`: `ShutdownGuard::simulate_shutdown`,
+//!   `ShutdownGuard::force_shutdown` and `Registry::snapshot` for integration
+//!   tests, `ShutdownGuardBuilder::executor` to observe the order callbacks
+//!   run in, `OrderRecorder` to assert that order, and
+//!   `ShutdownGuardBuilder::clock` with a `MockClock` to trigger timeouts
+//!   without waiting for them
+//! - `systemd`: tell systemd that a `Type=notify` service is stopping and how
+//!   far the callbacks got, through `NOTIFY_SOCKET`
+//! - `windows-service`: handle `SERVICE_CONTROL_SHUTDOWN` for a guard running
+//!   inside a Windows service, see `ShutdownGuardBuilder::windows_service`
+//! - `macros`: the `#[shutdown_hook]` attribute, which registers a function
+//!   with the [`global`] guard without any wiring in `main`
+//! - `capi`: a C API in the `ffi` module, declared in `include/shutdown_guard.h`
+
+use parking_lot::{Condvar, Mutex, RwLock, RwLockReadGuard};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::ops::{ControlFlow, Deref};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+#[cfg(all(unix, feature = "admin-socket"))]
+mod admin_socket;
+#[cfg(feature = "tokio")]
+mod async_guard;
+mod backend;
+mod builder;
+mod clock;
+#[cfg(feature = "macros")]
+mod collect;
+mod context;
+mod coordinator;
+mod deadline;
+#[cfg(any(
+    target_os = "windows",
+    all(target_os = "linux", feature = "dbus-support")
+))]
+mod decision;
+mod dependency;
+mod durable;
+mod error;
+mod executor;
+#[cfg(unix)]
+mod exit;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "futures")]
+mod future;
+mod global;
+mod heartbeat;
+mod hook;
+mod in_callback;
+mod local;
+mod metrics;
+mod monitor;
+mod notify;
+mod order;
+mod panic;
+mod parallel;
+mod pause;
+mod phase;
+mod priority;
+mod readiness;
+mod reason;
+#[cfg(any(test, feature = "testing"))]
+mod recorder;
+mod register_error;
+mod registration;
+mod registry;
+mod scope;
+#[cfg(unix)]
+mod signal_log;
+mod spawner;
+mod steps;
+mod subscribe;
+mod summary;
+mod system_log;
+mod systemd;
+mod timeout;
+mod trace;
+mod trigger;
+mod weak;
+
+#[cfg(feature = "tokio")]
+pub use async_guard::{AsyncShutdownCallback, AsyncShutdownGuard, ShutdownFuture};
+pub use backend::{ActiveBackend, Backend, LoginManager};
+pub use builder::ShutdownGuardBuilder;
+#[cfg(feature = "testing")]
+pub use clock::{Clock, MockClock, SystemClock};
+pub use context::{ShutdownContext, SignalSender, TriggerSource};
+pub use coordinator::CallbackController;
+pub use deadline::{
+    CallbackSkipped, DeadlineAction, ShutdownDeadline, DEADLINE_CAP_EXIT_CODE,
+    FINAL_CALLBACK_TIMEOUT,
+};
+#[cfg(any(
+    target_os = "windows",
+    all(target_os = "linux", feature = "dbus-support")
+))]
+pub use decision::ShutdownDecision;
+pub use durable::durable_write;
+pub use error::ShutdownError;
+#[cfg(feature = "testing")]
+pub use executor::{Executor, SequentialExecutor, Task};
+#[cfg(unix)]
+pub use exit::ExitBehavior;
+pub use global::{global, register, start};
+pub use hook::ShutdownHook;
+pub use in_callback::in_shutdown_callback;
+pub use local::{LocalShutdownCallback, LocalShutdownGuard};
+pub use metrics::MetricsSnapshot;
+pub use monitor::MonitorHandle;
+pub use notify::ShutdownSender;
+pub use order::ExecutionOrder;
+pub use panic::{CallbackPanic, PanicPolicy};
+pub use pause::PauseGuard;
+pub use phase::Phase;
+#[cfg(target_os = "windows")]
+pub use platform::EndSessionPhase;
+#[cfg(all(target_os = "linux", feature = "dbus-support"))]
+pub use platform::{DBusBus, LinuxBackend, LogindMonitor, NoBackendPolicy};
+pub use priority::DispatchPriority;
+pub use readiness::Readiness;
+pub use reason::ShutdownReason;
+#[cfg(any(test, feature = "testing"))]
+pub use recorder::OrderRecorder;
+pub use register_error::RegisterError;
+pub use registration::Registration;
+pub use registry::Registry;
+#[cfg(feature = "testing")]
+pub use registry::RegistrySnapshot;
+pub use scope::ShutdownScope;
+#[cfg(feature = "macros")]
+pub use shutdown_guard_macros::shutdown_hook;
+#[cfg(unix)]
+pub use signal_log::{shutdown_log, SignalSafeLogger};
+pub use spawner::Spawner;
+pub use steps::{CallbackBuilder, StepFailed};
+pub use subscribe::{ShutdownSignal, ShutdownWatcher};
+pub use summary::{CallbackOutcome, CallbackSummary, ShutdownSummary};
+pub use timeout::CallbackTimeout;
+pub use trigger::{FileWatchTrigger, ShutdownTrigger};
+
+/// Used by the code `#[shutdown_hook]` generates; not public API
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::collect::{collect, CollectedHook};
+}
 
-use parking_lot::RwLock;
-use std::sync::Arc;
+use clock::SharedClock;
+use coordinator::CoordinatorCallback;
+use deadline::{FinalCallbacks, PendingCallbacks, Watchdog};
+use executor::SharedExecutor;
+use in_callback::InCallback;
+use spawner::SharedSpawner;
+use timeout::{run_with_timeout, SharedCallback};
+use weak::WeakCallback;
 
 /// A callback function that will be executed before system shutdown
-pub type ShutdownCallback = Box<dyn Fn() + Send + Sync + 'static>;
+///
+/// The callback receives the [`ShutdownContext`] detected by the platform
+/// backend: the [`ShutdownReason`], when it was detected and what triggered it.
+pub type ShutdownCallback = Box<dyn Fn(ShutdownContext) + Send + Sync + 'static>;
+
+/// The error type returned by a [`FallibleCallback`]
+pub type CallbackError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A shutdown callback that can report failure
+///
+/// Failures do not stop the remaining callbacks from running; they are
+/// collected by [`Registry::execute_callbacks_collect`] and logged when
+/// the callbacks run because of a detected shutdown.
+pub type FallibleCallback =
+    Box<dyn Fn(ShutdownContext) -> Result<(), CallbackError> + Send + Sync + 'static>;
+
+/// A callback invoked with the signal number of every monitored signal delivery
+///
+/// Unlike [`ShutdownCallback`], this runs for every delivery, including the ones
+/// that are deduplicated after the first shutdown trigger. The signal handler
+/// only forwards the signal number through a pipe; the callback itself runs on a
+/// background thread, so it may allocate and take locks, but it should return
+/// quickly to keep up with bursts of signals.
+pub type RawSignalCallback = Box<dyn Fn(i32) + Send + Sync + 'static>;
+
+/// A callback invoked when the running monitoring thread hits a fatal error
+pub(crate) type MonitorErrorCallback = Box<dyn Fn(&ShutdownError) + Send + Sync + 'static>;
+
+/// A predicate deciding whether to veto an ending session; `true` vetoes
+#[cfg(target_os = "windows")]
+pub type VetoCallback = Box<dyn Fn(ShutdownReason) -> bool + Send + Sync + 'static>;
+
+/// Identifies a registered callback so it can later be unregistered
+///
+/// Ids are handed out in increasing order from a 64-bit counter per guard,
+/// which no register/unregister loop can exhaust, and are never reused: an
+/// id whose callback was removed never names another callback of the same
+/// guard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CallbackId(u64);
+
+/// Tags callbacks registered by one subsystem so they can be removed together
+///
+/// Every [`GroupId::new`] is unique within the process, so a group can be
+/// shared between guards. See [`Registry::register_in_group`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GroupId(u64);
+
+impl GroupId {
+    /// Creates a new, unique group
+    pub fn new() -> Self {
+        static NEXT_GROUP: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_GROUP.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for GroupId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Metadata describing a registered callback
+///
+/// Closures cannot be inspected or serialized, so this only carries the
+/// information recorded at registration time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CallbackInfo {
+    /// The id returned when the callback was registered
+    pub id: CallbackId,
+    /// The execution priority; higher priorities run first
+    pub priority: i32,
+    /// The name given with [`Registry::register_named`], if any
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub name: Option<String>,
+    /// The group given with [`Registry::register_in_group`], if any
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub group: Option<GroupId>,
+    /// How long after the guard was created the callback was registered
+    ///
+    /// Callbacks sharing a priority run in the order of this, or its reverse
+    /// for [`ExecutionOrder::Lifo`], unless one was registered after another.
+    pub registered_at: Duration,
+}
+
+/// The kinds of callback that can be registered
+pub(crate) enum Callback {
+    Infallible(ShutdownCallback),
+    Fallible(FallibleCallback),
+    Timed(SharedCallback, Duration),
+    Weak(Box<dyn WeakCallback>),
+    Hook(Arc<dyn ShutdownHook>),
+    Coordinator(CoordinatorCallback),
+}
+
+impl Callback {
+    fn invoke(
+        &self,
+        context: ShutdownContext,
+        clock: &SharedClock,
+        spawner: &SharedSpawner,
+    ) -> Result<(), CallbackError> {
+        let _in_callback = InCallback::enter();
+        match self {
+            Callback::Infallible(callback) => {
+                callback(context);
+                Ok(())
+            }
+            Callback::Fallible(callback) => callback(context),
+            Callback::Timed(callback, timeout) => {
+                run_with_timeout(Arc::clone(callback), context, *timeout, clock, spawner)
+            }
+            Callback::Weak(callback) => {
+                callback.invoke(context);
+                Ok(())
+            }
+            Callback::Hook(hook) => {
+                hook.on_shutdown(&context);
+                Ok(())
+            }
+            // Dispatched by `GuardState::run_coordinator`; anywhere else, e.g.
+            // in a parallel run, there is nothing for it to drive
+            Callback::Coordinator(coordinator) => {
+                let controlled = Mutex::new(HashSet::new());
+                coordinator(
+                    context,
+                    &CallbackController::new(&[], &controlled, &|_| false, Instant::now()),
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns false for a weak callback whose target was dropped
+    fn is_live(&self) -> bool {
+        match self {
+            Callback::Weak(callback) => callback.is_live(),
+            _ => true,
+        }
+    }
+}
+
+/// A registered callback together with its registration metadata
+#[derive(Clone)]
+pub(crate) struct CallbackEntry {
+    pub(crate) id: CallbackId,
+    pub(crate) phase: Phase,
+    pub(crate) priority: i32,
+    pub(crate) name: Option<String>,
+    pub(crate) group: Option<GroupId>,
+    /// Callbacks that have to finish before this one starts
+    pub(crate) after: Vec<CallbackId>,
+    /// The reasons `register_for` limited the callback to, or `None` for
+    /// every reason
+    pub(crate) reasons: Option<Vec<ShutdownReason>>,
+    /// When the callback was registered, by the guard's clock
+    pub(crate) registered_at: Instant,
+    pub(crate) callback: Arc<Callback>,
+}
+
+impl CallbackEntry {
+    /// Returns an entry without a name, group, dependencies or reasons
+    pub(crate) fn new(
+        id: CallbackId,
+        phase: Phase,
+        priority: i32,
+        registered_at: Instant,
+        callback: impl Into<Arc<Callback>>,
+    ) -> Self {
+        Self {
+            id,
+            phase,
+            priority,
+            name: None,
+            group: None,
+            after: Vec::new(),
+            reasons: None,
+            registered_at,
+            callback: callback.into(),
+        }
+    }
+
+    /// Returns the callback's metadata, timed from `created`, when the guard
+    /// was created
+    pub(crate) fn info(&self, created: Instant) -> CallbackInfo {
+        CallbackInfo {
+            id: self.id,
+            priority: self.priority,
+            name: self.name.clone(),
+            group: self.group,
+            registered_at: self.registered_at.saturating_duration_since(created),
+        }
+    }
+
+    /// Identifies the callback in logs: its name, or else its id
+    pub(crate) fn label(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{:?}", self.id))
+    }
+}
+
+/// The callbacks of a guard, borrowed for a run
+pub(crate) enum Entries<'a> {
+    Sealed(&'a [CallbackEntry]),
+    Live(RwLockReadGuard<'a, Vec<CallbackEntry>>),
+}
+
+impl<'a> Entries<'a> {
+    /// Releases the read lock, copying the live callbacks, so the callbacks
+    /// can register and unregister while they run
+    ///
+    /// The entries share the closures, so the copy is cheap.
+    pub(crate) fn release(self) -> Cow<'a, [CallbackEntry]> {
+        match self {
+            Entries::Sealed(entries) => Cow::Borrowed(entries),
+            Entries::Live(entries) => Cow::Owned(entries.to_vec()),
+        }
+    }
+}
+
+impl Deref for Entries<'_> {
+    type Target = [CallbackEntry];
+
+    fn deref(&self) -> &[CallbackEntry] {
+        match self {
+            Entries::Sealed(entries) => entries,
+            Entries::Live(entries) => entries,
+        }
+    }
+}
+
+/// Returns the entries in execution order: graceful before forceful, then
+/// priority descending, then registration order (or its reverse for
+/// [`ExecutionOrder::Lifo`]) within the same priority, with every callback
+/// moved behind the ones it was registered after
+fn execution_order(entries: &[CallbackEntry], order: ExecutionOrder) -> Vec<&CallbackEntry> {
+    let mut ordered: Vec<&CallbackEntry> = entries.iter().collect();
+    if order == ExecutionOrder::Lifo {
+        ordered.reverse();
+    }
+    // `sort_by_key` is stable, so equal priorities keep the order chosen above
+    ordered.sort_by_key(|entry| (entry.phase, Reverse(entry.priority)));
+    dependency::sort(ordered)
+}
+
+/// Runs sleep or resume callbacks, logging the panics as `kind` callbacks
+#[cfg(any(
+    target_os = "windows",
+    all(target_os = "linux", feature = "dbus-support"),
+    all(target_os = "macos", feature = "cocoa")
+))]
+fn run_power_callbacks(callbacks: &[ShutdownCallback], context: ShutdownContext, kind: &str) {
+    for callback in callbacks {
+        if let Err(payload) = std::panic::catch_unwind(AssertUnwindSafe(|| callback(context))) {
+            let panic = CallbackPanic::from_payload(&*payload);
+            eprintln!("{} callback panicked: {}", kind, panic);
+        }
+    }
+}
+
+/// How long after the first trigger further triggers are recorded as part
+/// of the same run, unless `ShutdownGuardBuilder::debounce` sets it
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// When a guard was created, the moment `CallbackInfo::registered_at` is
+/// measured from
+pub(crate) struct CreatedAt(pub(crate) Instant);
+
+impl Default for CreatedAt {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+/// State shared between a guard and the platform monitor
+#[derive(Default)]
+pub(crate) struct GuardState {
+    pub(crate) callbacks: RwLock<Vec<CallbackEntry>>,
+    pub(crate) raw_signal_callback: RwLock<Option<RawSignalCallback>>,
+    pub(crate) monitor_error_callback: RwLock<Option<MonitorErrorCallback>>,
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support"),
+        all(target_os = "macos", feature = "cocoa")
+    ))]
+    pub(crate) sleep_callbacks: RwLock<Vec<ShutdownCallback>>,
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support"),
+        all(target_os = "macos", feature = "cocoa")
+    ))]
+    pub(crate) resume_callbacks: RwLock<Vec<ShutdownCallback>>,
+    pub(crate) deadline: RwLock<Option<(Duration, DeadlineAction)>>,
+    pub(crate) panic_policy: PanicPolicy,
+    pub(crate) execution_order: ExecutionOrder,
+    pub(crate) graceful_timeout: Option<Duration>,
+    pub(crate) dispatch_priority: DispatchPriority,
+    /// Set with `Registry::set_spawner`
+    pub(crate) spawner: SharedSpawner,
+    /// The message template if the builder's `emit_system_log` is set
+    pub(crate) system_log: Option<String>,
+    /// The builder's `critical_priority_floor` and `budget_threshold`
+    pub(crate) critical_priority_floor: Option<i32>,
+    pub(crate) budget_threshold: Option<Duration>,
+    /// The builder's `run_groups_for`
+    pub(crate) group_policy: HashMap<ShutdownReason, Vec<GroupId>>,
+    pub(crate) verbose: bool,
+    pub(crate) max_callbacks: Option<usize>,
+    pub(crate) seal_on_start: bool,
+    /// Where to append the summary of a shutdown, opened on start
+    pub(crate) summary_path: Option<std::path::PathBuf>,
+    pub(crate) summary_file: Mutex<Option<std::fs::File>>,
+    /// The callbacks as they were when a `seal_on_start` guard started
+    pub(crate) sealed: OnceLock<Box<[CallbackEntry]>>,
+    pub(crate) executor: SharedExecutor,
+    /// What per-callback timeouts, the graceful phase and the watchdog
+    /// measure time with
+    pub(crate) clock: SharedClock,
+    pub(crate) metrics: metrics::Metrics,
+    /// When the guard was created, by its clock
+    pub(crate) created: CreatedAt,
+    #[cfg(unix)]
+    pub(crate) exit_behavior: ExitBehavior,
+    /// Inverted `call_global_sync`, so the default keeps calling sync(2)
+    #[cfg(unix)]
+    pub(crate) skip_global_sync: bool,
+    #[cfg(unix)]
+    pub(crate) post_cleanup_delay: Option<Duration>,
+    /// The builder's `abort_on_dispatch_panic`
+    #[cfg(unix)]
+    pub(crate) abort_on_dispatch_panic: bool,
+    /// The builder's `signal_reasons` overrides
+    #[cfg(unix)]
+    pub(crate) signal_reasons: std::collections::HashMap<i32, ShutdownReason>,
+    /// The builder's `treat_interrupt_as_shutdown`; on Unix it only adds
+    /// SIGINT to the signals
+    #[cfg(target_os = "windows")]
+    pub(crate) interrupt_is_shutdown: bool,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub(crate) reconnect_max_backoff: Option<Duration>,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub(crate) linux_backend: LinuxBackend,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub(crate) no_backend_policy: NoBackendPolicy,
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub(crate) dbus_bus: DBusBus,
+    pub(crate) next_id: AtomicU64,
+    pub(crate) executed: AtomicBool,
+    pub(crate) finished: (Mutex<bool>, Condvar),
+    pub(crate) last_reason: Mutex<Option<ShutdownReason>>,
+    /// The builder's `debounce`, or [`DEFAULT_DEBOUNCE`] if unset
+    pub(crate) debounce: Option<Duration>,
+    /// When the current run was first triggered, and the reasons of the
+    /// triggers coalesced into it
+    pub(crate) triggers: Mutex<Option<(Instant, Vec<ShutdownReason>)>>,
+    /// Set while a run requested for a signal has not claimed the callbacks
+    /// yet, holding the reasons of the signals that arrived meanwhile
+    pub(crate) requested: Mutex<Option<Vec<ShutdownReason>>>,
+    /// The summary of the last sequential run, recorded before the waiters
+    /// are woken
+    pub(crate) last_summary: Mutex<Option<ShutdownSummary>>,
+    pub(crate) pending: PendingCallbacks,
+    pub(crate) final_callbacks: Arc<FinalCallbacks>,
+    pub(crate) pause: pause::Pause,
+    pub(crate) shutdown_signal: ShutdownSignal,
+    /// The timers started by `Registry::register_heartbeat`, stopped on stop
+    pub(crate) heartbeats: Mutex<Vec<heartbeat::Heartbeat>>,
+    /// The sockets opened by `Registry::listen_on_socket`, closed on stop
+    #[cfg(all(unix, feature = "admin-socket"))]
+    pub(crate) admin_sockets: Mutex<Vec<admin_socket::AdminSocket>>,
+    #[cfg(target_os = "windows")]
+    pub(crate) shutdown_block_reason: Option<String>,
+    /// The builder's `run_callbacks_on`
+    #[cfg(target_os = "windows")]
+    pub(crate) run_callbacks_on: EndSessionPhase,
+    #[cfg(all(target_os = "windows", feature = "windows-service"))]
+    pub(crate) service_name: Option<String>,
+    #[cfg(target_os = "windows")]
+    pub(crate) veto: RwLock<Option<VetoCallback>>,
+    /// The builder's `shutdown_decision_timeout`
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    pub(crate) decision_timeout: Option<Duration>,
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    pub(crate) pending_decision: decision::PendingDecision,
+}
+
+impl GuardState {
+    /// Runs every entry in execution order with the given context and returns
+    /// the failures reported by fallible callbacks
+    ///
+    /// Shared by [`Registry::execute_callbacks`] and the platform
+    /// backends so every trigger observes the same ordering. Panics are caught
+    /// and reported as [`CallbackPanic`] failures, so they never unwind into a
+    /// platform thread. Only the first call runs anything, whichever path it
+    /// comes from, until [`Registry::reset_executed`].
+    ///
+    /// If the graceful phase is limited (see [`phase::graceful_budget`]), its
+    /// callbacks run on scratch threads and those that overrun or never start
+    /// are reported as [`CallbackTimeout`] failures.
+    pub(crate) fn run_callbacks(
+        &self,
+        entries: Entries<'_>,
+        context: ShutdownContext,
+    ) -> Vec<(CallbackId, CallbackError)> {
+        let mut failures = Vec::new();
+        self.run_callbacks_with(entries, context, |entry, _, result| {
+            if let Err(e) = result {
+                failures.push((entry.id, e));
+            }
+        });
+        failures
+    }
+
+    /// Like [`run_callbacks`](Self::run_callbacks), but hands each callback's
+    /// duration and result to `finished` as soon as it ends
+    ///
+    /// Panics are passed on as [`CallbackPanic`] errors. The run's
+    /// [`ShutdownSummary`] is kept in `last_summary`. Returns false if the
+    /// callbacks already ran.
+    ///
+    /// The read lock `entries` holds is kept until the run is claimed, so a
+    /// registration either makes it into this run or is refused by
+    /// `try_register`, and released before the first callback starts.
+    pub(crate) fn run_callbacks_with(
+        &self,
+        entries: Entries<'_>,
+        context: ShutdownContext,
+        finished: impl FnMut(&CallbackEntry, Duration, Result<(), CallbackError>),
+    ) -> bool {
+        let deadline = self.deadline.read().map(|(deadline, _)| deadline);
+        let context = context.with_deadline(deadline);
+        if !self.begin_shutdown(context) {
+            return false;
+        }
+
+        let entries = entries.release();
+        let _span = trace::enter_execute(&context);
+        let ordered = self.dispatch_order(&entries, context.reason);
+        let graceful = phase::graceful_budget(
+            self.graceful_timeout,
+            deadline,
+            ordered.iter().any(|entry| entry.phase == Phase::Forceful),
+        )
+        .map(|budget| (budget, self.clock.now() + budget));
+        // From this point on only callbacks at or above the floor run
+        let critical = self
+            .critical_priority_floor
+            .zip(self.budget_threshold)
+            .zip(deadline)
+            .map(|((floor, threshold), deadline)| {
+                let expires_at = self.clock.now() + deadline;
+                (floor, expires_at - threshold.min(deadline), expires_at)
+            });
+        *self.pending.lock() = ordered
+            .iter()
+            .map(|entry| (entry.id, entry.label()))
+            .collect();
+
+        let started = self.clock.now();
+        let mut summary = ShutdownSummary::default();
+        let mut finished = finished;
+        let finished = RefCell::new(|entry: &CallbackEntry, duration, result| {
+            summary.callbacks.push(CallbackSummary {
+                id: entry.id,
+                name: entry.name.clone(),
+                duration,
+                outcome: CallbackOutcome::from_result(&result),
+            });
+            finished(entry, duration, result);
+        });
+        // The callbacks a coordinator ran or skipped
+        let controlled = Mutex::new(HashSet::new());
+        let tasks = ordered
+            .iter()
+            .enumerate()
+            .map(|(i, &entry)| {
+                let finished = &finished;
+                let controlled = &controlled;
+                let remaining = &ordered[i + 1..];
+                executor::Task::new(entry.id, entry.name.as_deref(), move || {
+                    if controlled.lock().contains(&entry.id) {
+                        self.pending.lock().retain(|(id, _)| *id != entry.id);
+                        return ControlFlow::Continue(());
+                    }
+                    if let Some(skipped) = self.skip_under_pressure(entry, critical) {
+                        self.pending.lock().retain(|(id, _)| *id != entry.id);
+                        finished.borrow_mut()(entry, Duration::ZERO, Err(Box::new(skipped)));
+                        return ControlFlow::Continue(());
+                    }
+                    match &*entry.callback {
+                        Callback::Coordinator(coordinator) => self.run_coordinator(
+                            entry,
+                            coordinator,
+                            context,
+                            remaining,
+                            controlled,
+                            graceful,
+                            finished,
+                        ),
+                        _ => self.run_entry(entry, context, graceful, &mut *finished.borrow_mut()),
+                    }
+                })
+            })
+            .collect();
+        self.executor.0.execute(tasks);
+        self.pending.lock().clear();
+        summary.elapsed = self.clock.elapsed(started);
+        {
+            // Under the lock, so a trigger coalesced meanwhile is not lost
+            let triggers = self.triggers.lock();
+            summary.reasons = triggers
+                .as_ref()
+                .map(|(_, reasons)| reasons.clone())
+                .unwrap_or_default();
+            *self.last_summary.lock() = Some(summary);
+        }
+        self.final_callbacks.run(context, &self.clock);
+        self.finish_shutdown();
+        true
+    }
+
+    /// Returns the entries to run for `reason` in execution order, leaving
+    /// out those `run_groups_for` excludes and those `register_for` limited
+    /// to other reasons
+    pub(crate) fn dispatch_order<'a>(
+        &self,
+        entries: &'a [CallbackEntry],
+        reason: ShutdownReason,
+    ) -> Vec<&'a CallbackEntry> {
+        let mut ordered = execution_order(entries, self.execution_order);
+        let groups = self.group_policy.get(&reason).or_else(|| {
+            reason
+                .signal()
+                .and_then(|_| self.group_policy.get(&ShutdownReason::ANY_SIGNAL))
+        });
+        if let Some(groups) = groups {
+            ordered.retain(|entry| entry.group.is_some_and(|group| groups.contains(&group)));
+        }
+        ordered.retain(|entry| {
+            entry
+                .reasons
+                .as_ref()
+                .is_none_or(|reasons| reasons.iter().any(|selector| selector.selects(reason)))
+        });
+        ordered
+    }
+
+    /// Returns the error to report instead of running `entry` if it is below
+    /// the critical priority floor and the deadline is too close
+    fn skip_under_pressure(
+        &self,
+        entry: &CallbackEntry,
+        critical: Option<(i32, Instant, Instant)>,
+    ) -> Option<CallbackSkipped> {
+        let (floor, pressure_at, expires_at) = critical?;
+        let now = self.clock.now();
+        if entry.priority >= floor || now < pressure_at {
+            return None;
+        }
+        eprintln!(
+            "Skipping shutdown callback {} below the critical priority floor",
+            entry.label()
+        );
+        Some(CallbackSkipped {
+            remaining: expires_at.saturating_duration_since(now),
+        })
+    }
+
+    /// Runs a single entry for [`run_callbacks_with`](Self::run_callbacks_with)
+    /// and breaks if the remaining entries must be skipped
+    fn run_entry(
+        &self,
+        entry: &CallbackEntry,
+        context: ShutdownContext,
+        graceful: Option<(Duration, Instant)>,
+        finished: &mut impl FnMut(&CallbackEntry, Duration, Result<(), CallbackError>),
+    ) -> ControlFlow<()> {
+        let started = self.clock.now();
+        let result = match graceful {
+            Some((budget, until)) if entry.phase == Phase::Graceful => phase::invoke_until(
+                &entry.callback,
+                context,
+                budget,
+                until,
+                &self.clock,
+                &self.spawner,
+            ),
+            _ => std::panic::catch_unwind(AssertUnwindSafe(|| {
+                entry.callback.invoke(context, &self.clock, &self.spawner)
+            })),
+        };
+        self.complete_entry(entry, started, result, finished)
+    }
+
+    /// Runs a coordinator on the calling thread with a controller over the
+    /// `remaining` entries, then reports the callbacks it ran and its own
+    /// outcome to `finished`
+    #[allow(clippy::too_many_arguments)]
+    fn run_coordinator(
+        &self,
+        entry: &CallbackEntry,
+        coordinator: &CoordinatorCallback,
+        context: ShutdownContext,
+        remaining: &[&CallbackEntry],
+        controlled: &Mutex<HashSet<CallbackId>>,
+        graceful: Option<(Duration, Instant)>,
+        finished: &RefCell<impl FnMut(&CallbackEntry, Duration, Result<(), CallbackError>)>,
+    ) -> ControlFlow<()> {
+        let outcomes = RefCell::new(Vec::new());
+        let aborted = Cell::new(false);
+        let started = self.clock.now();
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let _in_callback = InCallback::enter();
+            let run = &|controlled| {
+                if aborted.get() {
+                    return false;
+                }
+                let flow =
+                    self.run_entry(controlled, context, graceful, &mut |_, elapsed, result| {
+                        outcomes.borrow_mut().push((controlled.id, elapsed, result));
+                    });
+                aborted.set(flow.is_break());
+                true
+            };
+            coordinator(
+                context,
+                &CallbackController::new(remaining, controlled, run, self.created.0),
+            );
+            Ok(())
+        }));
+
+        let finished = &mut *finished.borrow_mut();
+        for (id, elapsed, result) in outcomes.into_inner() {
+            if let Some(controlled) = remaining.iter().find(|entry| entry.id == id) {
+                finished(controlled, elapsed, result);
+            }
+        }
+        let flow = self.complete_entry(entry, started, result, finished);
+        if aborted.get() {
+            ControlFlow::Break(())
+        } else {
+            flow
+        }
+    }
+
+    /// Logs and records how `entry`, started at `started`, ended, and breaks
+    /// if the remaining entries must be skipped
+    fn complete_entry(
+        &self,
+        entry: &CallbackEntry,
+        started: Instant,
+        result: std::thread::Result<Result<(), CallbackError>>,
+        finished: &mut impl FnMut(&CallbackEntry, Duration, Result<(), CallbackError>),
+    ) -> ControlFlow<()> {
+        self.pending.lock().retain(|(id, _)| *id != entry.id);
+        let elapsed = self.clock.elapsed(started);
+
+        let (result, abort) = match result {
+            Ok(result) => {
+                trace::callback_finished(entry.id, entry.name.as_deref(), elapsed, &result);
+                (result, false)
+            }
+            Err(payload) => {
+                let panic = CallbackPanic::from_payload(&*payload);
+                trace::callback_panicked(entry.id, entry.name.as_deref(), elapsed, &panic);
+                eprintln!("Shutdown callback {} panicked: {}", entry.label(), panic);
+                let abort = self.panic_policy == PanicPolicy::AbortOnPanic;
+                (Err(Box::new(panic) as CallbackError), abort)
+            }
+        };
+        self.metrics.callback_completed(&result);
+        finished(entry, elapsed, result);
+
+        if abort {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// Claims the run-once flag and wakes the subscribers; returns false if the
+    /// callbacks already ran
+    pub(crate) fn begin_shutdown(&self, context: ShutdownContext) -> bool {
+        self.metrics.shutdown_triggered();
+        let claimed = !self.executed.swap(true, Ordering::SeqCst);
+        let queued = self.requested.lock().take().unwrap_or_default();
+        if !claimed {
+            self.coalesce(context.reason);
+            for reason in queued {
+                self.coalesce(reason);
+            }
+            return false;
+        }
+        let reasons = std::iter::once(context.reason).chain(queued).collect();
+        *self.triggers.lock() = Some((self.clock.now(), reasons));
+        *self.last_reason.lock() = Some(context.reason);
+        self.shutdown_signal.trigger(context);
+        true
+    }
+
+    /// Returns whether a signal needs a run of its own, or records it as
+    /// part of the run that is already requested or running
+    ///
+    /// Called on the signal dispatch thread, so it never blocks: a signal
+    /// arriving before the requested run claimed the callbacks, e.g. while a
+    /// pause is held, is queued and recorded once the run claims them.
+    #[cfg(unix)]
+    pub(crate) fn request_shutdown(&self, context: ShutdownContext) -> bool {
+        let mut requested = self.requested.lock();
+        if let Some(queued) = requested.as_mut() {
+            self.metrics.shutdown_triggered();
+            queued.push(context.reason);
+            return false;
+        }
+        if self.executed.load(Ordering::SeqCst) {
+            drop(requested);
+            self.metrics.shutdown_triggered();
+            self.coalesce(context.reason);
+            return false;
+        }
+        *requested = Some(Vec::new());
+        true
+    }
+
+    /// Records the reason of a trigger that arrived after the callbacks
+    /// started, if it came within the debounce window of the first one
+    fn coalesce(&self, reason: ShutdownReason) {
+        let mut triggers = self.triggers.lock();
+        let Some((first, reasons)) = triggers.as_mut() else {
+            return;
+        };
+        if self.clock.elapsed(*first) > self.debounce.unwrap_or(DEFAULT_DEBOUNCE) {
+            return;
+        }
+        reasons.push(reason);
+        // Too late for the summary of a run that already finished otherwise
+        if let Some(summary) = self.last_summary.lock().as_mut() {
+            summary.reasons.push(reason);
+        }
+    }
+
+    /// Returns the callbacks to run: the snapshot taken when the guard was
+    /// sealed, or else the registered ones under the read lock, which the run
+    /// releases once it is claimed
+    ///
+    /// The read lock waits out a concurrent registration instead of skipping
+    /// the callbacks. This never runs inside a signal handler, which only
+    /// forwards the signal to the dispatch thread, so blocking here is safe.
+    pub(crate) fn entries(&self) -> Entries<'_> {
+        match self.sealed.get() {
+            Some(sealed) => Entries::Sealed(sealed),
+            None => Entries::Live(self.callbacks.read()),
+        }
+    }
+
+    /// Snapshots the registered callbacks and refuses changes from now on
+    pub(crate) fn seal(&self) {
+        let callbacks = self.callbacks.read();
+        let _ = self.sealed.set(callbacks.iter().cloned().collect());
+    }
+
+    /// Removes the callback with the given id; returns whether it was found
+    pub(crate) fn unregister(&self, id: CallbackId) -> bool {
+        if self.sealed.get().is_some() {
+            return false;
+        }
+        let mut callbacks = self.callbacks.write();
+        let before = callbacks.len();
+        callbacks.retain(|entry| entry.id != id);
+        callbacks.len() != before
+    }
+
+    /// Wakes the threads in [`Registry::wait_for_shutdown`] once the
+    /// callbacks finished
+    pub(crate) fn finish_shutdown(&self) {
+        let (lock, condvar) = &self.finished;
+        *lock.lock() = true;
+        condvar.notify_all();
+    }
+
+    /// Runs the sleep callbacks with [`ShutdownReason::Suspend`], catching and
+    /// logging panics
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support"),
+        all(target_os = "macos", feature = "cocoa")
+    ))]
+    pub(crate) fn run_sleep_callbacks(&self, source: TriggerSource) {
+        let context = ShutdownContext::new(ShutdownReason::Suspend, source);
+        run_power_callbacks(&self.sleep_callbacks.read(), context, "Sleep");
+    }
+
+    /// Runs the resume callbacks with [`ShutdownReason::Resume`], catching and
+    /// logging panics
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support"),
+        all(target_os = "macos", feature = "cocoa")
+    ))]
+    pub(crate) fn run_resume_callbacks(&self, source: TriggerSource) {
+        let context = ShutdownContext::new(ShutdownReason::Resume, source);
+        run_power_callbacks(&self.resume_callbacks.read(), context, "Resume");
+    }
+
+    /// Runs the callbacks for a detected shutdown under the deadline watchdog,
+    /// once no [`PauseGuard`] is held
+    ///
+    /// The callbacks are only read once the pause ends, so the critical
+    /// section may still register or unregister them.
+    pub(crate) fn run_shutdown(&self, context: ShutdownContext) {
+        self.pause.wait();
+        let entries = self.entries();
+        let watchdog = self.deadline.read().map(|(deadline, action)| {
+            Watchdog::arm(
+                deadline,
+                action,
+                Arc::clone(&self.pending),
+                Arc::clone(&self.final_callbacks),
+                context,
+                self.clock.clone(),
+            )
+        });
+
+        let _boost = priority::PriorityBoost::raise(self.dispatch_priority);
+        // Once per shutdown, not for the triggers that follow the first
+        let first = !self.executed.load(Ordering::SeqCst);
+        let total = entries.len();
+        if let Some(template) = self.system_log.as_ref().filter(|_| first) {
+            system_log::log_shutdown_event(&system_log::render(template, context, total));
+        }
+        systemd::stopping();
+        let mut done = 0;
+        let ran = self.run_callbacks_with(entries, context, |entry, _, result| {
+            if let Err(e) = result {
+                eprintln!("Shutdown callback {} failed: {}", entry.label(), e);
+            }
+            done += 1;
+            systemd::progress(&entry.label(), done, total);
+        });
+
+        if let Some(watchdog) = watchdog {
+            watchdog.disarm();
+        }
+        if ran {
+            if let Some(summary) = self.last_summary.lock().as_ref() {
+                self.write_summary(context, summary);
+            }
+        }
+    }
+
+    /// Opens the summary file, if configured, so that writing it after the
+    /// callbacks does not depend on the file system accepting new opens
+    fn open_summary_file(&self) -> Result<(), ShutdownError> {
+        let Some(path) = &self.summary_path else {
+            return Ok(());
+        };
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                ShutdownError::Platform(format!(
+                    "Failed to open summary file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        *self.summary_file.lock() = Some(file);
+        Ok(())
+    }
+
+    /// Appends `summary` to the summary file and syncs it, before the process
+    /// may exit
+    fn write_summary(&self, context: ShutdownContext, summary: &ShutdownSummary) {
+        use std::io::Write;
+
+        let mut file = self.summary_file.lock();
+        let Some(file) = file.as_mut() else {
+            return;
+        };
+        let detected_at = chrono::DateTime::<chrono::Utc>::from(context.detected_at);
+        let result = writeln!(
+            file,
+            "{} shutdown ({}): {}",
+            detected_at.to_rfc3339(),
+            context.reason,
+            summary
+        )
+        .and_then(|()| file.sync_all());
+        if let Err(e) = result {
+            eprintln!("Failed to write the shutdown summary: {}", e);
+        }
+    }
+}
 
 /// Platform-specific shutdown monitoring implementation
 #[cfg(target_os = "macos")]
@@ -40,36 +1137,176 @@ mod platform;
 mod platform;
 
 /// Main structure for managing shutdown callbacks
+///
+/// A guard owns a [`Registry`] and runs it when the platform reports a
+/// shutdown. It dereferences to the registry, so the registration and
+/// execution methods are called on the guard directly.
+///
+/// Cloning a guard is cheap and yields another handle to the same guard: the
+/// clones share their callbacks, executed state and monitoring, so any of
+/// them can register callbacks or stop what another one started.
+///
+/// Dropping the last handle to a guard runs the callbacks only if it was
+/// built with [`ShutdownGuardBuilder::run_on_drop`].
+#[derive(Clone)]
 pub struct ShutdownGuard {
-    callbacks: Arc<RwLock<Vec<ShutdownCallback>>>,
+    registry: Registry,
+    /// Shared by the clones, which also counts the handles for `run_on_drop`
+    running: Arc<AtomicBool>,
+    signals: Vec<i32>,
+    run_on_drop: bool,
 }
 
 impl ShutdownGuard {
-    /// Creates a new ShutdownGuard instance
+    /// Creates a new ShutdownGuard instance with the default configuration
     pub fn new() -> Self {
+        ShutdownGuardBuilder::new().build()
+    }
+
+    /// Returns a builder for configuring a guard
+    pub fn builder() -> ShutdownGuardBuilder {
+        ShutdownGuardBuilder::new()
+    }
+
+    fn with_state(state: GuardState, signals: Vec<i32>, run_on_drop: bool) -> Self {
         Self {
-            callbacks: Arc::new(RwLock::new(Vec::new())),
+            registry: Registry::from_state(Arc::new(state)),
+            running: Arc::new(AtomicBool::new(false)),
+            signals,
+            run_on_drop,
         }
     }
 
-    /// Registers a callback to be executed before shutdown
+    /// Sets a callback invoked for every delivery of a monitored signal
     ///
-    /// # Arguments
+    /// The callback receives the raw signal number (the console control event
+    /// code on Windows) and runs even when the shutdown callbacks have already
+    /// been triggered, which makes it suitable for counting signals. It does not
+    /// affect the run-once semantics of the shutdown callbacks.
     ///
-    /// * `callback` - A boxed closure that will be called before shutdown
+    /// # Async-signal-safety
     ///
-    /// # Example
+    /// On Unix the signal handler itself only writes the signal number to a
+    /// pre-created pipe, which is async-signal-safe. The callback is run later
+    /// on a dedicated thread, so it is free to allocate or lock, but deliveries
+    /// are reported asynchronously and may lag slightly behind the signal.
+    /// On Windows the callback runs directly on the console control thread.
     ///
-    /// ```
-    /// use shutdown_guard::ShutdownGuard;
+    /// Only signals watched by the signal-handler backends are reported; the
+    /// Linux D-Bus backend does not install signal handlers.
+    pub fn on_raw_signal(&self, callback: RawSignalCallback) {
+        *self.registry.state.raw_signal_callback.write() = Some(callback);
+    }
+
+    /// Sets a callback invoked when the monitoring thread hits a fatal error
     ///
-    /// let guard = ShutdownGuard::new();
-    /// guard.register(Box::new(|| {
-    ///     println!("Cleanup in progress...");
-    /// }));
-    /// ```
-    pub fn register(&self, callback: ShutdownCallback) {
-        self.callbacks.write().push(callback);
+    /// This covers failures after [`start`](Self::start) returned, such as
+    /// losing the system bus connection with `dbus-support` or the hidden
+    /// window's message loop ending on Windows. Without a callback, these
+    /// errors are printed to stderr. The monitoring thread is shared, so its
+    /// errors reach the callback of every started guard. Calling this again
+    /// replaces the previous callback.
+    pub fn on_monitor_error(&self, callback: impl Fn(&ShutdownError) + Send + Sync + 'static) {
+        *self.registry.state.monitor_error_callback.write() = Some(Box::new(callback));
+    }
+
+    /// Registers a callback that runs every time the system is about to
+    /// suspend or hibernate
+    ///
+    /// Sleep callbacks are separate from the shutdown callbacks: they run on
+    /// every suspend, receive [`ShutdownReason::Suspend`] and do not affect
+    /// [`has_executed`](Registry::has_executed).
+    ///
+    /// On Linux they run on logind's `PrepareForSleep`. When any are
+    /// registered before [`start`](Self::start), the guard also takes a logind
+    /// sleep delay lock, so the machine waits for them (up to logind's
+    /// `InhibitDelayMaxSec`) before suspending. On Windows they run on
+    /// `WM_POWERBROADCAST` with `PBT_APMSUSPEND`, which allows about two
+    /// seconds before the system suspends. On macOS with the `cocoa` feature
+    /// they run on `NSWorkspaceWillSleepNotification`, which is only delivered
+    /// while the main run loop runs.
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support"),
+        all(target_os = "macos", feature = "cocoa")
+    ))]
+    pub fn on_sleep(&self, callback: ShutdownCallback) {
+        self.registry.state.sleep_callbacks.write().push(callback);
+    }
+
+    /// Registers a pair of callbacks around every suspend: `on_suspend` runs
+    /// like an [`on_sleep`](Self::on_sleep) callback and `on_resume` once the
+    /// system woke up again
+    ///
+    /// Use it for resources that do not survive sleep, e.g. to close a
+    /// database connection and reopen it after waking. `on_resume` receives
+    /// [`ShutdownReason::Resume`]. Like sleep callbacks, both are separate from
+    /// the shutdown callbacks.
+    ///
+    /// On Linux `on_resume` runs on logind's `PrepareForSleep(false)`, on
+    /// Windows on `PBT_APMRESUMEAUTOMATIC`, and on macOS with the `cocoa`
+    /// feature on `NSWorkspaceDidWakeNotification`.
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support"),
+        all(target_os = "macos", feature = "cocoa")
+    ))]
+    pub fn register_lifecycle(&self, on_suspend: ShutdownCallback, on_resume: ShutdownCallback) {
+        self.registry.state.sleep_callbacks.write().push(on_suspend);
+        self.registry.state.resume_callbacks.write().push(on_resume);
+    }
+
+    /// Sets a predicate that can veto the end of the Windows session
+    ///
+    /// The predicate runs when `WM_QUERYENDSESSION` arrives; returning `true`
+    /// asks Windows to cancel the shutdown or logoff, e.g. to let the user
+    /// save their work. Windows may ignore the veto for forced shutdowns.
+    ///
+    /// The callbacks only run on the committing `WM_ENDSESSION`. Note that
+    /// `WM_QUERYENDSESSION` can arrive without a following `WM_ENDSESSION`
+    /// (for example when another application vetoes), so the predicate must
+    /// not assume the session is really ending.
+    #[cfg(target_os = "windows")]
+    pub fn set_veto(&self, veto: VetoCallback) {
+        *self.registry.state.veto.write() = Some(veto);
+    }
+
+    /// Answers a shutdown the system announced and is holding open for the
+    /// application
+    ///
+    /// With [`ShutdownGuardBuilder::shutdown_decision_timeout`], a shutdown
+    /// announced by `WM_QUERYENDSESSION` on Windows or logind's
+    /// `PrepareForShutdown` on Linux waits that long for a decision before
+    /// the callbacks run; call this meanwhile from another thread, or from
+    /// the veto predicate. No answer counts as [`ShutdownDecision::Proceed`].
+    ///
+    /// [`ShutdownDecision::Abort`] makes Windows cancel the session end, like
+    /// a veto. logind cannot call a shutdown off, so on Linux `Abort` only
+    /// keeps its delay inhibitor lock and skips the callbacks for this
+    /// announcement; logind goes on once `InhibitDelayMaxSec` passed, and a
+    /// later trigger such as SIGTERM still runs them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ShutdownError::NoPendingShutdown`] unless a shutdown is
+    /// waiting for a decision, see
+    /// [`is_shutdown_pending`](Self::is_shutdown_pending).
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    pub fn respond_to_pending(&self, decision: ShutdownDecision) -> Result<(), ShutdownError> {
+        self.registry.state.pending_decision.respond(decision)
+    }
+
+    /// Returns whether an announced shutdown is waiting for
+    /// [`respond_to_pending`](Self::respond_to_pending)
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    pub fn is_shutdown_pending(&self) -> bool {
+        self.registry.state.pending_decision.is_pending()
     }
 
     /// Starts monitoring for shutdown events
@@ -79,76 +1316,1679 @@ impl ShutdownGuard {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if monitoring started successfully, or an error if
+    /// Returns a [`MonitorHandle`] for the monitoring thread if monitoring
+    /// started successfully, or an error if monitoring is already running or
     /// the platform-specific implementation failed to initialize.
-    pub fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let callbacks = Arc::clone(&self.callbacks);
-        platform::start_monitoring(callbacks).map_err(|e| e.into())
+    ///
+    /// On Linux this handles SIGTERM, SIGHUP and SIGPWR (reported as
+    /// [`ShutdownReason::PowerFailure`]), on macOS SIGTERM and SIGHUP, unless
+    /// other signals were chosen with [`ShutdownGuardBuilder::signals`]; see
+    /// also [`start_with_signals`](Self::start_with_signals). Ctrl+C only
+    /// runs the callbacks with
+    /// [`treat_interrupt_as_shutdown`](ShutdownGuardBuilder::treat_interrupt_as_shutdown).
+    ///
+    /// Several guards can be started in one process, e.g. by libraries that
+    /// don't know about each other, and every started guard runs its
+    /// callbacks on a shutdown. The handlers are installed once, by the first
+    /// guard, whose signals and platform settings apply to all of them, and
+    /// stay installed until the last guard stops.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ShutdownError::AlreadyStarted`] if this guard is running,
+    /// [`ShutdownError::UnsupportedSignal`] for a signal that cannot trigger
+    /// a shutdown, and otherwise the platform failure, e.g.
+    /// [`ShutdownError::DBusUnavailable`] for `LinuxBackend::DBus` without a
+    /// system bus, or `ShutdownError::NoBackendAvailable` for
+    /// `NoBackendPolicy::Error` without a login manager.
+    pub fn start(&self) -> Result<MonitorHandle, ShutdownError> {
+        self.start_with_signals(&self.signals)
     }
 
-    /// Executes all registered callbacks
+    /// Starts monitoring, installing handlers only for the given Unix signals
     ///
-    /// This method is typically called automatically when a shutdown is detected,
-    /// but can also be called manually if needed.
-    pub fn execute_callbacks(&self) {
-        let callbacks = self.callbacks.read();
-        for callback in callbacks.iter() {
-            callback();
+    /// Supported signals are SIGTERM, SIGINT, SIGHUP, SIGQUIT, SIGUSR1,
+    /// SIGUSR2 and, on Linux, SIGPWR; anything else, including SIGKILL and
+    /// SIGSTOP, is rejected before any handler is installed. The `dbus-support` backend installs
+    /// no signal handlers, so there the list is only validated. On Windows
+    /// the list must be empty.
+    ///
+    /// ```no_run
+    /// # #[cfg(unix)]
+    /// # {
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard
+    ///     .start_with_signals(&[libc::SIGTERM, libc::SIGQUIT])
+    ///     .expect("Failed to start shutdown monitoring");
+    /// # }
+    /// ```
+    pub fn start_with_signals(&self, signals: &[i32]) -> Result<MonitorHandle, ShutdownError> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err(ShutdownError::AlreadyStarted);
         }
+
+        let state = &self.registry.state;
+        let handle = state
+            .open_summary_file()
+            .and_then(|()| platform::start_monitoring(Arc::clone(state), signals))
+            .inspect_err(|_| {
+                self.running.store(false, Ordering::SeqCst);
+            })?;
+        if state.seal_on_start {
+            state.seal();
+        }
+        Ok(handle)
     }
 
-    /// Returns the number of registered callbacks
-    pub fn callback_count(&self) -> usize {
-        self.callbacks.read().len()
+    /// Checks that monitoring is actually active, e.g. for a health check
+    ///
+    /// [`start`](Self::start) only reports failures it sees right away. This
+    /// also catches the ones afterwards: on Unix, another library replacing
+    /// one of the signal handlers; with `dbus-support`, a lost system bus
+    /// connection that is still being re-established; on Windows, the hidden
+    /// window going away. Returns [`ShutdownError::NotRunning`] if the guard
+    /// was not started.
+    pub fn verify_active(&self) -> Result<(), ShutdownError> {
+        if !self.is_running() {
+            return Err(ShutdownError::NotRunning);
+        }
+        platform::verify_active()
     }
 
-    /// Clears all registered callbacks
-    pub fn clear(&self) {
-        self.callbacks.write().clear();
+    /// Returns the backend monitoring for shutdowns and what it watches, or
+    /// `None` if this guard is not running
+    ///
+    /// Since started guards share the backend, this is the one the first of
+    /// them chose, e.g. signal handlers after `LinuxBackend::Auto` found no
+    /// system bus. Worth logging at startup and including in bug reports
+    /// about callbacks that never ran.
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.start().expect("Failed to start shutdown monitoring");
+    /// if let Some(backend) = guard.active_backend() {
+    ///     println!("Shutdown monitoring uses {}", backend);
+    /// }
+    /// ```
+    pub fn active_backend(&self) -> Option<ActiveBackend> {
+        if !self.is_running() {
+            return None;
+        }
+        platform::active_backend()
     }
-}
 
-impl Default for ShutdownGuard {
-    fn default() -> Self {
-        Self::new()
+    /// Returns the signals whose handlers are installed, or none if this
+    /// guard is not running
+    ///
+    /// Starting only fails if none of the signals could be installed; a
+    /// signal whose `sigaction(2)` failed is logged and missing here, while
+    /// the others still trigger shutdown. The dispositions they replaced are
+    /// restored by [`stop`](Self::stop). Always empty on Windows, and with
+    /// `dbus-support` while systemd-logind is used instead of signals.
+    pub fn installed_signals(&self) -> Vec<i32> {
+        if !self.is_running() {
+            return Vec::new();
+        }
+        platform::installed_signals()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    /// Connects to systemd-logind for monitoring from the caller's event loop
+    ///
+    /// An alternative to [`start`](Self::start) for applications that already
+    /// run an event loop and would rather not dedicate a thread to D-Bus; see
+    /// [`LogindMonitor`]. Use one or the other, not both.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::ShutdownGuard;
+    /// use std::os::fd::AsRawFd;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let monitor = guard.logind_monitor()?;
+    /// let fd = monitor.as_raw_fd();
+    /// // Whenever the event loop reports `fd` as readable:
+    /// if let Some(reason) = monitor.poll_once()? {
+    ///     println!("Shutting down ({})", reason);
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    pub fn logind_monitor(&self) -> Result<LogindMonitor, ShutdownError> {
+        LogindMonitor::new(Arc::clone(&self.registry.state))
+    }
 
-    #[test]
-    fn test_register_callback() {
-        let guard = ShutdownGuard::new();
-        assert_eq!(guard.callback_count(), 0);
+    /// Delivers a real shutdown trigger to this process, so a test exercises
+    /// the platform handlers rather than just the registry
+    ///
+    /// On Unix this raises SIGTERM, on Windows it posts `WM_QUERYENDSESSION`
+    /// and `WM_ENDSESSION` to the hidden window. The guard must be started
+    /// and handle that trigger; the callbacks run asynchronously, so wait
+    /// with [`wait_for_shutdown`](Registry::wait_for_shutdown). Unless the
+    /// guard was built with `ExitBehavior::DoNotExit`, Linux and macOS end
+    /// the process afterwards, and with the D-Bus backend, which installs no
+    /// signal handlers, SIGTERM terminates it right away.
+    ///
+    /// Only available in this crate's tests and with the `testing` feature.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn force_shutdown(&self) {
+        platform::force_shutdown();
+    }
 
-        guard.register(Box::new(|| println!("Test callback")));
-        assert_eq!(guard.callback_count(), 1);
+    /// Returns whether monitoring is running, i.e. [`start`](Self::start)
+    /// succeeded and [`stop`](Self::stop) has not been called since
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
     }
 
-    #[test]
-    fn test_execute_callbacks() {
-        let guard = ShutdownGuard::new();
-        let executed = Arc::new(AtomicBool::new(false));
-        let executed_clone = Arc::clone(&executed);
+    /// Stops monitoring for shutdown events
+    ///
+    /// On Linux and macOS the signal dispositions that were in place before
+    /// [`start`](Self::start) are restored, failing with
+    /// [`ShutdownError::Platform`] if one cannot be; on Windows the console
+    /// control handler is removed and the hidden window is closed, waiting a
+    /// few seconds for its thread to exit before failing with
+    /// [`ShutdownError::StopTimedOut`]; with `dbus-support` the monitoring
+    /// thread exits within a second. Registered callbacks are kept, and `start` can be
+    /// called again afterwards. Does nothing if monitoring is not running,
+    /// except that heartbeats are always stopped, and the sockets opened by
+    /// `listen_on_socket` closed and their files removed.
+    pub fn stop(&self) -> Result<(), ShutdownError> {
+        for heartbeat in std::mem::take(&mut *self.registry.state.heartbeats.lock()) {
+            heartbeat.stop();
+        }
+        #[cfg(all(unix, feature = "admin-socket"))]
+        for socket in std::mem::take(&mut *self.registry.state.admin_sockets.lock()) {
+            socket.close();
+        }
 
-        guard.register(Box::new(move || {
-            executed_clone.store(true, Ordering::SeqCst);
-        }));
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
 
-        guard.execute_callbacks();
-        assert!(executed.load(Ordering::SeqCst));
+        platform::stop_monitoring(&self.registry.state)
     }
 
-    #[test]
-    fn test_clear_callbacks() {
-        let guard = ShutdownGuard::new();
-        guard.register(Box::new(|| println!("Test")));
-        assert_eq!(guard.callback_count(), 1);
+    /// Puts a child forked after [`start`](Self::start) back into a state
+    /// where `start` works again
+    ///
+    /// The child inherits the signal handlers and the global state but not
+    /// the monitoring thread, so without this a shutdown signal is swallowed
+    /// and `start` reports [`ShutdownError::AlreadyStarted`]. Call it in the
+    /// child first thing after `fork(2)`: it restores the signal
+    /// dispositions from before `start`, closes the inherited signal pipe,
+    /// forgets every guard started in the parent and marks this guard as
+    /// not running with its callbacks not yet executed. Other guards started
+    /// in the parent still report running until they are reset or stopped.
+    ///
+    /// This is not registered with `pthread_atfork`: a lock another thread
+    /// of the parent held at the time of the fork stays locked in the child,
+    /// so only call this if no other thread was starting, stopping or
+    /// running the callbacks of a guard then. A [`MonitorHandle`] from the
+    /// parent must not be joined in the child.
+    #[cfg(unix)]
+    pub fn reset_after_fork(&self) -> Result<(), ShutdownError> {
+        self.running.store(false, Ordering::SeqCst);
+        self.registry.reset_executed();
+        // The thread that would have run a requested shutdown is gone
+        *self.registry.state.requested.lock() = None;
+        platform::reset_after_fork()
+    }
 
-        guard.clear();
-        assert_eq!(guard.callback_count(), 0);
+    /// Starts monitoring in a process image that replaced itself with
+    /// `execve(2)`, e.g. a daemon upgrading in place
+    ///
+    /// Nothing of the old image's guard survives the exec: its callbacks and
+    /// state are gone with its memory, the signal pipe is closed on exec, and
+    /// every caught signal is reset to its default action, so SIGTERM ends
+    /// the new image outright until it starts a guard of its own. The signal
+    /// mask is inherited, though, and a signal the old image had blocked,
+    /// e.g. while it handed its sockets over, would never reach the new
+    /// handlers. This unblocks the guard's signals in the calling thread,
+    /// which the monitoring thread inherits its mask from, then starts like
+    /// [`start`](Self::start). Register the callbacks first, and call it
+    /// early in `main`.
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// // In the freshly exec'd binary
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|_| println!("Closing the listener")))?;
+    /// guard.reinstall_after_exec().expect("Failed to start shutdown monitoring");
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    #[cfg(unix)]
+    pub fn reinstall_after_exec(&self) -> Result<MonitorHandle, ShutdownError> {
+        platform::unblock_signals(&self.signals)?;
+        self.start()
+    }
+
+    /// Starts monitoring, blocks until a shutdown ran the callbacks, then
+    /// stops and returns how they went
+    ///
+    /// The whole lifecycle of a simple CLI in one call, for applications that
+    /// would rather return from `main` with an exit code of their choice than
+    /// have the process end right after the callbacks. On Unix this requires
+    /// a guard built with `ExitBehavior::DoNotExit`, and no other started
+    /// guard that exits, since the first guard's exit behavior applies to
+    /// all of them. On Windows the system may still end the process once a
+    /// console event or `WM_ENDSESSION` was handled.
+    ///
+    /// Returns an empty summary if the callbacks ran through
+    /// [`execute_callbacks_parallel`](Registry::execute_callbacks_parallel).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ShutdownError::ExitsAfterShutdown`] for a guard that exits,
+    /// and otherwise the errors of [`start`](Self::start).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::{ExitBehavior, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::builder()
+    ///     .exit_behavior(ExitBehavior::DoNotExit)
+    ///     .build();
+    /// guard.register(Box::new(|_| println!("Cleaning up...")))?;
+    ///
+    /// let summary = guard.run().expect("Failed to start shutdown monitoring");
+    /// println!("{}", summary);
+    /// std::process::exit(if summary.is_success() { 0 } else { 1 });
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn run(self) -> Result<ShutdownSummary, ShutdownError> {
+        #[cfg(unix)]
+        if self.registry.state.exit_behavior != ExitBehavior::DoNotExit {
+            return Err(ShutdownError::ExitsAfterShutdown);
+        }
+
+        self.start()?;
+        self.wait_for_shutdown();
+        if let Err(e) = self.stop() {
+            eprintln!("Failed to stop shutdown monitoring: {}", e);
+        }
+        Ok(self
+            .registry
+            .state
+            .last_summary
+            .lock()
+            .clone()
+            .unwrap_or_default())
+    }
+
+    /// Moves the callbacks of `other` into this guard, consuming it, so that
+    /// one guard monitors and runs both sets
+    ///
+    /// For composing libraries that each hand out a configured guard: adopt
+    /// their guards before starting this one, and start only this one. The
+    /// callbacks keep their phases, priorities, names, groups and
+    /// dependencies, and count as registered when adopted, by this guard's
+    /// clock, after this guard's callbacks; they get new ids, so the ids
+    /// `other` returned no longer unregister them. `other`'s own configuration, such as its deadline or exit
+    /// behavior, is dropped along with it, and clones of `other` are left
+    /// without callbacks.
+    ///
+    /// Adopting after [`start`](Self::start) follows the same rules as
+    /// [`register`](Registry::register): the callbacks take part in a
+    /// shutdown only if they were adopted before it began.
+    ///
+    /// # Errors
+    ///
+    /// Fails like `register`, adopting none of the callbacks, if this guard
+    /// is sealed or they do not fit under its maximum number of callbacks.
+    /// They are then left with clones of `other`, if there are any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let database = ShutdownGuard::new();
+    /// database.register(Box::new(|_| println!("Closing the database...")))?;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|_| println!("Flushing the logs...")))?;
+    /// guard.adopt(database)?;
+    /// assert_eq!(guard.callback_count(), 2);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn adopt(&self, other: ShutdownGuard) -> Result<(), RegisterError> {
+        self.registry.adopt_callbacks(&other.registry)
+    }
+
+    /// Runs the callbacks once, then stops monitoring so nothing fires again
+    ///
+    /// For a shutdown the application starts itself, e.g. to restart a
+    /// subsystem. The callbacks run like
+    /// [`execute_callbacks_with_summary`](Registry::execute_callbacks_with_summary),
+    /// with the same ordering, panic policy and timeouts, and see
+    /// [`TriggerSource::Manual`]; the process does not exit. The handlers are
+    /// then removed like [`stop`](Self::stop) does, and the callbacks stay
+    /// marked as executed, so a signal that arrives meanwhile does not run
+    /// them again. Unlike `execute_callbacks`, which leaves monitoring
+    /// running, and `stop`, which runs nothing, this does both.
+    ///
+    /// Returns an empty summary if the callbacks already ran. A failure to
+    /// stop is logged, as monitoring was then already torn down as far as it
+    /// could be.
+    pub fn drain_and_stop(&self) -> ShutdownSummary {
+        let summary = self.execute_callbacks_with_summary();
+        if let Err(e) = self.stop() {
+            eprintln!("Failed to stop shutdown monitoring: {}", e);
+        }
+        summary
+    }
+}
+
+impl Deref for ShutdownGuard {
+    type Target = Registry;
+
+    fn deref(&self) -> &Registry {
+        &self.registry
+    }
+}
+
+impl Default for ShutdownGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shows the state of the guard, not the registered closures
+impl std::fmt::Debug for ShutdownGuard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownGuard")
+            .field("callback_count", &self.callback_count())
+            .field("running", &self.is_running())
+            .field("active_backend", &self.active_backend())
+            .field("shutdown_detected", &self.is_shutdown_in_progress())
+            .finish()
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        // Other clones keep using the callbacks
+        if self.run_on_drop && Arc::get_mut(&mut self.running).is_some() {
+            // Does nothing if a shutdown or a manual call already ran them
+            self.registry.execute(ShutdownContext::new(
+                ShutdownReason::Unknown,
+                TriggerSource::Drop,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_callback() {
+        let guard = ShutdownGuard::new();
+        assert_eq!(guard.callback_count(), 0);
+
+        guard
+            .register(Box::new(|_| println!("Test callback")))
+            .unwrap();
+        assert_eq!(guard.callback_count(), 1);
+    }
+
+    #[test]
+    fn test_execute_callbacks() {
+        let guard = ShutdownGuard::new();
+        let executed = Arc::new(AtomicBool::new(false));
+        let executed_clone = Arc::clone(&executed);
+
+        guard
+            .register(Box::new(move |_| {
+                executed_clone.store(true, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        guard.execute_callbacks();
+        assert!(executed.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_clear_callbacks() {
+        let guard = ShutdownGuard::new();
+        guard.register(Box::new(|_| println!("Test"))).unwrap();
+        assert_eq!(guard.callback_count(), 1);
+
+        guard.clear().unwrap();
+        assert_eq!(guard.callback_count(), 0);
+    }
+
+    #[test]
+    fn test_unregister_callback() {
+        let guard = ShutdownGuard::new();
+        let removed = Arc::new(AtomicBool::new(false));
+        let kept = Arc::new(AtomicBool::new(false));
+        let removed_clone = Arc::clone(&removed);
+        let kept_clone = Arc::clone(&kept);
+
+        let id = guard
+            .register(Box::new(move |_| {
+                removed_clone.store(true, Ordering::SeqCst)
+            }))
+            .unwrap();
+        guard
+            .register(Box::new(move |_| kept_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+
+        assert!(guard.unregister(id));
+        assert!(!guard.unregister(id));
+        assert_eq!(guard.callback_count(), 1);
+
+        guard.execute_callbacks();
+        assert!(!removed.load(Ordering::SeqCst));
+        assert!(kept.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_callbacks_receive_reason() {
+        let guard = ShutdownGuard::new();
+        let received = Arc::new(RwLock::new(None));
+        let received_clone = Arc::clone(&received);
+
+        guard
+            .register(Box::new(move |context| {
+                assert!(context.is_manual());
+                *received_clone.write() = Some(context.reason);
+            }))
+            .unwrap();
+
+        guard.execute_callbacks_with_reason(ShutdownReason::Logout);
+        assert_eq!(*received.read(), Some(ShutdownReason::Logout));
+
+        guard.reset_executed();
+        guard.execute_callbacks();
+        assert_eq!(*received.read(), Some(ShutdownReason::Unknown));
+    }
+
+    #[test]
+    fn test_callbacks_see_remaining_deadline() {
+        let guard = ShutdownGuard::new();
+        let remaining = Arc::new(RwLock::new(None));
+        let remaining_clone = Arc::clone(&remaining);
+        guard
+            .register(Box::new(move |context| {
+                *remaining_clone.write() = context.deadline.remaining();
+            }))
+            .unwrap();
+
+        guard.execute_callbacks();
+        assert_eq!(*remaining.read(), None);
+
+        guard.reset_executed();
+        guard.set_deadline(
+            Duration::from_secs(10),
+            DeadlineAction::LogAndContinue {
+                absolute_cap: Duration::from_secs(60),
+            },
+        );
+        guard.execute_callbacks();
+        let remaining = remaining.read().unwrap();
+        assert!(remaining > Duration::from_secs(5) && remaining <= Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_execute_callbacks_with_summary() {
+        let guard = ShutdownGuard::new();
+        let ok = guard.register_named("ok", Box::new(|_| {})).unwrap();
+        let failed = guard
+            .register_fallible(Box::new(|_| Err("flush failed".into())))
+            .unwrap();
+        let panicked = guard.register(Box::new(|_| panic!("boom"))).unwrap();
+        let timed_out = guard
+            .register_with_timeout(
+                Duration::from_millis(20),
+                Box::new(|_| std::thread::sleep(Duration::from_millis(500))),
+            )
+            .unwrap();
+
+        let summary = guard.execute_callbacks_with_summary();
+        let outcomes: Vec<_> = summary
+            .callbacks
+            .iter()
+            .map(|callback| (callback.id, callback.outcome.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (ok, CallbackOutcome::Ok),
+                (failed, CallbackOutcome::Failed("flush failed".to_string())),
+                (panicked, CallbackOutcome::Panicked("boom".to_string())),
+                (timed_out, CallbackOutcome::TimedOut),
+            ]
+        );
+        assert_eq!(summary.callbacks[0].name.as_deref(), Some("ok"));
+        assert!(!summary.is_success());
+        assert!(summary.elapsed >= summary.callbacks[3].duration);
+
+        assert_eq!(
+            guard.execute_callbacks_with_summary(),
+            ShutdownSummary::default()
+        );
+    }
+
+    #[test]
+    fn test_execute_callbacks_collect() {
+        let guard = ShutdownGuard::new();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+
+        let failing = guard
+            .register_fallible(Box::new(|_| Err("flush failed".into())))
+            .unwrap();
+        guard.register_fallible(Box::new(|_| Ok(()))).unwrap();
+        guard
+            .register(Box::new(move |_| ran_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+
+        let failures = guard.execute_callbacks_collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, failing);
+        assert_eq!(failures[0].1.to_string(), "flush failed");
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_register_with_timeout_abandons_hung_callback() {
+        let guard = ShutdownGuard::new();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+
+        let hung = guard
+            .register_with_timeout(
+                Duration::from_millis(50),
+                Box::new(|_| std::thread::sleep(Duration::from_secs(2))),
+            )
+            .unwrap();
+        guard
+            .register(Box::new(move |_| ran_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let failures = guard.execute_callbacks_collect();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(ran.load(Ordering::SeqCst));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, hung);
+        assert_eq!(
+            failures[0].1.downcast_ref::<CallbackTimeout>(),
+            Some(&CallbackTimeout {
+                timeout: Duration::from_millis(50)
+            })
+        );
+    }
+
+    #[test]
+    fn test_mock_clock_times_out_without_waiting() {
+        let clock = clock::MockClock::new();
+        let guard = ShutdownGuard::builder().clock(clock.clone()).build();
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        guard
+            .register_with_timeout(
+                Duration::from_secs(60),
+                Box::new(move |_| {
+                    let _ = started_tx.send(());
+                    let _ = release_rx.lock().recv();
+                }),
+            )
+            .unwrap();
+
+        let advancer = std::thread::spawn(move || {
+            started_rx.recv().unwrap();
+            clock.advance(Duration::from_secs(61));
+        });
+        let started = Instant::now();
+        let summary = guard.execute_callbacks_with_summary();
+        advancer.join().unwrap();
+        drop(release_tx);
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(summary.callbacks[0].outcome, CallbackOutcome::TimedOut);
+        assert_eq!(summary.callbacks[0].duration, Duration::from_secs(61));
+    }
+
+    #[test]
+    fn test_only_critical_callbacks_run_close_to_the_deadline() {
+        let clock = clock::MockClock::new();
+        let guard = ShutdownGuard::builder()
+            .clock(clock.clone())
+            .deadline(Duration::from_secs(5), DeadlineAction::ForceExit(1))
+            .critical_priority_floor(100)
+            .budget_threshold(Duration::from_secs(2))
+            .build();
+        guard
+            .register_with_priority(
+                200,
+                Box::new(move |_| clock.advance(Duration::from_secs(4))),
+            )
+            .unwrap();
+        let wal = guard.register_with_priority(100, Box::new(|_| {})).unwrap();
+        let temp = guard.register(Box::new(|_| {})).unwrap();
+
+        let summary = guard.execute_callbacks_with_summary();
+        assert_eq!(summary.callbacks.len(), 3);
+        assert_eq!(summary.callbacks[1].id, wal);
+        assert_eq!(summary.callbacks[1].outcome, CallbackOutcome::Ok);
+        assert_eq!(summary.callbacks[2].id, temp);
+        assert_eq!(summary.callbacks[2].outcome, CallbackOutcome::Skipped);
+        assert!(!summary.is_success());
+    }
+
+    #[test]
+    fn test_triggers_within_the_debounce_window_are_coalesced() {
+        let clock = clock::MockClock::new();
+        let guard = ShutdownGuard::builder()
+            .clock(clock.clone())
+            .debounce(Duration::from_millis(500))
+            .build();
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+        guard
+            .register(Box::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        let context = |reason| ShutdownContext::new(reason, TriggerSource::Manual);
+        guard.execute(context(ShutdownReason::Signal(15)));
+        clock.advance(Duration::from_millis(100));
+        guard.execute(context(ShutdownReason::Signal(1)));
+        clock.advance(Duration::from_secs(1));
+        guard.execute(context(ShutdownReason::PowerOff));
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        let summary = guard.registry.state.last_summary.lock().clone().unwrap();
+        assert_eq!(
+            summary.reasons,
+            vec![ShutdownReason::Signal(15), ShutdownReason::Signal(1)]
+        );
+    }
+
+    #[test]
+    fn test_notify_on_shutdown() {
+        let guard = ShutdownGuard::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        guard.notify_on_shutdown(tx).unwrap();
+
+        guard.execute_callbacks_with_reason(ShutdownReason::Reboot);
+        assert_eq!(rx.recv().unwrap(), ShutdownReason::Reboot);
+
+        // A dropped receiver must not turn into a panic
+        drop(rx);
+        guard.reset_executed();
+        guard.execute_callbacks_with_reason(ShutdownReason::Reboot);
+    }
+
+    #[test]
+    fn test_inspect_lists_callbacks_in_execution_order() {
+        let guard = ShutdownGuard::new();
+        let first = guard.register(Box::new(|_| {})).unwrap();
+        let second = guard.register(Box::new(|_| {})).unwrap();
+        let urgent = guard.register_with_priority(10, Box::new(|_| {})).unwrap();
+
+        let ids: Vec<CallbackId> = guard.inspect().iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![urgent, first, second]);
+    }
+
+    #[test]
+    fn test_inspect_reports_names_and_groups() {
+        let guard = ShutdownGuard::new();
+        let cache = GroupId::new();
+        guard.register_named("flush-wal", Box::new(|_| {})).unwrap();
+        guard.register_in_group(cache, Box::new(|_| {})).unwrap();
+
+        let info = guard.inspect();
+        assert_eq!(info[0].name.as_deref(), Some("flush-wal"));
+        assert_eq!(info[0].group, None);
+        assert_eq!(info[1].group, Some(cache));
+    }
+
+    #[test]
+    fn test_inspect_reports_registration_times() {
+        let clock = clock::MockClock::new();
+        let guard = ShutdownGuard::builder().clock(clock.clone()).build();
+        clock.advance(Duration::from_secs(1));
+        guard.register(Box::new(|_| {})).unwrap();
+        clock.advance(Duration::from_secs(2));
+        guard.register_with_priority(10, Box::new(|_| {})).unwrap();
+
+        let times: Vec<(i32, Duration)> = guard
+            .inspect()
+            .iter()
+            .map(|info| (info.priority, info.registered_at))
+            .collect();
+        assert_eq!(
+            times,
+            vec![(10, Duration::from_secs(3)), (0, Duration::from_secs(1))]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_inspect_json() {
+        let clock = clock::MockClock::new();
+        let guard = ShutdownGuard::builder().clock(clock.clone()).build();
+        guard.register(Box::new(|_| {})).unwrap();
+        clock.advance(Duration::from_millis(2));
+        guard.register_with_priority(5, Box::new(|_| {})).unwrap();
+
+        assert_eq!(
+            guard.inspect_json(),
+            concat!(
+                r#"[{"id":1,"priority":5,"registered_at":{"secs":0,"nanos":2000000}},"#,
+                r#"{"id":0,"priority":0,"registered_at":{"secs":0,"nanos":0}}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_priority_ordering() {
+        let guard = ShutdownGuard::new();
+        let order = Arc::new(RwLock::new(Vec::new()));
+
+        for (priority, label) in [
+            (0, "close db"),
+            (100, "stop work"),
+            (50, "flush"),
+            (0, "log"),
+        ] {
+            let order = Arc::clone(&order);
+            guard
+                .register_with_priority(priority, Box::new(move |_| order.write().push(label)))
+                .unwrap();
+        }
+
+        guard.execute_callbacks();
+        assert_eq!(*order.read(), vec!["stop work", "flush", "close db", "log"]);
+    }
+
+    #[test]
+    fn test_seal_on_start_fixes_callbacks() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let guard = ShutdownGuard::builder().seal_on_start(true).build();
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+        let id = guard
+            .register(Box::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+        assert!(!guard.is_sealed());
+
+        guard.start().unwrap();
+        assert!(guard.is_sealed());
+        assert_eq!(guard.register(Box::new(|_| {})), Err(RegisterError::Sealed));
+        assert_eq!(
+            guard.try_register(Box::new(|_| {})),
+            Err(RegisterError::Sealed)
+        );
+        assert_eq!(
+            guard.register_final(Box::new(|_| {})),
+            Err(RegisterError::Sealed)
+        );
+        assert!(!guard.unregister(id));
+        assert_eq!(guard.clear(), Err(RegisterError::Sealed));
+
+        guard.simulate_shutdown(ShutdownReason::Unknown);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        guard.stop().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_force_shutdown_goes_through_the_signal_handler() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder().exit_behavior(ExitBehavior::DoNotExit);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        guard.register(Box::new(|_| {})).unwrap();
+
+        guard.start().unwrap();
+        guard.force_shutdown();
+        let context = guard.wait_for_shutdown();
+        assert_eq!(context.source, TriggerSource::UnixSignal(libc::SIGTERM));
+        guard.stop().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_installed_signals_until_stopped() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder().exit_behavior(ExitBehavior::DoNotExit);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        assert!(guard.installed_signals().is_empty());
+
+        guard
+            .start_with_signals(&[libc::SIGUSR2, libc::SIGHUP])
+            .unwrap();
+        assert_eq!(guard.installed_signals(), vec![libc::SIGUSR2, libc::SIGHUP]);
+        guard.stop().unwrap();
+        assert!(guard.installed_signals().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reinstall_after_exec_unblocks_signals() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        // The mask is per thread, so block the signal on a thread of its own
+        std::thread::spawn(|| {
+            let builder = ShutdownGuard::builder()
+                .exit_behavior(ExitBehavior::DoNotExit)
+                .signals(&[libc::SIGUSR2]);
+            #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+            let builder = builder.linux_backend(LinuxBackend::Signals);
+            let guard = builder.build();
+
+            let blocked = || unsafe {
+                let mut current: libc::sigset_t = std::mem::zeroed();
+                libc::pthread_sigmask(libc::SIG_BLOCK, std::ptr::null(), &mut current);
+                libc::sigismember(&current, libc::SIGUSR2) == 1
+            };
+            unsafe {
+                let mut set: libc::sigset_t = std::mem::zeroed();
+                libc::sigemptyset(&mut set);
+                libc::sigaddset(&mut set, libc::SIGUSR2);
+                libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+            }
+            assert!(blocked());
+
+            guard.reinstall_after_exec().unwrap();
+            assert!(!blocked());
+            assert_eq!(guard.installed_signals(), vec![libc::SIGUSR2]);
+            guard.stop().unwrap();
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    #[test]
+    fn test_lifecycle_callbacks_run_around_sleep() {
+        let guard = ShutdownGuard::new();
+        let reasons = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let (suspend, resume) = (Arc::clone(&reasons), Arc::clone(&reasons));
+        guard.register_lifecycle(
+            Box::new(move |context| suspend.lock().push(context.reason)),
+            Box::new(move |context| resume.lock().push(context.reason)),
+        );
+
+        let state = &guard.registry.state;
+        state.run_sleep_callbacks(TriggerSource::DBusSignal);
+        state.run_resume_callbacks(TriggerSource::DBusSignal);
+        assert_eq!(
+            *reasons.lock(),
+            vec![ShutdownReason::Suspend, ShutdownReason::Resume]
+        );
+        assert!(!guard.has_executed());
+    }
+
+    #[test]
+    fn test_summary_file_is_written_after_the_callbacks() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let path =
+            std::env::temp_dir().join(format!("shutdown-guard-summary-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let missing = ShutdownGuard::builder()
+            .summary_path(path.join("missing"))
+            .build();
+        assert!(matches!(missing.start(), Err(ShutdownError::Platform(_))));
+        assert!(!missing.is_running());
+
+        let builder = ShutdownGuard::builder().summary_path(&path);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        guard.register_named("flush-wal", Box::new(|_| {})).unwrap();
+        guard.start().unwrap();
+        guard.execute(ShutdownContext::new(
+            ShutdownReason::PowerOff,
+            TriggerSource::Manual,
+        ));
+        guard.stop().unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains("shutdown (power off): 1 callbacks in"));
+        assert!(written.contains("\n  flush-wal: ok in "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_active_detects_a_replaced_handler() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder();
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        assert_eq!(guard.verify_active(), Err(ShutdownError::NotRunning));
+
+        guard.start().unwrap();
+        assert_eq!(guard.verify_active(), Ok(()));
+        unsafe {
+            libc::signal(libc::SIGHUP, libc::SIG_IGN);
+        }
+        assert!(matches!(
+            guard.verify_active(),
+            Err(ShutdownError::MonitorFailed(_))
+        ));
+        guard.stop().unwrap();
+    }
+
+    #[test]
+    fn test_clones_share_the_registry() {
+        let guard = ShutdownGuard::builder().run_on_drop(true).build();
+        let clone = guard.clone();
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let count_clone = Arc::clone(&count);
+        clone
+            .register(Box::new(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+        assert_eq!(guard.callback_count(), 1);
+
+        // Only the last handle runs the callbacks on drop
+        drop(clone);
+        assert!(!guard.has_executed());
+        drop(guard);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_reasons_override_the_built_in_mapping() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder()
+            .exit_behavior(ExitBehavior::DoNotExit)
+            .signal_reasons([(libc::SIGTERM, ShutdownReason::Reboot)].into());
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+
+        guard.start().unwrap();
+        guard.force_shutdown();
+        let context = guard.wait_for_shutdown();
+        assert_eq!(context.reason, ShutdownReason::Reboot);
+        assert_eq!(context.source, TriggerSource::UnixSignal(libc::SIGTERM));
+        guard.stop().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_interrupt_is_a_shutdown_only_when_enabled() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        assert!(!ShutdownGuard::new().signals.contains(&libc::SIGINT));
+
+        let builder = ShutdownGuard::builder()
+            .exit_behavior(ExitBehavior::DoNotExit)
+            .treat_interrupt_as_shutdown(true);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+
+        guard.start().unwrap();
+        unsafe {
+            libc::raise(libc::SIGINT);
+        }
+        let context = guard.wait_for_shutdown();
+        assert_eq!(context.reason, ShutdownReason::Interrupt);
+        assert_eq!(context.source, TriggerSource::UnixSignal(libc::SIGINT));
+        guard.stop().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_returns_the_summary_after_a_signal() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        assert_eq!(
+            ShutdownGuard::new().run().unwrap_err(),
+            ShutdownError::ExitsAfterShutdown
+        );
+
+        let builder = ShutdownGuard::builder().exit_behavior(ExitBehavior::DoNotExit);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        let flush = guard.register_named("flush", Box::new(|_| {})).unwrap();
+
+        let handle = guard.clone();
+        let runner = std::thread::spawn(move || handle.run());
+        // Started once the guard is registered with the backend
+        while !platform::started_guards()
+            .iter()
+            .any(|state| Arc::ptr_eq(state, &guard.registry.state))
+        {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        guard.force_shutdown();
+
+        let summary = runner.join().unwrap().unwrap();
+        assert_eq!(summary.callbacks.len(), 1);
+        assert_eq!(summary.callbacks[0].id, flush);
+        assert!(summary.is_success());
+        assert!(!guard.is_running());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reset_after_fork_allows_a_fresh_start() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let handler = || unsafe {
+            let mut current: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGTERM, std::ptr::null(), &mut current);
+            current.sa_sigaction
+        };
+        let before = handler();
+        let builder = ShutdownGuard::builder();
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        guard.start().unwrap();
+        guard.execute_callbacks();
+
+        // What a forked child would do before using the guard
+        guard.reset_after_fork().unwrap();
+        assert_eq!(handler(), before);
+        assert!(!guard.is_running());
+        assert!(!guard.has_executed());
+        assert!(platform::started_guards().is_empty());
+        assert_eq!(guard.active_backend(), None);
+
+        guard.start().unwrap();
+        assert_ne!(handler(), before);
+        guard.stop().unwrap();
+        assert_eq!(handler(), before);
+    }
+
+    #[test]
+    fn test_drain_and_stop_runs_the_callbacks_and_stops() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder();
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        let flush = guard.register_named("flush", Box::new(|_| {})).unwrap();
+        guard.start().unwrap();
+
+        let summary = guard.drain_and_stop();
+        assert_eq!(summary.callbacks.len(), 1);
+        assert_eq!(summary.callbacks[0].id, flush);
+        assert!(guard.has_executed());
+        assert!(!guard.is_running());
+        assert!(!platform::started_guards()
+            .iter()
+            .any(|state| Arc::ptr_eq(state, &guard.registry.state)));
+
+        // Already drained
+        assert!(guard.drain_and_stop().callbacks.is_empty());
+    }
+
+    #[test]
+    fn test_debug_shows_the_guard_state() {
+        let guard = ShutdownGuard::new();
+        guard.register(Box::new(|_| {})).unwrap();
+        assert_eq!(
+            format!("{:?}", guard),
+            "ShutdownGuard { callback_count: 1, running: false, active_backend: None, shutdown_detected: false }"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_active_backend_lists_the_watched_signals() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder().signals(&[libc::SIGTERM, libc::SIGUSR1]);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+        assert_eq!(guard.active_backend(), None);
+
+        guard.start().unwrap();
+        let active = guard.active_backend().unwrap();
+        assert_eq!(active.watched[..2], ["SIGTERM", "SIGUSR1"]);
+        #[cfg(target_os = "linux")]
+        assert_eq!(active.backend, Backend::Signals);
+        guard.stop().unwrap();
+        assert_eq!(guard.active_backend(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_context_names_the_sender() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let builder = ShutdownGuard::builder().exit_behavior(ExitBehavior::DoNotExit);
+        #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+        let builder = builder.linux_backend(LinuxBackend::Signals);
+        let guard = builder.build();
+
+        guard.start().unwrap();
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGTERM);
+        }
+        let sender = guard.wait_for_shutdown().sender.unwrap();
+        assert_eq!(sender.pid, std::process::id() as i32);
+        assert_eq!(sender.uid, unsafe { libc::getuid() });
+        assert!(!sender.is_init());
+        guard.stop().unwrap();
+    }
+
+    #[test]
+    fn test_double_start_is_rejected() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let guard = ShutdownGuard::new();
+        assert!(!guard.is_running());
+
+        guard.start().unwrap();
+        assert!(guard.is_running());
+
+        let err = guard.start().unwrap_err();
+        assert_eq!(err, ShutdownError::AlreadyStarted);
+        assert_eq!(err.to_string(), "shutdown monitoring already started");
+        assert!(guard.is_running());
+
+        guard.stop().unwrap();
+        assert!(!guard.is_running());
+        guard.start().unwrap();
+        guard.stop().unwrap();
+    }
+
+    // The D-Bus thread exits on its own when no system bus is reachable
+    #[cfg(not(all(target_os = "linux", feature = "dbus-support")))]
+    #[test]
+    fn test_monitor_handle_finishes_after_stop() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let guard = ShutdownGuard::new();
+
+        let handle = guard.start().unwrap();
+        assert!(!handle.is_finished());
+
+        guard.stop().unwrap();
+        handle.join().unwrap();
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "dbus-support")))]
+    #[test]
+    fn test_guards_share_the_monitor_until_the_last_stops() {
+        let _lock = platform::SIGNAL_TEST_LOCK.lock();
+        let first = ShutdownGuard::new();
+        let second = ShutdownGuard::new();
+
+        let handle = first.start().unwrap();
+        second.start().unwrap();
+        first.stop().unwrap();
+        assert!(!handle.is_finished());
+
+        second.stop().unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_panicking_callback_does_not_stop_the_rest() {
+        let guard = ShutdownGuard::new();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+
+        let panicking = guard.register(Box::new(|_| panic!("boom"))).unwrap();
+        guard
+            .register(Box::new(move |_| ran_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+
+        let failures = guard.execute_callbacks_collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, panicking);
+        assert_eq!(failures[0].1.to_string(), "callback panicked: boom");
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_abort_on_panic_skips_remaining_callbacks() {
+        let guard = ShutdownGuard::builder()
+            .panic_policy(PanicPolicy::AbortOnPanic)
+            .build();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+
+        guard.register(Box::new(|_| panic!("boom"))).unwrap();
+        guard
+            .register(Box::new(move |_| ran_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+
+        assert_eq!(guard.execute_callbacks_collect().len(), 1);
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_callbacks_run_once_until_reset() {
+        let guard = ShutdownGuard::new();
+        let count = Arc::new(AtomicU64::new(0));
+        let count_clone = Arc::clone(&count);
+        guard
+            .register(Box::new(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        assert!(!guard.has_executed());
+        guard.execute_callbacks();
+        guard.execute_callbacks();
+        assert!(guard.execute_callbacks_collect().is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(guard.has_executed());
+
+        guard.reset_executed();
+        guard.execute_callbacks();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_racing_shutdown_triggers_run_callbacks_once() {
+        let guard = ShutdownGuard::new();
+        let count = Arc::new(AtomicU64::new(0));
+        let count_clone = Arc::clone(&count);
+        guard
+            .register(Box::new(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        // E.g. a D-Bus PrepareForShutdown racing a delivered SIGTERM
+        std::thread::scope(|scope| {
+            let contexts = [
+                ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::DBusSignal),
+                ShutdownContext::new(ShutdownReason::Signal(15), TriggerSource::UnixSignal(15)),
+            ];
+            for context in contexts {
+                let state = &guard.state;
+                scope.spawn(move || state.run_shutdown(context));
+            }
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(guard.has_executed());
+    }
+
+    #[test]
+    fn test_simulate_shutdown_matches_a_detected_shutdown() {
+        let guard = ShutdownGuard::builder()
+            .panic_policy(PanicPolicy::AbortOnPanic)
+            .build();
+        let signal = guard.subscribe();
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        let seen_clone = Arc::clone(&seen);
+        guard
+            .register(Box::new(move |context| {
+                seen_clone.lock().push((context.reason, context.source));
+            }))
+            .unwrap();
+        guard
+            .register_with_priority(5, Box::new(|_| panic!("first")))
+            .unwrap();
+
+        guard.simulate_shutdown(ShutdownReason::Reboot);
+        guard.simulate_shutdown(ShutdownReason::Logout);
+
+        // The panicking high-priority callback ran first and aborted the rest
+        assert!(seen.lock().is_empty());
+        assert_eq!(signal.context().unwrap().reason, ShutdownReason::Reboot);
+        assert_eq!(signal.context().unwrap().source, TriggerSource::Simulated);
+        assert!(guard.has_executed());
+    }
+
+    #[test]
+    fn test_weak_callback_skipped_after_target_dropped() {
+        let guard = ShutdownGuard::new();
+        let calls = Arc::new(AtomicU64::new(0));
+
+        let alive = Arc::clone(&calls);
+        guard
+            .register_weak(&alive, |calls, _| {
+                calls.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+        let dropped = Arc::new(AtomicU64::new(0));
+        guard
+            .register_weak(&dropped, |calls, _| {
+                calls.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap();
+
+        // The guard must not keep the target alive
+        let weak = Arc::downgrade(&dropped);
+        drop(dropped);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(guard.callback_count(), 2);
+        assert_eq!(guard.live_callback_count(), 1);
+
+        guard.execute_callbacks();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_pending_tracks_unfinished_callbacks() {
+        let guard = ShutdownGuard::new();
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        let pending = Arc::clone(&guard.state.pending);
+        let seen_clone = Arc::clone(&seen);
+        let first = guard
+            .register_named(
+                "first",
+                Box::new(move |_| {
+                    *seen_clone.lock() = pending.lock().clone();
+                }),
+            )
+            .unwrap();
+        let second = guard.register(Box::new(|_| {})).unwrap();
+
+        guard.execute_callbacks();
+        assert_eq!(
+            *seen.lock(),
+            vec![
+                (first, "first".to_string()),
+                (second, format!("{:?}", second))
+            ]
+        );
+        assert!(guard.state.pending.lock().is_empty());
+    }
+
+    #[test]
+    fn test_register_once_survives_reset() {
+        let guard = ShutdownGuard::new();
+        let count = Arc::new(AtomicU64::new(0));
+        let count_clone = Arc::clone(&count);
+        guard
+            .register_once(Box::new(move |_| {
+                count_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        guard.execute_callbacks();
+        guard.reset_executed();
+        guard.simulate_shutdown(ShutdownReason::PowerOff);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_forceful_phase_runs_after_graceful_timeout() {
+        let guard = ShutdownGuard::builder()
+            .graceful_timeout(Duration::from_millis(100))
+            .build();
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        let order_clone = Arc::clone(&order);
+        let killed = guard
+            .register_phase(
+                Phase::Forceful,
+                Box::new(move |_| order_clone.lock().push("kill")),
+            )
+            .unwrap();
+        let hung = guard
+            .register(Box::new(|_| std::thread::sleep(Duration::from_secs(5))))
+            .unwrap();
+        let order_clone = Arc::clone(&order);
+        let skipped = guard
+            .register(Box::new(move |_| order_clone.lock().push("drain")))
+            .unwrap();
+
+        let started = Instant::now();
+        let failures = guard.execute_callbacks_collect();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(*order.lock(), vec!["kill"]);
+
+        let failed: Vec<CallbackId> = failures.iter().map(|(id, _)| *id).collect();
+        assert_eq!(failed, vec![hung, skipped]);
+        assert!(failures[0].1.downcast_ref::<CallbackTimeout>().is_some());
+        assert!(!failed.contains(&killed));
+    }
+
+    #[test]
+    fn test_shutdown_in_progress_before_callbacks_run() {
+        let guard = Arc::new(ShutdownGuard::new());
+        let seen = Arc::new(AtomicBool::new(false));
+        let seen_clone = Arc::clone(&seen);
+        let weak = Arc::downgrade(&guard);
+        guard
+            .register(Box::new(move |_| {
+                let in_progress = weak.upgrade().unwrap().is_shutdown_in_progress();
+                seen_clone.store(in_progress, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        assert!(!guard.is_shutdown_in_progress());
+        guard.simulate_shutdown(ShutdownReason::PowerOff);
+        assert!(seen.load(Ordering::SeqCst));
+
+        guard.reset_executed();
+        assert!(guard.is_shutdown_in_progress());
+    }
+
+    #[test]
+    fn test_last_reason_tracks_latest_run() {
+        let guard = ShutdownGuard::new();
+        assert_eq!(guard.last_reason(), None);
+
+        guard.execute_callbacks_with_reason(ShutdownReason::Logout);
+        assert_eq!(guard.last_reason(), Some(ShutdownReason::Logout));
+
+        // A deduplicated trigger does not overwrite it
+        guard.execute_callbacks_with_reason(ShutdownReason::PowerOff);
+        assert_eq!(guard.last_reason(), Some(ShutdownReason::Logout));
+
+        guard.reset_executed();
+        guard.execute_callbacks_with_reason(ShutdownReason::Reboot);
+        assert_eq!(guard.last_reason(), Some(ShutdownReason::Reboot));
+    }
+
+    #[test]
+    fn test_hook_shared_between_guards() {
+        struct Counter(AtomicU64);
+        impl ShutdownHook for Counter {
+            fn on_shutdown(&self, context: &ShutdownContext) {
+                assert!(context.is_manual());
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let counter = Arc::new(Counter(AtomicU64::new(0)));
+        let first = ShutdownGuard::new();
+        let second = ShutdownGuard::new();
+        first.register_hook(counter.clone()).unwrap();
+        second.register_hook(counter.clone()).unwrap();
+
+        first.execute_callbacks();
+        second.execute_callbacks();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_wait_for_shutdown_returns_after_callbacks() {
+        let guard = Arc::new(ShutdownGuard::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let done_clone = Arc::clone(&done);
+        guard
+            .register(Box::new(move |_| {
+                std::thread::sleep(Duration::from_millis(100));
+                done_clone.store(true, Ordering::SeqCst);
+            }))
+            .unwrap();
+
+        let guard_clone = Arc::clone(&guard);
+        let trigger = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            guard_clone.execute_callbacks_with_reason(ShutdownReason::Reboot);
+        });
+
+        let context = guard.wait_for_shutdown();
+        assert!(done.load(Ordering::SeqCst));
+        assert_eq!(context.reason, ShutdownReason::Reboot);
+        trigger.join().unwrap();
+    }
+
+    #[test]
+    fn test_clear_group_keeps_other_callbacks() {
+        let guard = ShutdownGuard::new();
+        let ran = Arc::new(AtomicU64::new(0));
+        let (cache, network) = (GroupId::new(), GroupId::new());
+        for group in [cache, cache, network] {
+            let ran = Arc::clone(&ran);
+            guard
+                .register_in_group(
+                    group,
+                    Box::new(move |_| {
+                        ran.fetch_add(1, Ordering::SeqCst);
+                    }),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(guard.clear_group(cache), 2);
+        assert_eq!(guard.clear_group(cache), 0);
+        guard.execute_callbacks();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_unregister_by_name_removes_every_match() {
+        let guard = ShutdownGuard::new();
+        guard.register_named("flush", Box::new(|_| {})).unwrap();
+        guard.register_named("flush", Box::new(|_| {})).unwrap();
+        guard.register_named("close", Box::new(|_| {})).unwrap();
+        guard.register(Box::new(|_| {})).unwrap();
+
+        assert_eq!(guard.unregister_by_name("flush"), 2);
+        assert_eq!(guard.unregister_by_name("flush"), 0);
+        assert_eq!(guard.callback_count(), 2);
+        assert!(guard.callback_names().contains(&"close".to_string()));
+    }
+
+    #[test]
+    fn test_registration_outlives_guard() {
+        let guard = ShutdownGuard::new();
+        let kept = guard.register_scoped(Box::new(|_| {})).unwrap();
+        let dropped = guard.register_scoped(Box::new(|_| {})).unwrap();
+        drop(dropped);
+        assert_eq!(
+            guard
+                .inspect()
+                .iter()
+                .map(|info| info.id)
+                .collect::<Vec<_>>(),
+            vec![kept.id()]
+        );
+
+        // Dropping the handle after the guard is a no-op
+        drop(guard);
+        drop(kept);
+    }
+
+    #[test]
+    fn test_lifo_execution_order() {
+        let guard = ShutdownGuard::builder()
+            .execution_order(ExecutionOrder::Lifo)
+            .build();
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        for name in ["open db", "start workers"] {
+            let order = Arc::clone(&order);
+            guard
+                .register(Box::new(move |_| order.lock().push(name)))
+                .unwrap();
+        }
+        let order_clone = Arc::clone(&order);
+        guard
+            .register_with_priority(10, Box::new(move |_| order_clone.lock().push("flush logs")))
+            .unwrap();
+
+        guard.execute_callbacks();
+        assert_eq!(
+            *order.lock(),
+            vec!["flush logs", "start workers", "open db"]
+        );
+    }
+
+    #[test]
+    fn test_adopt_moves_the_callbacks_with_their_dependencies() {
+        let recorder = OrderRecorder::new();
+
+        let library = ShutdownGuard::new();
+        let pool = library
+            .register_named("close pool", recorder.hook("close pool"))
+            .unwrap();
+        library
+            .register_after(&[pool], recorder.hook("drop tables"))
+            .unwrap();
+        library
+            .register_with_priority(10, recorder.hook("flush cache"))
+            .unwrap();
+
+        let guard = ShutdownGuard::new();
+        guard
+            .register_with_priority(5, recorder.hook("flush logs"))
+            .unwrap();
+        guard.adopt(library).unwrap();
+        assert_eq!(guard.callback_count(), 4);
+        assert!(guard.callback_names().contains(&"close pool".to_string()));
+
+        guard.execute_callbacks();
+        assert_eq!(
+            recorder.order(),
+            vec!["flush cache", "flush logs", "close pool", "drop tables"]
+        );
+    }
+
+    #[test]
+    fn test_adopt_refuses_callbacks_that_do_not_fit() {
+        let library = ShutdownGuard::new();
+        library.register(Box::new(|_| {})).unwrap();
+        library.register(Box::new(|_| {})).unwrap();
+        let handle = library.clone();
+
+        let guard = ShutdownGuard::builder().max_callbacks(2).build();
+        guard.register(Box::new(|_| {})).unwrap();
+        assert_eq!(
+            guard.adopt(library),
+            Err(RegisterError::CapacityExceeded { max: 2 })
+        );
+        assert_eq!(guard.callback_count(), 1);
+        assert_eq!(handle.callback_count(), 2);
+    }
+
+    #[test]
+    fn test_adopted_callbacks_are_timed_by_the_adopting_guard() {
+        let library = ShutdownGuard::new();
+        library.register(Box::new(|_| {})).unwrap();
+
+        let clock = clock::MockClock::new();
+        let guard = ShutdownGuard::builder().clock(clock.clone()).build();
+        guard.register(Box::new(|_| {})).unwrap();
+        clock.advance(Duration::from_secs(10));
+        guard.adopt(library).unwrap();
+
+        let registered_at: Vec<Duration> = guard
+            .inspect()
+            .iter()
+            .map(|info| info.registered_at)
+            .collect();
+        assert_eq!(registered_at, vec![Duration::ZERO, Duration::from_secs(10)]);
     }
 }
@@ -0,0 +1,216 @@
+//! Shutdown callbacks that stay on the thread that registered them
+
+use crate::in_callback::InCallback;
+use crate::{
+    CallbackPanic, MonitorHandle, RegisterError, ShutdownContext, ShutdownError, ShutdownGuard,
+    ShutdownReason, ShutdownSignal, TriggerSource,
+};
+use parking_lot::{Condvar, Mutex};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+/// A callback run on the thread owning a [`LocalShutdownGuard`]
+///
+/// Unlike [`ShutdownCallback`](crate::ShutdownCallback) it needs neither
+/// `Send` nor `Sync`, so it may capture `Rc` or `RefCell` state.
+pub type LocalShutdownCallback = Box<dyn Fn(ShutdownContext) + 'static>;
+
+/// Set by the owning thread once the local callbacks ran
+type LocalDone = Arc<(Mutex<bool>, Condvar)>;
+
+/// A guard for single-threaded programs whose callbacks are not `Send`
+///
+/// Shutdowns are still detected on the platform's own threads, but local
+/// callbacks never run there: the detecting thread wakes the owning thread
+/// and waits until it ran them in [`poll`](Self::poll) or
+/// [`wait`](Self::wait), e.g. from a GUI main loop. A shutdown therefore
+/// stalls until the owning thread gets to it; bound that with a deadline on
+/// the inner guard if the owner may be busy.
+///
+/// ```no_run
+/// use shutdown_guard_rs::LocalShutdownGuard;
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+///
+/// let log = Rc::new(RefCell::new(Vec::new()));
+/// let guard = LocalShutdownGuard::new();
+///
+/// let log_clone = Rc::clone(&log);
+/// guard.register_local(Box::new(move |context| {
+///     log_clone.borrow_mut().push(context.reason);
+/// }));
+///
+/// guard.start().expect("Failed to start shutdown monitoring");
+/// loop {
+///     // ... handle events ...
+///     if guard.poll() {
+///         break;
+///     }
+/// }
+/// ```
+pub struct LocalShutdownGuard {
+    guard: ShutdownGuard,
+    signal: ShutdownSignal,
+    done: LocalDone,
+    callbacks: RefCell<Vec<LocalShutdownCallback>>,
+    executed: Cell<bool>,
+    /// Keeps the guard on the thread that created it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl LocalShutdownGuard {
+    /// Creates a local guard around a default [`ShutdownGuard`]
+    pub fn new() -> Self {
+        Self::with_guard(ShutdownGuard::new()).expect("a new guard accepts callbacks")
+    }
+
+    /// Creates a local guard around a configured [`ShutdownGuard`]
+    ///
+    /// The local guard registers a callback of its own on `guard`, so this
+    /// fails like [`register`](crate::Registry::register) if `guard` is
+    /// sealed or full.
+    pub fn with_guard(guard: ShutdownGuard) -> Result<Self, RegisterError> {
+        let done: LocalDone = Arc::new((Mutex::new(false), Condvar::new()));
+
+        // Holds back the detecting thread, and with it the process exit,
+        // until the owning thread ran the local callbacks
+        let done_clone = Arc::clone(&done);
+        guard.register(Box::new(move |_| {
+            let (lock, condvar) = &*done_clone;
+            let mut done = lock.lock();
+            while !*done {
+                condvar.wait(&mut done);
+            }
+        }))?;
+
+        Ok(Self {
+            signal: guard.subscribe(),
+            guard,
+            done,
+            callbacks: RefCell::new(Vec::new()),
+            executed: Cell::new(false),
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Registers a callback run on this thread when a shutdown is detected
+    pub fn register_local(&self, callback: LocalShutdownCallback) {
+        self.callbacks.borrow_mut().push(callback);
+    }
+
+    /// Returns the inner guard, e.g. to register `Send` callbacks as well
+    pub fn guard(&self) -> &ShutdownGuard {
+        &self.guard
+    }
+
+    /// Starts shutdown monitoring, like [`ShutdownGuard::start`]
+    pub fn start(&self) -> Result<MonitorHandle, ShutdownError> {
+        self.guard.start()
+    }
+
+    /// Runs the local callbacks if a shutdown was detected and returns whether
+    /// one was; never blocks
+    pub fn poll(&self) -> bool {
+        match self.signal.context() {
+            Some(context) => {
+                self.run_local(context);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Blocks until a shutdown is detected, then runs the local callbacks
+    pub fn wait(&self) -> ShutdownContext {
+        let context = self.signal.wait();
+        self.run_local(context);
+        context
+    }
+
+    /// Runs the local callbacks, then the inner guard's callbacks, on this
+    /// thread
+    pub fn execute_callbacks(&self) {
+        let context = ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual);
+        self.run_local(context);
+        self.guard.execute_callbacks();
+    }
+
+    fn run_local(&self, context: ShutdownContext) {
+        if self.executed.replace(true) {
+            return;
+        }
+
+        for callback in self.callbacks.borrow().iter() {
+            let _in_callback = InCallback::enter();
+            if let Err(payload) = std::panic::catch_unwind(AssertUnwindSafe(|| callback(context))) {
+                let panic = CallbackPanic::from_payload(&*payload);
+                eprintln!("Local shutdown callback panicked: {}", panic);
+            }
+        }
+
+        let (lock, condvar) = &*self.done;
+        *lock.lock() = true;
+        condvar.notify_all();
+    }
+}
+
+impl Default for LocalShutdownGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_local_callbacks_run_on_owning_thread() {
+        let guard = LocalShutdownGuard::new();
+        let owner = std::thread::current().id();
+        let ran_on = Rc::new(RefCell::new(None));
+
+        let ran_on_clone = Rc::clone(&ran_on);
+        guard.register_local(Box::new(move |context| {
+            *ran_on_clone.borrow_mut() = Some((std::thread::current().id(), context.reason));
+        }));
+
+        let after = Arc::new(AtomicBool::new(false));
+        let after_clone = Arc::clone(&after);
+        guard
+            .guard()
+            .register(Box::new(move |_| {
+                after_clone.store(true, Ordering::SeqCst);
+            }))
+            .unwrap();
+        assert!(!guard.poll());
+
+        // Simulates a platform thread detecting the shutdown
+        let state = Arc::clone(&guard.guard.state);
+        let detector = std::thread::spawn(move || {
+            let context = ShutdownContext::new(ShutdownReason::PowerOff, TriggerSource::Manual);
+            state.run_shutdown(context);
+        });
+
+        assert_eq!(guard.wait().reason, ShutdownReason::PowerOff);
+        detector.join().unwrap();
+        assert_eq!(*ran_on.borrow(), Some((owner, ShutdownReason::PowerOff)));
+        assert!(after.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_execute_callbacks_does_not_wait_on_itself() {
+        let guard = LocalShutdownGuard::new();
+        let ran = Rc::new(Cell::new(false));
+        let ran_clone = Rc::clone(&ran);
+        guard.register_local(Box::new(move |_| ran_clone.set(true)));
+
+        guard.execute_callbacks();
+        assert!(ran.get());
+        assert!(guard.guard().has_executed());
+    }
+}
@@ -0,0 +1,80 @@
+//! Counters of shutdown events and callback outcomes
+
+use crate::{CallbackError, CallbackOutcome};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of a guard, bumped by the platform backends and the
+/// dispatcher
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    shutdowns_triggered: AtomicU64,
+    shutdowns_cancelled: AtomicU64,
+    callbacks_completed: AtomicU64,
+    callback_panics: AtomicU64,
+    callback_timeouts: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts a trigger asking the callbacks to run, whether or not they
+    /// already ran
+    pub(crate) fn shutdown_triggered(&self) {
+        self.shutdowns_triggered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a shutdown the system announced and then called off
+    #[cfg(any(
+        target_os = "windows",
+        all(target_os = "linux", feature = "dbus-support")
+    ))]
+    pub(crate) fn shutdown_cancelled(&self) {
+        self.shutdowns_cancelled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a callback that ended, with its outcome
+    pub(crate) fn callback_completed(&self, result: &Result<(), CallbackError>) {
+        self.callbacks_completed.fetch_add(1, Ordering::Relaxed);
+        match CallbackOutcome::from_result(result) {
+            CallbackOutcome::Panicked(_) => {
+                self.callback_panics.fetch_add(1, Ordering::Relaxed);
+            }
+            CallbackOutcome::TimedOut => {
+                self.callback_timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            shutdowns_triggered: self.shutdowns_triggered.load(Ordering::Relaxed),
+            shutdowns_cancelled: self.shutdowns_cancelled.load(Ordering::Relaxed),
+            callbacks_completed: self.callbacks_completed.load(Ordering::Relaxed),
+            callback_panics: self.callback_panics.load(Ordering::Relaxed),
+            callback_timeouts: self.callback_timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters since a guard was created, returned by
+/// [`Registry::metrics`](crate::Registry::metrics)
+///
+/// A `shutdowns_triggered` well above one in a long-lived service points at a
+/// misconfigured environment sending shutdown signals it does not follow
+/// through on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MetricsSnapshot {
+    /// How often the callbacks were asked to run, by the platform or
+    /// manually, including repeats after they already ran
+    pub shutdowns_triggered: u64,
+    /// How often the system announced a shutdown and then cancelled it, e.g.
+    /// a vetoed Windows session end or logind's `PrepareForShutdown(false)`
+    pub shutdowns_cancelled: u64,
+    /// How many callbacks ended, however they ended
+    pub callbacks_completed: u64,
+    /// How many callbacks panicked
+    pub callback_panics: u64,
+    /// How many callbacks overran their timeout or were given up on at a
+    /// deadline
+    pub callback_timeouts: u64,
+}
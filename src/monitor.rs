@@ -0,0 +1,53 @@
+//! Handle to the thread that monitors for shutdown
+
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Handle to the background thread started by `ShutdownGuard::start`
+///
+/// The thread is the D-Bus listener with `dbus-support`, the hidden window's
+/// message loop on Windows, and the raw signal forwarding thread on the Unix
+/// signal-handler backends. It runs until `ShutdownGuard::stop` is called or
+/// the monitor fails, so a server can park its main thread on
+/// [`join`](Self::join) instead of sleeping in a loop.
+///
+/// Guards started while another one is running share its thread, and with it
+/// the handle; the thread then runs until the last of them stops.
+///
+/// `Registry::register_trigger` returns one for its trigger's thread, which
+/// finishes once the trigger fired and the callbacks ran.
+#[derive(Debug, Clone)]
+pub struct MonitorHandle {
+    thread: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl MonitorHandle {
+    pub(crate) fn new(thread: JoinHandle<()>) -> Self {
+        Self {
+            thread: Arc::new(Mutex::new(Some(thread))),
+        }
+    }
+
+    /// Waits for the monitoring thread to finish
+    ///
+    /// Returns an error if the thread panicked. Only the first of several
+    /// handles to the same thread sees the panic.
+    pub fn join(self) -> std::thread::Result<()> {
+        // Other handles block on the lock until the thread finished
+        let mut thread = self.thread.lock();
+        match thread.take() {
+            Some(thread) => thread.join(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether the monitoring thread has finished, e.g. because
+    /// monitoring was stopped or the monitor failed
+    pub fn is_finished(&self) -> bool {
+        // Locked only while another handle joins the thread
+        self.thread
+            .try_lock()
+            .is_some_and(|thread| thread.as_ref().is_none_or(JoinHandle::is_finished))
+    }
+}
@@ -0,0 +1,47 @@
+//! Channel senders that can be notified when shutdown is detected
+
+use crate::ShutdownReason;
+
+/// A channel sender that can deliver the [`ShutdownReason`] on shutdown
+///
+/// Implemented for the standard library channels and, behind the `tokio` and
+/// `crossbeam` features, for their channel senders. Sending never blocks: a
+/// full bounded channel or a dropped receiver is silently ignored, since
+/// there is nothing useful a shutdown callback could do about either.
+pub trait ShutdownSender: Send + Sync + 'static {
+    /// Sends the reason, ignoring a closed or full channel
+    fn send_reason(&self, reason: ShutdownReason);
+}
+
+impl ShutdownSender for std::sync::mpsc::Sender<ShutdownReason> {
+    fn send_reason(&self, reason: ShutdownReason) {
+        let _ = self.send(reason);
+    }
+}
+
+impl ShutdownSender for std::sync::mpsc::SyncSender<ShutdownReason> {
+    fn send_reason(&self, reason: ShutdownReason) {
+        let _ = self.try_send(reason);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ShutdownSender for tokio::sync::mpsc::UnboundedSender<ShutdownReason> {
+    fn send_reason(&self, reason: ShutdownReason) {
+        let _ = self.send(reason);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ShutdownSender for tokio::sync::mpsc::Sender<ShutdownReason> {
+    fn send_reason(&self, reason: ShutdownReason) {
+        let _ = self.try_send(reason);
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+impl ShutdownSender for crossbeam_channel::Sender<ShutdownReason> {
+    fn send_reason(&self, reason: ShutdownReason) {
+        let _ = self.try_send(reason);
+    }
+}
@@ -0,0 +1,14 @@
+//! Ordering of callbacks that share a priority
+
+/// The order in which callbacks with the same priority run
+///
+/// Priorities always take precedence: higher priorities run first regardless
+/// of the execution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionOrder {
+    /// Callbacks run in registration order
+    #[default]
+    Fifo,
+    /// Callbacks run in reverse registration order, so teardown mirrors setup
+    Lifo,
+}
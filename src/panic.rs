@@ -0,0 +1,105 @@
+//! Panic isolation for shutdown callbacks
+
+use std::any::Any;
+use std::fmt;
+
+/// What to do when a shutdown callback panics
+///
+/// The panic is always caught, so it never unwinds into a platform thread or
+/// the caller of `execute_callbacks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// Log the panic and run the remaining callbacks
+    #[default]
+    ContinueOnPanic,
+    /// Log the panic and skip the remaining callbacks
+    AbortOnPanic,
+}
+
+/// Reported for a callback that panicked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackPanic {
+    /// The panic message, if the payload was a string
+    pub message: Option<String>,
+}
+
+impl CallbackPanic {
+    pub(crate) fn from_payload(payload: &(dyn Any + Send)) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+        Self { message }
+    }
+}
+
+impl fmt::Display for CallbackPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "callback panicked: {}", message),
+            None => write!(f, "callback panicked"),
+        }
+    }
+}
+
+impl std::error::Error for CallbackPanic {}
+
+/// Runs `f`, aborting the process if a panic escapes it
+///
+/// For the signal dispatch thread, which must not die quietly: the signals
+/// would still be caught, but nothing would read them any more. The
+/// diagnostic goes straight to stderr with `write(2)`, without taking the
+/// stderr lock or allocating, which the panic may have left unusable.
+#[cfg(unix)]
+pub(crate) fn abort_on_panic<R>(what: &'static str, f: impl FnOnce() -> R) -> R {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("Box<dyn Any>");
+            for part in [
+                "shutdown-guard: panic in ",
+                what,
+                ": ",
+                message,
+                "; aborting\n",
+            ] {
+                unsafe {
+                    libc::write(libc::STDERR_FILENO, part.as_ptr().cast(), part.len());
+                }
+            }
+            unsafe { libc::abort() }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_on_panic_aborts_the_process() {
+        assert_eq!(abort_on_panic("test", || 7), 7);
+
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            // resume_unwind skips the panic hook, which takes locks another
+            // thread may have held at the fork
+            unsafe {
+                let null = libc::open(c"/dev/null".as_ptr(), libc::O_WRONLY);
+                libc::dup2(null, libc::STDERR_FILENO);
+            }
+            abort_on_panic("test", || std::panic::resume_unwind(Box::new("boom")));
+            unsafe { libc::_exit(0) };
+        }
+
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFSIGNALED(status));
+        assert_eq!(libc::WTERMSIG(status), libc::SIGABRT);
+    }
+}
@@ -0,0 +1,269 @@
+//! Parallel execution of shutdown callbacks
+
+use crate::{
+    trace, Callback, CallbackError, CallbackId, CallbackPanic, CallbackTimeout, Entries,
+    GuardState, PanicPolicy, ShutdownContext,
+};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A callback to run, with its id and its name, if any
+type Job = (CallbackId, Option<String>, Arc<Callback>);
+
+/// Runs the entries on a bounded pool of scratch threads and returns the
+/// failures in completion order
+///
+/// Entries of the same phase and priority run concurrently unless one was
+/// registered after another; a tier only starts once the previous one
+/// finished. The graceful timeout does not apply here. With a `deadline`,
+/// callbacks that have not finished when it passes are abandoned on their
+/// threads and reported as [`CallbackTimeout`], together with those that never
+/// started.
+pub(crate) fn run_parallel(
+    state: &GuardState,
+    entries: Entries<'_>,
+    context: ShutdownContext,
+    deadline: Option<Duration>,
+) -> Vec<(CallbackId, CallbackError)> {
+    let mut failures: Vec<(CallbackId, CallbackError)> = Vec::new();
+    let context = context.with_deadline(deadline);
+    if !state.begin_shutdown(context) {
+        return failures;
+    }
+
+    let _span = trace::enter_execute(&context);
+    let until = deadline.map(|deadline| state.clock.now() + deadline);
+    let entries = entries.release();
+    let ordered = state.dispatch_order(&entries, context.reason);
+    let mut tiers: Vec<Vec<Job>> = Vec::new();
+    let mut tier_start = 0;
+    for (i, entry) in ordered.iter().enumerate() {
+        let first = ordered[tier_start];
+        let depends_on_tier = ordered[tier_start..i]
+            .iter()
+            .any(|other| entry.after.contains(&other.id));
+        if i == 0
+            || first.phase != entry.phase
+            || first.priority != entry.priority
+            || depends_on_tier
+        {
+            tiers.push(Vec::new());
+            tier_start = i;
+        }
+        let job = (entry.id, entry.name.clone(), Arc::clone(&entry.callback));
+        tiers.last_mut().expect("a tier was started").push(job);
+    }
+
+    let aborted = Arc::new(AtomicBool::new(false));
+    let mut tiers = tiers.into_iter();
+    while let Some(tier) = tiers.next() {
+        let mut pending: Vec<CallbackId> = tier.iter().map(|(id, _, _)| *id).collect();
+        let rx = spawn_workers(tier, context, state, &aborted);
+
+        while !pending.is_empty() {
+            let received = match until {
+                Some(until) => state.clock.recv_until(&rx, until),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match received {
+                Ok((id, result)) => {
+                    pending.retain(|pending_id| *pending_id != id);
+                    state.metrics.callback_completed(&result);
+                    if let Err(e) = result {
+                        failures.push((id, e));
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // Keep idle workers from picking up the remaining jobs
+                    aborted.store(true, Ordering::SeqCst);
+                    let timeout = deadline.unwrap_or_default();
+                    let skipped = tiers.by_ref().flatten().map(|(id, _, _)| id);
+                    for id in pending.drain(..).chain(skipped) {
+                        let result = Err(Box::new(CallbackTimeout { timeout }) as CallbackError);
+                        state.metrics.callback_completed(&result);
+                        failures.extend(result.err().map(|e| (id, e)));
+                    }
+                    state.final_callbacks.run(context, &state.clock);
+                    state.finish_shutdown();
+                    return failures;
+                }
+                // Every worker stopped early after a panic under
+                // `PanicPolicy::AbortOnPanic`
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        if aborted.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    state.final_callbacks.run(context, &state.clock);
+    state.finish_shutdown();
+    failures
+}
+
+/// Spawns up to `available_parallelism` workers draining the tier's jobs, on
+/// the guard's spawner if one is set
+fn spawn_workers(
+    tier: Vec<Job>,
+    context: ShutdownContext,
+    state: &GuardState,
+    aborted: &Arc<AtomicBool>,
+) -> mpsc::Receiver<(CallbackId, Result<(), CallbackError>)> {
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(tier.len());
+    let jobs = Arc::new(Mutex::new(VecDeque::from(tier)));
+    let (tx, rx) = mpsc::channel();
+
+    for _ in 0..workers {
+        let jobs = Arc::clone(&jobs);
+        let aborted = Arc::clone(aborted);
+        let clock = state.clock.clone();
+        let panic_policy = state.panic_policy;
+        let tx = tx.clone();
+        let spawner = state.spawner.clone();
+        state.spawner.spawn(move || {
+            while !aborted.load(Ordering::SeqCst) {
+                let Some((id, name, callback)) = jobs.lock().pop_front() else {
+                    break;
+                };
+
+                let started = clock.now();
+                let result = match std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    callback.invoke(context, &clock, &spawner)
+                })) {
+                    Ok(result) => {
+                        let elapsed = clock.elapsed(started);
+                        trace::callback_finished(id, name.as_deref(), elapsed, &result);
+                        result
+                    }
+                    Err(payload) => {
+                        let panic = CallbackPanic::from_payload(&*payload);
+                        let elapsed = clock.elapsed(started);
+                        trace::callback_panicked(id, name.as_deref(), elapsed, &panic);
+                        let label = name.clone().unwrap_or_else(|| format!("{:?}", id));
+                        eprintln!("Shutdown callback {} panicked: {}", label, panic);
+                        if panic_policy == PanicPolicy::AbortOnPanic {
+                            aborted.store(true, Ordering::SeqCst);
+                        }
+                        Err(Box::new(panic) as CallbackError)
+                    }
+                };
+
+                // The caller stops listening once the deadline passed
+                let _ = tx.send((id, result));
+            }
+        });
+    }
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CallbackTimeout, DeadlineAction, ShutdownGuard};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parallel_callbacks_overlap() {
+        let guard = ShutdownGuard::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..2 {
+            let count = Arc::clone(&count);
+            guard
+                .register(Box::new(move |_| {
+                    std::thread::sleep(Duration::from_millis(300));
+                    count.fetch_add(1, Ordering::SeqCst);
+                }))
+                .unwrap();
+        }
+
+        let started = Instant::now();
+        assert!(guard.execute_callbacks_parallel().is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        if std::thread::available_parallelism().map_or(1, |n| n.get()) > 1 {
+            assert!(started.elapsed() < Duration::from_millis(550));
+        }
+        assert!(guard.has_executed());
+    }
+
+    #[test]
+    fn test_parallel_respects_priority_tiers() {
+        let guard = ShutdownGuard::new();
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        for priority in [0, 10] {
+            let order = Arc::clone(&order);
+            guard
+                .register_with_priority(
+                    priority,
+                    Box::new(move |_| {
+                        // The higher priority is slower, so only tiering keeps it first
+                        std::thread::sleep(Duration::from_millis(priority as u64 * 10));
+                        order.lock().push(priority);
+                    }),
+                )
+                .unwrap();
+        }
+
+        guard.execute_callbacks_parallel();
+        assert_eq!(*order.lock(), vec![10, 0]);
+    }
+
+    #[test]
+    fn test_parallel_waits_for_dependencies() {
+        let guard = ShutdownGuard::new();
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let slow = {
+            let order = Arc::clone(&order);
+            guard
+                .register(Box::new(move |_| {
+                    std::thread::sleep(Duration::from_millis(100));
+                    order.lock().push("flush");
+                }))
+                .unwrap()
+        };
+        let order_after = Arc::clone(&order);
+        guard
+            .register_after(
+                &[slow],
+                Box::new(move |_| order_after.lock().push("unmount")),
+            )
+            .unwrap();
+
+        assert!(guard.execute_callbacks_parallel().is_empty());
+        assert_eq!(*order.lock(), vec!["flush", "unmount"]);
+    }
+
+    #[test]
+    fn test_parallel_abandons_callbacks_after_deadline() {
+        let guard = ShutdownGuard::new();
+        guard.set_deadline(
+            Duration::from_millis(100),
+            DeadlineAction::LogAndContinue {
+                absolute_cap: Duration::from_secs(60),
+            },
+        );
+        guard.register(Box::new(|_| {})).unwrap();
+        let hung = guard
+            .register(Box::new(|_| std::thread::sleep(Duration::from_secs(5))))
+            .unwrap();
+
+        let started = Instant::now();
+        let failures = guard.execute_callbacks_parallel();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, hung);
+        assert!(failures[0].1.downcast_ref::<CallbackTimeout>().is_some());
+    }
+}
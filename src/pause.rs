@@ -0,0 +1,86 @@
+//! Deferring detected shutdowns across a critical section
+
+use crate::GuardState;
+use parking_lot::{Condvar, Mutex};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How many [`PauseGuard`]s are held, and whether a shutdown is waiting for
+/// them
+#[derive(Default)]
+pub(crate) struct Pause {
+    held: Mutex<usize>,
+    released: Condvar,
+    deferred: AtomicBool,
+}
+
+impl Pause {
+    /// Blocks the thread reporting a shutdown until no `PauseGuard` is held
+    pub(crate) fn wait(&self) {
+        let mut held = self.held.lock();
+        if *held == 0 {
+            return;
+        }
+        self.deferred.store(true, Ordering::SeqCst);
+        while *held > 0 {
+            self.released.wait(&mut held);
+        }
+        self.deferred.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Defers detected shutdowns for as long as it is held
+///
+/// Returned by [`Registry::pause`](crate::Registry::pause). A shutdown
+/// detected meanwhile waits on the thread that reported it, and its callbacks
+/// run as soon as the last `PauseGuard` is dropped. The platform's own
+/// timeouts keep running while it waits, so hold it only across short
+/// critical sections such as an atomic rename:
+///
+/// - on Unix, the thread started for the signal waits, and with it the exit
+///   that follows the callbacks; a supervisor still sends SIGKILL once its stop
+///   timeout, e.g. systemd's `TimeoutStopSec`, passes
+/// - with `dbus-support`, the logind inhibitor lock stays held, up to
+///   logind's `InhibitDelayMaxSec`
+/// - on Windows, the session end stays blocked while `WM_ENDSESSION` waits,
+///   but a console control event ends the process about five seconds after
+///   it arrived
+///
+/// The deadline watchdog only starts once the callbacks do.
+#[must_use = "dropping a PauseGuard resumes shutdown handling immediately"]
+pub struct PauseGuard {
+    state: Arc<GuardState>,
+}
+
+impl PauseGuard {
+    pub(crate) fn new(state: Arc<GuardState>) -> Self {
+        *state.pause.held.lock() += 1;
+        Self { state }
+    }
+
+    /// Returns true if a shutdown was detected and is waiting for the pause
+    /// to end, e.g. to skip optional work in the critical section
+    pub fn shutdown_pending(&self) -> bool {
+        self.state.pause.deferred.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for PauseGuard {
+    fn drop(&mut self) {
+        let pause = &self.state.pause;
+        let mut held = pause.held.lock();
+        *held -= 1;
+        if *held == 0 {
+            pause.released.notify_all();
+        }
+    }
+}
+
+impl fmt::Debug for PauseGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PauseGuard")
+            .field("shutdown_pending", &self.shutdown_pending())
+            .finish()
+    }
+}
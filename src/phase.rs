@@ -0,0 +1,110 @@
+//! Graceful and forceful shutdown phases
+
+use crate::clock::SharedClock;
+use crate::spawner::SharedSpawner;
+use crate::{Callback, CallbackError, CallbackPanic, CallbackTimeout, ShutdownContext};
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The phase a callback runs in
+///
+/// Every graceful callback runs before any forceful one, regardless of
+/// priority. The graceful phase can be cut short by
+/// [`ShutdownGuardBuilder::graceful_timeout`](crate::ShutdownGuardBuilder::graceful_timeout);
+/// the forceful phase always runs afterwards, e.g. to kill child processes
+/// that did not drain in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Phase {
+    /// Drains work and closes resources cleanly; the default
+    #[default]
+    Graceful,
+    /// Hard-stops whatever is left; should finish quickly
+    Forceful,
+}
+
+/// Returns how long the graceful phase may take, if it is limited at all
+///
+/// With forceful callbacks and a global deadline, the graceful phase ends
+/// after three quarters of the deadline at the latest, so the forceful phase
+/// keeps the last quarter.
+pub(crate) fn graceful_budget(
+    graceful_timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    has_forceful: bool,
+) -> Option<Duration> {
+    let share = deadline
+        .filter(|_| has_forceful)
+        .map(|deadline| deadline * 3 / 4);
+    match (graceful_timeout, share) {
+        (Some(timeout), Some(share)) => Some(timeout.min(share)),
+        (timeout, share) => timeout.or(share),
+    }
+}
+
+/// Runs a graceful callback on a scratch thread until `until` at the latest
+///
+/// A callback that is still running then is abandoned and reported as a
+/// [`CallbackTimeout`] for `budget`; once `until` passed, callbacks are not
+/// started at all.
+pub(crate) fn invoke_until(
+    callback: &Arc<Callback>,
+    context: ShutdownContext,
+    budget: Duration,
+    until: Instant,
+    clock: &SharedClock,
+    spawner: &SharedSpawner,
+) -> thread::Result<Result<(), CallbackError>> {
+    let timed_out = || {
+        Ok(Err(
+            Box::new(CallbackTimeout { timeout: budget }) as CallbackError
+        ))
+    };
+    if clock.now() >= until {
+        return timed_out();
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let callback = Arc::clone(callback);
+    let thread_clock = clock.clone();
+    let thread_spawner = spawner.clone();
+    spawner.spawn(move || {
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            callback.invoke(context, &thread_clock, &thread_spawner)
+        }));
+        let _ = tx.send(result);
+    });
+
+    match clock.recv_until(&rx, until) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => timed_out(),
+        Err(RecvTimeoutError::Disconnected) => Ok(Err(
+            Box::new(CallbackPanic { message: None }) as CallbackError
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forceful_phase_keeps_a_share_of_the_deadline() {
+        let deadline = Some(Duration::from_secs(8));
+        assert_eq!(
+            graceful_budget(None, deadline, true),
+            Some(Duration::from_secs(6))
+        );
+        assert_eq!(
+            graceful_budget(Some(Duration::from_secs(2)), deadline, true),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(graceful_budget(None, deadline, false), None);
+        assert_eq!(
+            graceful_budget(Some(Duration::from_secs(2)), None, false),
+            Some(Duration::from_secs(2))
+        );
+    }
+}
@@ -1,141 +1,1174 @@
 //! Linux platform-specific implementation using signal handlers or D-Bus
+//!
+//! With the `dbus-support` feature both backends are compiled in and the
+//! choice is made at `start`, see `LinuxBackend`.
 
-use crate::ShutdownCallback;
-use parking_lot::RwLock;
+use crate::{
+    trace, ActiveBackend, Backend, DispatchPriority, GuardState, MonitorHandle, ShutdownContext,
+    ShutdownError, SignalSender, TriggerSource,
+};
 use std::sync::Arc;
 
+#[cfg(feature = "dbus-support")]
+use crate::{LoginManager, ShutdownDecision, ShutdownReason};
+#[cfg(feature = "dbus-support")]
+use dbus::arg::OwnedFd;
 #[cfg(feature = "dbus-support")]
 use dbus::blocking::Connection;
 #[cfg(feature = "dbus-support")]
+use dbus::channel::{BusType, Channel};
+#[cfg(feature = "dbus-support")]
 use dbus::Message;
 #[cfg(feature = "dbus-support")]
-use std::time::Duration;
+use parking_lot::Mutex;
+#[cfg(feature = "dbus-support")]
+use std::cell::RefCell;
+#[cfg(feature = "dbus-support")]
+use std::os::fd::{AsRawFd, RawFd};
+#[cfg(feature = "dbus-support")]
+use std::sync::atomic::AtomicU64;
+#[cfg(feature = "dbus-support")]
+use std::time::{Duration, Instant};
 
-#[cfg(not(feature = "dbus-support"))]
+use super::unix;
+#[cfg(feature = "dbus-support")]
 use std::sync::atomic::{AtomicBool, Ordering};
 
-#[cfg(not(feature = "dbus-support"))]
-static mut GLOBAL_CALLBACKS: Option<Arc<RwLock<Vec<ShutdownCallback>>>> = None;
-#[cfg(not(feature = "dbus-support"))]
-static SIGNAL_RECEIVED: AtomicBool = AtomicBool::new(false);
+/// Bumped by `stop_monitoring`; a D-Bus thread exits once it changes
+#[cfg(feature = "dbus-support")]
+static MONITOR_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The first delay before reconnecting to the system bus
+#[cfg(feature = "dbus-support")]
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The longest delay between reconnection attempts unless configured with
+/// `ShutdownGuardBuilder::reconnect_max_backoff`
+#[cfg(feature = "dbus-support")]
+const DEFAULT_RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Set while the D-Bus backend, rather than the signal handlers, is in use
+#[cfg(feature = "dbus-support")]
+static USING_DBUS: AtomicBool = AtomicBool::new(false);
+
+/// Whether the D-Bus thread currently has a working bus connection
+#[cfg(feature = "dbus-support")]
+static DBUS_CONNECTED: AtomicBool = AtomicBool::new(false);
+
+/// The login manager found by the last successful `connect_logind`
+#[cfg(feature = "dbus-support")]
+static LOGIN_MANAGER: Mutex<Option<LoginManager>> = Mutex::new(None);
+
+/// The bus name systemd-logind and elogind both claim
+#[cfg(feature = "dbus-support")]
+const LOGIN1_NAME: &str = "org.freedesktop.login1";
+
+/// Signals handled by `ShutdownGuard::start`
+///
+/// SIGHUP is included as on macOS: for a daemon it often means the controlling
+/// terminal closed ahead of a shutdown.
+pub const DEFAULT_SIGNALS: &[i32] = &[libc::SIGTERM, libc::SIGHUP, libc::SIGPWR];
+
+/// How shutdowns are detected on Linux with the `dbus-support` feature
+///
+/// Chosen with
+/// [`ShutdownGuardBuilder::linux_backend`](crate::ShutdownGuardBuilder::linux_backend).
+#[cfg(feature = "dbus-support")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinuxBackend {
+    /// Use systemd-logind or elogind if one answers on the system bus, and
+    /// signal handlers otherwise, e.g. in containers
+    #[default]
+    Auto,
+    /// Always use logind; `start` fails without a system bus or a login
+    /// manager on it
+    DBus,
+    /// Always use signal handlers
+    Signals,
+}
+
+/// What [`LinuxBackend::Auto`] does when no login manager answers at start
+///
+/// Chosen with
+/// [`ShutdownGuardBuilder::on_no_backend`](crate::ShutdownGuardBuilder::on_no_backend).
+#[cfg(feature = "dbus-support")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoBackendPolicy {
+    /// Install the signal handlers instead
+    #[default]
+    FallbackToSignals,
+    /// Fail `start` with
+    /// [`ShutdownError::NoBackendAvailable`](crate::ShutdownError::NoBackendAvailable)
+    Error,
+    /// Log the failure and keep trying to connect in the background, without
+    /// signal handlers; shutdowns are missed until a login manager answers,
+    /// which [`ShutdownGuard::verify_active`](crate::ShutdownGuard::verify_active)
+    /// reports
+    Warn,
+}
 
-/// Starts monitoring for Linux shutdown events
+/// The D-Bus bus the logind backend listens on
+///
+/// Chosen with
+/// [`ShutdownGuardBuilder::dbus_bus`](crate::ShutdownGuardBuilder::dbus_bus).
+#[cfg(feature = "dbus-support")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DBusBus {
+    /// The system bus, where systemd-logind lives; honors
+    /// `DBUS_SYSTEM_BUS_ADDRESS`
+    #[default]
+    System,
+    /// The session bus of the current user
+    Session,
+    /// The bus at the given address, e.g. `unix:path=/tmp/test-bus` for a
+    /// mock bus started with `dbus-daemon`
+    Address(String),
+}
+
+/// Starts monitoring for Linux shutdown events on the given signals
 pub fn start_monitoring(
-    callbacks: Arc<RwLock<Vec<ShutdownCallback>>>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    state: Arc<GuardState>,
+    signals: &[i32],
+) -> Result<MonitorHandle, ShutdownError> {
+    unix::validate_signals(signals)?;
+
     #[cfg(feature = "dbus-support")]
-    {
-        std::thread::spawn(move || {
-            if let Err(e) = monitor_systemd_signals(callbacks) {
-                eprintln!("Failed to monitor systemd signals: {}", e);
+    if state.linux_backend != LinuxBackend::Signals {
+        match connect_logind(&state, false) {
+            Ok(conn) => {
+                // The D-Bus backend installs no signal handlers, so `signals`
+                // is only validated and the raw signal callback is never
+                // invoked here
+                trace::status(
+                    state.verbose,
+                    format_args!(
+                        "Linux shutdown monitoring active (using {} over D-Bus)",
+                        LOGIN_MANAGER.lock().unwrap_or(LoginManager::Other)
+                    ),
+                );
+                let generation = MONITOR_GENERATION.load(Ordering::SeqCst);
+                USING_DBUS.store(true, Ordering::SeqCst);
+                DBUS_CONNECTED.store(true, Ordering::SeqCst);
+                let thread = std::thread::spawn(move || {
+                    monitor_systemd_signals(state, generation, Some(conn))
+                });
+                return Ok(MonitorHandle::new(thread));
             }
-        });
+            Err(e) if state.linux_backend == LinuxBackend::DBus => {
+                return Err(ShutdownError::DBusUnavailable(e.to_string()));
+            }
+            Err(e) => match state.no_backend_policy {
+                NoBackendPolicy::FallbackToSignals => trace::status(
+                    state.verbose,
+                    format_args!("D-Bus unavailable, falling back to signal handlers: {}", e),
+                ),
+                NoBackendPolicy::Error => {
+                    return Err(ShutdownError::NoBackendAvailable(e.to_string()));
+                }
+                NoBackendPolicy::Warn => {
+                    eprintln!(
+                        "No shutdown monitoring backend available, retrying in the background: {}",
+                        e
+                    );
+                    let generation = MONITOR_GENERATION.load(Ordering::SeqCst);
+                    USING_DBUS.store(true, Ordering::SeqCst);
+                    DBUS_CONNECTED.store(false, Ordering::SeqCst);
+                    let thread = std::thread::spawn(move || {
+                        monitor_systemd_signals(state, generation, None)
+                    });
+                    return Ok(MonitorHandle::new(thread));
+                }
+            },
+        }
+    }
+
+    #[cfg(feature = "dbus-support")]
+    USING_DBUS.store(false, Ordering::SeqCst);
+    let verbose = state.verbose;
+    let thread = unix::start_signal_dispatch(state, dispatch_shutdown)?;
+    unsafe {
+        unix::register_signal_handlers(signals)?;
+    }
+    trace::status(
+        verbose,
+        "Linux shutdown monitoring active (using signal handlers)",
+    );
+    Ok(MonitorHandle::new(thread))
+}
+
+/// Describes the backend [`start_monitoring`] just started
+pub fn active_backend(_state: &GuardState, signals: &[i32]) -> ActiveBackend {
+    #[cfg(feature = "dbus-support")]
+    if USING_DBUS.load(Ordering::SeqCst) {
+        return ActiveBackend {
+            backend: Backend::Logind,
+            watched: vec!["PrepareForShutdown", "PrepareForSleep"],
+            login_manager: *LOGIN_MANAGER.lock(),
+        };
     }
+    ActiveBackend {
+        backend: Backend::Signals,
+        watched: signals.iter().map(|&sig| unix::signal_name(sig)).collect(),
+        login_manager: None,
+    }
+}
 
-    #[cfg(not(feature = "dbus-support"))]
+/// Stops monitoring started by [`start_monitoring`]
+///
+/// Restores the previous signal dispositions, or makes the D-Bus thread exit
+/// within its one-second poll interval.
+pub fn stop_monitoring() -> Result<(), ShutdownError> {
+    #[cfg(feature = "dbus-support")]
+    MONITOR_GENERATION.fetch_add(1, Ordering::SeqCst);
+
+    // A no-op if the D-Bus backend was used
+    unix::stop_signal_handling()
+}
+
+/// Forgets the monitoring a forked child inherited, see
+/// [`unix::reset_after_fork`]
+///
+/// A D-Bus connection inherited from the parent is left to the parent; the
+/// child connects anew when it starts again.
+pub fn reset_after_fork() -> Result<(), ShutdownError> {
+    #[cfg(feature = "dbus-support")]
     {
-        // Fallback to signal handlers when dbus is not available
-        unsafe {
-            GLOBAL_CALLBACKS = Some(callbacks);
-            register_signal_handlers()?;
-        }
-        println!("Linux shutdown monitoring active (using signal handlers)");
+        MONITOR_GENERATION.fetch_add(1, Ordering::SeqCst);
+        USING_DBUS.store(false, Ordering::SeqCst);
+        DBUS_CONNECTED.store(false, Ordering::SeqCst);
+        *LOGIN_MANAGER.lock() = None;
     }
+    unix::reset_after_fork()
+}
 
-    Ok(())
+/// Checks that the signal handlers are still installed, or that the D-Bus
+/// thread is connected
+pub fn verify_active() -> Result<(), ShutdownError> {
+    #[cfg(feature = "dbus-support")]
+    if USING_DBUS.load(Ordering::SeqCst) {
+        return if DBUS_CONNECTED.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(ShutdownError::MonitorFailed(
+                "the D-Bus connection is down, reconnecting".to_string(),
+            ))
+        };
+    }
+    unix::verify_signal_handlers()
 }
 
+/// Watches logind until stopped, reconnecting with exponential backoff
+/// whenever the system bus connection fails or drops
+///
+/// Without `conn`, e.g. for `NoBackendPolicy::Warn`, it starts out
+/// reconnecting.
 #[cfg(feature = "dbus-support")]
-fn monitor_systemd_signals(
-    callbacks: Arc<RwLock<Vec<ShutdownCallback>>>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Connect to the system bus
-    let conn = Connection::new_system()?;
+fn monitor_systemd_signals(state: Arc<GuardState>, generation: u64, conn: Option<Connection>) {
+    let max_backoff = state
+        .reconnect_max_backoff
+        .unwrap_or(DEFAULT_RECONNECT_MAX_BACKOFF);
+    let mut backoff = INITIAL_RECONNECT_BACKOFF;
+    let mut reconnecting = conn.is_none();
+    let mut conn = conn;
+
+    while is_current(generation) {
+        let connected = conn
+            .take()
+            .map_or_else(|| connect_logind(&state, false), Ok);
+        let result = connected.and_then(|conn| {
+            if reconnecting {
+                trace::status(state.verbose, "Reconnected to the system bus");
+                backoff = INITIAL_RECONNECT_BACKOFF;
+                DBUS_CONNECTED.store(true, Ordering::SeqCst);
+            }
+            listen(&state, &conn, generation)
+        });
+
+        let Err(e) = result else {
+            // Stopped
+            return;
+        };
+        DBUS_CONNECTED.store(false, Ordering::SeqCst);
+        if reconnecting {
+            trace::status(
+                state.verbose,
+                format_args!(
+                    "Failed to reconnect to the system bus, retrying in {:?}: {}",
+                    backoff, e
+                ),
+            );
+        } else {
+            super::report_monitor_error(ShutdownError::MonitorFailed(format!(
+                "lost the D-Bus connection, reconnecting in {:?}: {}",
+                backoff, e
+            )));
+        }
+
+        reconnecting = true;
+        sleep_while_current(backoff, generation);
+        backoff = (backoff * 2).min(max_backoff);
+    }
+}
+
+/// Connects to the system bus, checks that a login manager answers there and
+/// subscribes to the logind signals
+///
+/// `watch` enables the watch that exposes the connection's file descriptor.
+#[cfg(feature = "dbus-support")]
+fn connect_logind(state: &GuardState, watch: bool) -> Result<Connection, dbus::Error> {
+    let mut channel = match &state.dbus_bus {
+        DBusBus::System => Channel::get_private(BusType::System)?,
+        DBusBus::Session => Channel::get_private(BusType::Session)?,
+        DBusBus::Address(address) => {
+            let mut channel = Channel::open_private(address)?;
+            channel.register()?;
+            channel
+        }
+    };
+    channel.set_watch_enabled(watch);
+    let conn = Connection::from(channel);
+    *LOGIN_MANAGER.lock() = Some(probe_login_manager(&conn)?);
 
-    // Add a match rule for PrepareForShutdown signal
     conn.add_match_no_cb(
         "type='signal',\
          interface='org.freedesktop.login1.Manager',\
          member='PrepareForShutdown'",
     )?;
 
-    // Listen for incoming messages
-    loop {
-        // Process messages with a timeout
-        if let Some(msg) = conn.process(Duration::from_millis(1000))? {
-            if is_shutdown_signal(&msg) {
-                // Execute all registered callbacks
-                let callbacks_lock = callbacks.read();
-                for callback in callbacks_lock.iter() {
-                    callback();
+    // Sleep is only watched when sleep callbacks were registered
+    if !state.sleep_callbacks.read().is_empty() {
+        conn.add_match_no_cb(
+            "type='signal',\
+             interface='org.freedesktop.login1.Manager',\
+             member='PrepareForSleep'",
+        )?;
+    }
+
+    Ok(conn)
+}
+
+/// Finds out which login manager owns `org.freedesktop.login1`, starting it
+/// through D-Bus activation if needed
+///
+/// Fails if no service provides the name, e.g. on a distribution without
+/// systemd or elogind, so that `LinuxBackend::Auto` falls back to signals.
+#[cfg(feature = "dbus-support")]
+fn probe_login_manager(conn: &Connection) -> Result<LoginManager, dbus::Error> {
+    conn.with_proxy(
+        LOGIN1_NAME,
+        "/org/freedesktop/login1",
+        Duration::from_secs(5),
+    )
+    .method_call::<(), _, _, _>("org.freedesktop.DBus.Peer", "Ping", ())
+    .map_err(|e| {
+        dbus::Error::new_custom(
+            e.name()
+                .unwrap_or("org.freedesktop.DBus.Error.ServiceUnknown"),
+            &format!(
+                "no login manager provides {}: {}",
+                LOGIN1_NAME,
+                e.message().unwrap_or_default()
+            ),
+        )
+    })?;
+
+    // The owner's process tells systemd-logind and elogind apart
+    let pid: Result<(u32,), dbus::Error> = conn
+        .with_proxy(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            Duration::from_secs(5),
+        )
+        .method_call(
+            "org.freedesktop.DBus",
+            "GetConnectionUnixProcessID",
+            (LOGIN1_NAME,),
+        );
+    let comm = pid
+        .ok()
+        .and_then(|(pid,)| std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok());
+    Ok(comm.map_or(LoginManager::Other, |comm| login_manager_from_comm(&comm)))
+}
+
+/// Identifies a login manager by the `comm` name of its process
+#[cfg(feature = "dbus-support")]
+fn login_manager_from_comm(comm: &str) -> LoginManager {
+    match comm.trim() {
+        "systemd-logind" => LoginManager::SystemdLogind,
+        // The daemon is installed as `elogind-daemon` by some distributions
+        comm if comm.starts_with("elogind") => LoginManager::Elogind,
+        _ => LoginManager::Other,
+    }
+}
+
+/// Handles logind signals on `conn` until stopped or the connection fails
+#[cfg(feature = "dbus-support")]
+fn listen(state: &GuardState, conn: &Connection, generation: u64) -> Result<(), dbus::Error> {
+    let mut inhibitors = Inhibitors::take(state, conn);
+
+    // Listen for incoming messages until stopped
+    while is_current(generation) {
+        // Pop messages with a timeout
+        if let Some(msg) = conn
+            .channel()
+            .blocking_pop_message(Duration::from_millis(1000))?
+        {
+            handle_message(&super::started_guards(), conn, &msg, &mut inhibitors);
+        }
+    }
+
+    Ok(())
+}
+
+/// The logind delay inhibitor locks held while waiting for logind signals
+#[cfg(feature = "dbus-support")]
+struct Inhibitors {
+    shutdown: Option<OwnedFd>,
+    sleep: Option<OwnedFd>,
+}
+
+#[cfg(feature = "dbus-support")]
+impl Inhibitors {
+    /// Takes the shutdown lock, and the sleep lock if sleep is watched;
+    /// logind only waits for us while we hold them
+    fn take(state: &GuardState, conn: &Connection) -> Self {
+        let watch_sleep = !state.sleep_callbacks.read().is_empty();
+        Self {
+            shutdown: take_inhibitor(conn, "shutdown", "Running shutdown callbacks"),
+            sleep: if watch_sleep {
+                take_inhibitor(conn, "sleep", "Running sleep callbacks")
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Runs the callbacks of `guards` for a logind signal and returns the reason
+/// if it announced a shutdown
+#[cfg(feature = "dbus-support")]
+fn handle_message(
+    guards: &[Arc<GuardState>],
+    conn: &Connection,
+    msg: &Message,
+    inhibitors: &mut Inhibitors,
+) -> Option<ShutdownReason> {
+    // PrepareForSleep only arrives when sleep callbacks were registered
+    if let Some(suspending) = manager_signal_flag(msg, "PrepareForSleep") {
+        // True before suspending, false after resuming
+        if suspending {
+            for guard in guards {
+                guard.run_sleep_callbacks(TriggerSource::DBusSignal);
+            }
+            drop(inhibitors.sleep.take());
+        } else {
+            for guard in guards {
+                guard.run_resume_callbacks(TriggerSource::DBusSignal);
+            }
+            if inhibitors.sleep.is_none() {
+                inhibitors.sleep = take_inhibitor(conn, "sleep", "Running sleep callbacks");
+            }
+        }
+    } else if let Some(starting) = manager_signal_flag(msg, "PrepareForShutdown") {
+        // The argument is true when the shutdown starts and false when it
+        // was cancelled
+        if starting {
+            // PrepareForShutdown does not say whether the system is rebooting
+            // or powering off
+            let reason = ShutdownReason::Unknown;
+
+            // Execute all registered callbacks, then let logind go on. The
+            // guard's run-once flag makes this a no-op if another trigger
+            // already ran them.
+            let context = ShutdownContext::new(reason, TriggerSource::DBusSignal);
+            let mut aborted = false;
+            for (guard, decision) in guards.iter().zip(decide(guards)) {
+                match decision {
+                    ShutdownDecision::Proceed => guard.run_shutdown(context),
+                    ShutdownDecision::Abort => aborted = true,
                 }
             }
+            if aborted {
+                // Keep the lock: logind waits until InhibitDelayMaxSec passed
+                eprintln!("The application aborted the shutdown, holding the logind inhibitor");
+                return None;
+            }
+            drop(inhibitors.shutdown.take());
+            return Some(reason);
+        } else {
+            for guard in guards {
+                guard.metrics.shutdown_cancelled();
+            }
+            if inhibitors.shutdown.is_none() {
+                inhibitors.shutdown =
+                    take_inhibitor(conn, "shutdown", "Running shutdown callbacks");
+            }
         }
     }
+    None
 }
 
+/// Gives guards built with a `shutdown_decision_timeout` that long to answer
+/// the shutdown and returns each guard's decision
 #[cfg(feature = "dbus-support")]
-fn is_shutdown_signal(msg: &Message) -> bool {
-    msg.interface()
-        .map(|i| i.as_ref() == "org.freedesktop.login1.Manager")
-        .unwrap_or(false)
-        && msg
-            .member()
-            .map(|m| m.as_ref() == "PrepareForShutdown")
-            .unwrap_or(false)
+fn decide(guards: &[Arc<GuardState>]) -> Vec<ShutdownDecision> {
+    let started = Instant::now();
+    for guard in guards {
+        if guard.decision_timeout.is_some() {
+            guard.pending_decision.open();
+        }
+    }
+    guards
+        .iter()
+        .map(|guard| {
+            let Some(timeout) = guard.decision_timeout else {
+                return ShutdownDecision::Proceed;
+            };
+            guard.pending_decision.wait_until(started + timeout);
+            guard
+                .pending_decision
+                .close()
+                .unwrap_or(ShutdownDecision::Proceed)
+        })
+        .collect()
+}
+
+/// Watches logind from the caller's event loop instead of a dedicated thread
+///
+/// Created by [`ShutdownGuard::logind_monitor`](crate::ShutdownGuard::logind_monitor)
+/// as an alternative to [`ShutdownGuard::start`](crate::ShutdownGuard::start).
+/// Register the file descriptor from [`as_raw_fd`](AsRawFd::as_raw_fd) for
+/// readability with `mio`, `calloop`, tokio's `AsyncFd` or `poll(2)`, and call
+/// [`poll_once`](Self::poll_once) whenever it is readable. The callbacks run
+/// on the thread that calls `poll_once`.
+///
+/// The monitor holds logind's delay inhibitor locks, so logind waits for the
+/// callbacks as long as the event loop keeps polling. Dropping it releases
+/// the locks and stops monitoring.
+#[cfg(feature = "dbus-support")]
+pub struct LogindMonitor {
+    state: Arc<GuardState>,
+    conn: Connection,
+    inhibitors: RefCell<Inhibitors>,
+}
+
+#[cfg(feature = "dbus-support")]
+impl LogindMonitor {
+    pub(crate) fn new(state: Arc<GuardState>) -> Result<Self, ShutdownError> {
+        let conn = connect_logind(&state, true)
+            .map_err(|e| ShutdownError::DBusUnavailable(e.to_string()))?;
+        let inhibitors = RefCell::new(Inhibitors::take(&state, &conn));
+        Ok(Self {
+            state,
+            conn,
+            inhibitors,
+        })
+    }
+
+    /// Handles the pending logind signals without blocking
+    ///
+    /// Returns the reason once a shutdown was announced and the callbacks
+    /// ran, and an error if the system bus connection was lost; create a new
+    /// monitor to reconnect.
+    pub fn poll_once(&self) -> Result<Option<ShutdownReason>, Box<dyn std::error::Error>> {
+        let channel = self.conn.channel();
+        channel
+            .read_write(Some(Duration::ZERO))
+            .map_err(|_| "Lost the system bus connection")?;
+
+        let mut reason = None;
+        while let Some(msg) = channel.pop_message() {
+            let handled = handle_message(
+                std::slice::from_ref(&self.state),
+                &self.conn,
+                &msg,
+                &mut self.inhibitors.borrow_mut(),
+            );
+            reason = reason.or(handled);
+        }
+        Ok(reason)
+    }
+}
+
+#[cfg(feature = "dbus-support")]
+impl AsRawFd for LogindMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.conn.channel().watch().fd
+    }
+}
+
+/// Returns false once `stop_monitoring` was called for this generation
+#[cfg(feature = "dbus-support")]
+fn is_current(generation: u64) -> bool {
+    MONITOR_GENERATION.load(Ordering::SeqCst) == generation
+}
+
+/// Sleeps for `duration`, waking early once monitoring is stopped
+#[cfg(feature = "dbus-support")]
+fn sleep_while_current(duration: Duration, generation: u64) {
+    let until = Instant::now() + duration;
+    while is_current(generation) {
+        let remaining = until.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        std::thread::sleep(remaining.min(Duration::from_millis(100)));
+    }
 }
 
-#[cfg(not(feature = "dbus-support"))]
-unsafe fn register_signal_handlers() -> Result<(), Box<dyn std::error::Error>> {
-    use std::mem;
+/// Takes a logind delay inhibitor lock for `what` (`"shutdown"` or `"sleep"`)
+///
+/// The lock is held for as long as the returned fd is open. Returns `None`
+/// when logind refuses, e.g. for lack of permission, in which case monitoring
+/// is best effort.
+#[cfg(feature = "dbus-support")]
+fn take_inhibitor(conn: &Connection, what: &str, why: &str) -> Option<OwnedFd> {
+    let proxy = conn.with_proxy(
+        LOGIN1_NAME,
+        "/org/freedesktop/login1",
+        Duration::from_secs(5),
+    );
 
-    // Register SIGTERM handler
-    let mut sa_term: libc::sigaction = mem::zeroed();
-    sa_term.sa_sigaction = handle_shutdown_signal as usize;
-    sa_term.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
-    libc::sigemptyset(&mut sa_term.sa_mask);
+    let result: Result<(OwnedFd,), dbus::Error> = proxy.method_call(
+        "org.freedesktop.login1.Manager",
+        "Inhibit",
+        (what, "shutdown-guard-rs", why, "delay"),
+    );
 
-    if libc::sigaction(libc::SIGTERM, &sa_term, std::ptr::null_mut()) != 0 {
-        return Err("Failed to register SIGTERM handler".into());
+    match result {
+        Ok((fd,)) => Some(fd),
+        Err(e) => {
+            eprintln!(
+                "Failed to take a logind {} inhibitor lock, it will not wait for callbacks: {}",
+                what, e
+            );
+            None
+        }
     }
+}
 
-    // Register SIGINT handler
-    let mut sa_int: libc::sigaction = mem::zeroed();
-    sa_int.sa_sigaction = handle_shutdown_signal as usize;
-    sa_int.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
-    libc::sigemptyset(&mut sa_int.sa_mask);
+/// Returns the boolean argument of the logind `member` signal, or `None` if
+/// `msg` is any other message
+#[cfg(feature = "dbus-support")]
+fn manager_signal_flag(msg: &Message, member: &str) -> Option<bool> {
+    let matches = msg
+        .interface()
+        .map(|i| &*i == "org.freedesktop.login1.Manager")
+        .unwrap_or(false)
+        && msg.member().map(|m| &*m == member).unwrap_or(false);
+    // logind always sends the argument; treat a message without one as a start
+    matches.then(|| msg.get1::<bool>().unwrap_or(true))
+}
 
-    if libc::sigaction(libc::SIGINT, &sa_int, std::ptr::null_mut()) != 0 {
-        return Err("Failed to register SIGINT handler".into());
+/// Sets the nice value of the calling thread for `priority` and returns the
+/// previous one, or `None` for [`DispatchPriority::Normal`]
+///
+/// Linux keeps a nice value per thread, addressed by its thread id.
+pub fn raise_thread_priority(priority: DispatchPriority) -> std::io::Result<Option<i32>> {
+    let nice = match priority {
+        DispatchPriority::Normal => return Ok(None),
+        DispatchPriority::High => -10,
+        DispatchPriority::Highest => -20,
+    };
+    unsafe {
+        let tid = libc::gettid() as libc::id_t;
+        // -1 is a valid nice value, so only errno tells a failure apart
+        *libc::__errno_location() = 0;
+        let previous = libc::getpriority(libc::PRIO_PROCESS, tid);
+        if previous == -1 && *libc::__errno_location() != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Some(previous))
     }
+}
 
+/// Sets the nice value of the calling thread back to `previous`
+pub fn restore_thread_priority(previous: i32) -> std::io::Result<()> {
+    let tid = unsafe { libc::gettid() } as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, previous) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
     Ok(())
 }
 
-#[cfg(not(feature = "dbus-support"))]
-extern "C" fn handle_shutdown_signal(
-    _sig: libc::c_int,
-    _: *mut libc::siginfo_t,
-    _: *mut libc::c_void,
-) {
-    if SIGNAL_RECEIVED.swap(true, Ordering::SeqCst) {
+/// Runs the callbacks of the guards a shutdown signal starts a run for, then
+/// exits the process unless configured otherwise; guards already running
+/// them coalesce the signal into that run
+fn dispatch_shutdown(state: &Arc<GuardState>, sig: libc::c_int, sender: Option<SignalSender>) {
+    let reason = state
+        .signal_reasons
+        .get(&sig)
+        .copied()
+        .unwrap_or_else(|| unix::signal_reason(sig));
+    let context = ShutdownContext::new(reason, TriggerSource::UnixSignal(sig)).with_sender(sender);
+    let guards = super::request_shutdown(context);
+    if guards.is_empty() {
         return;
     }
 
-    unsafe {
-        let callbacks_ptr = std::ptr::addr_of!(GLOBAL_CALLBACKS);
-        if let Some(callbacks) = (*callbacks_ptr).as_ref() {
-            if let Some(callbacks_lock) = callbacks.try_read() {
-                for callback in callbacks_lock.iter() {
-                    callback();
-                }
+    unix::spawn_shutdown_run(state, move |state| {
+        for guard in &guards {
+            guard.run_shutdown(context);
+        }
+        unix::exit_after_shutdown(state, sig);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{self, SIGNAL_TEST_LOCK};
+    use crate::ShutdownReason;
+    use crate::{ExitBehavior, RawSignalCallback, Registry};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[cfg(feature = "dbus-support")]
+    #[test]
+    fn test_unreachable_bus_address_fails_to_start() {
+        let state = Arc::new(GuardState {
+            linux_backend: LinuxBackend::DBus,
+            dbus_bus: DBusBus::Address("unix:path=/nonexistent/shutdown-guard-bus".to_string()),
+            ..GuardState::default()
+        });
+        let err = start_monitoring(state, DEFAULT_SIGNALS).unwrap_err();
+        assert!(matches!(err, ShutdownError::DBusUnavailable(_)));
+    }
+
+    #[cfg(feature = "dbus-support")]
+    #[test]
+    fn test_login_manager_is_identified_by_process_name() {
+        assert_eq!(
+            login_manager_from_comm("systemd-logind\n"),
+            LoginManager::SystemdLogind
+        );
+        assert_eq!(login_manager_from_comm("elogind\n"), LoginManager::Elogind);
+        assert_eq!(
+            login_manager_from_comm("elogind-daemon\n"),
+            LoginManager::Elogind
+        );
+        assert_eq!(login_manager_from_comm("python3\n"), LoginManager::Other);
+    }
+
+    #[cfg(feature = "dbus-support")]
+    #[test]
+    fn test_no_backend_policy_decides_what_auto_does() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+        let state = |policy| {
+            Arc::new(GuardState {
+                no_backend_policy: policy,
+                dbus_bus: DBusBus::Address("unix:path=/nonexistent/shutdown-guard-bus".to_string()),
+                ..GuardState::default()
+            })
+        };
+
+        let err = start_monitoring(state(NoBackendPolicy::Error), DEFAULT_SIGNALS).unwrap_err();
+        assert!(matches!(err, ShutdownError::NoBackendAvailable(_)));
+
+        start_monitoring(state(NoBackendPolicy::Warn), DEFAULT_SIGNALS).unwrap();
+        assert!(matches!(
+            verify_active(),
+            Err(ShutdownError::MonitorFailed(_))
+        ));
+        stop_monitoring().unwrap();
+
+        start_monitoring(state(NoBackendPolicy::FallbackToSignals), DEFAULT_SIGNALS).unwrap();
+        assert!(verify_active().is_ok());
+        stop_monitoring().unwrap();
+    }
+
+    #[cfg(feature = "dbus-support")]
+    #[test]
+    fn test_decide_waits_for_the_application() {
+        use crate::ShutdownGuard;
+
+        let guard = ShutdownGuard::builder()
+            .shutdown_decision_timeout(Duration::from_secs(5))
+            .build();
+        let undecided = Arc::new(GuardState::default());
+        assert_eq!(
+            guard.respond_to_pending(ShutdownDecision::Abort),
+            Err(ShutdownError::NoPendingShutdown)
+        );
+
+        let responder = guard.clone();
+        let thread = std::thread::spawn(move || {
+            while !responder.is_shutdown_pending() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            responder.respond_to_pending(ShutdownDecision::Abort)
+        });
+        let decisions = decide(&[Arc::clone(&guard.state), undecided]);
+        assert_eq!(
+            decisions,
+            vec![ShutdownDecision::Abort, ShutdownDecision::Proceed]
+        );
+        thread.join().unwrap().unwrap();
+        assert!(!guard.is_shutdown_pending());
+    }
+
+    #[cfg(feature = "dbus-support")]
+    #[test]
+    fn test_cancelled_shutdown_is_not_a_start() {
+        let signal = |member: &str, flag: bool| {
+            Message::new_signal(
+                "/org/freedesktop/login1",
+                "org.freedesktop.login1.Manager",
+                member,
+            )
+            .unwrap()
+            .append1(flag)
+        };
+
+        let cancelled = signal("PrepareForShutdown", false);
+        assert_eq!(
+            manager_signal_flag(&cancelled, "PrepareForShutdown"),
+            Some(false)
+        );
+        let started = signal("PrepareForShutdown", true);
+        assert_eq!(
+            manager_signal_flag(&started, "PrepareForShutdown"),
+            Some(true)
+        );
+        let sleep = signal("PrepareForSleep", true);
+        assert_eq!(manager_signal_flag(&sleep, "PrepareForShutdown"), None);
+    }
+
+    #[cfg(feature = "dbus-support")]
+    #[test]
+    fn test_monitor_errors_reach_every_started_guard() {
+        use crate::MonitorErrorCallback;
+        use parking_lot::RwLock;
+
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        let errors = Arc::new(AtomicUsize::new(0));
+        let states: Vec<Arc<GuardState>> = (0..2)
+            .map(|_| {
+                let errors = Arc::clone(&errors);
+                let callback: MonitorErrorCallback = Box::new(move |e| {
+                    assert!(matches!(e, ShutdownError::MonitorFailed(_)));
+                    errors.fetch_add(1, Ordering::SeqCst);
+                });
+                Arc::new(GuardState {
+                    linux_backend: LinuxBackend::Signals,
+                    monitor_error_callback: RwLock::new(Some(callback)),
+                    ..GuardState::default()
+                })
+            })
+            .collect();
+        for state in &states {
+            platform::start_monitoring(Arc::clone(state), DEFAULT_SIGNALS).unwrap();
+        }
+
+        platform::report_monitor_error(ShutdownError::MonitorFailed("test".to_string()));
+        for state in &states {
+            platform::stop_monitoring(state).unwrap();
+        }
+        assert_eq!(errors.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_raw_signal_callback_counts_every_delivery() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = Arc::clone(&count);
+        let raw_signal_callback: RawSignalCallback = Box::new(move |sig| {
+            if sig == libc::SIGTERM {
+                count_clone.fetch_add(1, Ordering::SeqCst);
             }
-            libc::sync();
-            libc::usleep(100_000);
+        });
+
+        // The first delivery runs the callbacks, which must not exit the test
+        // process; the raw callback still sees every delivery
+        let state = Arc::new(GuardState {
+            exit_behavior: ExitBehavior::DoNotExit,
+            #[cfg(feature = "dbus-support")]
+            linux_backend: LinuxBackend::Signals,
+            ..GuardState::default()
+        });
+        *state.raw_signal_callback.write() = Some(raw_signal_callback);
+        platform::start_monitoring(Arc::clone(&state), DEFAULT_SIGNALS).unwrap();
+
+        for _ in 0..3 {
+            unsafe {
+                libc::raise(libc::SIGTERM);
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while count.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
         }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        platform::stop_monitoring(&state).unwrap();
     }
 
-    unsafe {
-        libc::_exit(0);
+    #[test]
+    fn test_signal_reaches_every_started_guard() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        // The first guard installs the handlers and decides not to exit
+        let first = Arc::new(GuardState {
+            exit_behavior: ExitBehavior::DoNotExit,
+            #[cfg(feature = "dbus-support")]
+            linux_backend: LinuxBackend::Signals,
+            ..GuardState::default()
+        });
+        let second = signal_state();
+        let registries = [
+            Registry::from_state(Arc::clone(&first)),
+            Registry::from_state(Arc::clone(&second)),
+        ];
+        for registry in &registries {
+            registry.register(Box::new(|_| {})).unwrap();
+        }
+        platform::start_monitoring(Arc::clone(&first), DEFAULT_SIGNALS).unwrap();
+        platform::start_monitoring(Arc::clone(&second), DEFAULT_SIGNALS).unwrap();
+
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        for registry in &registries {
+            let context = registry.wait_for_shutdown();
+            assert_eq!(context.source, TriggerSource::UnixSignal(libc::SIGTERM));
+        }
+
+        // The handlers stay installed until the last guard stops
+        platform::stop_monitoring(&first).unwrap();
+        assert_eq!(
+            current_handler(libc::SIGTERM),
+            unix::handle_signal as *const () as libc::sighandler_t
+        );
+        platform::stop_monitoring(&second).unwrap();
+        assert_ne!(
+            current_handler(libc::SIGTERM),
+            unix::handle_signal as *const () as libc::sighandler_t
+        );
+    }
+
+    #[test]
+    fn test_signal_burst_is_coalesced_into_one_run() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        let state = Arc::new(GuardState {
+            exit_behavior: ExitBehavior::DoNotExit,
+            #[cfg(feature = "dbus-support")]
+            linux_backend: LinuxBackend::Signals,
+            ..GuardState::default()
+        });
+        let registry = Registry::from_state(Arc::clone(&state));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = Arc::clone(&runs);
+        registry
+            .register(Box::new(move |_| {
+                runs_clone.fetch_add(1, Ordering::SeqCst);
+                // Still running when SIGHUP arrives
+                std::thread::sleep(Duration::from_millis(100));
+            }))
+            .unwrap();
+        platform::start_monitoring(Arc::clone(&state), DEFAULT_SIGNALS).unwrap();
+
+        unsafe {
+            libc::raise(libc::SIGTERM);
+            libc::raise(libc::SIGHUP);
+        }
+
+        let expected = vec![
+            ShutdownReason::Signal(libc::SIGTERM),
+            ShutdownReason::Signal(libc::SIGHUP),
+        ];
+        let reasons = || {
+            state
+                .last_summary
+                .lock()
+                .as_ref()
+                .map(|s| s.reasons.clone())
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while reasons().as_ref() != Some(&expected) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(reasons(), Some(expected));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        platform::stop_monitoring(&state).unwrap();
+    }
+
+    #[test]
+    fn test_signal_after_reset_executed_runs_the_callbacks_again() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        let state = Arc::new(GuardState {
+            exit_behavior: ExitBehavior::DoNotExit,
+            #[cfg(feature = "dbus-support")]
+            linux_backend: LinuxBackend::Signals,
+            ..GuardState::default()
+        });
+        let registry = Registry::from_state(Arc::clone(&state));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let runs_clone = Arc::clone(&runs);
+        registry
+            .register(Box::new(move |_| {
+                runs_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+        platform::start_monitoring(Arc::clone(&state), DEFAULT_SIGNALS).unwrap();
+
+        for expected in 1..=2 {
+            unsafe {
+                libc::raise(libc::SIGTERM);
+            }
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while state.last_summary.lock().is_none() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            assert_eq!(runs.load(Ordering::SeqCst), expected);
+            *state.last_summary.lock() = None;
+            registry.reset_executed();
+        }
+        platform::stop_monitoring(&state).unwrap();
+    }
+
+    #[test]
+    fn test_guard_started_after_a_signal_runs_on_the_next_one() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        let deliveries = Arc::new(AtomicUsize::new(0));
+        let deliveries_clone = Arc::clone(&deliveries);
+        let first = Arc::new(GuardState {
+            exit_behavior: ExitBehavior::DoNotExit,
+            #[cfg(feature = "dbus-support")]
+            linux_backend: LinuxBackend::Signals,
+            ..GuardState::default()
+        });
+        *first.raw_signal_callback.write() = Some(Box::new(move |_| {
+            deliveries_clone.fetch_add(1, Ordering::SeqCst);
+        }));
+        platform::start_monitoring(Arc::clone(&first), DEFAULT_SIGNALS).unwrap();
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        Registry::from_state(Arc::clone(&first)).wait_for_shutdown();
+
+        // Shares the handlers of the first guard, which already ran
+        let second = signal_state();
+        let registry = Registry::from_state(Arc::clone(&second));
+        platform::start_monitoring(Arc::clone(&second), DEFAULT_SIGNALS).unwrap();
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        let context = registry.wait_for_shutdown();
+        assert_eq!(context.source, TriggerSource::UnixSignal(libc::SIGTERM));
+
+        // The dispatch thread still handles the deliveries that follow
+        unsafe {
+            libc::raise(libc::SIGHUP);
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while deliveries.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(deliveries.load(Ordering::SeqCst), 3);
+        platform::stop_monitoring(&first).unwrap();
+        platform::stop_monitoring(&second).unwrap();
+    }
+
+    #[test]
+    fn test_stop_restores_previous_handlers() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        let before = current_handler(libc::SIGTERM);
+        start_monitoring(signal_state(), DEFAULT_SIGNALS).unwrap();
+        assert_eq!(
+            current_handler(libc::SIGTERM),
+            unix::handle_signal as *const () as libc::sighandler_t
+        );
+
+        stop_monitoring().unwrap();
+        assert_eq!(current_handler(libc::SIGTERM), before);
+
+        // Monitoring can be started again after a stop
+        start_monitoring(signal_state(), DEFAULT_SIGNALS).unwrap();
+        stop_monitoring().unwrap();
+        assert_eq!(current_handler(libc::SIGTERM), before);
+    }
+
+    #[test]
+    fn test_only_requested_signals_are_handled() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+        let ours = unix::handle_signal as *const () as libc::sighandler_t;
+
+        start_monitoring(signal_state(), &[libc::SIGUSR1]).unwrap();
+        assert_eq!(current_handler(libc::SIGUSR1), ours);
+        assert_ne!(current_handler(libc::SIGINT), ours);
+        stop_monitoring().unwrap();
+
+        let err = start_monitoring(signal_state(), &[libc::SIGKILL]).unwrap_err();
+        assert_eq!(err, ShutdownError::UnsupportedSignal(libc::SIGKILL));
+        assert_ne!(current_handler(libc::SIGKILL), ours);
+    }
+
+    #[test]
+    fn test_handler_blocks_the_other_shutdown_signals() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        start_monitoring(signal_state(), &[libc::SIGTERM, libc::SIGINT]).unwrap();
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::sigaction(libc::SIGTERM, std::ptr::null(), &mut action);
+            assert_eq!(libc::sigismember(&action.sa_mask, libc::SIGINT), 1);
+            assert_eq!(libc::sigismember(&action.sa_mask, libc::SIGHUP), 0);
+        }
+        stop_monitoring().unwrap();
+    }
+
+    #[test]
+    fn test_sigpwr_reports_power_failure() {
+        assert!(DEFAULT_SIGNALS.contains(&libc::SIGPWR));
+        assert_eq!(
+            unix::signal_reason(libc::SIGPWR),
+            ShutdownReason::PowerFailure
+        );
+        assert_eq!(
+            unix::signal_reason(libc::SIGTERM),
+            ShutdownReason::Signal(libc::SIGTERM)
+        );
+    }
+
+    #[test]
+    fn test_sighup_is_handled_by_default() {
+        let _lock = SIGNAL_TEST_LOCK.lock();
+
+        start_monitoring(signal_state(), DEFAULT_SIGNALS).unwrap();
+        assert_eq!(
+            current_handler(libc::SIGHUP),
+            unix::handle_signal as *const () as libc::sighandler_t
+        );
+        assert_eq!(
+            unix::signal_reason(libc::SIGHUP),
+            ShutdownReason::Signal(libc::SIGHUP)
+        );
+        stop_monitoring().unwrap();
+    }
+
+    /// State for the signal backend, even if a system bus is reachable
+    fn signal_state() -> Arc<GuardState> {
+        Arc::new(GuardState {
+            #[cfg(feature = "dbus-support")]
+            linux_backend: LinuxBackend::Signals,
+            ..GuardState::default()
+        })
+    }
+
+    fn current_handler(sig: libc::c_int) -> libc::sighandler_t {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(sig, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        }
     }
 }
@@ -1,115 +1,299 @@
 //! macOS platform-specific implementation using signal handlers
 //!
-//! This implementation uses Unix signals (SIGTERM, SIGINT) to detect shutdown.
-//! On macOS, we need to handle signals synchronously and ensure immediate file writes.
+//! This implementation uses Unix signals (SIGTERM and SIGHUP by default)
+//! to detect shutdown. The callbacks run on a thread started by the signal
+//! dispatch thread, after which file data is synced and the process exits.
+//!
+//! With the `cocoa` feature, an observer for
+//! `NSWorkspaceWillPowerOffNotification` is registered as well, along with the
+//! sleep and wake notifications for `on_sleep` and `register_lifecycle`, and
+//! one for `NSApplicationWillTerminateNotification`, which a GUI app gets on
+//! Cmd-Q and at logout. Notifications are only delivered while the main run
+//! loop runs, as in GUI and menu-bar apps, and the last one only once an
+//! `NSApplication` exists; the signal handlers stay installed as the fallback
+//! for headless use.
 
-use crate::ShutdownCallback;
-use parking_lot::RwLock;
+use super::unix;
+use crate::{
+    trace, ActiveBackend, Backend, DispatchPriority, GuardState, MonitorHandle, ShutdownContext,
+    ShutdownError, ShutdownReason, SignalSender, TriggerSource,
+};
+#[cfg(feature = "cocoa")]
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-static mut GLOBAL_CALLBACKS: Option<Arc<RwLock<Vec<ShutdownCallback>>>> = None;
-static SIGNAL_RECEIVED: AtomicBool = AtomicBool::new(false);
+/// Set once the callbacks ran for a power-off or terminate notification
+#[cfg(feature = "cocoa")]
+static NOTIFICATION_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Signals handled by `ShutdownGuard::start`
+///
+/// SIGINT is added by `treat_interrupt_as_shutdown`.
+pub const DEFAULT_SIGNALS: &[i32] = &[libc::SIGTERM, libc::SIGHUP];
 
-/// Starts monitoring for macOS shutdown events
+/// Starts monitoring for macOS shutdown events on the given signals
 pub fn start_monitoring(
-    callbacks: Arc<RwLock<Vec<ShutdownCallback>>>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Store callbacks globally for signal handler access
+    state: Arc<GuardState>,
+    signals: &[i32],
+) -> Result<MonitorHandle, ShutdownError> {
+    unix::validate_signals(signals)?;
+    let verbose = state.verbose;
+
+    // Signal deliveries are dispatched from a dedicated thread
+    let thread = unix::start_signal_dispatch(state, dispatch_shutdown)?;
+
+    // Register signal handlers
     unsafe {
-        GLOBAL_CALLBACKS = Some(callbacks);
+        unix::register_signal_handlers(signals)?;
     }
 
-    // Register signal handlers
+    #[cfg(feature = "cocoa")]
+    unsafe {
+        workspace::add_power_off_observer();
+    }
+
+    trace::status(
+        verbose,
+        "macOS shutdown monitoring active (using signal handlers); run in the background \
+         or use nohup to avoid shutdown dialogs",
+    );
+
+    Ok(MonitorHandle::new(thread))
+}
+
+/// Describes the backend [`start_monitoring`] just started
+pub fn active_backend(_state: &GuardState, signals: &[i32]) -> ActiveBackend {
+    let backend = if cfg!(feature = "cocoa") {
+        Backend::SignalsAndWorkspace
+    } else {
+        Backend::Signals
+    };
+    #[allow(unused_mut)]
+    let mut watched: Vec<_> = signals.iter().map(|&sig| unix::signal_name(sig)).collect();
+    #[cfg(feature = "cocoa")]
+    watched.extend([
+        "NSWorkspaceWillPowerOffNotification",
+        "NSWorkspaceWillSleepNotification",
+        "NSWorkspaceDidWakeNotification",
+        "NSApplicationWillTerminateNotification",
+    ]);
+    ActiveBackend {
+        backend,
+        watched,
+        login_manager: None,
+    }
+}
+
+/// Checks that the signal handlers are still installed
+pub fn verify_active() -> Result<(), ShutdownError> {
+    unix::verify_signal_handlers()
+}
+
+/// Stops monitoring, restoring the signal dispositions saved at registration
+pub fn stop_monitoring() -> Result<(), ShutdownError> {
+    #[cfg(feature = "cocoa")]
     unsafe {
-        register_signal_handlers()?;
+        workspace::remove_power_off_observer();
     }
 
-    println!("macOS shutdown monitoring active (using signal handlers)");
-    println!("⚠️  Note: The application should run in the background or use nohup to avoid shutdown dialogs");
+    unix::stop_signal_handling()
+}
 
+/// Forgets the monitoring a forked child inherited, see
+/// [`unix::reset_after_fork`]
+///
+/// The `NSWorkspace` observer is left alone: Cocoa cannot be used in a
+/// forked child before it calls `exec`.
+pub fn reset_after_fork() -> Result<(), ShutdownError> {
+    #[cfg(feature = "cocoa")]
+    NOTIFICATION_RECEIVED.store(false, Ordering::SeqCst);
+    unix::reset_after_fork()
+}
+
+/// Leaves the calling thread's priority alone: macOS has no nice value per
+/// thread, and the Mach scheduling policies are not worth the risk here
+pub fn raise_thread_priority(_priority: DispatchPriority) -> std::io::Result<Option<i32>> {
+    Ok(None)
+}
+
+/// Never called, since [`raise_thread_priority`] changes nothing
+pub fn restore_thread_priority(_previous: i32) -> std::io::Result<()> {
     Ok(())
 }
 
-unsafe fn register_signal_handlers() -> Result<(), Box<dyn std::error::Error>> {
-    use std::mem;
+/// Runs the callbacks of the guards a shutdown signal starts a run for, then
+/// exits the process unless configured otherwise; guards already running
+/// them coalesce the signal into that run
+fn dispatch_shutdown(state: &Arc<GuardState>, sig: libc::c_int, sender: Option<SignalSender>) {
+    trace::status(
+        state.verbose,
+        format_args!("Received {}", unix::signal_name(sig)),
+    );
 
-    // Register SIGTERM handler (most common shutdown signal)
-    let mut sa_term: libc::sigaction = mem::zeroed();
-    sa_term.sa_sigaction = handle_shutdown_signal as usize;
-    sa_term.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
-    libc::sigemptyset(&mut sa_term.sa_mask);
+    let reason = state
+        .signal_reasons
+        .get(&sig)
+        .copied()
+        .unwrap_or_else(|| unix::signal_reason(sig));
+    let context = ShutdownContext::new(reason, TriggerSource::UnixSignal(sig)).with_sender(sender);
+    let guards = super::request_shutdown(context);
 
-    if libc::sigaction(libc::SIGTERM, &sa_term, std::ptr::null_mut()) != 0 {
-        return Err("Failed to register SIGTERM handler".into());
+    // The callbacks already ran if power-off or termination was announced
+    // first, but the signal still ends the process
+    #[cfg(feature = "cocoa")]
+    let notified = NOTIFICATION_RECEIVED.load(Ordering::SeqCst);
+    #[cfg(not(feature = "cocoa"))]
+    let notified = false;
+    if guards.is_empty() && !notified {
+        return;
     }
 
-    // Register SIGINT handler (Ctrl+C)
-    let mut sa_int: libc::sigaction = mem::zeroed();
-    sa_int.sa_sigaction = handle_shutdown_signal as usize;
-    sa_int.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
-    libc::sigemptyset(&mut sa_int.sa_mask);
+    unix::spawn_shutdown_run(state, move |state| {
+        for guard in &guards {
+            guard.run_shutdown(context);
+        }
+        unix::exit_after_shutdown(state, sig);
+    });
+}
 
-    if libc::sigaction(libc::SIGINT, &sa_int, std::ptr::null_mut()) != 0 {
-        return Err("Failed to register SIGINT handler".into());
+/// `NSWorkspace` power-off and `NSApplication` terminate notifications
+#[cfg(feature = "cocoa")]
+mod workspace {
+    use super::NOTIFICATION_RECEIVED;
+    use crate::{ShutdownContext, ShutdownReason, TriggerSource};
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::NSString;
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Class, Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    const OBSERVER_CLASS: &str = "ShutdownGuardPowerOffObserver";
+
+    /// The registered observer, or null
+    static OBSERVER: AtomicPtr<Object> = AtomicPtr::new(std::ptr::null_mut());
+
+    pub(super) unsafe fn add_power_off_observer() {
+        let observer: id = msg_send![observer_class(), new];
+        let center = notification_center();
+        let notifications = [
+            (
+                "NSWorkspaceWillPowerOffNotification",
+                sel!(workspaceWillPowerOff:),
+            ),
+            (
+                "NSWorkspaceWillSleepNotification",
+                sel!(workspaceWillSleep:),
+            ),
+            ("NSWorkspaceDidWakeNotification", sel!(workspaceDidWake:)),
+        ];
+        for (name, selector) in notifications {
+            let name = NSString::alloc(nil).init_str(name);
+            let _: () = msg_send![center,
+                addObserver: observer
+                selector: selector
+                name: name
+                object: nil];
+        }
+
+        // Posted by NSApplication, which need not exist yet; without one it
+        // never arrives and the signal handlers remain
+        let name = NSString::alloc(nil).init_str("NSApplicationWillTerminateNotification");
+        let _: () = msg_send![default_center(),
+            addObserver: observer
+            selector: sel!(applicationWillTerminate:)
+            name: name
+            object: nil];
+
+        let previous = OBSERVER.swap(observer, Ordering::SeqCst);
+        remove_observer(previous);
     }
 
-    // Register SIGHUP handler
-    let mut sa_hup: libc::sigaction = mem::zeroed();
-    sa_hup.sa_sigaction = handle_shutdown_signal as usize;
-    sa_hup.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
-    libc::sigemptyset(&mut sa_hup.sa_mask);
+    pub(super) unsafe fn remove_power_off_observer() {
+        remove_observer(OBSERVER.swap(std::ptr::null_mut(), Ordering::SeqCst));
+    }
 
-    if libc::sigaction(libc::SIGHUP, &sa_hup, std::ptr::null_mut()) != 0 {
-        return Err("Failed to register SIGHUP handler".into());
+    unsafe fn remove_observer(observer: id) {
+        if !observer.is_null() {
+            let _: () = msg_send![notification_center(), removeObserver: observer];
+            let _: () = msg_send![default_center(), removeObserver: observer];
+            let _: () = msg_send![observer, release];
+        }
     }
 
-    Ok(())
-}
+    unsafe fn notification_center() -> id {
+        let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        msg_send![workspace, notificationCenter]
+    }
 
-extern "C" fn handle_shutdown_signal(
-    sig: libc::c_int,
-    _: *mut libc::siginfo_t,
-    _: *mut libc::c_void,
-) {
-    // Avoid executing multiple times
-    if SIGNAL_RECEIVED.swap(true, Ordering::SeqCst) {
-        return;
+    /// The center `NSApplication` posts its notifications to
+    unsafe fn default_center() -> id {
+        msg_send![class!(NSNotificationCenter), defaultCenter]
     }
 
-    // Write signal info immediately (signal-safe)
-    let msg = match sig {
-        libc::SIGTERM => "Received SIGTERM\n\0",
-        libc::SIGINT => "Received SIGINT\n\0",
-        libc::SIGHUP => "Received SIGHUP\n\0",
-        _ => "Received unknown signal\n\0",
-    };
+    /// Declares the observer class on first use
+    fn observer_class() -> &'static Class {
+        if let Some(class) = Class::get(OBSERVER_CLASS) {
+            return class;
+        }
 
-    unsafe {
-        libc::write(2, msg.as_ptr() as *const libc::c_void, msg.len() - 1);
+        let mut decl = ClassDecl::new(OBSERVER_CLASS, class!(NSObject))
+            .expect("observer class is only declared once");
+        unsafe {
+            decl.add_method(
+                sel!(workspaceWillPowerOff:),
+                will_power_off as extern "C" fn(&Object, Sel, id),
+            );
+            decl.add_method(
+                sel!(workspaceWillSleep:),
+                will_sleep as extern "C" fn(&Object, Sel, id),
+            );
+            decl.add_method(
+                sel!(workspaceDidWake:),
+                did_wake as extern "C" fn(&Object, Sel, id),
+            );
+            decl.add_method(
+                sel!(applicationWillTerminate:),
+                will_terminate as extern "C" fn(&Object, Sel, id),
+            );
+        }
+        decl.register()
     }
 
-    // Execute callbacks - note: this is NOT signal-safe but we need it for functionality
-    unsafe {
-        let callbacks_ptr = std::ptr::addr_of!(GLOBAL_CALLBACKS);
-        if let Some(callbacks) = (*callbacks_ptr).as_ref() {
-            // Try to lock, but don't block forever
-            if let Some(callbacks_lock) = callbacks.try_read() {
-                for callback in callbacks_lock.iter() {
-                    callback();
-                }
-            }
-
-            // Force sync all file descriptors to disk
-            libc::sync();
-
-            // Small delay to ensure writes complete
-            libc::usleep(100_000); // 100ms
+    /// Runs the callbacks on the main thread when power-off is announced
+    extern "C" fn will_power_off(_this: &Object, _cmd: Sel, _notification: id) {
+        if NOTIFICATION_RECEIVED.swap(true, Ordering::SeqCst) {
+            return;
         }
+
+        let context = ShutdownContext::new(
+            ShutdownReason::PowerOff,
+            TriggerSource::WorkspaceNotification,
+        );
+        super::super::run_shutdown(context);
     }
 
-    // Exit immediately
-    unsafe {
-        libc::_exit(0);
+    /// Runs the callbacks on the main thread before the application quits;
+    /// AppKit exits the process once this returns
+    extern "C" fn will_terminate(_this: &Object, _cmd: Sel, _notification: id) {
+        if NOTIFICATION_RECEIVED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let context = ShutdownContext::new(
+            ShutdownReason::AppClose,
+            TriggerSource::ApplicationNotification,
+        );
+        super::super::run_shutdown(context);
+    }
+
+    /// Runs the sleep callbacks on the main thread before the system sleeps
+    extern "C" fn will_sleep(_this: &Object, _cmd: Sel, _notification: id) {
+        super::super::run_sleep_callbacks(TriggerSource::WorkspaceNotification);
+    }
+
+    /// Runs the resume callbacks on the main thread after the system woke up
+    extern "C" fn did_wake(_this: &Object, _cmd: Sel, _notification: id) {
+        super::super::run_resume_callbacks(TriggerSource::WorkspaceNotification);
     }
 }
@@ -1,19 +1,261 @@
 //! Platform-specific implementations
 
+use crate::{ActiveBackend, GuardState, MonitorHandle, ShutdownContext, ShutdownError};
+use parking_lot::Mutex;
+#[cfg(target_os = "windows")]
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Weak};
+
 #[cfg(target_os = "macos")]
 mod macos;
 
 #[cfg(target_os = "windows")]
 mod windows;
 
+#[cfg(all(target_os = "windows", feature = "windows-service"))]
+mod windows_service;
+
 #[cfg(target_os = "linux")]
 mod linux;
 
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+mod unix;
+
 #[cfg(target_os = "macos")]
-pub use macos::start_monitoring;
+use macos as backend;
 
 #[cfg(target_os = "windows")]
-pub use windows::start_monitoring;
+use windows as backend;
 
 #[cfg(target_os = "linux")]
-pub use linux::start_monitoring;
+use linux as backend;
+
+pub use backend::{raise_thread_priority, restore_thread_priority, DEFAULT_SIGNALS};
+#[cfg(all(target_os = "linux", feature = "dbus-support"))]
+pub use linux::{DBusBus, LinuxBackend, LogindMonitor, NoBackendPolicy};
+#[cfg(target_os = "windows")]
+pub use windows::EndSessionPhase;
+
+/// Guards that started monitoring and have not stopped, oldest first, and the
+/// handle of the backend they share
+///
+/// Guards are held weakly, so a dropped guard simply stops receiving
+/// shutdowns.
+static STARTED: Mutex<(Vec<Weak<GuardState>>, Option<MonitorHandle>)> =
+    Mutex::new((Vec::new(), None));
+
+/// What the running backend watches, set when it starts
+static ACTIVE: Mutex<Option<ActiveBackend>> = Mutex::new(None);
+
+/// Starts the platform backend for the first guard, and adds later guards to
+/// the ones it notifies
+///
+/// The handlers are only installed once, so the first guard's signals and
+/// platform settings apply to all of them; the signals of a later guard are
+/// only validated.
+pub fn start_monitoring(
+    state: Arc<GuardState>,
+    signals: &[i32],
+) -> Result<MonitorHandle, ShutdownError> {
+    let mut started = STARTED.lock();
+    let (guards, monitor) = &mut *started;
+    guards.retain(|guard| guard.strong_count() > 0);
+
+    let handle = match monitor {
+        Some(handle) if !guards.is_empty() => {
+            #[cfg(unix)]
+            unix::validate_signals(signals)?;
+            handle.clone()
+        }
+        _ => {
+            let handle = backend::start_monitoring(Arc::clone(&state), signals)?;
+            *ACTIVE.lock() = Some(backend::active_backend(&state, signals));
+            *monitor = Some(handle.clone());
+            handle
+        }
+    };
+    guards.push(Arc::downgrade(&state));
+    Ok(handle)
+}
+
+/// Stops notifying `state`, and stops the platform backend once no started
+/// guard is left
+pub fn stop_monitoring(state: &GuardState) -> Result<(), ShutdownError> {
+    let mut started = STARTED.lock();
+    let (guards, monitor) = &mut *started;
+    guards.retain(|guard| guard.strong_count() > 0 && !std::ptr::eq(guard.as_ptr(), state));
+    if !guards.is_empty() {
+        return Ok(());
+    }
+
+    *monitor = None;
+    *ACTIVE.lock() = None;
+    backend::stop_monitoring()
+}
+
+/// Forgets the started guards and the backend a forked child inherited
+///
+/// The monitor thread did not survive the fork, so its handle is leaked
+/// rather than detached.
+#[cfg(unix)]
+pub fn reset_after_fork() -> Result<(), ShutdownError> {
+    let mut started = STARTED.lock();
+    started.0.clear();
+    std::mem::forget(started.1.take());
+    *ACTIVE.lock() = None;
+    backend::reset_after_fork()
+}
+
+/// Unblocks `signals` in the calling thread, whose mask an exec'd image
+/// inherits
+#[cfg(unix)]
+pub fn unblock_signals(signals: &[i32]) -> Result<(), ShutdownError> {
+    unix::unblock_signals(signals)
+}
+
+/// Returns what the running backend watches, or `None` if no guard started it
+pub fn active_backend() -> Option<ActiveBackend> {
+    ACTIVE.lock().clone()
+}
+
+/// Returns the signals whose handlers are installed, none on Windows or while
+/// the D-Bus backend runs
+pub fn installed_signals() -> Vec<i32> {
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    return unix::installed_signals();
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    Vec::new()
+}
+
+/// Checks that the shared monitoring thread is alive and the backend still
+/// receives shutdown notifications
+pub fn verify_active() -> Result<(), ShutdownError> {
+    if let (_, Some(monitor)) = &*STARTED.lock() {
+        if monitor.is_finished() {
+            return Err(ShutdownError::MonitorFailed(
+                "the monitoring thread exited".to_string(),
+            ));
+        }
+    }
+    backend::verify_active()
+}
+
+/// Returns every started guard that is still alive, oldest first
+pub(crate) fn started_guards() -> Vec<Arc<GuardState>> {
+    STARTED.lock().0.iter().filter_map(Weak::upgrade).collect()
+}
+
+/// Reports a fatal monitoring-thread error to every started guard's
+/// [`on_monitor_error`](crate::ShutdownGuard::on_monitor_error) callback, or
+/// to stderr if none of them has one
+#[cfg(any(
+    target_os = "windows",
+    all(target_os = "linux", feature = "dbus-support")
+))]
+pub(crate) fn report_monitor_error(error: ShutdownError) {
+    let mut reported = false;
+    for guard in started_guards() {
+        if let Some(callback) = guard.monitor_error_callback.read().as_ref() {
+            callback(&error);
+            reported = true;
+        }
+    }
+    if !reported {
+        eprintln!("{}", error);
+    }
+}
+
+/// Runs the callbacks of every started guard for a detected shutdown
+#[cfg(any(target_os = "windows", all(target_os = "macos", feature = "cocoa")))]
+pub(crate) fn run_shutdown(context: ShutdownContext) {
+    for guard in started_guards() {
+        guard.run_shutdown(context);
+    }
+}
+
+/// Returns the started guards a signal starts a run for; the others record
+/// it as part of the run they already have
+#[cfg(unix)]
+pub(crate) fn request_shutdown(context: ShutdownContext) -> Vec<Arc<GuardState>> {
+    started_guards()
+        .into_iter()
+        .filter(|guard| guard.request_shutdown(context))
+        .collect()
+}
+
+/// Reports a signal delivery, or a console control event on Windows, to the
+/// raw signal callback of every started guard
+pub(crate) fn raw_signal(sig: i32) {
+    for guard in started_guards() {
+        if let Some(callback) = guard.raw_signal_callback.read().as_ref() {
+            callback(sig);
+        }
+    }
+}
+
+/// Delivers a real shutdown trigger to this process: SIGTERM on Unix, or the
+/// session-end messages to the hidden window on Windows
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn force_shutdown() {
+    #[cfg(unix)]
+    unsafe {
+        libc::raise(libc::SIGTERM);
+    }
+    #[cfg(target_os = "windows")]
+    windows::force_shutdown();
+}
+
+/// Runs the sleep callbacks of every started guard
+#[cfg(any(target_os = "windows", all(target_os = "macos", feature = "cocoa")))]
+pub(crate) fn run_sleep_callbacks(source: crate::TriggerSource) {
+    for guard in started_guards() {
+        guard.run_sleep_callbacks(source);
+    }
+}
+
+/// Runs the resume callbacks of every started guard
+#[cfg(any(target_os = "windows", all(target_os = "macos", feature = "cocoa")))]
+pub(crate) fn run_resume_callbacks(source: crate::TriggerSource) {
+    for guard in started_guards() {
+        guard.run_resume_callbacks(source);
+    }
+}
+
+/// Process-wide slot holding the state of the guard that started the backend
+///
+/// Console handlers and OS callbacks read it with a single atomic load, without
+/// locking and without ever creating a `&mut` to a static. A replaced state is
+/// intentionally leaked, since a handler running concurrently may still be
+/// using it.
+#[cfg(target_os = "windows")]
+pub(crate) struct GlobalState(AtomicPtr<GuardState>);
+
+#[cfg(target_os = "windows")]
+impl GlobalState {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicPtr::new(std::ptr::null_mut()))
+    }
+
+    /// Publishes the state for the handlers
+    pub(crate) fn set(&self, state: Arc<GuardState>) {
+        self.0
+            .swap(Arc::into_raw(state) as *mut GuardState, Ordering::SeqCst);
+    }
+
+    /// Withdraws the published state so handlers stop using it
+    pub(crate) fn clear(&self) {
+        self.0.swap(std::ptr::null_mut(), Ordering::SeqCst);
+    }
+
+    /// Returns the published state, if any
+    pub(crate) fn get(&self) -> Option<&'static GuardState> {
+        let ptr = self.0.load(Ordering::SeqCst);
+        // SAFETY: non-null pointers come from `Arc::into_raw` and are never
+        // released, so they stay valid for the rest of the process
+        unsafe { ptr.as_ref() }
+    }
+}
+
+/// Serializes tests that install process-wide signal or console handlers
+#[cfg(test)]
+pub(crate) static SIGNAL_TEST_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
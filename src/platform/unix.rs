@@ -0,0 +1,466 @@
+//! Signal plumbing shared by the Unix signal-handler backends
+//!
+//! The signal handler only writes the signal number and its sender to a
+//! pre-created pipe (the self-pipe trick). A dispatch thread reads them,
+//! forwards the signal to the raw
+//! signal callback and hands it to the backend, which runs the shutdown
+//! callbacks outside of signal context, where taking locks, allocating and
+//! catching panics are all safe.
+//!
+//! The Linux D-Bus backend only uses the signal validation.
+//!
+//! A child forked after `start` inherits the handlers, the pipe and the
+//! global state, but none of the threads: the dispatch thread is gone, so a
+//! signal only fills the pipe and the callbacks never run, and a lock held by
+//! another thread at the time of the fork stays held forever. Such a child
+//! should `exec` right away, or call `ShutdownGuard::reset_after_fork` before
+//! anything else to restore the previous dispositions and start again.
+#![cfg_attr(all(target_os = "linux", feature = "dbus-support"), allow(dead_code))]
+
+use crate::{ExitBehavior, GuardState, ShutdownError, ShutdownReason, SignalSender};
+use parking_lot::Mutex;
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Write end of the pipe from the signal handler to the dispatch thread, or -1
+static SIGNAL_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Read end of the current pipe while its dispatch thread runs, or -1
+static SIGNAL_READ_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// The process that created the current pipe and its dispatch thread
+static SIGNAL_PIPE_PID: AtomicI32 = AtomicI32::new(0);
+
+/// Held by the thread running the callbacks for a signal
+static SHUTDOWN_RUN: Mutex<()> = Mutex::new(());
+
+/// Called on the dispatch thread for every delivered signal, after the raw
+/// signal callback
+pub type SignalDispatch = fn(&Arc<GuardState>, libc::c_int, Option<SignalSender>);
+
+/// One signal delivery as written to the pipe, in a single write so that it
+/// is never interleaved with another
+#[repr(C)]
+#[derive(Default)]
+struct Delivery {
+    sig: libc::c_int,
+    /// Non-zero if a process sent the signal and `pid` and `uid` are set
+    has_sender: libc::c_int,
+    pid: libc::pid_t,
+    uid: libc::uid_t,
+}
+
+const DELIVERY_SIZE: usize = std::mem::size_of::<Delivery>();
+
+/// Signals that may be passed to `ShutdownGuard::start_with_signals`
+pub const SUPPORTED_SIGNALS: &[libc::c_int] = &[
+    libc::SIGTERM,
+    libc::SIGINT,
+    libc::SIGHUP,
+    libc::SIGQUIT,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+    #[cfg(target_os = "linux")]
+    libc::SIGPWR,
+];
+
+/// How long to wait before exiting unless `post_cleanup_delay` is set:
+/// sync(2) only schedules the writes on macOS, so give them a moment
+#[cfg(target_os = "macos")]
+const DEFAULT_POST_CLEANUP_DELAY: Duration = Duration::from_millis(100);
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_POST_CLEANUP_DELAY: Duration = Duration::ZERO;
+
+/// Maps a signal to the reason reported to callbacks, unless the builder's
+/// `signal_reasons` overrides it
+pub fn signal_reason(sig: libc::c_int) -> ShutdownReason {
+    match sig {
+        libc::SIGINT => ShutdownReason::Interrupt,
+        // Sent by UPS daemons and init on imminent power loss
+        #[cfg(target_os = "linux")]
+        libc::SIGPWR => ShutdownReason::PowerFailure,
+        _ => ShutdownReason::Signal(sig),
+    }
+}
+
+/// Flushes the file system and ends the process after the callbacks for `sig`
+/// ran, as configured with `ShutdownGuardBuilder::exit_behavior`,
+/// `call_global_sync` and `post_cleanup_delay`
+pub fn exit_after_shutdown(state: &GuardState, sig: libc::c_int) {
+    if !state.skip_global_sync {
+        unsafe {
+            libc::sync();
+        }
+    }
+    let exit_behavior = state.exit_behavior;
+    if exit_behavior == ExitBehavior::DoNotExit {
+        return;
+    }
+
+    std::thread::sleep(
+        state
+            .post_cleanup_delay
+            .unwrap_or(DEFAULT_POST_CLEANUP_DELAY),
+    );
+
+    unsafe {
+        if let ExitBehavior::Exit(code) = exit_behavior {
+            libc::_exit(code);
+        }
+        libc::signal(sig, libc::SIG_DFL);
+        libc::raise(sig);
+        // Only reached if the signal is blocked on this thread
+        libc::_exit(128 + sig);
+    }
+}
+
+/// Runs the callbacks for a shutdown signal on a thread of their own
+///
+/// The dispatch thread keeps reading deliveries meanwhile, so the signals that
+/// follow, e.g. SIGHUP right after SIGTERM, reach the debounce window instead
+/// of queueing behind the run. Runs for later signals wait for the earlier
+/// ones, so one never exits the process in the middle of another.
+pub fn spawn_shutdown_run(state: &Arc<GuardState>, run: impl FnOnce(&GuardState) + Send + 'static) {
+    let state = Arc::clone(state);
+    std::thread::spawn(move || {
+        let _running = SHUTDOWN_RUN.lock();
+        if state.abort_on_dispatch_panic {
+            crate::panic::abort_on_panic("signal dispatch", || run(&state));
+        } else {
+            run(&state);
+        }
+    });
+}
+
+/// Checks that every signal can be caught and is meant to trigger shutdown
+///
+/// SIGKILL and SIGSTOP are not in [`SUPPORTED_SIGNALS`], since they cannot be
+/// caught.
+pub fn validate_signals(signals: &[libc::c_int]) -> Result<(), ShutdownError> {
+    match signals.iter().find(|sig| !SUPPORTED_SIGNALS.contains(sig)) {
+        Some(&sig) => Err(ShutdownError::UnsupportedSignal(sig)),
+        None => Ok(()),
+    }
+}
+
+/// Returns the conventional name of a signal, e.g. `"SIGTERM"`
+pub fn signal_name(sig: libc::c_int) -> &'static str {
+    match sig {
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGINT => "SIGINT",
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGUSR2 => "SIGUSR2",
+        #[cfg(target_os = "linux")]
+        libc::SIGPWR => "SIGPWR",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGSTOP => "SIGSTOP",
+        _ => "unknown signal",
+    }
+}
+
+/// Dispositions replaced by [`install_handler`], restored by [`stop_signal_handling`]
+static PREVIOUS_HANDLERS: Mutex<Vec<(libc::c_int, libc::sigaction)>> = Mutex::new(Vec::new());
+
+/// Installs `action` for `sig`, remembering the disposition it replaces
+///
+/// Returns `false` if `sigaction(2)` fails.
+pub unsafe fn install_handler(sig: libc::c_int, action: &libc::sigaction) -> bool {
+    let mut previous: libc::sigaction = std::mem::zeroed();
+    if libc::sigaction(sig, action, &mut previous) != 0 {
+        return false;
+    }
+
+    // Keep the oldest disposition so a repeated install doesn't save our own
+    let mut saved = PREVIOUS_HANDLERS.lock();
+    if !saved.iter().any(|(saved_sig, _)| *saved_sig == sig) {
+        saved.push((sig, previous));
+    }
+    true
+}
+
+/// Installs [`handle_signal`] for every signal
+///
+/// While the handler runs for one of `signals`, all of `signals` are blocked,
+/// so a second shutdown signal, e.g. SIGINT right after SIGTERM, waits until
+/// the first byte is in the pipe instead of interrupting the handler. Later
+/// deliveries are coalesced on the dispatch thread: the callbacks run once and
+/// only the raw signal callback sees every delivery.
+///
+/// A signal whose `sigaction(2)` fails is logged and skipped, so the others
+/// still trigger shutdown; [`installed_signals`] tells which ones did. Fails
+/// with the first error only if none could be installed.
+pub unsafe fn register_signal_handlers(signals: &[libc::c_int]) -> Result<(), ShutdownError> {
+    let mut mask: libc::sigset_t = std::mem::zeroed();
+    libc::sigemptyset(&mut mask);
+    for &sig in signals {
+        libc::sigaddset(&mut mask, sig);
+    }
+
+    let mut failed = Vec::new();
+    for &sig in signals {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        action.sa_mask = mask;
+
+        if !install_handler(sig, &action) {
+            failed.push(ShutdownError::SignalRegistrationFailed {
+                signo: sig,
+                errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
+            });
+        }
+    }
+
+    if !signals.is_empty() && failed.len() == signals.len() {
+        return Err(failed.swap_remove(0));
+    }
+    for error in failed {
+        eprintln!("{}", error);
+    }
+    Ok(())
+}
+
+/// Returns the signals [`handle_signal`] is installed for, in the order they
+/// were installed
+pub fn installed_signals() -> Vec<libc::c_int> {
+    PREVIOUS_HANDLERS
+        .lock()
+        .iter()
+        .map(|&(sig, _)| sig)
+        .collect()
+}
+
+/// Unblocks `signals` in the calling thread's signal mask
+///
+/// The mask survives `execve(2)`, so an image exec'd from a thread that had
+/// them blocked would otherwise never see them, and threads spawned
+/// afterwards inherit it.
+pub fn unblock_signals(signals: &[libc::c_int]) -> Result<(), ShutdownError> {
+    let result = unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for &sig in signals {
+            libc::sigaddset(&mut set, sig);
+        }
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut())
+    };
+    if result != 0 {
+        return Err(ShutdownError::Platform(format!(
+            "Failed to unblock the shutdown signals: {}",
+            io::Error::from_raw_os_error(result)
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that [`handle_signal`] is still installed for every signal it was
+/// installed for, e.g. that no other library replaced it since
+pub fn verify_signal_handlers() -> Result<(), ShutdownError> {
+    for &(sig, _) in PREVIOUS_HANDLERS.lock().iter() {
+        let mut current: libc::sigaction = unsafe { std::mem::zeroed() };
+        if unsafe { libc::sigaction(sig, std::ptr::null(), &mut current) } != 0 {
+            return Err(ShutdownError::Platform(format!(
+                "Failed to query the handler for signal {}: {}",
+                sig,
+                io::Error::last_os_error()
+            )));
+        }
+        if current.sa_sigaction != handle_signal as *const () as libc::sighandler_t {
+            return Err(ShutdownError::MonitorFailed(format!(
+                "the handler for signal {} was replaced",
+                sig
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Restores the dispositions saved by [`install_handler`] and closes the
+/// signal pipe, which makes the dispatch thread exit
+pub fn stop_signal_handling() -> Result<(), ShutdownError> {
+    let mut failed = Vec::new();
+    for (sig, previous) in PREVIOUS_HANDLERS.lock().drain(..) {
+        if unsafe { libc::sigaction(sig, &previous, std::ptr::null_mut()) } != 0 {
+            failed.push(sig);
+        }
+    }
+
+    let fd = SIGNAL_WRITE_FD.swap(-1, Ordering::SeqCst);
+    if fd >= 0 {
+        unsafe {
+            libc::close(fd);
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(ShutdownError::Platform(format!(
+            "Failed to restore handlers for signals {:?}",
+            failed
+        )))
+    }
+}
+
+/// Creates the signal pipe and spawns the thread that dispatches deliveries
+/// to the raw signal callback and then to `dispatch`
+///
+/// Calling this again replaces the pipe; the previous dispatch thread sees
+/// end-of-file and exits.
+pub fn start_signal_dispatch(
+    state: Arc<GuardState>,
+    dispatch: SignalDispatch,
+) -> Result<JoinHandle<()>, ShutdownError> {
+    let mut fds = [0 as libc::c_int; 2];
+
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(ShutdownError::Platform(format!(
+                "Failed to create signal pipe: {}",
+                io::Error::last_os_error()
+            )));
+        }
+
+        // The handler must never block, so the write end is non-blocking
+        libc::fcntl(fds[0], libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(fds[1], libc::F_SETFD, libc::FD_CLOEXEC);
+        let flags = libc::fcntl(fds[1], libc::F_GETFL);
+        libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+
+    let read_fd = fds[0];
+    SIGNAL_READ_FD.store(read_fd, Ordering::SeqCst);
+    SIGNAL_PIPE_PID.store(unsafe { libc::getpid() }, Ordering::SeqCst);
+    let thread = std::thread::spawn(move || dispatch_signals(read_fd, state, dispatch));
+
+    let previous = SIGNAL_WRITE_FD.swap(fds[1], Ordering::SeqCst);
+    if previous >= 0 {
+        unsafe {
+            libc::close(previous);
+        }
+    }
+
+    Ok(thread)
+}
+
+/// Reports a signal delivery and its sender to the dispatch thread
+///
+/// Only reads the `siginfo_t`, performs a single `write(2)` to a pre-created
+/// pipe and preserves `errno`, so it is async-signal-safe.
+pub extern "C" fn handle_signal(
+    sig: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _: *mut libc::c_void,
+) {
+    let fd = SIGNAL_WRITE_FD.load(Ordering::SeqCst);
+    if fd < 0 {
+        return;
+    }
+
+    let mut delivery = Delivery {
+        sig,
+        ..Delivery::default()
+    };
+    unsafe {
+        // Only signals sent by a process carry a meaningful pid and uid
+        if !info.is_null() && sent_by_process((*info).si_code) {
+            delivery.has_sender = 1;
+            delivery.pid = (*info).si_pid();
+            delivery.uid = (*info).si_uid();
+        }
+
+        let saved_errno = *errno_location();
+        libc::write(
+            fd,
+            &delivery as *const Delivery as *const libc::c_void,
+            DELIVERY_SIZE,
+        );
+        *errno_location() = saved_errno;
+    }
+}
+
+/// Returns true if `si_code` marks a signal sent by a process with `kill(2)`,
+/// `sigqueue(3)` or, on Linux, `tgkill(2)`
+#[cfg(target_os = "linux")]
+fn sent_by_process(code: libc::c_int) -> bool {
+    // SI_USER is 0 and SI_QUEUE, SI_TKILL and friends are negative
+    code <= 0
+}
+
+#[cfg(target_os = "macos")]
+fn sent_by_process(code: libc::c_int) -> bool {
+    // SI_USER and SI_QUEUE, which `libc` does not define for macOS
+    matches!(code, 0x10001 | 0x10002)
+}
+
+fn dispatch_signals(read_fd: libc::c_int, state: Arc<GuardState>, dispatch: SignalDispatch) {
+    let mut delivery = Delivery::default();
+
+    loop {
+        // Writes this small are atomic, so every read returns whole deliveries
+        let n = unsafe {
+            libc::read(
+                read_fd,
+                &mut delivery as *mut Delivery as *mut libc::c_void,
+                DELIVERY_SIZE,
+            )
+        };
+
+        if n == DELIVERY_SIZE as isize {
+            let sender = (delivery.has_sender != 0).then_some(SignalSender {
+                pid: delivery.pid,
+                uid: delivery.uid,
+            });
+            let deliver = || {
+                super::raw_signal(delivery.sig);
+                dispatch(&state, delivery.sig, sender);
+            };
+            if state.abort_on_dispatch_panic {
+                crate::panic::abort_on_panic("signal dispatch", deliver);
+            } else {
+                deliver();
+            }
+        } else if n == 0
+            || (n < 0 && std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted)
+        {
+            break;
+        }
+    }
+
+    // A newer pipe may already have replaced this one
+    let _ = SIGNAL_READ_FD.compare_exchange(read_fd, -1, Ordering::SeqCst, Ordering::SeqCst);
+    unsafe {
+        libc::close(read_fd);
+    }
+}
+
+/// Restores the previous dispositions and closes the signal pipe
+///
+/// In a child forked from the process that created the pipe, the dispatch
+/// thread that would close the read end on end-of-file is gone, so the read
+/// end is closed here too.
+pub fn reset_after_fork() -> Result<(), ShutdownError> {
+    let restored = stop_signal_handling();
+    let fd = SIGNAL_READ_FD.swap(-1, Ordering::SeqCst);
+    if fd >= 0 && SIGNAL_PIPE_PID.load(Ordering::SeqCst) != unsafe { libc::getpid() } {
+        unsafe {
+            libc::close(fd);
+        }
+    }
+    restored
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__error()
+}
@@ -1,52 +1,848 @@
 //! Windows platform-specific implementation using Console Control Handler
+//! and a hidden window
+//!
+//! Console processes that load user32.dll no longer receive
+//! `CTRL_SHUTDOWN_EVENT`/`CTRL_LOGOFF_EVENT`, so a hidden window listens for
+//! `WM_QUERYENDSESSION`/`WM_ENDSESSION` as well.
 
-use crate::ShutdownCallback;
-use parking_lot::RwLock;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use windows::core::BOOL;
+use super::GlobalState;
+use crate::{
+    trace, ActiveBackend, Backend, DispatchPriority, GuardState, MonitorHandle, ShutdownContext,
+    ShutdownDecision, ShutdownError, ShutdownReason, TriggerSource,
+};
+use parking_lot::{Condvar, Mutex};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use windows::core::{w, BOOL, PCWSTR};
+use windows::Win32::Foundation::{
+    GetLastError, ERROR_CLASS_ALREADY_EXISTS, HANDLE, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM,
+};
 use windows::Win32::System::Console::*;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Power::{
+    RegisterSuspendResumeNotification, UnregisterSuspendResumeNotification, HPOWERNOTIFY,
+};
+use windows::Win32::System::Shutdown::{ShutdownBlockReasonCreate, ShutdownBlockReasonDestroy};
+use windows::Win32::System::Threading::{
+    GetCurrentThread, GetThreadPriority, SetThreadPriority, THREAD_PRIORITY,
+    THREAD_PRIORITY_HIGHEST, THREAD_PRIORITY_TIME_CRITICAL,
+};
+use windows::Win32::UI::WindowsAndMessaging::*;
 
-static mut GLOBAL_CALLBACKS: Option<Arc<RwLock<Vec<ShutdownCallback>>>> = None;
+static GLOBAL_STATE: GlobalState = GlobalState::new();
 static HANDLER_CALLED: AtomicBool = AtomicBool::new(false);
 
+/// Set and notified once the callbacks started by the first trigger finished
+static HANDLER_DONE: Mutex<bool> = Mutex::new(false);
+static HANDLER_DONE_CHANGED: Condvar = Condvar::new();
+
+/// Roughly how long Windows lets a console control handler run for
+/// `CTRL_CLOSE_EVENT`, `CTRL_LOGOFF_EVENT` and `CTRL_SHUTDOWN_EVENT` before
+/// terminating the process
+const CONSOLE_TIME_BUDGET: Duration = Duration::from_secs(5);
+
+/// Windows' default `HungAppTimeout`, after which a window that stopped
+/// pumping messages is reported as not responding
+const HUNG_APP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long `WM_ENDSESSION` waits for the callbacks before returning, slightly
+/// under [`HUNG_APP_TIMEOUT`]
+const END_SESSION_WAIT: Duration = HUNG_APP_TIMEOUT.saturating_sub(Duration::from_millis(500));
+
+/// How long `WM_ENDSESSION` waits for the callbacks of a critical shutdown,
+/// which Windows forces through without waiting for applications
+const CRITICAL_END_SESSION_WAIT: Duration = Duration::from_secs(1);
+
+/// Posted to the hidden window once the callbacks started by `WM_ENDSESSION`
+/// finished
+const WM_CALLBACKS_DONE: u32 = WM_APP + 1;
+
+thread_local! {
+    /// The block reason shown while the `WM_ENDSESSION` callbacks run; only
+    /// touched on the window thread, which owns it
+    static BLOCK_REASON: RefCell<Option<BlockReason>> = const { RefCell::new(None) };
+}
+
+/// Handle of the hidden window, or null when it isn't running
+static WINDOW: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Disconnected once the window thread of the running guard exited, after
+/// it unregistered the window class
+static WINDOW_THREAD_EXITED: Mutex<Option<mpsc::Receiver<()>>> = Mutex::new(None);
+
+/// How long `stop_monitoring` waits for the window thread to exit; as long
+/// as a `WM_ENDSESSION` in progress may keep it busy
+const WINDOW_STOP_TIMEOUT: Duration = HUNG_APP_TIMEOUT;
+
+/// Suspend/resume registration of the hidden window, or 0
+static SUSPEND_NOTIFY: AtomicIsize = AtomicIsize::new(0);
+
+/// How often creating the hidden window is tried before `start` fails
+const WINDOW_CREATION_ATTEMPTS: u32 = 4;
+
+/// Wait before the first retry of a failed window creation, doubled for
+/// every further one
+const INITIAL_WINDOW_CREATION_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Which session-end message runs the callbacks
+///
+/// Chosen with
+/// [`ShutdownGuardBuilder::run_callbacks_on`](crate::ShutdownGuardBuilder::run_callbacks_on).
+/// Whichever it is, the callbacks run once per shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EndSessionPhase {
+    /// `WM_QUERYENDSESSION`, once the session end was not vetoed; the
+    /// callbacks get the most time, but another application may still cancel
+    /// the shutdown after they ran
+    QueryEndSession,
+    /// A committing `WM_ENDSESSION`, after which Windows may end the process
+    /// at any time
+    #[default]
+    EndSession,
+}
+
+/// Signals handled by `ShutdownGuard::start`; Windows has none
+pub const DEFAULT_SIGNALS: &[i32] = &[];
+
 /// Starts monitoring for Windows shutdown events
+///
+/// Console control events are always handled; Unix signals are rejected.
 pub fn start_monitoring(
-    callbacks: Arc<RwLock<Vec<ShutdownCallback>>>,
-) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    state: Arc<GuardState>,
+    signals: &[i32],
+) -> std::result::Result<MonitorHandle, ShutdownError> {
+    if let Some(&sig) = signals.first() {
+        return Err(ShutdownError::UnsupportedSignal(sig));
+    }
+
+    let verbose = state.verbose;
+    rearm_handler();
+    GLOBAL_STATE.set(Arc::clone(&state));
+
     unsafe {
-        GLOBAL_CALLBACKS = Some(callbacks);
+        SetConsoleCtrlHandler(Some(console_ctrl_handler), true).map_err(|e| {
+            ShutdownError::Platform(format!("Failed to set console control handler: {}", e))
+        })?;
+    }
 
-        SetConsoleCtrlHandler(Some(console_ctrl_handler), true)
-            .map_err(|e| format!("Failed to set console control handler: {}", e))?;
+    // The window must pump messages on the thread that created it; wait until
+    // it exists, so no session end is missed once this returns
+    let (tx, rx) = mpsc::channel();
+    let (exited_tx, exited_rx) = mpsc::channel();
+    let thread = std::thread::spawn(move || run_message_window(tx, exited_tx, verbose));
+    let created = rx
+        .recv()
+        .unwrap_or_else(|_| Err("the window thread exited unexpectedly".to_string()));
+    if let Err(e) = created {
+        unsafe {
+            let _ = SetConsoleCtrlHandler(Some(console_ctrl_handler), false);
+        }
+        GLOBAL_STATE.clear();
+        return Err(ShutdownError::WindowCreationFailed(e));
+    }
+    *WINDOW_THREAD_EXITED.lock() = Some(exited_rx);
 
-        println!("Windows shutdown monitoring active (using Console Control Handler)");
+    #[cfg(feature = "windows-service")]
+    if let Some(name) = state.service_name.as_deref() {
+        super::windows_service::register(name, &state)?;
     }
 
+    trace::status(
+        verbose,
+        "Windows shutdown monitoring active (using Console Control Handler and a hidden window)",
+    );
+
+    Ok(MonitorHandle::new(thread))
+}
+
+/// Describes the backend [`start_monitoring`] just started
+pub fn active_backend(state: &GuardState, _signals: &[i32]) -> ActiveBackend {
+    let mut watched = vec![
+        "WM_QUERYENDSESSION",
+        "WM_ENDSESSION",
+        "WM_POWERBROADCAST",
+        "CTRL_CLOSE_EVENT",
+        "CTRL_LOGOFF_EVENT",
+        "CTRL_SHUTDOWN_EVENT",
+    ];
+    if state.interrupt_is_shutdown {
+        watched.push("CTRL_C_EVENT");
+    }
+    #[cfg(feature = "windows-service")]
+    if state.service_name.is_some() {
+        watched.extend([
+            "SERVICE_CONTROL_SHUTDOWN",
+            "SERVICE_CONTROL_PRESHUTDOWN",
+            "SERVICE_CONTROL_STOP",
+        ]);
+        return ActiveBackend {
+            backend: Backend::WindowsService,
+            watched,
+            login_manager: None,
+        };
+    }
+    ActiveBackend {
+        backend: Backend::MessageWindow,
+        watched,
+        login_manager: None,
+    }
+}
+
+/// What `GetThreadPriority` returns on failure
+const THREAD_PRIORITY_ERROR_RETURN: i32 = i32::MAX;
+
+/// Sets the priority of the calling thread for `priority` and returns the
+/// previous one, or `None` for [`DispatchPriority::Normal`]
+pub fn raise_thread_priority(priority: DispatchPriority) -> std::io::Result<Option<i32>> {
+    let raised = match priority {
+        DispatchPriority::Normal => return Ok(None),
+        DispatchPriority::High => THREAD_PRIORITY_HIGHEST,
+        DispatchPriority::Highest => THREAD_PRIORITY_TIME_CRITICAL,
+    };
+    unsafe {
+        let thread = GetCurrentThread();
+        let previous = GetThreadPriority(thread);
+        if previous == THREAD_PRIORITY_ERROR_RETURN {
+            return Err(std::io::Error::last_os_error());
+        }
+        SetThreadPriority(thread, raised).map_err(|e| std::io::Error::other(e.to_string()))?;
+        Ok(Some(previous))
+    }
+}
+
+/// Sets the priority of the calling thread back to `previous`
+pub fn restore_thread_priority(previous: i32) -> std::io::Result<()> {
+    unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY(previous)) }
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Checks that the hidden window still exists
+pub fn verify_active() -> Result<(), ShutdownError> {
+    if WINDOW.load(Ordering::SeqCst).is_null() {
+        return Err(ShutdownError::MonitorFailed(
+            "the shutdown window is gone".to_string(),
+        ));
+    }
     Ok(())
 }
 
+/// Stops monitoring by removing the console control handler and closing the
+/// hidden window, which ends its message loop
+///
+/// Waits up to [`WINDOW_STOP_TIMEOUT`] for the window thread to exit, so the
+/// window class is unregistered before `start` can register it again, and
+/// fails with [`ShutdownError::StopTimedOut`] if it does not.
+pub fn stop_monitoring() -> std::result::Result<(), ShutdownError> {
+    unsafe {
+        SetConsoleCtrlHandler(Some(console_ctrl_handler), false).map_err(|e| {
+            ShutdownError::Platform(format!("Failed to remove console control handler: {}", e))
+        })?;
+    }
+
+    let hwnd = WINDOW.swap(std::ptr::null_mut(), Ordering::SeqCst);
+    if !hwnd.is_null() {
+        unsafe {
+            PostMessageW(Some(HWND(hwnd)), WM_CLOSE, WPARAM(0), LPARAM(0)).map_err(|e| {
+                ShutdownError::Platform(format!("Failed to close shutdown window: {}", e))
+            })?;
+        }
+    }
+
+    GLOBAL_STATE.clear();
+
+    if let Some(exited) = WINDOW_THREAD_EXITED.lock().take() {
+        if let Err(mpsc::RecvTimeoutError::Timeout) = exited.recv_timeout(WINDOW_STOP_TIMEOUT) {
+            return Err(ShutdownError::StopTimedOut);
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles console control events
+///
+/// Windows calls this on a thread it creates for each event, and terminates
+/// the process as soon as it returns for close, logoff and shutdown events,
+/// or once [`CONSOLE_TIME_BUDGET`] has passed.
 unsafe extern "system" fn console_ctrl_handler(ctrl_type: u32) -> BOOL {
-    // Only handle shutdown events, not Ctrl+C
+    // Report every control event, even the ones deduplicated below. The
+    // handler runs on its own thread, so calling back into Rust is fine here.
+    let Some(state) = GLOBAL_STATE.get() else {
+        return BOOL(0);
+    };
+
+    super::raw_signal(ctrl_type as i32);
+
+    // Only handle Ctrl+C if asked to, then let the default handler end the
+    // process as it would have without us
     match ctrl_type {
+        CTRL_C_EVENT if state.interrupt_is_shutdown => {
+            let context = ShutdownContext::new(
+                ShutdownReason::Interrupt,
+                TriggerSource::WindowsConsoleEvent(ctrl_type),
+            );
+            run_shutdown_once(context);
+
+            BOOL(0)
+        }
         CTRL_SHUTDOWN_EVENT | CTRL_LOGOFF_EVENT | CTRL_CLOSE_EVENT => {
-            // Avoid executing multiple times
-            if HANDLER_CALLED.swap(true, Ordering::SeqCst) {
-                return BOOL(1);
+            let within_budget = state
+                .deadline
+                .read()
+                .is_some_and(|(deadline, _)| deadline <= CONSOLE_TIME_BUDGET);
+            if !within_budget {
+                eprintln!(
+                    "Windows terminates the process about {:?} after a console control event; \
+                     set a shorter deadline to bound the shutdown callbacks",
+                    CONSOLE_TIME_BUDGET
+                );
+            }
+
+            let context = ShutdownContext::new(
+                shutdown_reason(ctrl_type),
+                TriggerSource::WindowsConsoleEvent(ctrl_type),
+            );
+            run_shutdown_once(context);
+
+            BOOL(1)
+        }
+        _ => BOOL(0),
+    }
+}
+
+/// Runs the callbacks for the first trigger, console event, window message
+/// or service control
+///
+/// Later triggers wait until those callbacks finished instead of returning
+/// right away, because Windows may end the process as soon as any of the
+/// handlers returns.
+pub(super) fn run_shutdown_once(context: ShutdownContext) {
+    if HANDLER_CALLED.swap(true, Ordering::SeqCst) {
+        let mut done = HANDLER_DONE.lock();
+        while !*done {
+            HANDLER_DONE_CHANGED.wait(&mut done);
+        }
+        return;
+    }
+
+    super::run_shutdown(context);
+
+    *HANDLER_DONE.lock() = true;
+    HANDLER_DONE_CHANGED.notify_all();
+}
+
+/// Posts `WM_QUERYENDSESSION` and a committing `WM_ENDSESSION` to the hidden
+/// window, as Windows does for a shutdown; does nothing if it isn't running
+#[cfg(any(test, feature = "testing"))]
+pub(super) fn force_shutdown() {
+    let hwnd = WINDOW.load(Ordering::SeqCst);
+    if hwnd.is_null() {
+        return;
+    }
+    unsafe {
+        let _ = PostMessageW(Some(HWND(hwnd)), WM_QUERYENDSESSION, WPARAM(0), LPARAM(0));
+        let _ = PostMessageW(Some(HWND(hwnd)), WM_ENDSESSION, WPARAM(1), LPARAM(0));
+    }
+}
+
+/// Lets the next trigger run the callbacks again after a restart
+fn rearm_handler() {
+    HANDLER_CALLED.store(false, Ordering::SeqCst);
+    *HANDLER_DONE.lock() = false;
+}
+
+/// Creates the hidden window, reports the outcome through `ready` and pumps
+/// its messages until the window is destroyed
+///
+/// `exited` is dropped last, once the window class is unregistered.
+fn run_message_window(
+    ready: mpsc::Sender<Result<(), String>>,
+    exited: mpsc::Sender<()>,
+    verbose: bool,
+) {
+    let hwnd = match create_message_window_retrying(verbose) {
+        Ok(hwnd) => hwnd,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    WINDOW.store(hwnd.0, Ordering::SeqCst);
+    let _ = ready.send(Ok(()));
+
+    let mut msg = MSG::default();
+    let failed = unsafe {
+        loop {
+            match GetMessageW(&mut msg, None, 0, 0).0 {
+                0 => break false,
+                -1 => break true,
+                _ => {
+                    let _ = TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+        }
+    };
+
+    // `stop_monitoring` takes the window before closing it, so a window that
+    // is still stored ended without being asked to
+    let unexpected = WINDOW
+        .compare_exchange(
+            hwnd.0,
+            std::ptr::null_mut(),
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .is_ok();
+    if failed {
+        super::report_monitor_error(ShutdownError::MonitorFailed(format!(
+            "Failed to read the shutdown window's messages: {}",
+            windows::core::Error::from_thread()
+        )));
+    } else if unexpected {
+        super::report_monitor_error(ShutdownError::MonitorFailed(
+            "the shutdown window was destroyed".to_string(),
+        ));
+    }
+
+    // So that a stopped guard leaves nothing behind
+    unsafe { unregister_window_class() };
+    drop(exited);
+}
+
+/// Creates the hidden window, retrying with exponential backoff up to
+/// [`WINDOW_CREATION_ATTEMPTS`] times, since creation can fail transiently
+/// while the system runs short of resources
+fn create_message_window_retrying(verbose: bool) -> Result<HWND, String> {
+    let mut backoff = INITIAL_WINDOW_CREATION_BACKOFF;
+    for _ in 1..WINDOW_CREATION_ATTEMPTS {
+        match unsafe { create_message_window() } {
+            Ok(hwnd) => return Ok(hwnd),
+            Err(e) => {
+                trace::status(
+                    verbose,
+                    format_args!(
+                        "Failed to create the shutdown window, retrying in {:?}: {}",
+                        backoff, e
+                    ),
+                );
+                std::thread::sleep(backoff);
+                backoff *= 2;
             }
+        }
+    }
+
+    unsafe { create_message_window() }.map_err(|e| {
+        unsafe { unregister_window_class() };
+        format!("{} (after {} attempts)", e, WINDOW_CREATION_ATTEMPTS)
+    })
+}
+
+/// Name of the hidden window's class
+fn window_class() -> PCWSTR {
+    w!("ShutdownGuardWindow")
+}
+
+/// Unregisters the hidden window's class once no window of it is left
+unsafe fn unregister_window_class() {
+    if let Ok(module) = GetModuleHandleW(None) {
+        let _ = UnregisterClassW(window_class(), Some(module.into()));
+    }
+}
+
+unsafe fn create_message_window() -> Result<HWND, String> {
+    let instance: HINSTANCE = GetModuleHandleW(None)
+        .map_err(|e| format!("Failed to get module handle: {}", e))?
+        .into();
+    let class_name = window_class();
+
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance,
+        lpszClassName: class_name,
+        ..Default::default()
+    };
+    // Fails harmlessly with ERROR_CLASS_ALREADY_EXISTS after a failed attempt
+    if RegisterClassW(&class) == 0 {
+        let error = GetLastError();
+        if error != ERROR_CLASS_ALREADY_EXISTS {
+            return Err(format!(
+                "Failed to register the window class: {}",
+                windows::core::Error::from(error)
+            ));
+        }
+    }
+
+    // Message-only windows (parent `HWND_MESSAGE`) never receive broadcast
+    // messages such as WM_QUERYENDSESSION, so this has to be a top-level
+    // window. It is never shown, and WS_EX_TOOLWINDOW keeps it out of the
+    // taskbar and Alt+Tab.
+    let hwnd = CreateWindowExW(
+        WS_EX_TOOLWINDOW,
+        class_name,
+        w!("Shutdown Guard"),
+        WINDOW_STYLE(0),
+        0,
+        0,
+        0,
+        0,
+        None,
+        None,
+        Some(instance),
+        None,
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Top-level windows get WM_POWERBROADCAST anyway, but on modern standby
+    // systems suspend is only reported after registering for it
+    match RegisterSuspendResumeNotification(HANDLE(hwnd.0), DEVICE_NOTIFY_WINDOW_HANDLE) {
+        Ok(notify) => SUSPEND_NOTIFY.store(notify.0, Ordering::SeqCst),
+        Err(e) => eprintln!("Failed to register for suspend notifications: {}", e),
+    }
+
+    Ok(hwnd)
+}
 
-            // Execute all registered callbacks
-            let callbacks_ptr = std::ptr::addr_of!(GLOBAL_CALLBACKS);
-            if let Some(callbacks) = (*callbacks_ptr).as_ref() {
-                if let Some(callbacks_lock) = callbacks.try_read() {
-                    for callback in callbacks_lock.iter() {
-                        callback();
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        // Allow the session to end unless the veto predicate or the
+        // application's decision objects. Nothing is latched here: the query
+        // may still be denied, by us or another app, and then the guard must
+        // stay armed for the next one.
+        WM_QUERYENDSESSION => {
+            let reason = end_session_reason(lparam);
+            let guards = super::started_guards();
+            let started = Instant::now();
+            for guard in &guards {
+                if guard.decision_timeout.is_some() {
+                    guard.pending_decision.open();
+                }
+            }
+            let mut vetoed = guards
+                .iter()
+                .any(|guard| guard.veto.read().as_ref().is_some_and(|veto| veto(reason)));
+            for guard in &guards {
+                let Some(timeout) = guard.decision_timeout else {
+                    continue;
+                };
+                if !vetoed {
+                    let until = started + timeout;
+                    wait_pumping_messages(
+                        until.saturating_duration_since(Instant::now()),
+                        |slice| {
+                            guard
+                                .pending_decision
+                                .wait_until(Instant::now() + slice)
+                                .map(|_| true)
+                        },
+                    );
+                }
+                vetoed |= guard.pending_decision.close() == Some(ShutdownDecision::Abort);
+            }
+            if !vetoed {
+                if let Some(state) = GLOBAL_STATE.get() {
+                    if state.run_callbacks_on == EndSessionPhase::QueryEndSession {
+                        run_session_callbacks(hwnd, state, msg, lparam);
                     }
                 }
             }
+            LRESULT(if vetoed { 0 } else { 1 })
+        }
+        WM_ENDSESSION => {
+            // wParam is FALSE when the shutdown was cancelled, so only a
+            // committing WM_ENDSESSION claims the run-once flags
+            // With `EndSessionPhase::QueryEndSession` the callbacks already
+            // ran, and `run_shutdown_once` returns right away
+            if wparam.0 != 0 {
+                if let Some(state) = GLOBAL_STATE.get() {
+                    run_session_callbacks(hwnd, state, msg, lparam);
+                }
+            } else {
+                for guard in super::started_guards() {
+                    guard.metrics.shutdown_cancelled();
+                }
+            }
+            LRESULT(0)
+        }
+        WM_CALLBACKS_DONE => {
+            BLOCK_REASON.with(|slot| drop(slot.borrow_mut().take()));
+            LRESULT(0)
+        }
+        WM_POWERBROADCAST => {
+            match wparam.0 as u32 {
+                PBT_APMSUSPEND => super::run_sleep_callbacks(TriggerSource::WindowsMessage(msg)),
+                // Sent on every resume, whether or not a user is present
+                PBT_APMRESUMEAUTOMATIC => {
+                    super::run_resume_callbacks(TriggerSource::WindowsMessage(msg))
+                }
+                _ => {}
+            }
+            LRESULT(1)
+        }
+        WM_CLOSE => {
+            let _ = DestroyWindow(hwnd);
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            let notify = SUSPEND_NOTIFY.swap(0, Ordering::SeqCst);
+            if notify != 0 {
+                let _ = UnregisterSuspendResumeNotification(HPOWERNOTIFY(notify));
+            }
+            PostQuitMessage(0);
+            LRESULT(0)
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
 
-            BOOL(1)
+/// Runs the callbacks for the session-end message `msg`, showing the
+/// block reason while they run
+unsafe fn run_session_callbacks(hwnd: HWND, state: &GuardState, msg: u32, lparam: LPARAM) {
+    // A critical shutdown ignores block reasons and does not wait, so keep
+    // its window short
+    let critical = lparam.0 as u32 & ENDSESSION_CRITICAL != 0;
+    let wait = if critical {
+        CRITICAL_END_SESSION_WAIT
+    } else {
+        END_SESSION_WAIT
+    };
+    if let Some(reason) = state.shutdown_block_reason.as_deref() {
+        if !critical {
+            let block_reason = BlockReason::create(hwnd, reason);
+            BLOCK_REASON.with(|slot| *slot.borrow_mut() = Some(block_reason));
         }
-        _ => BOOL(0),
+    }
+
+    let context = ShutdownContext::new(
+        end_session_reason(lparam),
+        TriggerSource::WindowsMessage(msg),
+    );
+    end_session(hwnd, context, wait);
+}
+
+/// Runs the callbacks for `WM_ENDSESSION` on a worker thread
+///
+/// Running them inline would stop the message pump, so Windows would report
+/// the app as not responding and could kill it mid-cleanup. Instead the window
+/// thread keeps pumping messages for up to `wait`, [`END_SESSION_WAIT`] unless
+/// the shutdown is critical, and then returns, letting the session end while
+/// a slow callback may still be running. The block reason stays up until the
+/// worker reports back.
+unsafe fn end_session(hwnd: HWND, context: ShutdownContext, wait: Duration) {
+    let (tx, rx) = mpsc::channel();
+    // HWND is not Send; the worker only posts a message to it
+    let window = hwnd.0 as isize;
+    std::thread::spawn(move || {
+        run_shutdown_once(context);
+        let _ = tx.send(());
+        let _ = PostMessageW(
+            Some(HWND(window as *mut c_void)),
+            WM_CALLBACKS_DONE,
+            WPARAM(0),
+            LPARAM(0),
+        );
+    });
+
+    let finished = wait_pumping_messages(wait, |slice| match rx.recv_timeout(slice) {
+        Ok(()) => Some(true),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Some(false),
+        Err(mpsc::RecvTimeoutError::Timeout) => None,
+    });
+    if finished {
+        BLOCK_REASON.with(|slot| drop(slot.borrow_mut().take()));
+    } else {
+        eprintln!(
+            "Shutdown callbacks are still running after {:?}, letting the session end",
+            wait
+        );
+    }
+}
+
+/// Dispatches this thread's messages until `done` reports a result or
+/// `timeout` passes; returns the result, or false on timeout
+///
+/// `done` blocks for at most the slice it is given and returns `None` while
+/// still waiting. A `WM_QUIT` seen meanwhile is posted again afterwards for
+/// the outer loop.
+unsafe fn wait_pumping_messages(
+    timeout: Duration,
+    mut done: impl FnMut(Duration) -> Option<bool>,
+) -> bool {
+    let until = Instant::now() + timeout;
+    let mut quit = None;
+    let finished = loop {
+        if let Some(finished) = done(Duration::from_millis(20)) {
+            break finished;
+        }
+        if Instant::now() >= until {
+            break false;
+        }
+
+        let mut msg = MSG::default();
+        while quit.is_none() && PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE).as_bool() {
+            if msg.message == WM_QUIT {
+                quit = Some(msg.wParam.0 as i32);
+            } else {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+    };
+
+    if let Some(code) = quit {
+        PostQuitMessage(code);
+    }
+    finished
+}
+
+/// Shows a shutdown block reason for as long as it is alive
+///
+/// Destroying it on drop keeps the reason from outliving the callbacks. Must
+/// be created and dropped on the window's thread.
+struct BlockReason(HWND);
+
+impl BlockReason {
+    unsafe fn create(hwnd: HWND, reason: &str) -> Self {
+        let wide: Vec<u16> = reason.encode_utf16().chain(std::iter::once(0)).collect();
+        if let Err(e) = ShutdownBlockReasonCreate(hwnd, PCWSTR(wide.as_ptr())) {
+            eprintln!("Failed to set shutdown block reason: {}", e);
+        }
+        Self(hwnd)
+    }
+}
+
+impl Drop for BlockReason {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = ShutdownBlockReasonDestroy(self.0);
+        }
+    }
+}
+
+/// Maps the `WM_QUERYENDSESSION`/`WM_ENDSESSION` flags to the reason reported to callbacks
+///
+/// `ENDSESSION_CLOSEAPP` is the Restart Manager closing the app, e.g. for an
+/// installer, while the session goes on. Like `CTRL_SHUTDOWN_EVENT`, a system
+/// shutdown does not distinguish a restart from a power-off, and
+/// `ENDSESSION_CRITICAL` only changes how long the system waits.
+fn end_session_reason(lparam: LPARAM) -> ShutdownReason {
+    let flags = lparam.0 as u32;
+    if flags & ENDSESSION_CLOSEAPP != 0 {
+        ShutdownReason::AppClose
+    } else if flags & ENDSESSION_LOGOFF != 0 {
+        ShutdownReason::Logout
+    } else {
+        ShutdownReason::PowerOff
+    }
+}
+
+/// Maps a console control event to the reason reported to callbacks
+///
+/// `CTRL_SHUTDOWN_EVENT` does not distinguish a restart from a power-off.
+fn shutdown_reason(ctrl_type: u32) -> ShutdownReason {
+    match ctrl_type {
+        CTRL_SHUTDOWN_EVENT => ShutdownReason::PowerOff,
+        CTRL_LOGOFF_EVENT => ShutdownReason::Logout,
+        _ => ShutdownReason::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShutdownGuard;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_aborted_decision_denies_the_query() {
+        let _lock = super::super::SIGNAL_TEST_LOCK.lock();
+        let guard = ShutdownGuard::builder()
+            .shutdown_decision_timeout(Duration::from_secs(5))
+            .build();
+        super::super::STARTED
+            .lock()
+            .0
+            .push(Arc::downgrade(&guard.state));
+
+        let responder = guard.clone();
+        let thread = std::thread::spawn(move || {
+            while !responder.is_shutdown_pending() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            responder.respond_to_pending(ShutdownDecision::Abort)
+        });
+        let hwnd = HWND(std::ptr::null_mut());
+        let answer = unsafe { window_proc(hwnd, WM_QUERYENDSESSION, WPARAM(0), LPARAM(0)) };
+        assert_eq!(answer.0, 0);
+        thread.join().unwrap().unwrap();
+        assert!(!guard.is_shutdown_pending());
+        assert_eq!(
+            guard.respond_to_pending(ShutdownDecision::Proceed),
+            Err(ShutdownError::NoPendingShutdown)
+        );
+        super::super::STARTED.lock().0.clear();
+    }
+
+    #[test]
+    fn test_end_session_flags_map_to_reasons() {
+        let reason = |flags: u32| end_session_reason(LPARAM(flags as isize));
+        assert_eq!(reason(0), ShutdownReason::PowerOff);
+        assert_eq!(reason(ENDSESSION_CRITICAL), ShutdownReason::PowerOff);
+        assert_eq!(reason(ENDSESSION_LOGOFF), ShutdownReason::Logout);
+        assert_eq!(
+            reason(ENDSESSION_LOGOFF | ENDSESSION_CRITICAL),
+            ShutdownReason::Logout
+        );
+        assert_eq!(reason(ENDSESSION_CLOSEAPP), ShutdownReason::AppClose);
+    }
+
+    #[test]
+    fn test_denied_query_keeps_guard_armed() {
+        let _lock = super::super::SIGNAL_TEST_LOCK.lock();
+        let guard = ShutdownGuard::new();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let ran_clone = Arc::clone(&ran);
+        guard
+            .register(Box::new(move |_| {
+                ran_clone.fetch_add(1, Ordering::SeqCst);
+            }))
+            .unwrap();
+        let deny = Arc::new(AtomicBool::new(true));
+        let deny_clone = Arc::clone(&deny);
+        guard.set_veto(Box::new(move |_| deny_clone.load(Ordering::SeqCst)));
+
+        rearm_handler();
+        GLOBAL_STATE.set(Arc::clone(&guard.state));
+        super::super::STARTED
+            .lock()
+            .0
+            .push(Arc::downgrade(&guard.state));
+        let hwnd = HWND(std::ptr::null_mut());
+        unsafe {
+            // The first query is denied and the session keeps running
+            let answer = window_proc(hwnd, WM_QUERYENDSESSION, WPARAM(0), LPARAM(0));
+            assert_eq!(answer.0, 0);
+            window_proc(hwnd, WM_ENDSESSION, WPARAM(0), LPARAM(0));
+            assert_eq!(ran.load(Ordering::SeqCst), 0);
+            assert!(!guard.has_executed());
+
+            // The next shutdown goes through and runs the callbacks once
+            deny.store(false, Ordering::SeqCst);
+            let answer = window_proc(hwnd, WM_QUERYENDSESSION, WPARAM(0), LPARAM(0));
+            assert_eq!(answer.0, 1);
+            window_proc(hwnd, WM_ENDSESSION, WPARAM(1), LPARAM(0));
+        }
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        assert!(guard.has_executed());
+        GLOBAL_STATE.clear();
+        super::super::STARTED.lock().0.clear();
     }
 }
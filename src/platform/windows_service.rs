@@ -0,0 +1,106 @@
+//! Service control handling for guards running inside a Windows service
+//!
+//! Services run in session 0, which has no interactive desktop, so the hidden
+//! window of the Windows backend may never see `WM_QUERYENDSESSION` there.
+//! The service control manager reports the shutdown to the service's control
+//! handler instead, which this module registers.
+
+use crate::{GuardState, ShutdownContext, ShutdownError, ShutdownReason, TriggerSource};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::System::Services::*;
+
+/// Reported as the wait hint while the callbacks run, unless a deadline is
+/// configured
+const STOP_WAIT_HINT: Duration = Duration::from_secs(30);
+
+/// `ERROR_CALL_NOT_IMPLEMENTED`, returned for controls the handler ignores
+const ERROR_CALL_NOT_IMPLEMENTED: u32 = 120;
+
+/// Status handle of the registered control handler, or null
+static STATUS_HANDLE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// The wait hint reported with `SERVICE_STOP_PENDING`, in milliseconds
+static WAIT_HINT_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Registers the control handler for the service `name` and reports the
+/// service as running
+///
+/// Has to be called from the service's `ServiceMain`, since the service
+/// control manager only accepts a handler for a service that is starting.
+pub(super) fn register(name: &str, state: &GuardState) -> std::result::Result<(), ShutdownError> {
+    let wait_hint = state
+        .deadline
+        .read()
+        .map_or(STOP_WAIT_HINT, |(deadline, _)| deadline);
+    WAIT_HINT_MS.store(wait_hint.as_millis() as u32, Ordering::SeqCst);
+
+    let wide: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let handle = unsafe {
+        RegisterServiceCtrlHandlerExW(PCWSTR(wide.as_ptr()), Some(service_ctrl_handler), None)
+    }
+    .map_err(|e| {
+        ShutdownError::Platform(format!("Failed to register service control handler: {}", e))
+    })?;
+    STATUS_HANDLE.store(handle.0, Ordering::SeqCst);
+
+    set_status(
+        SERVICE_RUNNING,
+        SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN | SERVICE_ACCEPT_PRESHUTDOWN,
+    )
+    .map_err(|e| ShutdownError::Platform(format!("Failed to report the service as running: {}", e)))
+}
+
+/// Handles service controls on the service control dispatcher thread
+///
+/// The dispatcher expects the handler to return quickly, so the callbacks run
+/// on a thread of their own, which reports the service as stopped once they
+/// finished.
+unsafe extern "system" fn service_ctrl_handler(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut c_void,
+    _context: *mut c_void,
+) -> u32 {
+    let reason = match control {
+        SERVICE_CONTROL_SHUTDOWN | SERVICE_CONTROL_PRESHUTDOWN => ShutdownReason::PowerOff,
+        SERVICE_CONTROL_STOP => ShutdownReason::Unknown,
+        SERVICE_CONTROL_INTERROGATE => return 0,
+        _ => return ERROR_CALL_NOT_IMPLEMENTED,
+    };
+
+    if let Err(e) = set_status(SERVICE_STOP_PENDING, 0) {
+        eprintln!("Failed to report the service as stopping: {}", e);
+    }
+    std::thread::spawn(move || {
+        let context = ShutdownContext::new(reason, TriggerSource::WindowsServiceControl(control));
+        super::windows::run_shutdown_once(context);
+        if let Err(e) = set_status(SERVICE_STOPPED, 0) {
+            eprintln!("Failed to report the service as stopped: {}", e);
+        }
+    });
+    0
+}
+
+/// Reports the service's state to the service control manager
+fn set_status(
+    current_state: SERVICE_STATUS_CURRENT_STATE,
+    controls_accepted: u32,
+) -> windows::core::Result<()> {
+    let handle = SERVICE_STATUS_HANDLE(STATUS_HANDLE.load(Ordering::SeqCst));
+    let wait_hint = if current_state == SERVICE_STOP_PENDING {
+        WAIT_HINT_MS.load(Ordering::SeqCst)
+    } else {
+        0
+    };
+    let status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: current_state,
+        dwControlsAccepted: controls_accepted,
+        dwWaitHint: wait_hint,
+        ..Default::default()
+    };
+    unsafe { SetServiceStatus(handle, &status) }
+}
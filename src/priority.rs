@@ -0,0 +1,79 @@
+//! Scheduling priority of the thread running the callbacks of a shutdown
+
+use crate::platform;
+
+/// How the thread running the callbacks of a detected shutdown is scheduled
+///
+/// Chosen with
+/// [`ShutdownGuardBuilder::dispatch_priority`](crate::ShutdownGuardBuilder::dispatch_priority).
+/// The priority is raised just before the callbacks run and restored once
+/// they finished, so durable writes are not starved by the rest of the
+/// system shutting down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DispatchPriority {
+    /// Leave the thread's priority alone
+    #[default]
+    Normal,
+    /// Nice value -10 on Linux, `THREAD_PRIORITY_HIGHEST` on Windows
+    High,
+    /// Nice value -20 on Linux, `THREAD_PRIORITY_TIME_CRITICAL` on Windows
+    Highest,
+}
+
+/// Keeps the calling thread's priority raised until dropped
+pub(crate) struct PriorityBoost {
+    previous: i32,
+}
+
+impl PriorityBoost {
+    /// Raises the calling thread's priority; returns `None` if `priority` is
+    /// [`DispatchPriority::Normal`], the platform has no per-thread priority,
+    /// or it refused, e.g. for lack of `CAP_SYS_NICE`, which is logged
+    pub(crate) fn raise(priority: DispatchPriority) -> Option<Self> {
+        match platform::raise_thread_priority(priority) {
+            Ok(previous) => previous.map(|previous| Self { previous }),
+            Err(e) => {
+                eprintln!("Failed to raise the priority of the shutdown thread: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for PriorityBoost {
+    fn drop(&mut self) {
+        if let Err(e) = platform::restore_thread_priority(self.previous) {
+            eprintln!(
+                "Failed to restore the priority of the shutdown thread: {}",
+                e
+            );
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn nice() -> i32 {
+        unsafe { libc::getpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t) }
+    }
+
+    #[test]
+    fn test_boost_is_restored_on_drop() {
+        // On a thread of its own, since Linux keeps a nice value per thread
+        std::thread::spawn(|| {
+            assert!(PriorityBoost::raise(DispatchPriority::Normal).is_none());
+
+            let before = nice();
+            // Without CAP_SYS_NICE the boost is refused and nothing changes
+            if let Some(boost) = PriorityBoost::raise(DispatchPriority::High) {
+                assert_eq!(nice(), -10);
+                drop(boost);
+            }
+            assert_eq!(nice(), before);
+        })
+        .join()
+        .unwrap();
+    }
+}
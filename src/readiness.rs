@@ -0,0 +1,56 @@
+//! Shutdown state for readiness probes
+
+use std::fmt;
+
+/// Whether the process should still receive traffic
+///
+/// Returned by [`Registry::readiness_state`](crate::Registry::readiness_state).
+/// A readiness probe, e.g. Kubernetes' `readinessProbe`, should start failing
+/// once a shutdown is detected so the load balancer drains the instance while
+/// the callbacks run.
+///
+/// ```
+/// use shutdown_guard_rs::{Readiness, ShutdownGuard};
+///
+/// let guard = ShutdownGuard::new();
+/// // In the handler of GET /readyz
+/// let readiness = guard.readiness_state();
+/// assert_eq!((readiness.http_status(), readiness.to_string()), (200, "ready".to_string()));
+///
+/// guard.execute_callbacks();
+/// assert_eq!(guard.readiness_state(), Readiness::Draining);
+/// assert_eq!(guard.readiness_state().http_status(), 503);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Readiness {
+    /// No shutdown was detected
+    Ready,
+    /// A shutdown was detected; stop routing new work here
+    Draining,
+}
+
+impl Readiness {
+    /// Returns true if the process should still receive traffic
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Readiness::Ready)
+    }
+
+    /// Returns the status code a readiness endpoint should answer with: 200
+    /// while ready, 503 Service Unavailable while draining
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Readiness::Ready => 200,
+            Readiness::Draining => 503,
+        }
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Readiness::Ready => write!(f, "ready"),
+            Readiness::Draining => write!(f, "draining"),
+        }
+    }
+}
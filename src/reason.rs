@@ -0,0 +1,97 @@
+//! Reasons reported to shutdown callbacks
+
+use std::fmt;
+
+/// Why the shutdown callbacks are being executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShutdownReason {
+    /// The system is restarting
+    Reboot,
+    /// The system is powering off
+    PowerOff,
+    /// The user session is ending
+    Logout,
+    /// Power is about to be lost, e.g. a UPS reported a power failure via
+    /// SIGPWR; callbacks should favor durability over speed
+    PowerFailure,
+    /// The system is about to suspend or hibernate; only reported to
+    /// `on_sleep` callbacks
+    Suspend,
+    /// The system woke up from suspend or hibernation; only reported to the
+    /// `on_resume` callbacks of `register_lifecycle`
+    Resume,
+    /// The application is being closed so it can be updated or restarted,
+    /// e.g. by the Windows Restart Manager during an installation, or a macOS
+    /// app is quitting; the system may keep running
+    AppClose,
+    /// The user pressed Ctrl+C: SIGINT on Unix, `CTRL_C_EVENT` on Windows;
+    /// only reported with `treat_interrupt_as_shutdown`
+    Interrupt,
+    /// A Unix signal with the given number was received
+    Signal(i32),
+    /// An operator sent `shutdown` to the socket opened by
+    /// `Registry::listen_on_socket`
+    Admin,
+    /// The trigger did not say why, e.g. a manual call to `execute_callbacks`
+    Unknown,
+}
+
+impl fmt::Display for ShutdownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownReason::Reboot => write!(f, "reboot"),
+            ShutdownReason::PowerOff => write!(f, "power off"),
+            ShutdownReason::Logout => write!(f, "logout"),
+            ShutdownReason::PowerFailure => write!(f, "power failure"),
+            ShutdownReason::Suspend => write!(f, "suspend"),
+            ShutdownReason::Resume => write!(f, "resume"),
+            ShutdownReason::AppClose => write!(f, "app close"),
+            ShutdownReason::Interrupt => write!(f, "interrupt"),
+            ShutdownReason::Signal(sig) => write!(f, "signal {}", sig),
+            ShutdownReason::Admin => write!(f, "admin command"),
+            ShutdownReason::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl ShutdownReason {
+    /// Matches every [`ShutdownReason::Signal`] when passed to
+    /// [`Registry::register_for`](crate::Registry::register_for)
+    ///
+    /// Signal 0 is never delivered, so it cannot be confused with a real one.
+    pub const ANY_SIGNAL: ShutdownReason = ShutdownReason::Signal(0);
+
+    /// Returns true if `self`, as passed to `register_for`, selects `reason`
+    pub(crate) fn selects(&self, reason: ShutdownReason) -> bool {
+        *self == reason || (*self == Self::ANY_SIGNAL && reason.signal().is_some())
+    }
+
+    /// Returns true if the system is restarting
+    pub fn is_reboot(&self) -> bool {
+        matches!(self, ShutdownReason::Reboot)
+    }
+
+    /// Returns true if the system is powering off
+    pub fn is_power_off(&self) -> bool {
+        matches!(self, ShutdownReason::PowerOff)
+    }
+
+    /// Returns true if only the user session is ending
+    pub fn is_logout(&self) -> bool {
+        matches!(self, ShutdownReason::Logout)
+    }
+
+    /// Returns true if power is about to be lost
+    pub fn is_power_failure(&self) -> bool {
+        matches!(self, ShutdownReason::PowerFailure)
+    }
+
+    /// Returns the signal number if a Unix signal triggered the shutdown
+    pub fn signal(&self) -> Option<i32> {
+        match self {
+            ShutdownReason::Signal(sig) => Some(*sig),
+            _ => None,
+        }
+    }
+}
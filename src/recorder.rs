@@ -0,0 +1,82 @@
+//! Recording the order callbacks run in, for tests
+
+use crate::ShutdownCallback;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Records the names of the callbacks it hands out as they run
+///
+/// The canonical way to test cleanup ordering: register
+/// [`hook`](Self::hook)s in place of the real callbacks, trigger a shutdown,
+/// and compare [`order`](Self::order) with the expected sequence, instead of
+/// wiring up flags by hand. Clones share the recording.
+///
+/// ```
+/// use shutdown_guard_rs::{OrderRecorder, ShutdownGuard, ShutdownReason};
+///
+/// let guard = ShutdownGuard::new();
+/// let recorder = OrderRecorder::new();
+/// guard.register(recorder.hook("close-db"))?;
+/// guard.register_with_priority(10, recorder.hook("stop-accepting"))?;
+///
+/// guard.simulate_shutdown(ShutdownReason::PowerOff);
+/// assert_eq!(recorder.order(), ["stop-accepting", "close-db"]);
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OrderRecorder {
+    order: Arc<Mutex<Vec<String>>>,
+}
+
+impl OrderRecorder {
+    /// Creates a recorder with nothing recorded
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a callback that appends `name` to the recording when it runs
+    pub fn hook(&self, name: impl Into<String>) -> ShutdownCallback {
+        let (order, name) = (Arc::clone(&self.order), name.into());
+        Box::new(move |_| order.lock().push(name.clone()))
+    }
+
+    /// Returns the names of the hooks that ran, in the order they ran
+    pub fn order(&self) -> Vec<String> {
+        self.order.lock().clone()
+    }
+
+    /// Forgets what was recorded, e.g. before triggering another shutdown
+    pub fn clear(&self) {
+        self.order.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecutionOrder, ShutdownGuard, ShutdownReason};
+
+    #[test]
+    fn test_records_fifo_lifo_and_priority_order() {
+        for (order, expected) in [
+            (ExecutionOrder::Fifo, ["urgent", "a", "b"]),
+            (ExecutionOrder::Lifo, ["urgent", "b", "a"]),
+        ] {
+            let guard = ShutdownGuard::builder().execution_order(order).build();
+            let recorder = OrderRecorder::new();
+            guard.register(recorder.hook("a")).unwrap();
+            guard.register(recorder.hook("b")).unwrap();
+            guard
+                .register_with_priority(10, recorder.hook("urgent"))
+                .unwrap();
+
+            guard.simulate_shutdown(ShutdownReason::PowerOff);
+            assert_eq!(recorder.order(), expected, "{:?}", order);
+
+            recorder.clear();
+            guard.reset_executed();
+            guard.simulate_shutdown(ShutdownReason::Reboot);
+            assert_eq!(recorder.clone().order(), expected);
+        }
+    }
+}
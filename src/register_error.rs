@@ -0,0 +1,53 @@
+//! Errors from registering a callback
+
+use crate::CallbackId;
+use std::fmt;
+
+/// Why a callback could not be registered
+///
+/// Returned by [`Registry::register`](crate::Registry::register) and the
+/// other register methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RegisterError {
+    /// The limit set with
+    /// [`ShutdownGuardBuilder::max_callbacks`](crate::ShutdownGuardBuilder::max_callbacks)
+    /// is reached
+    CapacityExceeded {
+        /// The configured maximum number of callbacks
+        max: usize,
+    },
+    /// The guard was built with
+    /// [`ShutdownGuardBuilder::seal_on_start`](crate::ShutdownGuardBuilder::seal_on_start)
+    /// and has been started
+    Sealed,
+    /// The callbacks already started running, so a callback registered now
+    /// would not run; see [`Registry::try_register`](crate::Registry::try_register)
+    /// and [`Registry::clear`](crate::Registry::clear)
+    ShutdownInProgress,
+    /// A dependency passed to
+    /// [`Registry::register_after`](crate::Registry::register_after) is not
+    /// registered
+    UnknownDependency(CallbackId),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterError::CapacityExceeded { max } => {
+                write!(f, "cannot register more than {} shutdown callbacks", max)
+            }
+            RegisterError::Sealed => {
+                write!(f, "guard sealed: cannot register callbacks after start")
+            }
+            RegisterError::ShutdownInProgress => {
+                write!(f, "shutdown in progress: the callbacks already ran")
+            }
+            RegisterError::UnknownDependency(id) => {
+                write!(f, "dependency {:?} is not registered", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegisterError {}
@@ -0,0 +1,45 @@
+//! Callbacks that are unregistered when their handle is dropped
+
+use crate::{CallbackId, GuardState};
+use std::fmt;
+use std::sync::Weak;
+
+/// Keeps a callback registered for as long as it is held
+///
+/// Returned by [`Registry::register_scoped`](crate::Registry::register_scoped).
+/// Dropping it unregisters the callback, so the callback never outlives the
+/// state it refers to. It only holds a weak reference to the guard's
+/// callbacks, so it neither keeps the guard alive nor fails once the guard
+/// is gone.
+#[must_use = "dropping a Registration unregisters the callback immediately"]
+pub struct Registration {
+    id: CallbackId,
+    state: Weak<GuardState>,
+}
+
+impl Registration {
+    pub(crate) fn new(id: CallbackId, state: Weak<GuardState>) -> Self {
+        Self { id, state }
+    }
+
+    /// Returns the id of the registered callback
+    pub fn id(&self) -> CallbackId {
+        self.id
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.upgrade() {
+            state.unregister(self.id);
+        }
+    }
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("id", &self.id)
+            .finish()
+    }
+}
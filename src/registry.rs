@@ -0,0 +1,2539 @@
+//! Platform-independent storage and execution of shutdown callbacks
+
+use crate::{
+    durable, execution_order, parallel, weak, Callback, CallbackController, CallbackEntry,
+    CallbackError, CallbackId, CallbackInfo, DeadlineAction, FallibleCallback, GroupId, GuardState,
+    MetricsSnapshot, MonitorHandle, PauseGuard, Phase, Readiness, RegisterError, Registration,
+    ShutdownCallback, ShutdownContext, ShutdownError, ShutdownHook, ShutdownReason, ShutdownScope,
+    ShutdownSender, ShutdownSignal, ShutdownSummary, ShutdownTrigger, ShutdownWatcher, Spawner,
+    TriggerSource,
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The callbacks of a guard with their ordering, priorities and run-once flag,
+/// independent of any platform
+///
+/// [`ShutdownGuard`](crate::ShutdownGuard) drives a registry from the
+/// platform's shutdown notifications. Applications with a trigger of their
+/// own, such as an admin endpoint or a custom signal, can use a registry
+/// directly and call [`execute`](Self::execute) instead. Clones share the same
+/// callbacks.
+///
+/// The callbacks are copied out of the registry before the first one runs,
+/// so a callback can register, unregister or clear callbacks without
+/// deadlocking. Such changes take effect for the next run, not the current
+/// one.
+///
+/// ```rust
+/// use shutdown_guard_rs::{Registry, ShutdownContext, ShutdownReason, TriggerSource};
+///
+/// let registry = Registry::new();
+/// registry.register(Box::new(|context| println!("Cleaning up ({})", context.reason)))?;
+///
+/// // e.g. from an HTTP handler
+/// registry.execute(ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual));
+/// assert!(registry.has_executed());
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct Registry {
+    pub(crate) state: Arc<GuardState>,
+}
+
+/// The callbacks of a registry as saved by [`Registry::snapshot`]
+#[cfg(any(test, feature = "testing"))]
+pub struct RegistrySnapshot {
+    callbacks: Vec<CallbackEntry>,
+}
+
+impl Registry {
+    /// Creates an empty registry with the default configuration
+    ///
+    /// Use [`ShutdownGuardBuilder::build_registry`](crate::ShutdownGuardBuilder::build_registry)
+    /// to configure the panic policy, ordering or timeouts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn from_state(state: Arc<GuardState>) -> Self {
+        Self { state }
+    }
+
+    /// Registers a callback to be executed before shutdown
+    ///
+    /// The callback is registered with priority 0; see
+    /// [`register_with_priority`](Self::register_with_priority).
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A boxed closure that will be called before shutdown
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`CallbackId`] that can be passed to [`unregister`](Self::unregister)
+    /// to remove this callback again.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`RegisterError::CapacityExceeded`] once the limit set with
+    /// [`ShutdownGuardBuilder::max_callbacks`](crate::ShutdownGuardBuilder::max_callbacks)
+    /// is reached, and with [`RegisterError::Sealed`] once a guard built with
+    /// [`seal_on_start`](crate::ShutdownGuardBuilder::seal_on_start) started.
+    /// The other register methods fail the same way.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let id = guard.register(Box::new(|context| {
+    ///     println!("Cleanup in progress ({})...", context.reason);
+    /// }))?;
+    ///
+    /// assert!(guard.unregister(id));
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    ///
+    /// With a limit on the number of callbacks:
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{RegisterError, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::builder().max_callbacks(1).build();
+    /// assert!(guard.register(Box::new(|_| {})).is_ok());
+    /// assert_eq!(
+    ///     guard.register(Box::new(|_| {})),
+    ///     Err(RegisterError::CapacityExceeded { max: 1 })
+    /// );
+    /// assert_eq!(guard.capacity_remaining(), Some(0));
+    /// ```
+    pub fn register(&self, callback: ShutdownCallback) -> Result<CallbackId, RegisterError> {
+        self.register_with_priority(0, callback)
+    }
+
+    /// Registers a callback like [`register`](Self::register), but also
+    /// refuses it once the callbacks started running
+    ///
+    /// Fails with [`RegisterError::ShutdownInProgress`] from the moment the
+    /// callbacks start running until
+    /// [`reset_executed`](Self::reset_executed), where `register` would
+    /// accept a callback that never runs. The check is made under the same
+    /// lock the dispatcher reads the callbacks with, so a callback this
+    /// accepts is seen by the next run.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{RegisterError, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// assert!(guard.try_register(Box::new(|_| {})).is_ok());
+    ///
+    /// guard.execute_callbacks();
+    /// assert_eq!(
+    ///     guard.try_register(Box::new(|_| {})),
+    ///     Err(RegisterError::ShutdownInProgress)
+    /// );
+    /// ```
+    pub fn try_register(&self, callback: ShutdownCallback) -> Result<CallbackId, RegisterError> {
+        self.try_push(
+            Phase::Graceful,
+            0,
+            None,
+            None,
+            None,
+            Callback::Infallible(callback),
+            true,
+        )
+    }
+
+    /// Returns how many more callbacks can be registered, or `None` if the
+    /// number is not limited
+    pub fn capacity_remaining(&self) -> Option<usize> {
+        let max = self.state.max_callbacks?;
+        let registered = self.state.callbacks.read().len() + self.state.final_callbacks.len();
+        Some(max.saturating_sub(registered))
+    }
+
+    /// Registers a callback with an explicit execution priority
+    ///
+    /// Callbacks with a higher priority run first, e.g. "stop accepting new
+    /// work" (100) before "flush the queue" (50) before "close the database"
+    /// (0). Priorities are resolved once when the callbacks are executed;
+    /// callbacks sharing a priority run in registration order, so the order
+    /// is always deterministic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_with_priority(0, Box::new(|_| println!("Closing database")))?;
+    /// guard.register_with_priority(100, Box::new(|_| println!("Rejecting new work")))?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_with_priority(
+        &self,
+        priority: i32,
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.push(
+            Phase::Graceful,
+            priority,
+            None,
+            None,
+            Callback::Infallible(callback),
+        )
+    }
+
+    /// Registers several callbacks at once, returning their ids in the same
+    /// order
+    ///
+    /// Takes the registry's lock once for the whole batch, so a plugin
+    /// loader registering dozens of callbacks does not contend with the
+    /// dispatcher for each of them. The callbacks behave as if registered
+    /// one by one with [`register`](Self::register).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let ids = guard.register_all(vec![
+    ///     Box::new(|_| println!("Closing plugin A")),
+    ///     Box::new(|_| println!("Closing plugin B")),
+    /// ])?;
+    /// assert_eq!(ids.len(), 2);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`register`](Self::register), without registering any of
+    /// the callbacks, if the guard is sealed or the batch does not fit under
+    /// the limit set with
+    /// [`ShutdownGuardBuilder::max_callbacks`](crate::ShutdownGuardBuilder::max_callbacks).
+    pub fn register_all(
+        &self,
+        callbacks: Vec<ShutdownCallback>,
+    ) -> Result<Vec<CallbackId>, RegisterError> {
+        self.register_all_with(
+            callbacks
+                .into_iter()
+                .map(|callback| (0, callback))
+                .collect(),
+        )
+    }
+
+    /// Registers several `(priority, callback)` pairs at once like
+    /// [`register_all`](Self::register_all), each with its priority as in
+    /// [`register_with_priority`](Self::register_with_priority)
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`register_all`](Self::register_all), without registering
+    /// any of the callbacks.
+    pub fn register_all_with(
+        &self,
+        callbacks: Vec<(i32, ShutdownCallback)>,
+    ) -> Result<Vec<CallbackId>, RegisterError> {
+        let mut entries = self.state.callbacks.write();
+        self.check_open(&entries, callbacks.len())?;
+
+        entries.reserve(callbacks.len());
+        let ids = callbacks
+            .into_iter()
+            .map(|(priority, callback)| {
+                let entry =
+                    self.new_entry(Phase::Graceful, priority, Callback::Infallible(callback));
+                let id = entry.id;
+                entries.push(entry);
+                id
+            })
+            .collect();
+        Ok(ids)
+    }
+
+    /// Registers a callback with a name used in logs and diagnostics
+    ///
+    /// The name shows up in panic, failure and deadline messages, in tracing
+    /// events, in [`inspect`](Self::inspect) and in
+    /// [`callback_names`](Self::callback_names), so "a callback hung" becomes
+    /// "flush-wal hung".
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_named("flush-wal", Box::new(|_| println!("Flushing WAL")))?;
+    /// assert_eq!(guard.callback_names(), vec!["flush-wal"]);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_named(
+        &self,
+        name: impl Into<String>,
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.push(
+            Phase::Graceful,
+            0,
+            Some(name.into()),
+            None,
+            Callback::Infallible(callback),
+        )
+    }
+
+    /// Registers a callback that runs at most once
+    ///
+    /// Unlike the guard-wide run-once flag, this is not undone by
+    /// [`reset_executed`](Self::reset_executed): once the callback ran, every
+    /// later execution skips it. Use it for cleanup that is not idempotent,
+    /// such as releasing a distributed lock, when both a manual
+    /// [`execute_callbacks`](Self::execute_callbacks) and a shutdown signal
+    /// may reach it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_once(Box::new(|_| println!("Releasing lock")))?;
+    ///
+    /// guard.execute_callbacks();
+    /// guard.reset_executed();
+    /// guard.execute_callbacks(); // Prints nothing
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_once(&self, callback: ShutdownCallback) -> Result<CallbackId, RegisterError> {
+        let ran = AtomicBool::new(false);
+        self.register(Box::new(move |context| {
+            if !ran.swap(true, Ordering::SeqCst) {
+                callback(context);
+            }
+        }))
+    }
+
+    /// Registers a callback that can fan its work out with a
+    /// [`ShutdownScope`]
+    ///
+    /// The callback is done once it returned and everything it spawned on the
+    /// scope finished. The wait is bounded by the deadline from
+    /// [`set_deadline`](Self::set_deadline): work still running then is
+    /// abandoned and the callback reports a
+    /// [`CallbackTimeout`](crate::CallbackTimeout), which
+    /// [`execute_callbacks_collect`](Self::execute_callbacks_collect) returns
+    /// like any other failure.
+    pub fn register_with_scope<F>(&self, callback: F) -> Result<CallbackId, RegisterError>
+    where
+        F: Fn(ShutdownContext, &ShutdownScope) + Send + Sync + 'static,
+    {
+        let spawner = self.state.spawner.clone();
+        self.register_fallible(Box::new(move |context| {
+            let scope = ShutdownScope::new(context.deadline, spawner.clone());
+            callback(context, &scope);
+            scope.join()
+        }))
+    }
+
+    /// Registers a coordinator, which decides at shutdown which of the
+    /// callbacks after it run, and in what order
+    ///
+    /// For conditional teardown that priorities and dependencies cannot
+    /// express. The coordinator is registered at the highest priority, so
+    /// every other callback runs after it; through the
+    /// [`CallbackController`] it can list them, run them right away in an
+    /// order of its choosing, or skip them. The dispatcher then runs the
+    /// ones it left alone as usual. A callback never runs twice: once the
+    /// coordinator ran or skipped it, the dispatcher passes over it.
+    ///
+    /// The coordinator runs on the dispatching thread, without the graceful
+    /// timeout. Under
+    /// [`execute_callbacks_parallel`](Self::execute_callbacks_parallel) it
+    /// sees no callbacks, and they all run as usual.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let flush = guard.register_named("flush", Box::new(|_| println!("Flushing...")))?;
+    /// let upload = guard.register_named("upload", Box::new(|_| println!("Uploading...")))?;
+    ///
+    /// guard.register_coordinator(move |context, controller| {
+    ///     if context.reason.is_power_failure() {
+    ///         // No time to upload before the power goes
+    ///         controller.skip(upload);
+    ///     }
+    ///     controller.run(flush);
+    /// })?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_coordinator<F>(&self, coordinator: F) -> Result<CallbackId, RegisterError>
+    where
+        F: Fn(ShutdownContext, &CallbackController<'_>) + Send + Sync + 'static,
+    {
+        self.push(
+            Phase::Graceful,
+            i32::MAX,
+            None,
+            None,
+            Callback::Coordinator(Box::new(coordinator)),
+        )
+    }
+
+    /// Registers a callback that only runs for the given reasons
+    ///
+    /// For cleanup that depends on why the system goes down, e.g. wiping a
+    /// cache on power-off but keeping it across a reboot, without checking
+    /// the reason in the callback. [`ShutdownReason::ANY_SIGNAL`] selects
+    /// every signal. For any other reason the callback is left out of the
+    /// run, like a group that `run_groups_for` excludes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{ShutdownGuard, ShutdownReason};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_for(
+    ///     &[ShutdownReason::PowerOff, ShutdownReason::ANY_SIGNAL],
+    ///     Box::new(|_| println!("Wiping the cache")),
+    /// )?;
+    ///
+    /// guard.execute_callbacks_with_reason(ShutdownReason::Reboot); // Prints nothing
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_for(
+        &self,
+        reasons: &[ShutdownReason],
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.try_push(
+            Phase::Graceful,
+            0,
+            None,
+            None,
+            Some(reasons.to_vec()),
+            Callback::Infallible(callback),
+            false,
+        )
+    }
+
+    /// Registers a callback that may mutate the state it captured
+    ///
+    /// The callback is kept behind a mutex that is locked while it runs, so it
+    /// can drain a buffer or bump a counter without wrapping them in a
+    /// `Mutex` or `Cell` of its own. This serializes the callback: with
+    /// [`execute_callbacks_parallel`](Self::execute_callbacks_parallel), or
+    /// when several triggers run the callbacks at once, a second run of it
+    /// waits for the first one to finish. Prefer [`register`](Self::register)
+    /// for callbacks that only need `Fn`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let mut pending = vec!["first", "second"];
+    /// guard.register_mut(move |_| {
+    ///     for line in pending.drain(..) {
+    ///         println!("Flushing {}", line);
+    ///     }
+    /// })?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_mut<F>(&self, callback: F) -> Result<CallbackId, RegisterError>
+    where
+        F: FnMut(ShutdownContext) + Send + 'static,
+    {
+        let callback = parking_lot::Mutex::new(callback);
+        self.register(Box::new(move |context| (callback.lock())(context)))
+    }
+
+    /// Registers a callback that can report failure
+    ///
+    /// Fallible callbacks run alongside regular ones in the same order. A
+    /// failure does not stop the remaining callbacks; use
+    /// [`execute_callbacks_collect`](Self::execute_callbacks_collect) to find
+    /// out which ones failed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let id = guard.register_fallible(Box::new(|_| Err("disk full".into())))?;
+    ///
+    /// let failures = guard.execute_callbacks_collect();
+    /// assert_eq!(failures[0].0, id);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_fallible(
+        &self,
+        callback: FallibleCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.push(Phase::Graceful, 0, None, None, Callback::Fallible(callback))
+    }
+
+    /// Registers a callback that deletes the file at `path`
+    ///
+    /// A file that is already gone counts as deleted; any other error is
+    /// reported as the callback's failure. Unregister the returned id if the
+    /// file is cleaned up early.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    /// use std::path::PathBuf;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let lock = guard.cleanup_file_on_shutdown(PathBuf::from("/tmp/my-app.lock"))?;
+    ///
+    /// // Released early after all
+    /// guard.unregister(lock);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn cleanup_file_on_shutdown(&self, path: PathBuf) -> Result<CallbackId, RegisterError> {
+        let name = format!("delete {}", path.display());
+        let callback: FallibleCallback = Box::new(move |_| match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        });
+        self.push(
+            Phase::Graceful,
+            0,
+            Some(name),
+            None,
+            Callback::Fallible(callback),
+        )
+    }
+
+    /// Registers a callback that syncs the file at `path` to disk, and on
+    /// Unix its directory entry
+    ///
+    /// For a file written unsynced while the application runs, e.g. a log,
+    /// so its contents survive the power going off after the callbacks. To
+    /// write a file at shutdown, use [`durable_write`](crate::durable_write)
+    /// instead. A missing file is reported as the callback's failure.
+    pub fn flush_file_on_shutdown(&self, path: PathBuf) -> Result<CallbackId, RegisterError> {
+        let name = format!("sync {}", path.display());
+        let callback: FallibleCallback =
+            Box::new(move |_| durable::sync_file(&path).map_err(Into::into));
+        self.push(
+            Phase::Graceful,
+            0,
+            Some(name),
+            None,
+            Callback::Fallible(callback),
+        )
+    }
+
+    /// Registers a callback that is abandoned if it runs longer than `timeout`
+    ///
+    /// The callback runs on a scratch thread while the dispatcher waits for at
+    /// most `timeout`; after that it moves on to the next callback and the
+    /// overrun is reported as a [`CallbackTimeout`](crate::CallbackTimeout) by
+    /// [`execute_callbacks_collect`](Self::execute_callbacks_collect). The
+    /// abandoned thread is not killed and may still finish later.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    /// use std::time::Duration;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_with_timeout(
+    ///     Duration::from_secs(2),
+    ///     Box::new(|_| println!("Flushing to the network...")),
+    /// )?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_with_timeout(
+        &self,
+        timeout: Duration,
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.push(
+            Phase::Graceful,
+            0,
+            None,
+            None,
+            Callback::Timed(Arc::from(callback), timeout),
+        )
+    }
+
+    /// Registers a future to run to completion when the callbacks run
+    ///
+    /// For one or two async cleanups in an application without a tokio
+    /// runtime: the dispatcher blocks on the future in its turn, with a
+    /// minimal executor, and moves on once it completed. The graceful timeout
+    /// and the deadline apply as to any other callback. The future is polled
+    /// only for the first run; if the callbacks run again after
+    /// [`reset_executed`](Self::reset_executed), it does nothing. Futures that
+    /// depend on a tokio reactor, e.g. tokio's I/O types, never complete here;
+    /// use `AsyncShutdownGuard` for those.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_future(async {
+    ///     println!("Closing the connection...");
+    /// })?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    #[cfg(feature = "futures")]
+    pub fn register_future<F>(&self, future: F) -> Result<CallbackId, RegisterError>
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let callback = crate::future::callback(future);
+        self.push(
+            Phase::Graceful,
+            0,
+            None,
+            None,
+            Callback::Infallible(Box::new(move |context| callback(context))),
+        )
+    }
+
+    /// Registers a future that is abandoned if it runs longer than `timeout`
+    ///
+    /// Like [`register_future`](Self::register_future), but the executor
+    /// blocks on a scratch thread, as for
+    /// [`register_with_timeout`](Self::register_with_timeout).
+    #[cfg(feature = "futures")]
+    pub fn register_future_with_timeout<F>(
+        &self,
+        timeout: Duration,
+        future: F,
+    ) -> Result<CallbackId, RegisterError>
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.push(
+            Phase::Graceful,
+            0,
+            None,
+            None,
+            Callback::Timed(crate::future::callback(future), timeout),
+        )
+    }
+
+    /// Registers a last-resort callback that runs even if the deadline is
+    /// blown, e.g. to release a license token
+    ///
+    /// Final callbacks run once per shutdown, after all other callbacks. If
+    /// the deadline from [`set_deadline`](Self::set_deadline) terminates the
+    /// process first, the watchdog runs them right before it exits, while the
+    /// overrunning callbacks are abandoned. Each gets
+    /// [`FINAL_CALLBACK_TIMEOUT`](crate::FINAL_CALLBACK_TIMEOUT) on a scratch
+    /// thread, so every final callback that hangs delays the exit by that
+    /// much, and one still running at exit is cut off mid-way. Keep them
+    /// few and short.
+    ///
+    /// Final callbacks count towards
+    /// [`ShutdownGuardBuilder::max_callbacks`](crate::ShutdownGuardBuilder::max_callbacks)
+    /// and are refused by a sealed guard like any other registration. They
+    /// cannot be unregistered, so the returned id only tells them apart.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`RegisterError::Sealed`] or
+    /// [`RegisterError::CapacityExceeded`] like [`register`](Self::register).
+    pub fn register_final(&self, callback: ShutdownCallback) -> Result<CallbackId, RegisterError> {
+        // Under the write lock, so the checks and the push cannot interleave
+        // with another registration
+        let callbacks = self.state.callbacks.write();
+        self.check_open(&callbacks, 1)?;
+        let id = CallbackId(self.state.next_id.fetch_add(1, Ordering::Relaxed));
+        self.state.final_callbacks.push(Arc::from(callback));
+        Ok(id)
+    }
+
+    /// Registers a callback that only holds a weak reference to `target`
+    ///
+    /// The guard does not keep `target` alive: once every other `Arc` to it
+    /// is dropped, the callback is skipped when the callbacks run. This keeps
+    /// subsystems that were torn down early from leaking until shutdown.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    /// use std::sync::Arc;
+    ///
+    /// struct Cache;
+    /// impl Cache {
+    ///     fn flush(&self) {}
+    /// }
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let cache = Arc::new(Cache);
+    /// guard.register_weak(&cache, |cache, _| cache.flush())?;
+    ///
+    /// drop(cache);
+    /// assert_eq!(guard.live_callback_count(), 0);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_weak<T, F>(
+        &self,
+        target: &Arc<T>,
+        callback: F,
+    ) -> Result<CallbackId, RegisterError>
+    where
+        T: Send + Sync + 'static,
+        F: Fn(&T, ShutdownContext) + Send + Sync + 'static,
+    {
+        self.push(
+            Phase::Graceful,
+            0,
+            None,
+            None,
+            Callback::Weak(weak::bind(target, callback)),
+        )
+    }
+
+    /// Registers an object implementing [`ShutdownHook`]
+    ///
+    /// The guard keeps the hook alive; registering the same `Arc` with
+    /// several guards runs it once per guard that shuts down.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{ShutdownContext, ShutdownGuard, ShutdownHook};
+    /// use std::sync::Arc;
+    ///
+    /// struct Flusher;
+    /// impl ShutdownHook for Flusher {
+    ///     fn on_shutdown(&self, _context: &ShutdownContext) {}
+    /// }
+    ///
+    /// let flusher = Arc::new(Flusher);
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_hook(flusher.clone())?;
+    ///
+    /// guard.clear()?;
+    /// guard.register_hook(flusher)?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_hook(&self, hook: Arc<dyn ShutdownHook>) -> Result<CallbackId, RegisterError> {
+        self.push(Phase::Graceful, 0, None, None, Callback::Hook(hook))
+    }
+
+    /// Registers a callback as part of `group`
+    ///
+    /// Lets a subsystem remove all of its callbacks at once with
+    /// [`clear_group`](Self::clear_group), without tracking their ids or
+    /// touching those of other subsystems.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{GroupId, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let cache = GroupId::new();
+    /// guard.register_in_group(cache, Box::new(|_| println!("Flushing cache")))?;
+    /// guard.register_in_group(cache, Box::new(|_| println!("Closing cache file")))?;
+    /// guard.register(Box::new(|_| println!("Closing database")))?;
+    ///
+    /// // The cache was torn down early
+    /// assert_eq!(guard.clear_group(cache), 2);
+    /// assert_eq!(guard.callback_count(), 1);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_in_group(
+        &self,
+        group: GroupId,
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.push(
+            Phase::Graceful,
+            0,
+            None,
+            Some(group),
+            Callback::Infallible(callback),
+        )
+    }
+
+    /// Registers a callback in the given shutdown phase
+    ///
+    /// All [`Phase::Graceful`] callbacks run before any [`Phase::Forceful`]
+    /// one; within a phase, priorities and the execution order apply as
+    /// usual. The forceful phase runs even if the graceful phase was cut
+    /// short by [`ShutdownGuardBuilder::graceful_timeout`](crate::ShutdownGuardBuilder::graceful_timeout) or by the global
+    /// deadline, of which it keeps the last quarter. Every other `register*`
+    /// method registers a graceful callback.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{Phase, ShutdownGuard};
+    /// use std::time::Duration;
+    ///
+    /// let guard = ShutdownGuard::builder()
+    ///     .graceful_timeout(Duration::from_secs(20))
+    ///     .build();
+    /// guard.register_phase(Phase::Graceful, Box::new(|_| println!("Draining connections")))?;
+    /// guard.register_phase(Phase::Forceful, Box::new(|_| println!("Killing workers")))?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_phase(
+        &self,
+        phase: Phase,
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.push(phase, 0, None, None, Callback::Infallible(callback))
+    }
+
+    pub(crate) fn push(
+        &self,
+        phase: Phase,
+        priority: i32,
+        name: Option<String>,
+        group: Option<GroupId>,
+        callback: Callback,
+    ) -> Result<CallbackId, RegisterError> {
+        self.try_push(phase, priority, name, group, None, callback, false)
+    }
+
+    /// Registers a callback unless the guard is sealed or full, or, with
+    /// `refuse_after_run`, its callbacks already started running
+    ///
+    /// The checks and the push happen under one write lock, which the
+    /// dispatcher reads the callbacks under, so the entry is never seen
+    /// without its metadata.
+    #[allow(clippy::too_many_arguments)]
+    fn try_push(
+        &self,
+        phase: Phase,
+        priority: i32,
+        name: Option<String>,
+        group: Option<GroupId>,
+        reasons: Option<Vec<ShutdownReason>>,
+        callback: Callback,
+        refuse_after_run: bool,
+    ) -> Result<CallbackId, RegisterError> {
+        let mut callbacks = self.state.callbacks.write();
+        self.check_open(&callbacks, 1)?;
+        if refuse_after_run && self.has_executed() {
+            return Err(RegisterError::ShutdownInProgress);
+        }
+        let entry = CallbackEntry {
+            name,
+            group,
+            reasons,
+            ..self.new_entry(phase, priority, callback)
+        };
+        let id = entry.id;
+        callbacks.push(entry);
+        Ok(id)
+    }
+
+    /// Returns an entry for `callback` under a fresh id, registered now
+    fn new_entry(
+        &self,
+        phase: Phase,
+        priority: i32,
+        callback: impl Into<Arc<Callback>>,
+    ) -> CallbackEntry {
+        let id = CallbackId(self.state.next_id.fetch_add(1, Ordering::Relaxed));
+        CallbackEntry::new(id, phase, priority, self.state.clock.now(), callback)
+    }
+
+    /// Moves the callbacks of `other` behind the ones of `self`, with new ids
+    /// and their dependencies carried over
+    ///
+    /// Fails, leaving the callbacks with `other`, if `self` is sealed or
+    /// they do not fit under its maximum number of callbacks.
+    pub(crate) fn adopt_callbacks(&self, other: &Registry) -> Result<(), RegisterError> {
+        if Arc::ptr_eq(&self.state, &other.state) {
+            return Ok(());
+        }
+        // One lock at a time, so two guards adopting each other cannot deadlock
+        let adopted = std::mem::take(&mut *other.state.callbacks.write());
+
+        let mut callbacks = self.state.callbacks.write();
+        if let Err(e) = self.check_open(&callbacks, adopted.len()) {
+            drop(callbacks);
+            let mut returned = other.state.callbacks.write();
+            let registered_meanwhile = std::mem::replace(&mut *returned, adopted);
+            returned.extend(registered_meanwhile);
+            return Err(e);
+        }
+
+        // Registered now as far as `self` is concerned, on its own clock, in
+        // the order they had on `other`
+        let moved: Vec<(CallbackId, CallbackEntry)> = adopted
+            .into_iter()
+            .map(|entry| {
+                let adopted_as = CallbackEntry {
+                    name: entry.name,
+                    group: entry.group,
+                    after: entry.after,
+                    reasons: entry.reasons,
+                    ..self.new_entry(entry.phase, entry.priority, entry.callback)
+                };
+                (entry.id, adopted_as)
+            })
+            .collect();
+        let ids: HashMap<CallbackId, CallbackId> =
+            moved.iter().map(|(old, entry)| (*old, entry.id)).collect();
+        callbacks.extend(moved.into_iter().map(|(_, entry)| {
+            CallbackEntry {
+                after: entry
+                    .after
+                    .iter()
+                    .filter_map(|dep| ids.get(dep).copied())
+                    .collect(),
+                ..entry
+            }
+        }));
+        Ok(())
+    }
+
+    /// Fails if the guard is sealed or `callbacks`, together with the final
+    /// callbacks, has no room left for `additional` more under the configured
+    /// maximum
+    fn check_open(
+        &self,
+        callbacks: &[CallbackEntry],
+        additional: usize,
+    ) -> Result<(), RegisterError> {
+        if self.is_sealed() {
+            return Err(RegisterError::Sealed);
+        }
+        match self.state.max_callbacks {
+            Some(max) if callbacks.len() + self.state.final_callbacks.len() + additional > max => {
+                Err(RegisterError::CapacityExceeded { max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether the callbacks are fixed because the guard was built
+    /// with [`seal_on_start`](crate::ShutdownGuardBuilder::seal_on_start)
+    /// and started
+    pub fn is_sealed(&self) -> bool {
+        self.state.sealed.get().is_some()
+    }
+
+    /// Registers a callback that only starts once the callbacks in `deps`
+    /// finished, whatever their priorities
+    ///
+    /// Priorities and the execution order still apply otherwise; a callback
+    /// is only held back as far as its dependencies require. It runs even if
+    /// a dependency failed or panicked. Callbacks are registered in the
+    /// graceful phase, or in the forceful phase if one of the dependencies
+    /// is forceful.
+    ///
+    /// Since every dependency has to be registered already, dependencies
+    /// cannot form a cycle. Unregistering a dependency later drops the
+    /// constraint.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RegisterError::UnknownDependency`] if one of `deps` is not
+    /// registered, and otherwise fails like [`register`](Self::register).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let flush = guard.register(Box::new(|_| println!("Flushing buffers")))?;
+    /// guard.register_after(&[flush], Box::new(|_| println!("Unmounting")))?;
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_after(
+        &self,
+        deps: &[CallbackId],
+        callback: ShutdownCallback,
+    ) -> Result<CallbackId, RegisterError> {
+        let mut callbacks = self.state.callbacks.write();
+        self.check_open(&callbacks, 1)?;
+        let mut phase = Phase::Graceful;
+        for dep in deps {
+            let entry = callbacks
+                .iter()
+                .find(|entry| entry.id == *dep)
+                .ok_or(RegisterError::UnknownDependency(*dep))?;
+            phase = phase.max(entry.phase);
+        }
+
+        let entry = CallbackEntry {
+            after: deps.to_vec(),
+            ..self.new_entry(phase, 0, Callback::Infallible(callback))
+        };
+        let id = entry.id;
+        callbacks.push(entry);
+        Ok(id)
+    }
+
+    /// Removes a single registered callback
+    ///
+    /// Returns `true` if the callback was found and removed, or `false` if it
+    /// had already been removed (for example by [`clear`](Self::clear)).
+    /// Since ids are never reused, a stale id cannot remove a callback
+    /// registered later.
+    pub fn unregister(&self, id: CallbackId) -> bool {
+        self.state.unregister(id)
+    }
+
+    /// Registers a callback that stays registered while the returned
+    /// [`Registration`] is held
+    ///
+    /// Dropping the `Registration` unregisters the callback, which keeps a
+    /// callback from running after the state it refers to was torn down.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// {
+    ///     let _registration = guard.register_scoped(Box::new(|_| println!("Closing session")))?;
+    ///     assert_eq!(guard.callback_count(), 1);
+    /// }
+    /// assert_eq!(guard.callback_count(), 0);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_scoped(
+        &self,
+        callback: ShutdownCallback,
+    ) -> Result<Registration, RegisterError> {
+        let id = self.register(callback)?;
+        Ok(Registration::new(id, Arc::downgrade(&self.state)))
+    }
+
+    /// Removes every callback registered in `group` and returns how many
+    /// were removed
+    pub fn clear_group(&self, group: GroupId) -> usize {
+        if self.is_sealed() {
+            return 0;
+        }
+        let mut callbacks = self.state.callbacks.write();
+        let before = callbacks.len();
+        callbacks.retain(|entry| entry.group != Some(group));
+        before - callbacks.len()
+    }
+
+    /// Removes every callback registered under `name` and returns how many
+    /// were removed
+    ///
+    /// Names need not be unique, so all callbacks sharing the name go, and
+    /// unnamed callbacks are never matched. Useful when the code tearing a
+    /// component down agrees with the code that registered it on a name
+    /// but has no [`CallbackId`] to hand.
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_named("flush-wal", Box::new(|_| {}))?;
+    /// guard.register_named("flush-wal", Box::new(|_| {}))?;
+    /// assert_eq!(guard.unregister_by_name("flush-wal"), 2);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn unregister_by_name(&self, name: &str) -> usize {
+        if self.is_sealed() {
+            return 0;
+        }
+        let mut callbacks = self.state.callbacks.write();
+        let before = callbacks.len();
+        callbacks.retain(|entry| entry.name.as_deref() != Some(name));
+        before - callbacks.len()
+    }
+
+    /// Defers detected shutdowns until the returned guard is dropped
+    ///
+    /// For critical sections the callbacks must not interrupt, e.g. an atomic
+    /// file rename. A shutdown detected while a [`PauseGuard`] is held runs
+    /// the callbacks right after the last one is dropped; see `PauseGuard` for
+    /// the limits each platform puts on the wait. Manual executions are not
+    /// deferred.
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// {
+    ///     let _pause = guard.pause();
+    ///     // std::fs::rename("state.tmp", "state")
+    /// }
+    /// ```
+    pub fn pause(&self) -> PauseGuard {
+        PauseGuard::new(Arc::clone(&self.state))
+    }
+
+    /// Registers a callback that sends the shutdown reason over a channel
+    ///
+    /// This is the usual way to wake up a main thread or event loop that wants
+    /// to run its own graceful shutdown. A receiver that was already dropped is
+    /// ignored rather than treated as an error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{ShutdownGuard, ShutdownReason};
+    /// use std::sync::mpsc;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let (tx, rx) = mpsc::channel();
+    /// guard.notify_on_shutdown(tx)?;
+    ///
+    /// guard.execute_callbacks_with_reason(ShutdownReason::PowerOff);
+    /// assert_eq!(rx.recv().unwrap(), ShutdownReason::PowerOff);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn notify_on_shutdown<S: ShutdownSender>(
+        &self,
+        sender: S,
+    ) -> Result<CallbackId, RegisterError> {
+        self.register(Box::new(move |context| sender.send_reason(context.reason)))
+    }
+
+    /// Sets a hard deadline for shutdown callbacks and what happens on overrun
+    ///
+    /// When the platform backend detects a shutdown, a watchdog thread is armed
+    /// for `deadline`. If the callbacks are still running when it expires, the
+    /// watchdog applies `action`: either terminating the process right away, or
+    /// logging the overrun and waiting up to an absolute cap. Manual calls to
+    /// [`execute_callbacks`](Self::execute_callbacks) are never interrupted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{DeadlineAction, ShutdownGuard};
+    /// use std::time::Duration;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.set_deadline(
+    ///     Duration::from_secs(4),
+    ///     DeadlineAction::LogAndContinue {
+    ///         absolute_cap: Duration::from_secs(20),
+    ///     },
+    /// );
+    /// ```
+    pub fn set_deadline(&self, deadline: Duration, action: DeadlineAction) {
+        *self.state.deadline.write() = Some((deadline, action));
+    }
+
+    /// Submits the callback work the guard would otherwise start threads
+    /// for to `spawner`, e.g. the application's thread pool
+    ///
+    /// This covers the workers of
+    /// [`execute_callbacks_parallel`](Self::execute_callbacks_parallel), timed
+    /// and graceful-phase callbacks and the work of a [`ShutdownScope`]; the
+    /// guard still waits for them with its deadline and timeouts, through a
+    /// channel the work reports back on. Work that overruns is abandoned on
+    /// the pool like on its own thread, so a pool with few threads may run
+    /// out of them during shutdown. The deadline watchdog and the final
+    /// callbacks keep threads of their own, so they run even then.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// // A stand-in for e.g. `rayon::spawn`
+    /// guard.set_spawner(Box::new(|work| {
+    ///     std::thread::spawn(work);
+    /// }));
+    /// ```
+    pub fn set_spawner(&self, spawner: Spawner) {
+        self.state.spawner.set(spawner);
+    }
+
+    /// Executes all registered callbacks
+    ///
+    /// This method is typically called automatically when a shutdown is detected,
+    /// but can also be called manually if needed.
+    ///
+    /// The callbacks run at most once per trigger: once they ran, from this
+    /// method or from a detected shutdown, further calls do nothing until
+    /// [`reset_executed`](Self::reset_executed) is called.
+    ///
+    /// Callbacks receive [`ShutdownReason::Unknown`] and
+    /// [`TriggerSource::Manual`]; use
+    /// [`execute_callbacks_with_reason`](Self::execute_callbacks_with_reason)
+    /// to pass a specific reason.
+    pub fn execute_callbacks(&self) {
+        self.execute_callbacks_with_reason(ShutdownReason::Unknown);
+    }
+
+    /// Executes all registered callbacks with the given reason
+    ///
+    /// Errors from fallible callbacks are discarded; use
+    /// [`execute_callbacks_collect`](Self::execute_callbacks_collect) to
+    /// inspect them.
+    pub fn execute_callbacks_with_reason(&self, reason: ShutdownReason) {
+        let context = ShutdownContext::new(reason, TriggerSource::Manual);
+        self.state.run_callbacks(self.state.entries(), context);
+    }
+
+    /// Executes all registered callbacks and returns the failures
+    ///
+    /// Every callback runs even if an earlier one failed. Each failure is
+    /// paired with the id of the callback that reported it, in execution order.
+    /// Returns no failures if the callbacks already ran.
+    pub fn execute_callbacks_collect(&self) -> Vec<(CallbackId, CallbackError)> {
+        let context = ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual);
+        self.state.run_callbacks(self.state.entries(), context)
+    }
+
+    /// Executes all registered callbacks and reports how each one ended
+    ///
+    /// Behaves like [`execute_callbacks`](Self::execute_callbacks), with the
+    /// same ordering, panic policy and graceful timeout, but returns the
+    /// duration and outcome of every callback that ran together with the
+    /// total elapsed time, ready to log or assert against. Returns an empty
+    /// summary if the callbacks already ran.
+    ///
+    /// ```rust
+    /// use shutdown_guard_rs::{CallbackOutcome, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_named("flush", Box::new(|_| {}))?;
+    ///
+    /// let summary = guard.execute_callbacks_with_summary();
+    /// assert_eq!(summary.callbacks[0].name.as_deref(), Some("flush"));
+    /// assert_eq!(summary.callbacks[0].outcome, CallbackOutcome::Ok);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn execute_callbacks_with_summary(&self) -> ShutdownSummary {
+        let context = ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual);
+        let ran = self
+            .state
+            .run_callbacks_with(self.state.entries(), context, |_, _, _| {});
+        if !ran {
+            return ShutdownSummary::default();
+        }
+        self.state.last_summary.lock().clone().unwrap_or_default()
+    }
+
+    /// Executes all registered callbacks concurrently and returns the failures
+    ///
+    /// Callbacks of the same priority are spread over a pool of scratch
+    /// threads sized by [`std::thread::available_parallelism`]; each priority
+    /// finishes before the next lower one starts. If a deadline is configured
+    /// (see [`set_deadline`](Self::set_deadline)), callbacks still running when
+    /// it passes are abandoned on their threads and reported as
+    /// [`CallbackTimeout`](crate::CallbackTimeout) failures; the deadline action is not taken.
+    ///
+    /// Like [`execute_callbacks`](Self::execute_callbacks), this does nothing
+    /// if the callbacks already ran. Detected shutdowns always run the callbacks
+    /// sequentially. Spawning threads is not async-signal-safe, so this must
+    /// never be called from inside a raw signal handler of your own.
+    pub fn execute_callbacks_parallel(&self) -> Vec<(CallbackId, CallbackError)> {
+        let context = ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual);
+        let deadline = self.state.deadline.read().map(|(deadline, _)| deadline);
+        parallel::run_parallel(&self.state, self.state.entries(), context, deadline)
+    }
+
+    /// Drives the callbacks through the same path as a detected shutdown
+    ///
+    /// Unlike [`execute_callbacks`](Self::execute_callbacks), this goes through
+    /// the deadline watchdog and logs failures like the platform backends do,
+    /// but without terminating the process afterwards. The run-once flag,
+    /// ordering, panic policy, subscribers and the reason behave as for a real
+    /// trigger; callbacks see [`TriggerSource::Simulated`].
+    ///
+    /// Only available in this crate's tests and with the `testing` feature.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn simulate_shutdown(&self, reason: ShutdownReason) {
+        let context = ShutdownContext::new(reason, TriggerSource::Simulated);
+        self.state.run_shutdown(context);
+    }
+
+    /// Returns a handle that resolves once a shutdown is detected
+    ///
+    /// Lets code pull the shutdown notification, e.g. to stop an accept loop,
+    /// instead of being called back. The handle is triggered just before the
+    /// callbacks run and stays triggered, even after
+    /// [`reset_executed`](Self::reset_executed).
+    pub fn subscribe(&self) -> ShutdownSignal {
+        self.state.shutdown_signal.clone()
+    }
+
+    /// Returns a handle that reports the reason once a shutdown is detected
+    ///
+    /// Like [`subscribe`](Self::subscribe), but [`ShutdownWatcher::wait`]
+    /// returns just the [`ShutdownReason`]. Clone it into each worker thread
+    /// that should begin its own drain.
+    pub fn watch(&self) -> ShutdownWatcher {
+        ShutdownWatcher::new(self.state.shutdown_signal.clone())
+    }
+
+    /// Blocks until a shutdown was detected and the callbacks finished
+    ///
+    /// Keeps `main` alive after [`start`](crate::ShutdownGuard::start) without a sleep loop,
+    /// and returns the context the callbacks ran with. The signal-handler
+    /// backends on Linux and macOS exit the process right after the
+    /// callbacks, so there this only returns for a manual execution, unless
+    /// the guard was built with `ExitBehavior::DoNotExit`; otherwise a
+    /// detected shutdown ends the process while it waits.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|_| println!("Cleaning up...")))?;
+    /// guard.start().expect("Failed to start shutdown monitoring");
+    /// guard.wait_for_shutdown();
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn wait_for_shutdown(&self) -> ShutdownContext {
+        let (lock, condvar) = &self.state.finished;
+        let mut finished = lock.lock();
+        while !*finished {
+            condvar.wait(&mut finished);
+        }
+        drop(finished);
+        self.state
+            .shutdown_signal
+            .context()
+            .expect("the callbacks only run after a shutdown was detected")
+    }
+
+    /// Returns whether a shutdown was detected, e.g. to stop starting new work
+    ///
+    /// Becomes true on every trigger path (a signal, a Windows session end, a
+    /// logind `PrepareForShutdown` or a manual execution) just before the
+    /// callbacks run, so it is already true inside them. Like
+    /// [`subscribe`](Self::subscribe), it stays true even after
+    /// [`reset_executed`](Self::reset_executed).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// while !guard.is_shutdown_in_progress() {
+    ///     // Accept and handle one request...
+    ///     # guard.execute_callbacks();
+    /// }
+    /// ```
+    pub fn is_shutdown_in_progress(&self) -> bool {
+        self.state.shutdown_signal.is_triggered()
+    }
+
+    /// Returns [`Readiness::Draining`] once a shutdown is detected, and
+    /// [`Readiness::Ready`] before
+    ///
+    /// Meant for a readiness endpoint: answering it with
+    /// [`Readiness::http_status`] makes the probe fail with 503 as soon as
+    /// the callbacks start, so the orchestrator stops routing traffic here
+    /// while they drain. Follows
+    /// [`is_shutdown_in_progress`](Self::is_shutdown_in_progress). See the
+    /// `readiness_probe` example.
+    pub fn readiness_state(&self) -> Readiness {
+        if self.is_shutdown_in_progress() {
+            Readiness::Draining
+        } else {
+            Readiness::Ready
+        }
+    }
+
+    /// Returns the reason of the detected shutdown, e.g. for logging after
+    /// [`wait_for_shutdown`](Self::wait_for_shutdown)
+    ///
+    /// Set on every trigger path before the callbacks run. After
+    /// [`reset_executed`](Self::reset_executed) it keeps the previous reason
+    /// until the callbacks run again.
+    pub fn last_reason(&self) -> Option<ShutdownReason> {
+        *self.state.last_reason.lock()
+    }
+
+    /// Appends the context of the detected shutdown to `path` as a JSON line
+    ///
+    /// See [`ShutdownContext::log_to`]. Fails with
+    /// [`std::io::ErrorKind::NotFound`] if no shutdown was detected yet.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::ShutdownGuard;
+    /// use std::path::Path;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.start().expect("Failed to start shutdown monitoring");
+    /// guard.wait_for_shutdown();
+    /// guard.log_context_to(Path::new("shutdowns.jsonl"))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn log_context_to(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let context = self.state.shutdown_signal.context().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no shutdown was detected")
+        })?;
+        context.log_to(path)
+    }
+
+    /// Returns whether the callbacks already ran
+    pub fn has_executed(&self) -> bool {
+        self.state.executed.load(Ordering::SeqCst)
+    }
+
+    /// Allows the callbacks to run again, e.g. between tests
+    pub fn reset_executed(&self) {
+        *self.state.finished.0.lock() = false;
+        self.state.final_callbacks.reset();
+        self.state.executed.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns metadata for every registered callback in execution order
+    ///
+    /// The snapshot is taken under the read lock and owned by the caller, so
+    /// it can back a diagnostics endpoint listing the registered cleanup
+    /// without running any of it.
+    pub fn inspect(&self) -> Vec<CallbackInfo> {
+        execution_order(&self.state.callbacks.read(), self.state.execution_order)
+            .into_iter()
+            .map(|entry| entry.info(self.state.created.0))
+            .collect()
+    }
+
+    /// Returns the metadata from [`inspect`](Self::inspect) as a JSON array
+    ///
+    /// Intended for admin or debug endpoints that report what will happen on
+    /// shutdown. Only metadata is serialized, never the callbacks themselves.
+    #[cfg(feature = "serde")]
+    pub fn inspect_json(&self) -> String {
+        serde_json::to_string(&self.inspect()).expect("callback metadata is always serializable")
+    }
+
+    /// Returns the number of registered callbacks
+    pub fn callback_count(&self) -> usize {
+        self.state.callbacks.read().len()
+    }
+
+    /// Returns how many callbacks a shutdown with `reason` would run, without
+    /// running them
+    ///
+    /// Applies the same filters as the dispatcher: the reasons given to
+    /// [`register_for`](Self::register_for) and the groups chosen with
+    /// [`ShutdownGuardBuilder::run_groups_for`](crate::ShutdownGuardBuilder::run_groups_for).
+    /// Like [`live_callback_count`](Self::live_callback_count), weak
+    /// callbacks whose target was dropped are not counted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{ShutdownGuard, ShutdownReason};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|_| {}))?;
+    /// guard.register_for(&[ShutdownReason::PowerOff], Box::new(|_| {}))?;
+    ///
+    /// assert_eq!(guard.callbacks_for_reason_count(ShutdownReason::PowerOff), 2);
+    /// assert_eq!(guard.callbacks_for_reason_count(ShutdownReason::Reboot), 1);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn callbacks_for_reason_count(&self, reason: ShutdownReason) -> usize {
+        let entries = self.state.entries();
+        self.state
+            .dispatch_order(&entries, reason)
+            .into_iter()
+            .filter(|entry| entry.callback.is_live())
+            .count()
+    }
+
+    /// Returns the name of every registered callback in execution order
+    ///
+    /// Unnamed callbacks are listed by their id, e.g. `"CallbackId(3)"`.
+    pub fn callback_names(&self) -> Vec<String> {
+        execution_order(&self.state.callbacks.read(), self.state.execution_order)
+            .into_iter()
+            .map(CallbackEntry::label)
+            .collect()
+    }
+
+    /// Checks that a callback is registered under each of `names`, e.g.
+    /// before [`start`](crate::ShutdownGuard::start), so a deployment whose
+    /// essential cleanup was not wired up fails fast
+    ///
+    /// Names are the ones given with
+    /// [`register_named`](Self::register_named) and the other named
+    /// registrations.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ShutdownError::MissingRequiredCallback`] for the first of
+    /// `names` that no callback is registered under.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::{ShutdownError, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register_named("flush-audit-log", Box::new(|_| {}))?;
+    ///
+    /// assert!(guard.require(&["flush-audit-log"]).is_ok());
+    /// assert_eq!(
+    ///     guard.require(&["flush-audit-log", "close-db"]),
+    ///     Err(ShutdownError::MissingRequiredCallback("close-db".to_string()))
+    /// );
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn require(&self, names: &[&str]) -> Result<(), ShutdownError> {
+        let callbacks = self.state.callbacks.read();
+        match names.iter().find(|name| {
+            !callbacks
+                .iter()
+                .any(|entry| entry.name.as_deref() == Some(**name))
+        }) {
+            Some(name) => Err(ShutdownError::MissingRequiredCallback(name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of registered callbacks that will actually run,
+    /// i.e. not counting weak callbacks whose target was dropped
+    pub fn live_callback_count(&self) -> usize {
+        self.state
+            .callbacks
+            .read()
+            .iter()
+            .filter(|entry| entry.callback.is_live())
+            .count()
+    }
+
+    /// Clears all registered callbacks, including those in groups
+    ///
+    /// # Errors
+    ///
+    /// Leaves the callbacks alone and fails with [`RegisterError::Sealed`]
+    /// once a sealed guard started, and with
+    /// [`RegisterError::ShutdownInProgress`] once the callbacks started
+    /// running, until [`reset_executed`](Self::reset_executed), so cleanup
+    /// registered for a shutdown cannot be cleared while that shutdown is
+    /// handled.
+    pub fn clear(&self) -> Result<(), RegisterError> {
+        if self.is_sealed() {
+            return Err(RegisterError::Sealed);
+        }
+        let mut callbacks = self.state.callbacks.write();
+        if self.has_executed() {
+            return Err(RegisterError::ShutdownInProgress);
+        }
+        callbacks.clear();
+        Ok(())
+    }
+
+    /// Replaces every registered callback with `callbacks` at once and
+    /// returns the ids of the callbacks it removed
+    ///
+    /// For configuration reloads that recompute the whole set of cleanup
+    /// hooks: unlike [`clear`](Self::clear) followed by `register`, the swap
+    /// happens under a single write lock, so a shutdown detected meanwhile
+    /// runs either the old set or the new one, never an empty or partial
+    /// one. A shutdown already running keeps running the set it started
+    /// with. The new callbacks get fresh ids, in the order given.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`register`](Self::register), leaving the registered
+    /// callbacks alone, if the guard is sealed or `callbacks` exceeds the
+    /// limit set with
+    /// [`ShutdownGuardBuilder::max_callbacks`](crate::ShutdownGuardBuilder::max_callbacks).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let old = guard.register(Box::new(|_| println!("Flushing the old config")))?;
+    ///
+    /// let removed = guard.set_callbacks(vec![Box::new(|_| println!("Flushing the new config"))])?;
+    /// assert_eq!(removed, vec![old]);
+    /// assert_eq!(guard.callback_count(), 1);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn set_callbacks(
+        &self,
+        callbacks: Vec<ShutdownCallback>,
+    ) -> Result<Vec<CallbackId>, RegisterError> {
+        let mut registered = self.state.callbacks.write();
+        self.check_open(&[], callbacks.len())?;
+        let replacement = callbacks
+            .into_iter()
+            .map(|callback| self.new_entry(Phase::Graceful, 0, Callback::Infallible(callback)))
+            .collect();
+        let removed = std::mem::replace(&mut *registered, replacement);
+        Ok(removed.into_iter().map(|entry| entry.id).collect())
+    }
+
+    /// Saves the registered callbacks so a test can put them back with
+    /// [`restore`](Self::restore)
+    ///
+    /// Meant for tests sharing a guard, such as the global one, that register
+    /// callbacks of their own. The snapshot shares the callbacks rather than
+    /// cloning the closures, and leaves them registered.
+    ///
+    /// Only available in this crate's tests and with the `testing` feature.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        RegistrySnapshot {
+            callbacks: self.state.callbacks.read().clone(),
+        }
+    }
+
+    /// Replaces the registered callbacks with those saved in `snapshot`
+    ///
+    /// Callbacks registered since are dropped, and ones unregistered since
+    /// come back under their old ids. Registration order, priorities, groups
+    /// and dependencies are restored as they were; the run-once flag and the
+    /// [`register_final`](Self::register_final) callbacks are left alone.
+    /// Does nothing if the guard is sealed.
+    ///
+    /// Only available in this crate's tests and with the `testing` feature.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn restore(&self, snapshot: RegistrySnapshot) {
+        if !self.is_sealed() {
+            *self.state.callbacks.write() = snapshot.callbacks;
+        }
+    }
+
+    /// Runs `f` with `temp` registered in place of the callbacks, and puts
+    /// the original callbacks back afterwards, also if `f` panics
+    ///
+    /// A shutdown detected while `f` runs executes `temp`, not the original
+    /// callbacks. Callbacks registered or unregistered during `f` only change
+    /// the temporary set and are dropped with it. The
+    /// [`register_final`](Self::register_final) callbacks are left alone, and
+    /// nothing is swapped if the guard is sealed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|_| println!("Closing the database")))?;
+    /// guard.with_callbacks(vec![Box::new(|_| println!("Rolling back the migration"))], || {
+    ///     assert_eq!(guard.callback_count(), 1);
+    ///     // Run the migration
+    /// });
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn with_callbacks<R>(&self, temp: Vec<ShutdownCallback>, f: impl FnOnce() -> R) -> R {
+        /// Puts the original callbacks back when dropped, even while unwinding
+        struct Restore<'a> {
+            callbacks: &'a parking_lot::RwLock<Vec<CallbackEntry>>,
+            original: Vec<CallbackEntry>,
+        }
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                *self.callbacks.write() = std::mem::take(&mut self.original);
+            }
+        }
+
+        if self.is_sealed() {
+            return f();
+        }
+
+        let temp = temp
+            .into_iter()
+            .map(|callback| self.new_entry(Phase::Graceful, 0, Callback::Infallible(callback)))
+            .collect();
+        let original = std::mem::replace(&mut *self.state.callbacks.write(), temp);
+        let _restore = Restore {
+            callbacks: &self.state.callbacks,
+            original,
+        };
+        f()
+    }
+
+    /// Runs the callbacks once `trigger` detects a shutdown
+    ///
+    /// The trigger waits on a thread of its own, then the callbacks run as
+    /// through [`execute`](Self::execute), with
+    /// [`TriggerSource::Custom`]. This works alongside or instead of
+    /// [`ShutdownGuard::start`](crate::ShutdownGuard::start), and on platforms
+    /// without any backend. The thread keeps the callbacks alive until the
+    /// trigger returns; the returned handle joins it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::{FileWatchTrigger, ShutdownGuard};
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|context| println!("Stopping: {}", context.reason)))?;
+    /// guard
+    ///     .register_trigger(Box::new(FileWatchTrigger::new("/run/myapp/stop")))
+    ///     .join()
+    ///     .unwrap();
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn register_trigger(&self, trigger: Box<dyn ShutdownTrigger>) -> MonitorHandle {
+        let registry = self.clone();
+        MonitorHandle::new(std::thread::spawn(move || {
+            let reason = trigger.wait();
+            registry.execute(ShutdownContext::new(reason, TriggerSource::Custom));
+        }))
+    }
+
+    /// Returns the counters of shutdown triggers and callback outcomes since
+    /// the guard was created
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|_| {}))?;
+    /// guard.execute_callbacks();
+    /// guard.execute_callbacks();
+    ///
+    /// let metrics = guard.metrics();
+    /// assert_eq!(metrics.shutdowns_triggered, 2);
+    /// assert_eq!(metrics.callbacks_completed, 1);
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.state.metrics.snapshot()
+    }
+
+    /// Runs the callbacks for a shutdown reported by the caller's own trigger
+    ///
+    /// This is the path the platform backends take: the deadline watchdog is
+    /// armed, failures are logged, and the callbacks run at most once until
+    /// [`reset_executed`](Self::reset_executed). Unlike on a signal, the
+    /// process is not terminated afterwards.
+    pub fn execute(&self, context: ShutdownContext) {
+        self.state.run_shutdown(context);
+    }
+
+    /// Runs `callback` every `interval` until the guard stops
+    ///
+    /// The callback runs on a timer thread of its own, first one interval
+    /// after this call, and is meant for liveness reports or periodic
+    /// flushes. A beat a slow callback overran is skipped rather than
+    /// caught up, and a panic is reported without ending the beat.
+    /// [`ShutdownGuard::stop`](crate::ShutdownGuard::stop) stops it; the
+    /// returned handle joins the timer thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use shutdown_guard_rs::ShutdownGuard;
+    /// use std::time::Duration;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// let heartbeat = guard.register_heartbeat(Duration::from_secs(30), || {
+    ///     println!("Shutdown guard alive");
+    /// });
+    /// guard.stop().unwrap();
+    /// heartbeat.join().unwrap();
+    /// ```
+    pub fn register_heartbeat(
+        &self,
+        interval: Duration,
+        callback: impl Fn() + Send + Sync + 'static,
+    ) -> MonitorHandle {
+        assert!(!interval.is_zero(), "heartbeat interval must not be zero");
+        let (heartbeat, thread) = crate::heartbeat::Heartbeat::start(interval, Box::new(callback));
+        self.state.heartbeats.lock().push(heartbeat);
+        MonitorHandle::new(thread)
+    }
+
+    /// Runs the callbacks when an operator sends `shutdown` to a Unix domain
+    /// socket at `path`
+    ///
+    /// Each line a client writes is one command. On `shutdown` the callbacks
+    /// run as through [`execute`](Self::execute), with
+    /// [`ShutdownReason::Admin`] and [`TriggerSource::AdminSocket`], so a
+    /// command racing a signal is deduplicated like any other trigger; the
+    /// client is answered `ok` once they finished. Any other line is answered
+    /// `unknown command`. The process is not terminated afterwards.
+    ///
+    /// A stale socket file at `path` is replaced. The socket is created with
+    /// the process umask, so put it in a directory only the operators can
+    /// reach. [`ShutdownGuard::stop`](crate::ShutdownGuard::stop) closes it
+    /// and removes the file; the returned handle joins the listener thread.
+    ///
+    /// Only available on Unix with the `admin-socket` feature.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use shutdown_guard_rs::ShutdownGuard;
+    ///
+    /// let guard = ShutdownGuard::new();
+    /// guard.register(Box::new(|context| println!("Stopping: {}", context.reason)))?;
+    /// guard.listen_on_socket("/run/myapp/admin.sock").unwrap();
+    /// // echo shutdown | socat - UNIX-CONNECT:/run/myapp/admin.sock
+    /// guard.wait_for_shutdown();
+    /// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+    /// ```
+    #[cfg(all(unix, feature = "admin-socket"))]
+    pub fn listen_on_socket(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<MonitorHandle> {
+        let (socket, thread) = crate::admin_socket::listen(self.clone(), path.as_ref())?;
+        self.state.admin_sockets.lock().push(socket);
+        Ok(MonitorHandle::new(thread))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecutionOrder, OrderRecorder, ShutdownGuardBuilder};
+    use parking_lot::Mutex;
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_futures_run_once_within_their_timeout() {
+        let registry = Registry::new();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+        registry
+            .register_future(async move {
+                std::future::ready(()).await;
+                ran_clone.store(true, Ordering::SeqCst);
+            })
+            .unwrap();
+        let hung = registry
+            .register_future_with_timeout(Duration::from_millis(50), std::future::pending())
+            .unwrap();
+
+        let failures = registry.execute_callbacks_collect();
+        assert!(ran.load(Ordering::SeqCst));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, hung);
+        assert!(failures[0].1.is::<crate::CallbackTimeout>());
+
+        // The futures were consumed by the first run
+        ran.store(false, Ordering::SeqCst);
+        registry.reset_executed();
+        assert!(registry.execute_callbacks_collect().is_empty());
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_restore_brings_back_the_snapshot() {
+        let registry = Registry::new();
+        let kept = registry.register_named("kept", Box::new(|_| {})).unwrap();
+        let snapshot = registry.snapshot();
+
+        registry.register_named("added", Box::new(|_| {})).unwrap();
+        assert!(registry.unregister(kept));
+        registry.restore(snapshot);
+
+        let info = registry.inspect();
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[0].id, kept);
+        assert_eq!(info[0].name.as_deref(), Some("kept"));
+        registry.execute_callbacks();
+        assert!(registry.has_executed());
+    }
+
+    #[test]
+    fn test_pause_defers_a_detected_shutdown() {
+        let registry = Registry::new();
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+        registry
+            .register(Box::new(move |_| ran_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+
+        let pause = registry.pause();
+        let detector = registry.clone();
+        let detected =
+            std::thread::spawn(move || detector.simulate_shutdown(ShutdownReason::PowerOff));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(pause.shutdown_pending());
+        assert!(!ran.load(Ordering::SeqCst));
+
+        drop(pause);
+        detected.join().unwrap();
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_pause_allows_registration_while_a_shutdown_waits() {
+        let registry = Registry::new();
+        let pause = registry.pause();
+        let detector = registry.clone();
+        let detected =
+            std::thread::spawn(move || detector.simulate_shutdown(ShutdownReason::PowerOff));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(pause.shutdown_pending());
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran_clone = Arc::clone(&ran);
+        let id = registry
+            .register(Box::new(move |_| ran_clone.store(true, Ordering::SeqCst)))
+            .unwrap();
+        assert!(registry.unregister(registry.register(Box::new(|_| {})).unwrap()));
+        assert!(registry.inspect().iter().any(|info| info.id == id));
+
+        drop(pause);
+        detected.join().unwrap();
+        assert!(ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_final_callbacks_run_after_the_others() {
+        let registry = Registry::new();
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let final_order = Arc::clone(&order);
+        registry
+            .register_final(Box::new(move |_| final_order.lock().push("final")))
+            .unwrap();
+        let other_order = Arc::clone(&order);
+        registry
+            .register(Box::new(move |_| other_order.lock().push("other")))
+            .unwrap();
+
+        registry.execute_callbacks();
+        registry.execute_callbacks();
+        assert_eq!(*order.lock(), vec!["other", "final"]);
+    }
+
+    #[test]
+    fn test_scope_joins_spawned_work() {
+        use std::sync::atomic::AtomicUsize;
+
+        let registry = Registry::new();
+        let flushed = Arc::new(AtomicUsize::new(0));
+        let flushed_clone = Arc::clone(&flushed);
+        registry
+            .register_with_scope(move |_, scope| {
+                for _ in 0..3 {
+                    let flushed = Arc::clone(&flushed_clone);
+                    scope.spawn(move || {
+                        std::thread::sleep(Duration::from_millis(20));
+                        flushed.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+            .unwrap();
+        let stuck = registry
+            .register_with_scope(|_, scope| {
+                scope.spawn(|| std::thread::sleep(Duration::from_secs(5)));
+            })
+            .unwrap();
+        registry.set_deadline(
+            Duration::from_millis(500),
+            DeadlineAction::LogAndContinue {
+                absolute_cap: Duration::from_secs(60),
+            },
+        );
+
+        let failures = registry.execute_callbacks_collect();
+        assert_eq!(flushed.load(Ordering::SeqCst), 3);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, stuck);
+        assert!(failures[0]
+            .1
+            .downcast_ref::<crate::CallbackTimeout>()
+            .is_some());
+    }
+
+    #[test]
+    fn test_metrics_count_outcomes() {
+        let registry = Registry::new();
+        registry
+            .register(Box::new(|_| panic!("disk gone")))
+            .unwrap();
+        registry
+            .register_fallible(Box::new(|_| Err("disk full".into())))
+            .unwrap();
+        registry
+            .register_with_timeout(
+                Duration::from_millis(10),
+                Box::new(|_| std::thread::sleep(Duration::from_millis(200))),
+            )
+            .unwrap();
+
+        registry.execute_callbacks();
+        registry.execute_callbacks_parallel();
+        assert_eq!(
+            registry.metrics(),
+            MetricsSnapshot {
+                shutdowns_triggered: 2,
+                shutdowns_cancelled: 0,
+                callbacks_completed: 3,
+                callback_panics: 1,
+                callback_timeouts: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_register_for_filters_by_reason() {
+        let registry = Registry::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = Arc::clone(&seen);
+        registry
+            .register_for(
+                &[ShutdownReason::PowerOff, ShutdownReason::ANY_SIGNAL],
+                Box::new(move |context| seen_clone.lock().push(context.reason)),
+            )
+            .unwrap();
+
+        for reason in [
+            ShutdownReason::Reboot,
+            ShutdownReason::PowerOff,
+            ShutdownReason::Signal(15),
+        ] {
+            registry.execute_callbacks_with_reason(reason);
+            registry.reset_executed();
+        }
+        assert_eq!(
+            *seen.lock(),
+            vec![ShutdownReason::PowerOff, ShutdownReason::Signal(15)]
+        );
+    }
+
+    #[test]
+    fn test_register_for_reasons_survive_sealing() {
+        let registry = Registry::new();
+        registry
+            .register_for(&[ShutdownReason::PowerOff], Box::new(|_| {}))
+            .unwrap();
+        registry.state.seal();
+
+        assert_eq!(
+            registry.callbacks_for_reason_count(ShutdownReason::PowerOff),
+            1
+        );
+        assert_eq!(
+            registry.callbacks_for_reason_count(ShutdownReason::Reboot),
+            0
+        );
+        let context = ShutdownContext::new(ShutdownReason::Reboot, TriggerSource::Manual);
+        let ran =
+            registry
+                .state
+                .run_callbacks_with(registry.state.entries(), context, |_, _, _| {});
+        assert!(ran);
+        assert!(registry
+            .state
+            .last_summary
+            .lock()
+            .as_ref()
+            .unwrap()
+            .callbacks
+            .is_empty());
+    }
+
+    #[test]
+    fn test_register_mut_keeps_its_state_between_runs() {
+        let registry = Registry::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut runs = 0;
+        registry
+            .register_mut(move |_| {
+                runs += 1;
+                tx.send(runs).unwrap();
+            })
+            .unwrap();
+
+        registry.execute_callbacks();
+        registry.reset_executed();
+        registry.execute_callbacks();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_file_watch_trigger_runs_callbacks() {
+        let path =
+            std::env::temp_dir().join(format!("shutdown-guard-trigger-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let registry = Registry::new();
+        let sources = Arc::new(Mutex::new(Vec::new()));
+        let sources_clone = Arc::clone(&sources);
+        registry
+            .register(Box::new(move |context| {
+                sources_clone.lock().push((context.reason, context.source));
+            }))
+            .unwrap();
+
+        let handle = registry.register_trigger(Box::new(
+            crate::FileWatchTrigger::new(&path)
+                .reason(ShutdownReason::PowerOff)
+                .poll_interval(Duration::from_millis(10)),
+        ));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!registry.has_executed());
+
+        std::fs::write(&path, b"").unwrap();
+        handle.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            *sources.lock(),
+            vec![(ShutdownReason::PowerOff, TriggerSource::Custom)]
+        );
+    }
+
+    #[test]
+    fn test_custom_trigger_runs_callbacks_once() {
+        let registry = Registry::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for priority in [0, 10] {
+            let order = Arc::clone(&order);
+            registry
+                .register_with_priority(
+                    priority,
+                    Box::new(move |context| order.lock().push((priority, context.source))),
+                )
+                .unwrap();
+        }
+
+        let trigger = registry.clone();
+        let context = ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual);
+        trigger.execute(context);
+        trigger.execute(context);
+
+        assert_eq!(
+            *order.lock(),
+            vec![(10, TriggerSource::Manual), (0, TriggerSource::Manual)]
+        );
+        assert!(registry.has_executed());
+        assert_eq!(registry.last_reason(), Some(ShutdownReason::Unknown));
+    }
+
+    #[test]
+    fn test_build_registry_keeps_configuration() {
+        let registry = ShutdownGuardBuilder::new()
+            .execution_order(ExecutionOrder::Lifo)
+            .build_registry();
+        let first = registry.register(Box::new(|_| {})).unwrap();
+        let second = registry.register(Box::new(|_| {})).unwrap();
+
+        let ids: Vec<CallbackId> = registry.inspect().iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![second, first]);
+    }
+
+    #[test]
+    fn test_register_after_overrides_priority() {
+        let registry = Registry::new();
+        let recorder = OrderRecorder::new();
+
+        let flush = registry
+            .register_with_priority(-10, recorder.hook("flush"))
+            .unwrap();
+        registry
+            .register_after(&[flush], recorder.hook("unmount"))
+            .unwrap();
+        registry.register(recorder.hook("other")).unwrap();
+
+        registry.execute_callbacks();
+        assert_eq!(recorder.order(), vec!["other", "flush", "unmount"]);
+    }
+
+    #[test]
+    fn test_register_after_rejects_unknown_dependency() {
+        let registry = Registry::new();
+        let gone = registry.register(Box::new(|_| {})).unwrap();
+        registry.unregister(gone);
+
+        let result = registry.register_after(&[gone], Box::new(|_| {}));
+        assert_eq!(result, Err(RegisterError::UnknownDependency(gone)));
+        assert!(registry.inspect().is_empty());
+    }
+
+    #[test]
+    fn test_max_callbacks_rejects_registrations_until_one_is_removed() {
+        let registry = ShutdownGuardBuilder::new()
+            .max_callbacks(2)
+            .build_registry();
+        assert_eq!(registry.capacity_remaining(), Some(2));
+        let first = registry.register(Box::new(|_| {})).unwrap();
+        registry.try_register(Box::new(|_| {})).unwrap();
+
+        assert_eq!(registry.capacity_remaining(), Some(0));
+        assert_eq!(
+            registry.try_register(Box::new(|_| {})).unwrap_err(),
+            RegisterError::CapacityExceeded { max: 2 }
+        );
+        let full = Err(RegisterError::CapacityExceeded { max: 2 });
+        assert_eq!(registry.register(Box::new(|_| {})), full);
+        assert_eq!(registry.register_named("named", Box::new(|_| {})), full);
+        assert_eq!(registry.register_fallible(Box::new(|_| Ok(()))), full);
+        assert_eq!(registry.register_final(Box::new(|_| {})), full);
+        assert_eq!(registry.callback_count(), 2);
+
+        registry.unregister(first);
+        assert!(registry.try_register(Box::new(|_| {})).is_ok());
+        assert_eq!(Registry::new().capacity_remaining(), None);
+    }
+
+    #[test]
+    fn test_register_all_keeps_order_and_priorities() {
+        let registry = ShutdownGuardBuilder::new()
+            .max_callbacks(3)
+            .build_registry();
+        let recorder = OrderRecorder::new();
+
+        let ids = registry
+            .register_all_with(vec![
+                (0, recorder.hook("close")),
+                (10, recorder.hook("drain")),
+            ])
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(ids[0] < ids[1]);
+        assert_eq!(
+            registry.register_all(vec![recorder.hook("a"), recorder.hook("b")]),
+            Err(RegisterError::CapacityExceeded { max: 3 })
+        );
+        assert_eq!(registry.callback_count(), 2);
+
+        registry.execute_callbacks();
+        assert_eq!(recorder.order(), vec!["drain", "close"]);
+    }
+
+    #[test]
+    fn test_shutdown_rejects_try_register_and_clear() {
+        let registry = Registry::new();
+        registry.register(Box::new(|_| {})).unwrap();
+        registry.execute_callbacks();
+
+        assert_eq!(
+            registry.try_register(Box::new(|_| {})).unwrap_err(),
+            RegisterError::ShutdownInProgress
+        );
+        assert_eq!(registry.clear(), Err(RegisterError::ShutdownInProgress));
+        assert_eq!(registry.callback_count(), 1);
+
+        registry.reset_executed();
+        assert!(registry.try_register(Box::new(|_| {})).is_ok());
+        assert_eq!(registry.clear(), Ok(()));
+        assert_eq!(registry.callback_count(), 0);
+    }
+
+    #[test]
+    fn test_file_cleanup_deletes_and_syncs() {
+        let dir =
+            std::env::temp_dir().join(format!("shutdown-guard-cleanup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lock = dir.join("app.lock");
+        let log = dir.join("app.log");
+        let kept = dir.join("kept.lock");
+        for path in [&lock, &log, &kept] {
+            std::fs::write(path, b"data").unwrap();
+        }
+
+        let registry = Registry::new();
+        registry.cleanup_file_on_shutdown(lock.clone()).unwrap();
+        registry.flush_file_on_shutdown(log.clone()).unwrap();
+        let id = registry.cleanup_file_on_shutdown(kept.clone()).unwrap();
+        assert!(registry.unregister(id));
+        // Already gone by the time the callbacks run
+        registry
+            .cleanup_file_on_shutdown(dir.join("missing.lock"))
+            .unwrap();
+
+        assert!(registry.execute_callbacks_collect().is_empty());
+        assert!(!lock.exists());
+        assert_eq!(std::fs::read(&log).unwrap(), b"data");
+        assert!(kept.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_callback_work_goes_to_the_spawner() {
+        let spawned = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&spawned);
+        let registry = Registry::new();
+        registry.set_spawner(Box::new(move |work| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            std::thread::spawn(work);
+        }));
+        registry
+            .register_with_timeout(Duration::from_secs(5), Box::new(|_| {}))
+            .unwrap();
+        registry
+            .register_with_scope(|_, scope| {
+                scope.spawn(|| {});
+                scope.spawn(|| {});
+            })
+            .unwrap();
+
+        assert!(registry.execute_callbacks_collect().is_empty());
+        assert_eq!(spawned.load(Ordering::SeqCst), 3);
+
+        registry.reset_executed();
+        assert!(registry.execute_callbacks_parallel().is_empty());
+        // Plus one worker per tier
+        assert!(spawned.load(Ordering::SeqCst) > 6);
+    }
+
+    #[test]
+    fn test_require_only_counts_named_callbacks() {
+        let registry = Registry::new();
+        let id = registry.register(Box::new(|_| {})).unwrap();
+        registry
+            .register_named("flush-audit-log", Box::new(|_| {}))
+            .unwrap();
+
+        assert!(registry.require(&[]).is_ok());
+        assert!(registry.require(&["flush-audit-log"]).is_ok());
+        let label = format!("{:?}", id);
+        assert_eq!(
+            registry.require(&[&label]),
+            Err(ShutdownError::MissingRequiredCallback(label.clone()))
+        );
+    }
+
+    #[test]
+    fn test_coordinator_drives_the_remaining_callbacks() {
+        let recorder = OrderRecorder::new();
+        let registry = Registry::new();
+        let first = registry
+            .register_with_priority(10, recorder.hook("first"))
+            .unwrap();
+        registry.register(recorder.hook("second")).unwrap();
+        let third = registry.register(recorder.hook("third")).unwrap();
+
+        let listed = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&listed);
+        registry
+            .register_coordinator(move |_, controller| {
+                seen.lock()
+                    .extend(controller.list().into_iter().map(|info| info.id));
+                assert!(controller.run(third));
+                assert!(!controller.run(third));
+                assert!(controller.skip(first));
+                assert!(!controller.run(first));
+            })
+            .unwrap();
+
+        let summary = registry.execute_callbacks_with_summary();
+        assert_eq!(listed.lock().len(), 3);
+        assert_eq!(recorder.order(), vec!["third", "second"]);
+        // The callbacks it ran come before the coordinator itself
+        assert_eq!(summary.callbacks.len(), 3);
+        assert_eq!(summary.callbacks[0].id, third);
+    }
+
+    #[cfg(all(unix, feature = "admin-socket"))]
+    #[test]
+    fn test_admin_socket_runs_callbacks_and_cleans_up() {
+        use crate::ShutdownGuard;
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let path =
+            std::env::temp_dir().join(format!("shutdown-guard-admin-{}.sock", std::process::id()));
+        let guard = ShutdownGuard::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        guard
+            .register(Box::new(move |context| {
+                recorded.lock().push((context.reason, context.source));
+            }))
+            .unwrap();
+        let handle = guard.listen_on_socket(&path).unwrap();
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut reply = String::new();
+        for (command, expected) in [
+            ("status", "unknown command"),
+            ("shutdown", "ok"),
+            ("shutdown", "ok"),
+        ] {
+            writeln!(&stream, "{}", command).unwrap();
+            reply.clear();
+            reader.read_line(&mut reply).unwrap();
+            assert_eq!(reply.trim_end(), expected);
+        }
+        drop((stream, reader));
+        assert_eq!(
+            *seen.lock(),
+            vec![(ShutdownReason::Admin, TriggerSource::AdminSocket)]
+        );
+
+        guard.stop().unwrap();
+        handle.join().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_callback_ids_are_monotonic_and_never_reused() {
+        let guard = ShutdownGuardBuilder::new().build();
+        let mut stale = Vec::new();
+        for _ in 0..1000 {
+            let id = guard.register(Box::new(|_| {})).unwrap();
+            assert!(stale.last().is_none_or(|last| *last < id));
+            assert!(guard.unregister(id));
+            stale.push(id);
+        }
+
+        let live = guard.register(Box::new(|_| {})).unwrap();
+        assert!(stale.iter().all(|id| *id < live));
+        for id in stale {
+            assert!(!guard.unregister(id));
+        }
+        assert_eq!(guard.callback_count(), 1);
+    }
+
+    #[test]
+    fn test_with_callbacks_swaps_and_restores() {
+        let guard = ShutdownGuardBuilder::new().build();
+        let recorder = OrderRecorder::new();
+        guard.register(recorder.hook("original")).unwrap();
+
+        guard.with_callbacks(vec![recorder.hook("temp"), recorder.hook("temp")], || {
+            assert_eq!(guard.callback_count(), 2);
+            guard.execute_callbacks();
+        });
+        assert_eq!(recorder.order(), vec!["temp", "temp"]);
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            guard.with_callbacks(Vec::new(), || panic!("in the critical section"))
+        }));
+        assert!(panicked.is_err());
+
+        recorder.clear();
+        guard.reset_executed();
+        guard.execute_callbacks();
+        assert_eq!(recorder.order(), vec!["original"]);
+    }
+
+    #[test]
+    fn test_heartbeat_beats_until_stopped() {
+        use crate::ShutdownGuard;
+        use std::sync::atomic::AtomicUsize;
+
+        let guard = ShutdownGuard::new();
+        let beats = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&beats);
+        let handle = guard.register_heartbeat(Duration::from_millis(5), move || {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first beat");
+            }
+        });
+        while beats.load(Ordering::SeqCst) < 3 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        guard.stop().unwrap();
+        handle.join().unwrap();
+        let stopped_at = beats.load(Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(beats.load(Ordering::SeqCst), stopped_at);
+    }
+
+    #[test]
+    fn test_callbacks_can_reenter_the_registry() {
+        let guard = ShutdownGuardBuilder::new().build();
+        let outcome = Arc::new(Mutex::new(None));
+        let (registry, recorded) = (Registry::clone(&guard), Arc::clone(&outcome));
+        let other = guard.register(Box::new(|_| {})).unwrap();
+        guard
+            .register(Box::new(move |_| {
+                let added = registry.register(Box::new(|_| {})).unwrap();
+                let refused =
+                    registry.try_register(Box::new(|_| {})).is_err() && registry.clear().is_err();
+                *recorded.lock() = Some((
+                    registry.unregister(other),
+                    registry.unregister(added),
+                    refused,
+                ));
+            }))
+            .unwrap();
+
+        let done = std::sync::mpsc::channel();
+        let runner = Registry::clone(&guard);
+        std::thread::spawn(move || {
+            runner.execute_callbacks();
+            done.0.send(()).unwrap();
+        });
+        done.1
+            .recv_timeout(Duration::from_secs(5))
+            .expect("a callback calling back into the registry deadlocked");
+        assert_eq!(*outcome.lock(), Some((true, true, true)));
+    }
+
+    #[test]
+    fn test_callbacks_for_reason_count_applies_reason_and_group_filters() {
+        let critical = GroupId::new();
+        let guard = ShutdownGuardBuilder::new()
+            .run_groups_for(ShutdownReason::Reboot, &[critical])
+            .build();
+        guard.register_in_group(critical, Box::new(|_| {})).unwrap();
+        guard.register(Box::new(|_| {})).unwrap();
+        guard
+            .register_for(&[ShutdownReason::PowerOff], Box::new(|_| {}))
+            .unwrap();
+        guard
+            .register_for(&[ShutdownReason::ANY_SIGNAL], Box::new(|_| {}))
+            .unwrap();
+
+        assert_eq!(
+            guard.callbacks_for_reason_count(ShutdownReason::PowerOff),
+            3
+        );
+        assert_eq!(guard.callbacks_for_reason_count(ShutdownReason::Reboot), 1);
+        assert_eq!(
+            guard.callbacks_for_reason_count(ShutdownReason::Signal(15)),
+            3
+        );
+        assert_eq!(guard.callbacks_for_reason_count(ShutdownReason::Logout), 2);
+    }
+
+    #[test]
+    fn test_set_callbacks_replaces_the_set_at_once() {
+        let guard = ShutdownGuardBuilder::new().max_callbacks(2).build();
+        let recorder = OrderRecorder::new();
+        let old = [
+            guard.register(recorder.hook("old")).unwrap(),
+            guard.register(recorder.hook("old")).unwrap(),
+        ];
+
+        let removed = guard
+            .set_callbacks(vec![recorder.hook("new"), recorder.hook("new")])
+            .unwrap();
+        assert_eq!(removed, old);
+        let overflow = vec![recorder.hook("a"), recorder.hook("b"), recorder.hook("c")];
+        assert_eq!(
+            guard.set_callbacks(overflow),
+            Err(RegisterError::CapacityExceeded { max: 2 })
+        );
+
+        guard.execute_callbacks();
+        assert_eq!(recorder.order(), ["new", "new"]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Concurrent work spawned by a shutdown callback
+
+use crate::in_callback::InCallback;
+use crate::spawner::SharedSpawner;
+use crate::{CallbackError, CallbackPanic, CallbackTimeout, ShutdownDeadline};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// Lets a callback registered with
+/// [`Registry::register_with_scope`](crate::Registry::register_with_scope)
+/// fan its work out over several threads
+///
+/// The callback only counts as done once everything it spawned finished, or
+/// once the guard's deadline passed. Work still running then is abandoned on
+/// its thread like an overrunning callback, and the callback reports a
+/// [`CallbackTimeout`]; a panic in spawned work is reported as the callback's
+/// [`CallbackPanic`].
+///
+/// ```
+/// use shutdown_guard_rs::ShutdownGuard;
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register_with_scope(|_context, scope| {
+///     for shard in 0..5 {
+///         scope.spawn(move || println!("Flushing shard {}", shard));
+///     }
+/// })?;
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+pub struct ShutdownScope {
+    deadline: ShutdownDeadline,
+    spawner: SharedSpawner,
+    spawned: AtomicUsize,
+    done: mpsc::Sender<Result<(), CallbackPanic>>,
+    finished: mpsc::Receiver<Result<(), CallbackPanic>>,
+}
+
+impl ShutdownScope {
+    pub(crate) fn new(deadline: ShutdownDeadline, spawner: SharedSpawner) -> Self {
+        let (done, finished) = mpsc::channel();
+        Self {
+            deadline,
+            spawner,
+            spawned: AtomicUsize::new(0),
+            done,
+            finished,
+        }
+    }
+
+    /// Runs `work` on a new thread, or the guard's
+    /// [spawner](crate::Registry::set_spawner), and makes the callback wait
+    /// for it
+    pub fn spawn<F>(&self, work: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.spawned.fetch_add(1, Ordering::SeqCst);
+        let done = self.done.clone();
+        self.spawner.spawn(move || {
+            let _in_callback = InCallback::enter();
+            let result = std::panic::catch_unwind(AssertUnwindSafe(work))
+                .map_err(|payload| CallbackPanic::from_payload(&*payload));
+            let _ = done.send(result);
+        });
+    }
+
+    /// Waits for the spawned work until the deadline, returning the first
+    /// panic or a timeout if some of it was abandoned
+    pub(crate) fn join(self) -> Result<(), CallbackError> {
+        let budget = self.deadline.remaining();
+        let mut first_panic = None;
+        for _ in 0..self.spawned.load(Ordering::SeqCst) {
+            let received = match self.deadline.remaining() {
+                Some(remaining) => self.finished.recv_timeout(remaining),
+                None => self
+                    .finished
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(Ok(())) => {}
+                Ok(Err(panic)) => {
+                    first_panic.get_or_insert(panic);
+                }
+                Err(_) => {
+                    let timeout = budget.unwrap_or(Duration::ZERO);
+                    return Err(Box::new(CallbackTimeout { timeout }));
+                }
+            }
+        }
+
+        match first_panic {
+            Some(panic) => Err(Box::new(panic)),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Logging for callbacks that neither allocates nor takes locks
+
+use std::cell::RefCell;
+use std::fmt;
+use std::os::unix::io::RawFd;
+
+/// A fixed buffer of log lines that is written to a file descriptor with
+/// `write(2)` alone, without allocating or locking
+///
+/// The guard never runs callbacks inside the signal handler, so callbacks may
+/// use `println!`. That takes the lock on stdout, though, which a callback
+/// abandoned at its timeout may still hold, and allocating can block on the
+/// allocator's lock for the same reason. Code that must not block during
+/// shutdown logs through this logger instead, usually the one per thread
+/// behind [`shutdown_log`]. Everything it does is async-signal-safe, so it
+/// can also be used from a signal handler of the application's own.
+///
+/// Lines are buffered and written once the buffer is full, on
+/// [`flush`](Self::flush) and on drop; a line longer than the buffer is cut
+/// off.
+///
+/// ```
+/// use shutdown_guard_rs::SignalSafeLogger;
+/// use std::fmt::Write;
+///
+/// let mut logger = SignalSafeLogger::new(2);
+/// writeln!(logger, "Flushed {} rows", 42).unwrap();
+/// logger.flush();
+/// ```
+pub struct SignalSafeLogger {
+    fd: RawFd,
+    buf: [u8; SignalSafeLogger::CAPACITY],
+    len: usize,
+}
+
+impl SignalSafeLogger {
+    /// Size of the buffer in bytes
+    pub const CAPACITY: usize = 4096;
+
+    /// Creates a logger writing to `fd`, e.g. 2 for stderr
+    pub const fn new(fd: RawFd) -> Self {
+        Self {
+            fd,
+            buf: [0; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Appends `message` and a newline, flushing first if they do not fit
+    pub fn log(&mut self, message: &str) {
+        let _ = fmt::Write::write_str(self, message);
+        let _ = fmt::Write::write_str(self, "\n");
+    }
+
+    /// Writes out the buffered lines; errors are ignored, as there is nowhere
+    /// left to report them
+    pub fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.len {
+            let n = unsafe {
+                libc::write(
+                    self.fd,
+                    self.buf[written..].as_ptr() as *const libc::c_void,
+                    self.len - written,
+                )
+            };
+            if n > 0 {
+                written += n as usize;
+            } else if n == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR)
+            {
+                break;
+            }
+        }
+        self.len = 0;
+    }
+}
+
+impl fmt::Write for SignalSafeLogger {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        if self.len + bytes.len() > Self::CAPACITY {
+            self.flush();
+        }
+        let n = bytes.len().min(Self::CAPACITY - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+impl Drop for SignalSafeLogger {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+thread_local! {
+    static LOGGER: RefCell<SignalSafeLogger> = const { RefCell::new(SignalSafeLogger::new(2)) };
+}
+
+/// Logs a line to stderr through this thread's [`SignalSafeLogger`]
+///
+/// The line is written once the callback running on this thread finished,
+/// or earlier if the buffer fills up. Formatting with `format_args!` does not
+/// allocate either. A line logged while formatting another one is dropped.
+///
+/// ```
+/// use shutdown_guard_rs::{shutdown_log, ShutdownGuard};
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register(Box::new(|context| {
+///     shutdown_log(format_args!("Cleaning up for {}", context.reason));
+/// }))?;
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+pub fn shutdown_log(message: fmt::Arguments<'_>) {
+    let _ = LOGGER.try_with(|logger| {
+        if let Ok(mut logger) = logger.try_borrow_mut() {
+            let _ = fmt::Write::write_fmt(&mut *logger, message);
+            let _ = fmt::Write::write_str(&mut *logger, "\n");
+        }
+    });
+}
+
+/// Writes out what [`shutdown_log`] buffered on this thread
+pub(crate) fn flush_thread() {
+    let _ = LOGGER.try_with(|logger| {
+        if let Ok(mut logger) = logger.try_borrow_mut() {
+            logger.flush();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
+
+    #[test]
+    fn test_logger_buffers_until_flushed_and_cuts_long_lines() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let mut reader = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+
+        let mut logger = SignalSafeLogger::new(fds[1]);
+        logger.log("first");
+        write!(logger, "second {}", 2).unwrap();
+        logger.flush();
+        let mut buf = [0; 64];
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"first\nsecond 2");
+
+        let long = "x".repeat(SignalSafeLogger::CAPACITY + 10);
+        logger.log(&long);
+        drop(logger);
+        unsafe { libc::close(fds[1]) };
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        // Cut off at the buffer size, then the newline
+        assert_eq!(rest.len(), SignalSafeLogger::CAPACITY + 1);
+    }
+}
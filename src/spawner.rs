@@ -0,0 +1,30 @@
+//! Threads for callback work, or the application's own pool
+
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// Runs a piece of callback work, e.g. on the application's thread pool;
+/// see [`Registry::set_spawner`](crate::Registry::set_spawner)
+pub type Spawner = Box<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+/// The spawner set on a guard, shared with the threads that run its
+/// callbacks; spawns a thread per piece of work while none is set
+#[derive(Clone, Default)]
+pub(crate) struct SharedSpawner(Arc<RwLock<Option<Arc<Spawner>>>>);
+
+impl SharedSpawner {
+    pub(crate) fn set(&self, spawner: Spawner) {
+        *self.0.write() = Some(Arc::new(spawner));
+    }
+
+    pub(crate) fn spawn(&self, work: impl FnOnce() + Send + 'static) {
+        // Called outside the lock, so the spawner may replace itself
+        let spawner = self.0.read().clone();
+        match spawner {
+            Some(spawner) => spawner(Box::new(work)),
+            None => {
+                std::thread::spawn(work);
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Fallible callbacks composed of named steps
+
+use crate::{CallbackError, FallibleCallback};
+use std::fmt;
+
+/// One step of a composed callback
+type Step = Box<dyn Fn() -> Result<(), CallbackError> + Send + Sync + 'static>;
+
+/// Builds one [`FallibleCallback`] out of ordered, named steps
+///
+/// The steps run as a unit: in the order they were added, stopping at the
+/// first one that fails, which is reported as a [`StepFailed`] naming it.
+/// Steps usually share state through captured `Arc`s instead of nesting
+/// closures.
+///
+/// ```rust
+/// use shutdown_guard_rs::{CallbackBuilder, ShutdownGuard};
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register_fallible(
+///     CallbackBuilder::new()
+///         .step("flush", || Ok::<(), std::io::Error>(()))
+///         .step("fsync", || Err("disk full"))
+///         .step("close", || Ok::<(), std::io::Error>(()))
+///         .build(),
+/// )?;
+///
+/// let failures = guard.execute_callbacks_collect();
+/// assert_eq!(failures[0].1.to_string(), "step fsync failed: disk full");
+/// # Ok::<(), shutdown_guard_rs::RegisterError>(())
+/// ```
+#[derive(Default)]
+pub struct CallbackBuilder {
+    steps: Vec<(String, Step)>,
+}
+
+impl CallbackBuilder {
+    /// Creates a builder without any steps
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a step that runs after the ones added before
+    pub fn step<F, E>(mut self, name: impl Into<String>, step: F) -> Self
+    where
+        F: Fn() -> Result<(), E> + Send + Sync + 'static,
+        E: Into<CallbackError>,
+    {
+        self.steps
+            .push((name.into(), Box::new(move || step().map_err(Into::into))));
+        self
+    }
+
+    /// Creates the callback running the steps in order
+    pub fn build(self) -> FallibleCallback {
+        let steps = self.steps;
+        Box::new(move |_| {
+            for (name, step) in &steps {
+                step().map_err(|source| {
+                    Box::new(StepFailed {
+                        step: name.clone(),
+                        source,
+                    }) as CallbackError
+                })?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl fmt::Debug for CallbackBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.steps.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+/// Reported by a callback from [`CallbackBuilder`] when one of its steps fails
+#[derive(Debug)]
+pub struct StepFailed {
+    /// The name of the failed step
+    pub step: String,
+    /// The error the step returned
+    pub source: CallbackError,
+}
+
+impl fmt::Display for StepFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {} failed: {}", self.step, self.source)
+    }
+}
+
+impl std::error::Error for StepFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ShutdownContext, ShutdownReason, TriggerSource};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_steps_stop_at_first_failure() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let step = |name: &'static str, ok: bool| {
+            let ran = Arc::clone(&ran);
+            move || {
+                ran.lock().push(name);
+                if ok {
+                    Ok(())
+                } else {
+                    Err("failed")
+                }
+            }
+        };
+        let callback = CallbackBuilder::new()
+            .step("flush", step("flush", true))
+            .step("fsync", step("fsync", false))
+            .step("close", step("close", true))
+            .build();
+
+        let context = ShutdownContext::new(ShutdownReason::Unknown, TriggerSource::Manual);
+        let error = callback(context).unwrap_err();
+        let failed = error.downcast_ref::<StepFailed>().unwrap();
+        assert_eq!(failed.step, "fsync");
+        assert_eq!(*ran.lock(), vec!["flush", "fsync"]);
+    }
+}
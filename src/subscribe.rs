@@ -0,0 +1,225 @@
+//! Pull-style notification of a detected shutdown
+
+use crate::{ShutdownContext, ShutdownReason};
+use parking_lot::{Condvar, Mutex};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A cloneable handle that resolves once a shutdown is detected
+///
+/// Returned by [`Registry::subscribe`](crate::Registry::subscribe).
+/// It is triggered right before the callbacks run, by whichever trigger runs
+/// them first, and stays triggered afterwards.
+///
+/// ```rust
+/// use shutdown_guard_rs::ShutdownGuard;
+///
+/// let guard = ShutdownGuard::new();
+/// let shutdown = guard.subscribe();
+///
+/// let worker = std::thread::spawn(move || {
+///     // E.g. stop accepting connections, then drain the in-flight ones
+///     shutdown.wait().reason
+/// });
+///
+/// guard.execute_callbacks();
+/// worker.join().unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    context: Mutex<Option<ShutdownContext>>,
+    changed: Condvar,
+    #[cfg(feature = "tokio")]
+    watch: tokio::sync::watch::Sender<Option<ShutdownContext>>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            context: Mutex::new(None),
+            changed: Condvar::new(),
+            #[cfg(feature = "tokio")]
+            watch: tokio::sync::watch::Sender::new(None),
+        }
+    }
+}
+
+impl ShutdownSignal {
+    /// Returns whether a shutdown was detected
+    pub fn is_triggered(&self) -> bool {
+        self.inner.context.lock().is_some()
+    }
+
+    /// Returns the context of the detected shutdown, if any
+    pub fn context(&self) -> Option<ShutdownContext> {
+        *self.inner.context.lock()
+    }
+
+    /// Blocks until a shutdown is detected
+    pub fn wait(&self) -> ShutdownContext {
+        let mut context = self.inner.context.lock();
+        loop {
+            if let Some(context) = *context {
+                return context;
+            }
+            self.inner.changed.wait(&mut context);
+        }
+    }
+
+    /// Blocks until a shutdown is detected or `timeout` passes
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<ShutdownContext> {
+        let mut context = self.inner.context.lock();
+        if context.is_none() {
+            self.inner
+                .changed
+                .wait_while_for(&mut context, |context| context.is_none(), timeout);
+        }
+        *context
+    }
+
+    /// Resolves once a shutdown is detected
+    ///
+    /// Suitable as a `tokio::select!` branch, e.g. in a server's accept loop.
+    #[cfg(feature = "tokio")]
+    pub async fn recv(&self) -> ShutdownContext {
+        let mut receiver = self.inner.watch.subscribe();
+        let context = receiver
+            .wait_for(Option::is_some)
+            .await
+            .expect("the sender lives as long as the signal");
+        context.expect("waited for a context")
+    }
+
+    /// Triggers the signal; only the first context is kept
+    pub(crate) fn trigger(&self, context: ShutdownContext) {
+        let mut current = self.inner.context.lock();
+        if current.is_some() {
+            return;
+        }
+        *current = Some(context);
+        self.inner.changed.notify_all();
+
+        #[cfg(feature = "tokio")]
+        self.inner.watch.send_replace(Some(context));
+    }
+}
+
+/// A cloneable handle that reports the reason of a detected shutdown
+///
+/// Returned by [`Registry::watch`](crate::Registry::watch). It shares its
+/// state with [`ShutdownSignal`], so it is triggered at the same moment, but
+/// only hands out the [`ShutdownReason`], for workers that each begin their
+/// own drain and need nothing else.
+///
+/// ```rust
+/// use shutdown_guard_rs::ShutdownGuard;
+///
+/// let guard = ShutdownGuard::new();
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let watcher = guard.watch();
+///         std::thread::spawn(move || watcher.wait())
+///     })
+///     .collect();
+///
+/// guard.execute_callbacks();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShutdownWatcher {
+    signal: ShutdownSignal,
+}
+
+impl ShutdownWatcher {
+    pub(crate) fn new(signal: ShutdownSignal) -> Self {
+        Self { signal }
+    }
+
+    /// Returns whether a shutdown was detected
+    pub fn is_triggered(&self) -> bool {
+        self.signal.is_triggered()
+    }
+
+    /// Blocks until a shutdown is detected and returns its reason
+    pub fn wait(&self) -> ShutdownReason {
+        self.signal.wait().reason
+    }
+}
+
+impl fmt::Debug for ShutdownSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShutdownSignal")
+            .field("context", &self.context())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ShutdownReason, TriggerSource};
+
+    #[test]
+    fn test_wait_timeout_before_and_after_trigger() {
+        let signal = ShutdownSignal::default();
+        assert_eq!(signal.wait_timeout(Duration::from_millis(10)), None);
+
+        let context = ShutdownContext::new(ShutdownReason::Reboot, TriggerSource::Manual);
+        signal.clone().trigger(context);
+        signal.trigger(ShutdownContext::new(
+            ShutdownReason::Logout,
+            TriggerSource::Manual,
+        ));
+
+        assert!(signal.is_triggered());
+        assert_eq!(
+            signal.wait_timeout(Duration::from_millis(10)),
+            Some(context)
+        );
+        assert_eq!(signal.wait(), context);
+    }
+
+    #[test]
+    fn test_watchers_wake_on_trigger() {
+        let signal = ShutdownSignal::default();
+        let watcher = ShutdownWatcher::new(signal.clone());
+        assert!(!watcher.is_triggered());
+
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let watcher = watcher.clone();
+                std::thread::spawn(move || watcher.wait())
+            })
+            .collect();
+        signal.trigger(ShutdownContext::new(
+            ShutdownReason::PowerOff,
+            TriggerSource::Manual,
+        ));
+
+        assert!(watcher.is_triggered());
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), ShutdownReason::PowerOff);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_recv_resolves_on_trigger() {
+        let signal = ShutdownSignal::default();
+        let waiter = {
+            let signal = signal.clone();
+            tokio::spawn(async move { signal.recv().await })
+        };
+
+        let context = ShutdownContext::new(ShutdownReason::PowerOff, TriggerSource::Manual);
+        signal.trigger(context);
+        assert_eq!(waiter.await.unwrap(), context);
+    }
+}
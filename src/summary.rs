@@ -0,0 +1,123 @@
+//! Structured report of a run of the shutdown callbacks
+
+use crate::{
+    CallbackError, CallbackId, CallbackPanic, CallbackSkipped, CallbackTimeout, ShutdownReason,
+};
+use std::fmt;
+use std::time::Duration;
+
+/// How a callback ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum CallbackOutcome {
+    /// The callback returned normally
+    Ok,
+    /// A fallible callback returned an error with the given message
+    Failed(String),
+    /// The callback panicked with the given message, or an empty one if the
+    /// payload was not a string
+    Panicked(String),
+    /// The callback overran its timeout or was skipped because the graceful
+    /// phase ran out of time
+    TimedOut,
+    /// The callback was below the
+    /// [critical priority floor](crate::ShutdownGuardBuilder::critical_priority_floor)
+    /// and did not run because the deadline was close
+    Skipped,
+}
+
+impl CallbackOutcome {
+    pub(crate) fn from_result(result: &Result<(), CallbackError>) -> Self {
+        let Err(e) = result else {
+            return Self::Ok;
+        };
+        if let Some(panic) = e.downcast_ref::<CallbackPanic>() {
+            Self::Panicked(panic.message.clone().unwrap_or_default())
+        } else if e.downcast_ref::<CallbackTimeout>().is_some() {
+            Self::TimedOut
+        } else if e.downcast_ref::<CallbackSkipped>().is_some() {
+            Self::Skipped
+        } else {
+            Self::Failed(e.to_string())
+        }
+    }
+}
+
+impl fmt::Display for CallbackOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackOutcome::Ok => write!(f, "ok"),
+            CallbackOutcome::Failed(e) => write!(f, "failed: {}", e),
+            CallbackOutcome::Panicked(message) => write!(f, "panicked: {}", message),
+            CallbackOutcome::TimedOut => write!(f, "timed out"),
+            CallbackOutcome::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// Timing and outcome of one callback
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CallbackSummary {
+    /// The id returned when the callback was registered
+    pub id: CallbackId,
+    /// The name given with
+    /// [`Registry::register_named`](crate::Registry::register_named), if any
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub name: Option<String>,
+    /// How long the callback ran
+    pub duration: Duration,
+    /// How the callback ended
+    pub outcome: CallbackOutcome,
+}
+
+/// Report of a run of the shutdown callbacks, returned by
+/// [`Registry::execute_callbacks_with_summary`](crate::Registry::execute_callbacks_with_summary)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ShutdownSummary {
+    /// The callbacks that ran, in execution order
+    pub callbacks: Vec<CallbackSummary>,
+    /// How long the whole run took
+    pub elapsed: Duration,
+    /// The reasons of the triggers coalesced into the run, the first one's
+    /// first; see
+    /// [`ShutdownGuardBuilder::debounce`](crate::ShutdownGuardBuilder::debounce)
+    pub reasons: Vec<ShutdownReason>,
+}
+
+impl ShutdownSummary {
+    /// Returns true if every callback ended with [`CallbackOutcome::Ok`]
+    pub fn is_success(&self) -> bool {
+        self.callbacks
+            .iter()
+            .all(|callback| callback.outcome == CallbackOutcome::Ok)
+    }
+}
+
+/// One line for the run, then one per callback, e.g.
+///
+/// ```text
+/// 2 callbacks in 15.2ms
+///   flush-wal: ok in 12.1ms
+///   CallbackId(3): failed: disk full in 3.1ms
+/// ```
+impl fmt::Display for ShutdownSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} callbacks in {:?}",
+            self.callbacks.len(),
+            self.elapsed
+        )?;
+        for callback in &self.callbacks {
+            match &callback.name {
+                Some(name) => write!(f, "\n  {}: ", name)?,
+                None => write!(f, "\n  {:?}: ", callback.id)?,
+            }
+            write!(f, "{} in {:?}", callback.outcome, callback.duration)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Records a detected shutdown in the system log, which survives the reboot
+//!
+//! Syslog on Unix, which the journal collects on Linux and the unified log on
+//! macOS, and the Application event log on Windows.
+
+use crate::ShutdownContext;
+
+/// Message logged unless `ShutdownGuardBuilder::system_log_message` sets one
+pub(crate) const DEFAULT_SYSTEM_LOG_MESSAGE: &str =
+    "Shutdown detected ({reason}, {source}), running {callbacks} cleanup callbacks";
+
+/// Fills in the `{reason}`, `{source}` and `{callbacks}` placeholders of
+/// `template`
+pub(crate) fn render(template: &str, context: ShutdownContext, callbacks: usize) -> String {
+    template
+        .replace("{reason}", &context.reason.to_string())
+        .replace("{source}", &format!("{:?}", context.source))
+        .replace("{callbacks}", &callbacks.to_string())
+}
+
+/// Writes `message` to the system log; failures are logged to stderr and
+/// otherwise ignored, since the callbacks must run regardless
+#[cfg(unix)]
+pub(crate) fn log_shutdown_event(message: &str) {
+    // Without its NULs, the message always converts
+    let message = std::ffi::CString::new(message.replace('\0', "")).unwrap_or_default();
+    // syslog(3) reports no errors
+    unsafe {
+        libc::syslog(
+            libc::LOG_USER | libc::LOG_NOTICE,
+            c"%s".as_ptr(),
+            message.as_ptr(),
+        );
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn log_shutdown_event(message: &str) {
+    use windows::core::{w, HSTRING, PCWSTR};
+    use windows::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_INFORMATION_TYPE,
+    };
+
+    let message = HSTRING::from(message);
+    let logged = unsafe {
+        RegisterEventSourceW(None, w!("ShutdownGuard")).and_then(|source| {
+            let reported = ReportEventW(
+                source,
+                EVENTLOG_INFORMATION_TYPE,
+                0,
+                0,
+                None,
+                0,
+                Some(&[PCWSTR(message.as_ptr())]),
+                None,
+            );
+            let _ = DeregisterEventSource(source);
+            reported
+        })
+    };
+    if let Err(e) = logged {
+        eprintln!("Failed to write the shutdown to the event log: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ShutdownReason, TriggerSource};
+
+    #[test]
+    fn test_render_fills_in_the_placeholders() {
+        let context = ShutdownContext::new(ShutdownReason::PowerOff, TriggerSource::DBusSignal);
+        assert_eq!(
+            render(DEFAULT_SYSTEM_LOG_MESSAGE, context, 3),
+            format!(
+                "Shutdown detected ({}, DBusSignal), running 3 cleanup callbacks",
+                ShutdownReason::PowerOff
+            )
+        );
+        assert_eq!(render("no placeholders", context, 3), "no placeholders");
+    }
+}
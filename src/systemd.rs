@@ -0,0 +1,80 @@
+//! Reports shutdown progress to systemd for `Type=notify` services
+//!
+//! Without the `systemd` feature, or off Linux, every function here compiles
+//! to nothing.
+
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+use std::io;
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+/// Tells systemd that the service is stopping, so `systemctl stop` waits for
+/// the callbacks instead of assuming the service hangs
+pub(crate) fn stopping() {
+    #[cfg(all(target_os = "linux", feature = "systemd"))]
+    notify("STOPPING=1");
+}
+
+/// Reports that `done` of `total` callbacks finished, the last one `label`
+#[cfg_attr(
+    not(all(target_os = "linux", feature = "systemd")),
+    allow(unused_variables)
+)]
+pub(crate) fn progress(label: &str, done: usize, total: usize) {
+    #[cfg(all(target_os = "linux", feature = "systemd"))]
+    notify(&format!(
+        "STATUS=Shutting down: {} finished ({}/{})",
+        label, done, total
+    ));
+}
+
+/// Sends `state` to the service manager, e.g. `"STOPPING=1"`
+///
+/// Does nothing if `NOTIFY_SOCKET` is unset, i.e. when the process is not a
+/// systemd service or the unit is not `Type=notify`. Failures are logged and
+/// otherwise ignored, since the callbacks must run regardless.
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let Some(socket) = socket.to_str() else {
+        eprintln!("NOTIFY_SOCKET is not valid UTF-8");
+        return;
+    };
+    if let Err(e) = notify_socket(socket, state) {
+        eprintln!("Failed to notify systemd ({}): {}", state, e);
+    }
+}
+
+/// Sends `state` as one datagram to `socket`, a path or an abstract socket
+/// name prefixed with `@`
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+fn notify_socket(socket: &str, state: &str) -> io::Result<()> {
+    let address = match socket.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux", feature = "systemd"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_socket_sends_datagram() {
+        let path = std::env::temp_dir().join(format!("notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.to_str().unwrap(), "STOPPING=1").unwrap();
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STOPPING=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+//! Per-callback timeouts
+
+use crate::clock::SharedClock;
+use crate::in_callback::InCallback;
+use crate::spawner::SharedSpawner;
+use crate::{CallbackError, CallbackPanic, ShutdownContext};
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A shared callback that can be moved to a scratch thread
+pub(crate) type SharedCallback = Arc<dyn Fn(ShutdownContext) + Send + Sync + 'static>;
+
+/// Reported for a callback that did not finish within its timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackTimeout {
+    /// The timeout the callback was registered with
+    pub timeout: Duration,
+}
+
+impl fmt::Display for CallbackTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "callback did not finish within {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for CallbackTimeout {}
+
+/// Runs the callback on a scratch thread and waits at most `timeout` on
+/// `clock` for it
+///
+/// A callback that overruns is abandoned: its thread keeps running in the
+/// background, but the caller moves on.
+pub(crate) fn run_with_timeout(
+    callback: SharedCallback,
+    context: ShutdownContext,
+    timeout: Duration,
+    clock: &SharedClock,
+    spawner: &SharedSpawner,
+) -> Result<(), CallbackError> {
+    let until = clock.now() + timeout;
+    let (tx, rx) = mpsc::channel();
+
+    spawner.spawn(move || {
+        let _in_callback = InCallback::enter();
+        callback(context);
+        let _ = tx.send(());
+    });
+
+    match clock.recv_until(&rx, until) {
+        Ok(()) => Ok(()),
+        Err(RecvTimeoutError::Timeout) => Err(Box::new(CallbackTimeout { timeout })),
+        Err(RecvTimeoutError::Disconnected) => Err(Box::new(CallbackPanic { message: None })),
+    }
+}
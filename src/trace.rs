@@ -0,0 +1,89 @@
+//! Optional `tracing` instrumentation of callback execution
+//!
+//! Without the `tracing` feature every function here compiles to nothing,
+//! except that [`status`] still prints for verbose guards.
+
+use crate::{CallbackError, CallbackId, ShutdownContext};
+use std::time::Duration;
+
+/// Callbacks running longer than this are reported with a warning
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+const SLOW_CALLBACK_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Entered while the callbacks run; dropping it exits the span
+#[cfg(feature = "tracing")]
+pub(crate) type ExecuteSpan = tracing::span::EnteredSpan;
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct ExecuteSpan;
+
+/// Enters the `shutdown_guard::execute` span
+#[cfg(feature = "tracing")]
+pub(crate) fn enter_execute(context: &ShutdownContext) -> ExecuteSpan {
+    tracing::info_span!(
+        "shutdown_guard::execute",
+        reason = %context.reason,
+        source = ?context.source,
+    )
+    .entered()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn enter_execute(_context: &ShutdownContext) -> ExecuteSpan {
+    ExecuteSpan
+}
+
+/// Reports a callback that returned, successfully or with an error
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn callback_finished(
+    id: CallbackId,
+    name: Option<&str>,
+    elapsed: Duration,
+    result: &Result<(), CallbackError>,
+) {
+    #[cfg(feature = "tracing")]
+    {
+        match result {
+            Ok(()) => {
+                tracing::debug!(callback = ?id, name, ?elapsed, "shutdown callback finished")
+            }
+            Err(e) => {
+                tracing::warn!(callback = ?id, name, ?elapsed, error = %e, "shutdown callback failed")
+            }
+        }
+        if elapsed > SLOW_CALLBACK_THRESHOLD {
+            tracing::warn!(
+                callback = ?id,
+                name,
+                ?elapsed,
+                threshold = ?SLOW_CALLBACK_THRESHOLD,
+                "shutdown callback was slow"
+            );
+        }
+    }
+}
+
+/// Reports a callback that panicked
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn callback_panicked(
+    id: CallbackId,
+    name: Option<&str>,
+    elapsed: Duration,
+    panic: &crate::CallbackPanic,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(callback = ?id, name, ?elapsed, %panic, "shutdown callback panicked");
+}
+
+/// Reports a status message such as "monitoring active"
+///
+/// Emitted as an info event with the `tracing` feature, and printed to stderr
+/// only if the guard was built with
+/// [`verbose`](crate::ShutdownGuardBuilder::verbose); silent otherwise.
+pub(crate) fn status(verbose: bool, message: impl std::fmt::Display) {
+    #[cfg(feature = "tracing")]
+    tracing::info!("{}", message);
+
+    if verbose {
+        eprintln!("{}", message);
+    }
+}
@@ -0,0 +1,93 @@
+//! User-supplied ways of detecting a shutdown
+
+use crate::ShutdownReason;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Detects a shutdown for environments the platform backends do not cover
+///
+/// Registered with [`Registry::register_trigger`](crate::Registry::register_trigger),
+/// a trigger runs on a thread of its own and feeds the same dispatch path as
+/// the built-in signals and notifications, e.g. in a sandbox where the
+/// supervisor touches a flag file instead of sending a signal.
+///
+/// ```
+/// use shutdown_guard_rs::{ShutdownReason, ShutdownTrigger};
+/// use std::sync::mpsc::Receiver;
+/// use std::sync::Mutex;
+///
+/// /// Shuts down once the control channel hangs up
+/// struct ControlChannel(Mutex<Receiver<()>>);
+///
+/// impl ShutdownTrigger for ControlChannel {
+///     fn wait(&self) -> ShutdownReason {
+///         while self.0.lock().unwrap().recv().is_ok() {}
+///         ShutdownReason::PowerOff
+///     }
+/// }
+/// ```
+pub trait ShutdownTrigger: Send + Sync {
+    /// Blocks until a shutdown is detected and returns its reason
+    fn wait(&self) -> ShutdownReason;
+}
+
+/// How often [`FileWatchTrigger`] checks for its file unless configured
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fires once a file exists, e.g. a flag file a supervisor creates to request
+/// a shutdown
+///
+/// The file is checked every second by default and left in place when the
+/// trigger fires.
+///
+/// ```no_run
+/// use shutdown_guard_rs::{FileWatchTrigger, ShutdownGuard, ShutdownReason};
+/// use std::time::Duration;
+///
+/// let guard = ShutdownGuard::new();
+/// guard.register_trigger(Box::new(
+///     FileWatchTrigger::new("/run/myapp/stop")
+///         .reason(ShutdownReason::PowerOff)
+///         .poll_interval(Duration::from_millis(200)),
+/// ));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileWatchTrigger {
+    path: PathBuf,
+    reason: ShutdownReason,
+    poll_interval: Duration,
+}
+
+impl FileWatchTrigger {
+    /// Creates a trigger that fires with [`ShutdownReason::Unknown`] once
+    /// `path` exists
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            reason: ShutdownReason::Unknown,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the reason reported to the callbacks
+    pub fn reason(mut self, reason: ShutdownReason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    /// Sets how often the file is checked
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+}
+
+impl ShutdownTrigger for FileWatchTrigger {
+    fn wait(&self) -> ShutdownReason {
+        while !self.path.exists() {
+            thread::sleep(self.poll_interval);
+        }
+        self.reason
+    }
+}
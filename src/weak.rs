@@ -0,0 +1,46 @@
+//! Callbacks that do not keep their target alive
+
+use crate::ShutdownContext;
+use std::sync::{Arc, Weak};
+
+/// A callback bound to a target it only holds weakly
+pub(crate) trait WeakCallback: Send + Sync {
+    /// Runs the callback if the target is still alive
+    fn invoke(&self, context: ShutdownContext);
+
+    /// Returns whether the target is still alive
+    fn is_live(&self) -> bool;
+}
+
+struct Bound<T, F> {
+    target: Weak<T>,
+    callback: F,
+}
+
+impl<T, F> WeakCallback for Bound<T, F>
+where
+    T: Send + Sync,
+    F: Fn(&T, ShutdownContext) + Send + Sync,
+{
+    fn invoke(&self, context: ShutdownContext) {
+        if let Some(target) = self.target.upgrade() {
+            (self.callback)(&target, context);
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        self.target.strong_count() > 0
+    }
+}
+
+/// Binds `callback` to a weak reference to `target`
+pub(crate) fn bind<T, F>(target: &Arc<T>, callback: F) -> Box<dyn WeakCallback>
+where
+    T: Send + Sync + 'static,
+    F: Fn(&T, ShutdownContext) + Send + Sync + 'static,
+{
+    Box::new(Bound {
+        target: Arc::downgrade(target),
+        callback,
+    })
+}
@@ -0,0 +1,34 @@
+//! The blocking API must behave identically with or without the async features
+//!
+//! CI runs this file both with default features and with `--features tokio`.
+
+use shutdown_guard_rs::{ShutdownGuard, ShutdownReason};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn blocking_api_is_available_regardless_of_features() {
+    let guard = ShutdownGuard::new();
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    let order_low = Arc::clone(&order);
+    guard
+        .register(Box::new(move |_| order_low.lock().unwrap().push("low")))
+        .unwrap();
+    let order_high = Arc::clone(&order);
+    guard
+        .register_with_priority(
+            10,
+            Box::new(move |_| order_high.lock().unwrap().push("high")),
+        )
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    guard.notify_on_shutdown(tx).unwrap();
+
+    guard.start().expect("Failed to start shutdown monitoring");
+
+    guard.execute_callbacks_with_reason(ShutdownReason::PowerOff);
+    assert_eq!(*order.lock().unwrap(), vec!["high", "low"]);
+    assert_eq!(rx.recv().unwrap(), ShutdownReason::PowerOff);
+}
@@ -0,0 +1,26 @@
+//! `#[shutdown_hook]` functions are registered with the global guard on start
+
+#![cfg(feature = "macros")]
+
+use shutdown_guard_rs::{shutdown_hook, ShutdownContext};
+
+#[shutdown_hook]
+fn flush_logs(_context: ShutdownContext) {}
+
+#[shutdown_hook(priority = 10, name = "close-database")]
+fn close_database(_context: ShutdownContext) {}
+
+#[test]
+fn hooks_are_registered_on_start() {
+    let guard = shutdown_guard_rs::global();
+    assert_eq!(guard.callback_count(), 0);
+
+    shutdown_guard_rs::start().unwrap();
+    assert_eq!(guard.callback_names(), vec!["close-database", "flush_logs"]);
+
+    // Starting again registers nothing twice
+    guard.stop().unwrap();
+    shutdown_guard_rs::start().unwrap();
+    assert_eq!(guard.callback_count(), 2);
+    guard.stop().unwrap();
+}
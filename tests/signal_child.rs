@@ -0,0 +1,82 @@
+//! The signal path end to end, in a child process
+//!
+//! The parent test starts this test binary again running only
+//! `signal_child`, which starts a guard whose callback writes a sentinel
+//! file. The parent then sends the child SIGTERM: the sentinel proves that
+//! the handler dispatched the callbacks, and the child's exit code that it
+//! went on to exit as configured.
+#![cfg(unix)]
+
+#[cfg(all(target_os = "linux", feature = "dbus-support"))]
+use shutdown_guard_rs::LinuxBackend;
+use shutdown_guard_rs::{ExitBehavior, ShutdownGuard};
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Set by the parent to the sentinel path; the child test does nothing
+/// without it
+const CHILD_ENV: &str = "SHUTDOWN_GUARD_SIGNAL_CHILD";
+
+const SIGTERM: i32 = 15;
+
+const EXIT_CODE: i32 = 42;
+
+#[test]
+fn signal_child() {
+    let Ok(sentinel) = std::env::var(CHILD_ENV) else {
+        return;
+    };
+
+    let builder = ShutdownGuard::builder()
+        .exit_behavior(ExitBehavior::Exit(EXIT_CODE))
+        .call_global_sync(false);
+    #[cfg(all(target_os = "linux", feature = "dbus-support"))]
+    let builder = builder.linux_backend(LinuxBackend::Signals);
+    let guard = builder.build();
+    guard
+        .register(Box::new(move |context| {
+            std::fs::write(&sentinel, format!("cleaned up: {}", context.reason)).unwrap();
+        }))
+        .unwrap();
+    guard.start().unwrap();
+    println!("ready");
+
+    // The signal ends the process; give up if it never arrives
+    std::thread::sleep(Duration::from_secs(30));
+    panic!("no SIGTERM within 30 seconds");
+}
+
+#[test]
+fn sigterm_runs_the_callbacks_in_a_child() {
+    let sentinel =
+        std::env::temp_dir().join(format!("shutdown-guard-sentinel-{}", std::process::id()));
+    let _ = std::fs::remove_file(&sentinel);
+
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "signal_child", "--nocapture", "--test-threads=1"])
+        .env(CHILD_ENV, &sentinel)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // libtest prints the test's name on the same line
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    assert!(
+        lines.any(|line| line.is_ok_and(|line| line.ends_with("ready"))),
+        "the child exited before starting its guard"
+    );
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(EXIT_CODE));
+    assert_eq!(
+        std::fs::read_to_string(&sentinel).unwrap(),
+        format!("cleaned up: signal {}", SIGTERM)
+    );
+    std::fs::remove_file(&sentinel).unwrap();
+}
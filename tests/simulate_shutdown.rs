@@ -0,0 +1,63 @@
+//! Integration tests can drive a realistic shutdown through the `testing` feature
+#![cfg(feature = "testing")]
+
+use shutdown_guard_rs::{CallbackId, Executor, ShutdownGuard, ShutdownReason, Task, TriggerSource};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn simulated_shutdown_reports_reason_in_priority_order() {
+    let guard = ShutdownGuard::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+
+    for (priority, name) in [(0, "flush"), (10, "stop-accepting")] {
+        let seen = Arc::clone(&seen);
+        guard
+            .register_with_priority(
+                priority,
+                Box::new(move |context| {
+                    assert_eq!(context.source, TriggerSource::Simulated);
+                    seen.lock().unwrap().push((name, context.reason));
+                }),
+            )
+            .unwrap();
+    }
+
+    guard.simulate_shutdown(ShutdownReason::PowerOff);
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            ("stop-accepting", ShutdownReason::PowerOff),
+            ("flush", ShutdownReason::PowerOff)
+        ]
+    );
+}
+
+/// Records the id of every callback before running it
+struct RecordingExecutor(Arc<Mutex<Vec<CallbackId>>>);
+
+impl Executor for RecordingExecutor {
+    fn execute(&self, tasks: Vec<Task<'_>>) {
+        for task in tasks {
+            self.0.lock().unwrap().push(task.id());
+            if task.run().is_break() {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn executor_observes_dependency_order() {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let guard = ShutdownGuard::builder()
+        .executor(Box::new(RecordingExecutor(Arc::clone(&order))))
+        .build();
+
+    let flush = guard.register_with_priority(-10, Box::new(|_| {})).unwrap();
+    let unmount = guard.register_after(&[flush], Box::new(|_| {})).unwrap();
+    let close = guard.register(Box::new(|_| {})).unwrap();
+    let stop = guard.register_with_priority(10, Box::new(|_| {})).unwrap();
+
+    guard.simulate_shutdown(ShutdownReason::Unknown);
+    assert_eq!(*order.lock().unwrap(), vec![stop, close, flush, unmount]);
+}